@@ -0,0 +1,8 @@
+CREATE TABLE invites (
+  code TEXT PRIMARY KEY NOT NULL,
+  user_id INTEGER NOT NULL,
+  kind TEXT NOT NULL,
+  created_at DATETIME NOT NULL,
+  FOREIGN KEY (user_id)
+    REFERENCES users (id)
+);
@@ -0,0 +1,30 @@
+CREATE TABLE emergency_contacts (
+  user_id INTEGER NOT NULL,
+  contact_id INTEGER NOT NULL,
+  PRIMARY KEY (user_id, contact_id),
+  FOREIGN KEY (user_id)
+    REFERENCES users (id),
+  FOREIGN KEY (contact_id)
+    REFERENCES users (id)
+);
+
+CREATE TABLE emergency_alerts (
+  id INTEGER PRIMARY KEY NOT NULL,
+  user_id INTEGER NOT NULL,
+  date_time DATETIME NOT NULL,
+  reason TEXT NOT NULL,
+  millimoles_per_liter FLOAT,
+  FOREIGN KEY (user_id)
+    REFERENCES users (id)
+);
+
+CREATE TABLE emergency_alert_acknowledgements (
+  alert_id INTEGER NOT NULL,
+  contact_id INTEGER NOT NULL,
+  date_time DATETIME NOT NULL,
+  PRIMARY KEY (alert_id, contact_id),
+  FOREIGN KEY (alert_id)
+    REFERENCES emergency_alerts (id),
+  FOREIGN KEY (contact_id)
+    REFERENCES users (id)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE hypo_rechecks (
+  user_id INTEGER PRIMARY KEY NOT NULL,
+  prompt_at DATETIME NOT NULL,
+  prompted BOOLEAN NOT NULL DEFAULT FALSE,
+  FOREIGN KEY (user_id)
+    REFERENCES users (id)
+);
@@ -0,0 +1,12 @@
+CREATE TABLE user_links (
+  patient_id INTEGER NOT NULL,
+  caregiver_id INTEGER NOT NULL,
+  permission TEXT NOT NULL,
+  entry_notifications TEXT NOT NULL DEFAULT 'out_of_range',
+  missed_reminder_notifications BOOLEAN NOT NULL DEFAULT TRUE,
+  PRIMARY KEY (patient_id, caregiver_id),
+  FOREIGN KEY (patient_id)
+    REFERENCES users (id),
+  FOREIGN KEY (caregiver_id)
+    REFERENCES users (id)
+);
@@ -0,0 +1,204 @@
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE profiles (
+  id INTEGER PRIMARY KEY NOT NULL,
+  user_id INTEGER NOT NULL,
+  name TEXT,
+  FOREIGN KEY (user_id)
+    REFERENCES users (id)
+);
+
+INSERT INTO profiles (user_id)
+SELECT id FROM users;
+
+ALTER TABLE users
+ADD COLUMN current_profile_id INTEGER
+  REFERENCES profiles (id);
+
+UPDATE users
+SET current_profile_id = (
+  SELECT id FROM profiles WHERE profiles.user_id = users.id
+);
+
+-- sugar_measurements
+
+CREATE TABLE new_sugar_measurements (
+  profile_id INTEGER NOT NULL,
+  date_time DATETIME NOT NULL,
+  millimoles_per_liter FLOAT NOT NULL,
+  PRIMARY KEY (profile_id, date_time),
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
+
+INSERT INTO new_sugar_measurements
+SELECT profiles.id, date_time, millimoles_per_liter
+FROM sugar_measurements
+JOIN profiles ON profiles.user_id = sugar_measurements.user_id;
+
+DROP TABLE sugar_measurements;
+
+ALTER TABLE new_sugar_measurements
+RENAME TO sugar_measurements;
+
+-- insulin_injections
+
+CREATE TABLE new_insulin_injections (
+  profile_id INTEGER NOT NULL,
+  date_time DATETIME NOT NULL,
+  cubic_centimeters FLOAT NOT NULL,
+  PRIMARY KEY (profile_id, date_time),
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
+
+INSERT INTO new_insulin_injections
+SELECT profiles.id, date_time, cubic_centimeters
+FROM insulin_injections
+JOIN profiles ON profiles.user_id = insulin_injections.user_id;
+
+DROP TABLE insulin_injections;
+
+ALTER TABLE new_insulin_injections
+RENAME TO insulin_injections;
+
+-- hypo_rechecks
+
+CREATE TABLE new_hypo_rechecks (
+  profile_id INTEGER PRIMARY KEY NOT NULL,
+  prompt_at DATETIME NOT NULL,
+  prompted BOOLEAN NOT NULL DEFAULT FALSE,
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
+
+INSERT INTO new_hypo_rechecks
+SELECT profiles.id, prompt_at, prompted
+FROM hypo_rechecks
+JOIN profiles ON profiles.user_id = hypo_rechecks.user_id;
+
+DROP TABLE hypo_rechecks;
+
+ALTER TABLE new_hypo_rechecks
+RENAME TO hypo_rechecks;
+
+-- invites
+
+CREATE TABLE new_invites (
+  code TEXT PRIMARY KEY NOT NULL,
+  profile_id INTEGER NOT NULL,
+  kind TEXT NOT NULL,
+  created_at DATETIME NOT NULL,
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
+
+INSERT INTO new_invites
+SELECT code, profiles.id, kind, created_at
+FROM invites
+JOIN profiles ON profiles.user_id = invites.user_id;
+
+DROP TABLE invites;
+
+ALTER TABLE new_invites
+RENAME TO invites;
+
+-- emergency_contacts
+
+CREATE TABLE new_emergency_contacts (
+  profile_id INTEGER NOT NULL,
+  contact_id INTEGER NOT NULL,
+  PRIMARY KEY (profile_id, contact_id),
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id),
+  FOREIGN KEY (contact_id)
+    REFERENCES users (id)
+);
+
+INSERT INTO new_emergency_contacts
+SELECT profiles.id, contact_id
+FROM emergency_contacts
+JOIN profiles ON profiles.user_id = emergency_contacts.user_id;
+
+DROP TABLE emergency_contacts;
+
+ALTER TABLE new_emergency_contacts
+RENAME TO emergency_contacts;
+
+-- emergency_alerts with acknowledgements referencing them
+
+CREATE TABLE old_emergency_alert_acknowledgements AS
+SELECT * FROM emergency_alert_acknowledgements;
+
+DROP TABLE emergency_alert_acknowledgements;
+
+CREATE TABLE new_emergency_alerts (
+  id INTEGER PRIMARY KEY NOT NULL,
+  profile_id INTEGER NOT NULL,
+  date_time DATETIME NOT NULL,
+  reason TEXT NOT NULL,
+  millimoles_per_liter FLOAT,
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
+
+INSERT INTO new_emergency_alerts
+SELECT
+  emergency_alerts.id,
+  profiles.id,
+  date_time,
+  reason,
+  millimoles_per_liter
+FROM emergency_alerts
+JOIN profiles ON profiles.user_id = emergency_alerts.user_id;
+
+DROP TABLE emergency_alerts;
+
+ALTER TABLE new_emergency_alerts
+RENAME TO emergency_alerts;
+
+CREATE TABLE emergency_alert_acknowledgements (
+  alert_id INTEGER NOT NULL,
+  contact_id INTEGER NOT NULL,
+  date_time DATETIME NOT NULL,
+  PRIMARY KEY (alert_id, contact_id),
+  FOREIGN KEY (alert_id)
+    REFERENCES emergency_alerts (id),
+  FOREIGN KEY (contact_id)
+    REFERENCES users (id)
+);
+
+INSERT INTO emergency_alert_acknowledgements
+SELECT * FROM old_emergency_alert_acknowledgements;
+
+DROP TABLE old_emergency_alert_acknowledgements;
+
+-- user_links
+
+CREATE TABLE new_user_links (
+  profile_id INTEGER NOT NULL,
+  caregiver_id INTEGER NOT NULL,
+  permission TEXT NOT NULL,
+  entry_notifications TEXT NOT NULL DEFAULT 'out_of_range',
+  missed_reminder_notifications BOOLEAN NOT NULL DEFAULT TRUE,
+  PRIMARY KEY (profile_id, caregiver_id),
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id),
+  FOREIGN KEY (caregiver_id)
+    REFERENCES users (id)
+);
+
+INSERT INTO new_user_links
+SELECT
+  profiles.id,
+  caregiver_id,
+  permission,
+  entry_notifications,
+  missed_reminder_notifications
+FROM user_links
+JOIN profiles ON profiles.user_id = user_links.patient_id;
+
+DROP TABLE user_links;
+
+ALTER TABLE new_user_links
+RENAME TO user_links;
@@ -0,0 +1,8 @@
+CREATE TABLE hba1c_results (
+  profile_id INTEGER NOT NULL,
+  date_time DATETIME NOT NULL,
+  percent FLOAT NOT NULL,
+  PRIMARY KEY (profile_id, date_time),
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
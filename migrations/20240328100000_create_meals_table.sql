@@ -0,0 +1,9 @@
+CREATE TABLE meals (
+  profile_id INTEGER NOT NULL,
+  date_time DATETIME NOT NULL,
+  carbs_grams FLOAT NOT NULL,
+  note TEXT,
+  PRIMARY KEY (profile_id, date_time),
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
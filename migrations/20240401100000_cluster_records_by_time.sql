@@ -0,0 +1,65 @@
+-- Store records clustered by (profile_id, date_time) so that range
+-- queries of a profile read adjacent pages and aggregates are
+-- answered from the primary key b-tree without rowid lookups.
+
+-- sugar_measurements
+
+CREATE TABLE new_sugar_measurements (
+  profile_id INTEGER NOT NULL,
+  date_time DATETIME NOT NULL,
+  millimoles_per_liter FLOAT NOT NULL,
+  PRIMARY KEY (profile_id, date_time),
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+) WITHOUT ROWID;
+
+INSERT INTO new_sugar_measurements
+SELECT profile_id, date_time, millimoles_per_liter
+FROM sugar_measurements;
+
+DROP TABLE sugar_measurements;
+
+ALTER TABLE new_sugar_measurements
+RENAME TO sugar_measurements;
+
+-- insulin_injections
+
+CREATE TABLE new_insulin_injections (
+  profile_id INTEGER NOT NULL,
+  date_time DATETIME NOT NULL,
+  cubic_centimeters FLOAT NOT NULL,
+  kind TEXT,
+  PRIMARY KEY (profile_id, date_time),
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+) WITHOUT ROWID;
+
+INSERT INTO new_insulin_injections
+SELECT profile_id, date_time, cubic_centimeters, kind
+FROM insulin_injections;
+
+DROP TABLE insulin_injections;
+
+ALTER TABLE new_insulin_injections
+RENAME TO insulin_injections;
+
+-- meals
+
+CREATE TABLE new_meals (
+  profile_id INTEGER NOT NULL,
+  date_time DATETIME NOT NULL,
+  carbs_grams FLOAT NOT NULL,
+  note TEXT,
+  PRIMARY KEY (profile_id, date_time),
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+) WITHOUT ROWID;
+
+INSERT INTO new_meals
+SELECT profile_id, date_time, carbs_grams, note
+FROM meals;
+
+DROP TABLE meals;
+
+ALTER TABLE new_meals
+RENAME TO meals;
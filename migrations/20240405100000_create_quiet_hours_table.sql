@@ -0,0 +1,7 @@
+CREATE TABLE quiet_hours (
+  user_id INTEGER PRIMARY KEY NOT NULL,
+  start TIME NOT NULL,
+  end TIME NOT NULL,
+  FOREIGN KEY (user_id)
+    REFERENCES users (id)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE daily_summaries (
+  profile_id INTEGER PRIMARY KEY NOT NULL,
+  time TIME NOT NULL,
+  next_at DATETIME NOT NULL,
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
@@ -0,0 +1,6 @@
+CREATE TABLE weekly_digests (
+  profile_id INTEGER PRIMARY KEY NOT NULL,
+  next_at DATETIME NOT NULL,
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
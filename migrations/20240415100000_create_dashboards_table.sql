@@ -0,0 +1,7 @@
+CREATE TABLE dashboards (
+  user_id INTEGER PRIMARY KEY NOT NULL,
+  message_id INTEGER NOT NULL,
+  created_at DATETIME NOT NULL,
+  FOREIGN KEY (user_id)
+    REFERENCES users (id)
+);
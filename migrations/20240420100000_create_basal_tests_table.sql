@@ -0,0 +1,9 @@
+CREATE TABLE basal_tests (
+  profile_id INTEGER PRIMARY KEY NOT NULL,
+  skipped_meal TEXT NOT NULL,
+  started_at DATETIME NOT NULL,
+  ends_at DATETIME NOT NULL,
+  next_prompt_at DATETIME NOT NULL,
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
@@ -0,0 +1,9 @@
+CREATE TABLE insulin_safety_rules (
+  profile_id INTEGER PRIMARY KEY NOT NULL,
+  max_bolus REAL NOT NULL,
+  max_basal REAL NOT NULL,
+  stacking_warning BOOLEAN NOT NULL,
+  double_basal_warning BOOLEAN NOT NULL,
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
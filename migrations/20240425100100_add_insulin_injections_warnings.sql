@@ -0,0 +1,2 @@
+ALTER TABLE insulin_injections
+ADD COLUMN warnings TEXT;
@@ -0,0 +1,10 @@
+CREATE TABLE basal_reminders (
+  profile_id INTEGER PRIMARY KEY NOT NULL,
+  time TIME NOT NULL,
+  follow_up_hours INTEGER NOT NULL,
+  stage TEXT NOT NULL,
+  reminded_at DATETIME,
+  next_at DATETIME NOT NULL,
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE missed_basal_doses (
+  profile_id INTEGER NOT NULL,
+  reminded_at DATETIME NOT NULL,
+  PRIMARY KEY (profile_id, reminded_at),
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE hypo_rechecks
+ADD COLUMN prompted_at DATETIME;
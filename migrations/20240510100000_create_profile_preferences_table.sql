@@ -0,0 +1,7 @@
+CREATE TABLE profile_preferences (
+  profile_id INTEGER PRIMARY KEY NOT NULL,
+  timezone TEXT NOT NULL,
+  units TEXT NOT NULL,
+  FOREIGN KEY (profile_id)
+    REFERENCES profiles (id)
+);
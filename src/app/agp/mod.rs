@@ -0,0 +1,328 @@
+pub mod render;
+
+use std::{collections::HashSet, sync::Arc};
+
+use chrono::{DateTime, Local, TimeZone, Timelike, Utc};
+use teloxide::{
+  dptree::case,
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile},
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::Db,
+  utils::{filter_callback_query, filter_message},
+};
+
+use super::{
+  profile::current_profile,
+  stats::{
+    statistics::{glucose_management_indicator, GlucoseStats},
+    Period,
+  },
+  sugar_measurement::{
+    repository::sugar_measurements, SugarMeasurement, HIGH_LEVEL,
+    LOW_LEVEL,
+  },
+  UpdateHandler,
+};
+
+const CALLBACK_PREFIX: &str = "agp:";
+/// Level 2 hypoglycemia threshold, mmol/L
+pub const VERY_LOW_LEVEL: f64 = 3.0;
+/// Level 2 hyperglycemia threshold, mmol/L
+pub const VERY_HIGH_LEVEL: f64 = 13.9;
+
+/// Ambulatory glucose profile: readings of a date range folded into
+/// one day
+#[derive(Debug, Clone, PartialEq)]
+pub struct Agp {
+  pub start: DateTime<Utc>,
+  pub end: DateTime<Utc>,
+  /// Days with at least one reading
+  pub days_with_data: usize,
+  pub stats: GlucoseStats,
+  /// Glucose management indicator, %
+  pub gmi: f64,
+  pub ranges: TimeInRanges,
+  /// Percentiles of readings by hour of day
+  pub hourly: [Option<Percentiles>; 24],
+  /// Hour of day in fractions and level of every reading
+  pub readings: Vec<(f64, f64)>,
+}
+
+/// Shares of readings in standard AGP ranges, 0..=1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeInRanges {
+  pub very_low: f64,
+  pub low: f64,
+  pub in_range: f64,
+  pub high: f64,
+  pub very_high: f64,
+}
+
+/// Level percentiles, mmol/L
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Percentiles {
+  pub p5: f64,
+  pub p25: f64,
+  pub p50: f64,
+  pub p75: f64,
+  pub p95: f64,
+}
+
+impl Agp {
+  /// Builds profile from readings within `start..end`. Time of day is
+  /// taken in `tz`. `None` if there are no readings.
+  #[allow(clippy::cast_precision_loss)]
+  pub fn generate<Tz: TimeZone>(
+    measurements: &[SugarMeasurement],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    tz: &Tz,
+  ) -> Option<Self> {
+    let measurements: Vec<_> = measurements
+      .iter()
+      .filter(|m| (start..end).contains(&m.date_time))
+      .copied()
+      .collect();
+    let stats = GlucoseStats::calculate(&measurements)?;
+    let local: Vec<_> = measurements
+      .iter()
+      .map(|m| {
+        let dt = m.date_time.with_timezone(tz);
+        (dt, m.level.as_millimoles_per_liter())
+      })
+      .collect();
+    let days_with_data = local
+      .iter()
+      .map(|(dt, _)| dt.date_naive())
+      .collect::<HashSet<_>>()
+      .len();
+    let n = local.len() as f64;
+    let share = |pred: fn(f64) -> bool| {
+      local.iter().filter(|&&(_, x)| pred(x)).count() as f64 / n
+    };
+    let ranges = TimeInRanges {
+      very_low: share(|x| x < VERY_LOW_LEVEL),
+      low: share(|x| (VERY_LOW_LEVEL..LOW_LEVEL).contains(&x)),
+      in_range: share(|x| (LOW_LEVEL..=HIGH_LEVEL).contains(&x)),
+      high: share(|x| x > HIGH_LEVEL && x <= VERY_HIGH_LEVEL),
+      very_high: share(|x| x > VERY_HIGH_LEVEL),
+    };
+    let hourly = std::array::from_fn(|hour| {
+      let mut levels: Vec<_> = local
+        .iter()
+        .filter(|(dt, _)| dt.hour() as usize == hour)
+        .map(|&(_, x)| x)
+        .collect();
+      Percentiles::calculate(&mut levels)
+    });
+    let readings = local
+      .iter()
+      .map(|(dt, x)| {
+        let seconds = dt.num_seconds_from_midnight();
+        (f64::from(seconds) / 3600.0, *x)
+      })
+      .collect();
+    Some(Self {
+      start,
+      end,
+      days_with_data,
+      stats,
+      gmi: glucose_management_indicator(stats.mean),
+      ranges,
+      hourly,
+      readings,
+    })
+  }
+}
+
+impl Percentiles {
+  /// `None` if there are no levels
+  pub fn calculate(levels: &mut [f64]) -> Option<Self> {
+    if levels.is_empty() {
+      return None;
+    }
+    levels.sort_by(f64::total_cmp);
+    Some(Self {
+      p5: percentile(levels, 5.0),
+      p25: percentile(levels, 25.0),
+      p50: percentile(levels, 50.0),
+      p75: percentile(levels, 75.0),
+      p95: percentile(levels, 95.0),
+    })
+  }
+}
+
+/// Percentile `p` of non-empty `sorted` values with linear
+/// interpolation between closest ranks
+#[allow(
+  clippy::cast_precision_loss,
+  clippy::cast_possible_truncation,
+  clippy::cast_sign_loss
+)]
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+  let rank = p / 100.0 * (sorted.len() - 1) as f64;
+  let lower = rank.floor() as usize;
+  let upper = rank.ceil() as usize;
+  let fraction = rank - rank.floor();
+  sorted[lower] + (sorted[upper] - sorted[lower]) * fraction
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    dptree::entry()
+      .branch(
+        filter_message()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::Agp].endpoint(ask_period)),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            let data = query.data.as_deref()?;
+            Period::parse(data.strip_prefix(CALLBACK_PREFIX)?)
+          })
+          .endpoint(send_agp),
+      )
+  }
+}
+
+async fn ask_period(bot: Bot, chat_id: ChatId) -> Result<()> {
+  let periods = Period::ALL
+    .into_iter()
+    .filter(|&period| period != Period::Today)
+    .map(|period| {
+      InlineKeyboardButton::callback(
+        period.label(),
+        format!("{CALLBACK_PREFIX}{}", period.data()),
+      )
+    })
+    .collect::<Vec<_>>();
+  bot
+    .send_message(
+      chat_id,
+      "Выберите период амбулаторного профиля глюкозы (AGP). \
+      Рекомендуется 14 дней:",
+    )
+    .reply_markup(InlineKeyboardMarkup::new([periods]))
+    .await?;
+  Ok(())
+}
+
+async fn send_agp(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  period: Period,
+  query: CallbackQuery,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  let profile = current_profile(&db, user_id).await?;
+  let now = Local::now();
+  let start = period.start(now);
+  let end = now.to_utc();
+  let measurements = sugar_measurements(&db, profile.id)
+    .fetch_between(start, end)
+    .await?;
+  let Some(agp) = Agp::generate(&measurements, start, end, &Local)
+  else {
+    bot
+      .send_message(msg.chat.id, "Нет измерений сахара за период")
+      .await?;
+    return Ok(());
+  };
+  let title = format!("AGP за {}{}", period.title(), profile.tag());
+  let png = tokio::task::spawn_blocking(move || {
+    render::render(&agp, &title, &Local)
+  })
+  .await
+  .map_err(any)??;
+  bot
+    .send_photo(
+      msg.chat.id,
+      InputFile::memory(png).file_name("agp.png"),
+    )
+    .await?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::app::sugar_measurement::SugarLevel;
+
+  use super::*;
+
+  fn at(s: &str) -> DateTime<Utc> {
+    s.parse().unwrap()
+  }
+
+  fn measurement(date_time: &str, level: f64) -> SugarMeasurement {
+    SugarMeasurement {
+      date_time: at(date_time),
+      level: SugarLevel::from_millimoles_per_liter(level),
+    }
+  }
+
+  #[test]
+  fn percentiles_interpolate() {
+    let mut levels = [9.0, 1.0, 5.0, 3.0, 7.0];
+    let p = Percentiles::calculate(&mut levels).unwrap();
+    assert!((p.p5 - 1.4).abs() < 1e-9);
+    assert!((p.p25 - 3.0).abs() < 1e-9);
+    assert!((p.p50 - 5.0).abs() < 1e-9);
+    assert!((p.p75 - 7.0).abs() < 1e-9);
+    assert!((p.p95 - 8.6).abs() < 1e-9);
+    assert_eq!(Percentiles::calculate(&mut []), None);
+  }
+
+  #[test]
+  fn generate_folds_readings_into_day() {
+    let measurements = [
+      measurement("2024-02-29T08:00:00Z", 20.0),
+      measurement("2024-03-01T08:10:00Z", 2.5),
+      measurement("2024-03-01T08:30:00Z", 3.5),
+      measurement("2024-03-02T08:50:00Z", 6.0),
+      measurement("2024-03-02T20:00:00Z", 12.0),
+      measurement("2024-03-03T20:30:00Z", 15.0),
+    ];
+    let agp = Agp::generate(
+      &measurements,
+      at("2024-03-01T00:00:00Z"),
+      at("2024-03-04T00:00:00Z"),
+      &Utc,
+    )
+    .unwrap();
+    assert_eq!(agp.stats.count, 5);
+    assert_eq!(agp.days_with_data, 3);
+    assert_eq!(agp.ranges.very_low, 0.2);
+    assert_eq!(agp.ranges.low, 0.2);
+    assert_eq!(agp.ranges.in_range, 0.2);
+    assert_eq!(agp.ranges.high, 0.2);
+    assert_eq!(agp.ranges.very_high, 0.2);
+    assert!((agp.hourly[8].unwrap().p50 - 3.5).abs() < 1e-9);
+    assert!((agp.hourly[20].unwrap().p50 - 13.5).abs() < 1e-9);
+    assert_eq!(agp.hourly[0], None);
+    assert_eq!(agp.readings[0], (8.0 + 10.0 / 60.0, 2.5));
+  }
+
+  #[test]
+  fn generate_without_readings() {
+    let agp = Agp::generate(
+      &[measurement("2024-03-01T08:00:00Z", 5.0)],
+      at("2024-03-02T00:00:00Z"),
+      at("2024-03-03T00:00:00Z"),
+      &Utc,
+    );
+    assert_eq!(agp, None);
+  }
+}
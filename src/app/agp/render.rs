@@ -0,0 +1,283 @@
+use chrono::TimeZone;
+use plotters::prelude::*;
+
+use crate::{
+  app::{
+    chart::render::{encode_png, register_bundled_font, FONT},
+    sugar_measurement::{HIGH_LEVEL, LOW_LEVEL},
+  },
+  common::Result,
+};
+
+use super::{Agp, Percentiles, VERY_HIGH_LEVEL, VERY_LOW_LEVEL};
+
+pub const WIDTH: u32 = 1200;
+pub const HEIGHT: u32 = 600;
+const PANEL_WIDTH: u32 = 360;
+/// Minimal upper bound of level axis, mmol/L
+const MIN_MAX_LEVEL: f64 = 15.0;
+
+const RANGE_COLOR: RGBColor = RGBColor(220, 245, 220);
+const READING_COLOR: RGBColor = RGBColor(170, 170, 170);
+const OUTER_BAND_COLOR: RGBColor = RGBColor(200, 215, 240);
+const INNER_BAND_COLOR: RGBColor = RGBColor(120, 155, 220);
+const MEDIAN_COLOR: RGBColor = RGBColor(20, 60, 160);
+
+/// Renders AGP chart with metrics panel to PNG. Dates are shown in
+/// `tz`.
+pub fn render<Tz: TimeZone>(
+  agp: &Agp,
+  title: &str,
+  tz: &Tz,
+) -> Result<Vec<u8>>
+where
+  Tz::Offset: std::fmt::Display,
+{
+  encode_png(&render_pixels(agp, title, tz)?, WIDTH, HEIGHT)
+}
+
+/// Renders AGP to RGB pixels of [`WIDTH`] x [`HEIGHT`] image
+pub fn render_pixels<Tz: TimeZone>(
+  agp: &Agp,
+  title: &str,
+  tz: &Tz,
+) -> Result<Vec<u8>>
+where
+  Tz::Offset: std::fmt::Display,
+{
+  register_bundled_font();
+  let mut pixels = vec![0; (WIDTH * HEIGHT * 3) as usize];
+  draw(agp, title, tz, &mut pixels).map_err(|err| err.to_string())?;
+  Ok(pixels)
+}
+
+fn draw<Tz: TimeZone>(
+  agp: &Agp,
+  title: &str,
+  tz: &Tz,
+  pixels: &mut [u8],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+  Tz::Offset: std::fmt::Display,
+{
+  let root = BitMapBackend::with_buffer(pixels, (WIDTH, HEIGHT))
+    .into_drawing_area();
+  root.fill(&WHITE)?;
+  let (chart_area, panel) =
+    root.split_horizontally(WIDTH - PANEL_WIDTH);
+  draw_profile(agp, title, &chart_area)?;
+  draw_metrics(agp, tz, &panel)?;
+  root.present()?;
+  Ok(())
+}
+
+fn draw_profile(
+  agp: &Agp,
+  title: &str,
+  area: &DrawingArea<BitMapBackend, plotters::coord::Shift>,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let max_level = agp
+    .readings
+    .iter()
+    .map(|&(_, level)| level + 1.0)
+    .fold(MIN_MAX_LEVEL, f64::max);
+  let mut chart = ChartBuilder::on(area)
+    .caption(title, (FONT, 24))
+    .margin(16)
+    .x_label_area_size(40)
+    .y_label_area_size(50)
+    .build_cartesian_2d((0.0..24.0).step(3.0), 0.0..max_level)?;
+  chart
+    .configure_mesh()
+    .x_label_formatter(&|hour| format!("{hour:02.0}:00"))
+    .y_desc("ммоль/л")
+    .label_style((FONT, 14))
+    .axis_desc_style((FONT, 16))
+    .light_line_style(WHITE.mix(0.0))
+    .draw()?;
+
+  chart.draw_series([Rectangle::new(
+    [(0.0, LOW_LEVEL), (24.0, HIGH_LEVEL)],
+    RANGE_COLOR.filled(),
+  )])?;
+  chart.draw_series(
+    agp
+      .readings
+      .iter()
+      .map(|&point| Circle::new(point, 2, READING_COLOR.filled())),
+  )?;
+  for segment in segments(&agp.hourly) {
+    let band = |lower: fn(&Percentiles) -> f64,
+                upper: fn(&Percentiles) -> f64| {
+      let mut points = line(&segment, upper);
+      points.extend(line(&segment, lower).into_iter().rev());
+      points
+    };
+    chart.draw_series([
+      Polygon::new(
+        band(|p| p.p5, |p| p.p95),
+        OUTER_BAND_COLOR.mix(0.7).filled(),
+      ),
+      Polygon::new(
+        band(|p| p.p25, |p| p.p75),
+        INNER_BAND_COLOR.mix(0.7).filled(),
+      ),
+    ])?;
+    chart.draw_series(LineSeries::new(
+      line(&segment, |p| p.p50),
+      MEDIAN_COLOR.stroke_width(3),
+    ))?;
+  }
+  Ok(())
+}
+
+fn draw_metrics<Tz: TimeZone>(
+  agp: &Agp,
+  tz: &Tz,
+  area: &DrawingArea<BitMapBackend, plotters::coord::Shift>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+  Tz::Offset: std::fmt::Display,
+{
+  let date = |dt: chrono::DateTime<chrono::Utc>| {
+    dt.with_timezone(tz).format("%d.%m.%Y").to_string()
+  };
+  let percent = |share: f64| share * 100.0;
+  let ranges = &agp.ranges;
+  let lines = [
+    format!("{} – {}", date(agp.start), date(agp.end)),
+    format!("Дней с данными: {}", agp.days_with_data),
+    format!("Измерений: {}", agp.stats.count),
+    String::new(),
+    format!("Средний сахар: {:.1} ммоль/л", agp.stats.mean),
+    format!("GMI: {:.1} %", agp.gmi),
+    format!("CV: {:.1} %", agp.stats.coefficient_of_variation()),
+    String::new(),
+    format!(
+      "Очень высокий >{VERY_HIGH_LEVEL}: {:.0} %",
+      percent(ranges.very_high)
+    ),
+    format!(
+      "Высокий {HIGH_LEVEL:.1}–{VERY_HIGH_LEVEL}: {:.0} %",
+      percent(ranges.high)
+    ),
+    format!(
+      "В диапазоне {LOW_LEVEL}–{HIGH_LEVEL:.1}: {:.0} %",
+      percent(ranges.in_range)
+    ),
+    format!(
+      "Низкий {VERY_LOW_LEVEL:.1}–{LOW_LEVEL}: {:.0} %",
+      percent(ranges.low)
+    ),
+    format!(
+      "Очень низкий <{VERY_LOW_LEVEL:.1}: {:.0} %",
+      percent(ranges.very_low)
+    ),
+    String::new(),
+    "Линия — медиана,".into(),
+    "полосы — 25–75 % и 5–95 %".into(),
+  ];
+  let style = (FONT, 16).into_font().color(&BLACK);
+  for (i, line) in (0..).zip(lines) {
+    area.draw(&Text::new(line, (10, 60 + i * 26), style.clone()))?;
+  }
+  Ok(())
+}
+
+/// Runs of consecutive hours with percentiles
+fn segments(
+  hourly: &[Option<Percentiles>; 24],
+) -> Vec<Vec<(f64, Percentiles)>> {
+  let mut segments = Vec::new();
+  let mut current = Vec::new();
+  for (hour, percentiles) in (0..24).zip(hourly) {
+    match percentiles {
+      Some(p) => current.push((f64::from(hour), *p)),
+      None if !current.is_empty() => {
+        segments.push(std::mem::take(&mut current));
+      }
+      None => {}
+    }
+  }
+  if !current.is_empty() {
+    segments.push(current);
+  }
+  segments
+}
+
+/// Line through hour centers, stretched to edges of segment
+fn line(
+  segment: &[(f64, Percentiles)],
+  value: fn(&Percentiles) -> f64,
+) -> Vec<(f64, f64)> {
+  let mut points: Vec<_> = segment
+    .iter()
+    .map(|(hour, p)| (hour + 0.5, value(p)))
+    .collect();
+  if let (Some(&(first, p)), Some(&(last, q))) =
+    (segment.first(), segment.last())
+  {
+    points.insert(0, (first, value(&p)));
+    points.push((last + 1.0, value(&q)));
+  }
+  points
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{DateTime, Duration, Utc};
+
+  use crate::app::{
+    chart::render::assert_matches_golden,
+    sugar_measurement::{SugarLevel, SugarMeasurement},
+  };
+
+  use super::*;
+
+  const GOLDEN: &str = "src/app/agp/testdata/agp.png";
+
+  fn agp() -> Agp {
+    let start: DateTime<Utc> =
+      "2024-03-01T00:00:00Z".parse().unwrap();
+    let measurements: Vec<_> = (0..14 * 24)
+      .map(|i| {
+        let hour = i % 24;
+        let day = i / 24;
+        let level = 6.0
+          + 4.0
+            * (f64::from(hour) / 24.0 * std::f64::consts::TAU).sin()
+          + f64::from(day % 5)
+          - 2.0;
+        SugarMeasurement {
+          date_time: start
+            + Duration::days(day.into())
+            + Duration::hours(hour.into())
+            + Duration::minutes((day * 7 % 60).into()),
+          level: SugarLevel::from_millimoles_per_liter(level),
+        }
+      })
+      .collect();
+    Agp::generate(
+      &measurements,
+      start,
+      start + Duration::days(14),
+      &Utc,
+    )
+    .unwrap()
+  }
+
+  #[test]
+  fn renders_deterministically() {
+    let agp = agp();
+    assert_eq!(
+      render(&agp, "AGP", &Utc).unwrap(),
+      render(&agp, "AGP", &Utc).unwrap()
+    );
+  }
+
+  #[test]
+  fn matches_golden_image() {
+    let png = render(&agp(), "AGP за 14 дней", &Utc).unwrap();
+    assert_matches_golden(&png, GOLDEN);
+  }
+}
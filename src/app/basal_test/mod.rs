@@ -0,0 +1,434 @@
+pub mod repository;
+pub mod summary;
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Local, Utc};
+use clokwerk::{AsyncScheduler, TimeUnits};
+use teloxide::{
+  dptree::case,
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup},
+  ApiError, RequestError,
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::Result,
+  db::Db,
+  event_handler::{filter_event, handler, EventHandler},
+  utils::{
+    event_publisher::EventPublisher, filter_callback_query,
+    filter_message,
+  },
+};
+
+use self::{
+  repository::basal_tests,
+  summary::{judge, render},
+};
+
+use super::{
+  insulin_injection::{
+    on_board::{action_start, insulin_on_board},
+    repository::insulin_injections,
+  },
+  profile::{
+    current_profile, repository::profiles, Profile, ProfileId,
+  },
+  sugar_measurement::{
+    repository::sugar_measurements, SugarMeasurementAdded,
+  },
+  user::repository::users,
+  UpdateHandler,
+};
+
+const CALLBACK_PREFIX: &str = "basal_test:";
+/// Test can't start while more bolus insulin is acting, units
+const MAX_INSULIN_ON_BOARD: f64 = 0.5;
+/// Time for last measurement before summary, minutes
+const LAST_READING_MINUTES: i64 = 30;
+
+#[derive(Debug, Clone)]
+struct BasalTestTick;
+
+/// Meal skipped during test
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "snake_case")]
+pub enum SkippedMeal {
+  Breakfast,
+  Lunch,
+  Dinner,
+}
+
+impl SkippedMeal {
+  const ALL: [Self; 3] = [Self::Breakfast, Self::Lunch, Self::Dinner];
+
+  fn label(self) -> &'static str {
+    match self {
+      Self::Breakfast => "Завтрак",
+      Self::Lunch => "Обед",
+      Self::Dinner => "Ужин",
+    }
+  }
+
+  fn genitive(self) -> &'static str {
+    match self {
+      Self::Breakfast => "завтрака",
+      Self::Lunch => "обеда",
+      Self::Dinner => "ужина",
+    }
+  }
+
+  /// Duration of test, hours. Test without dinner lasts overnight.
+  fn hours(self) -> i64 {
+    match self {
+      Self::Breakfast | Self::Lunch => 6,
+      Self::Dinner => 10,
+    }
+  }
+
+  /// Time between measurements, minutes
+  fn interval(self) -> i64 {
+    match self {
+      Self::Breakfast | Self::Lunch => 60,
+      Self::Dinner => 120,
+    }
+  }
+
+  fn parse(s: &str) -> Option<Self> {
+    match s {
+      "breakfast" => Some(Self::Breakfast),
+      "lunch" => Some(Self::Lunch),
+      "dinner" => Some(Self::Dinner),
+      _ => None,
+    }
+  }
+
+  fn data(self) -> &'static str {
+    match self {
+      Self::Breakfast => "breakfast",
+      Self::Lunch => "lunch",
+      Self::Dinner => "dinner",
+    }
+  }
+}
+
+/// Running basal test
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasalTest {
+  pub profile_id: ProfileId,
+  pub skipped_meal: SkippedMeal,
+  pub started_at: DateTime<Utc>,
+  pub ends_at: DateTime<Utc>,
+  /// When user is asked to measure next. Summary is sent when it is
+  /// after end of test.
+  pub next_prompt_at: DateTime<Utc>,
+}
+
+/// Action chosen by button
+#[derive(Debug, Clone, Copy)]
+struct Choice {
+  profile_id: ProfileId,
+  /// `None` aborts running test
+  start: Option<SkippedMeal>,
+}
+
+impl Choice {
+  fn parse(data: &str) -> Option<Self> {
+    let data = data.strip_prefix(CALLBACK_PREFIX)?;
+    let (profile_id, action) = data.split_once(':')?;
+    let start = match action {
+      "abort" => None,
+      meal => Some(SkippedMeal::parse(meal)?),
+    };
+    Some(Self {
+      profile_id: ProfileId(profile_id.parse().ok()?),
+      start,
+    })
+  }
+
+  fn button(self) -> InlineKeyboardButton {
+    let ProfileId(id) = self.profile_id;
+    let (label, action) = match self.start {
+      Some(meal) => (meal.label(), meal.data()),
+      None => ("⛔️ Прервать тест", "abort"),
+    };
+    InlineKeyboardButton::callback(
+      label,
+      format!("{CALLBACK_PREFIX}{id}:{action}"),
+    )
+  }
+}
+
+fn abort_keyboard(profile_id: ProfileId) -> InlineKeyboardMarkup {
+  let abort = Choice {
+    profile_id,
+    start: None,
+  };
+  InlineKeyboardMarkup::new([[abort.button()]])
+}
+
+fn local_time(dt: DateTime<Utc>) -> String {
+  dt.with_timezone(&Local).format("%H:%M").to_string()
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    dptree::entry()
+      .branch(
+        filter_message()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::BasalTest].endpoint(send_intro)),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            Choice::parse(query.data.as_deref()?)
+          })
+          .endpoint(choose),
+      )
+  }
+
+  fn event_handler(&self) -> EventHandler {
+    dptree::entry()
+      .branch(
+        filter_event::<SugarMeasurementAdded>()
+          .chain(handler(abort_on_hypo)),
+      )
+      .branch(
+        filter_event::<BasalTestTick>().chain(handler(send_due)),
+      )
+  }
+
+  fn schedule(
+    &self,
+    scheduler: &mut AsyncScheduler,
+    event_publisher: Arc<EventPublisher>,
+  ) {
+    scheduler.every(1.minute()).run(move || {
+      event_publisher.send(BasalTestTick);
+      async {}
+    });
+  }
+}
+
+/// Explains test and offers meal to skip, or shows running test
+async fn send_intro(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+) -> Result<()> {
+  let profile = current_profile(&db, user_id).await?;
+  if let Some(test) = basal_tests(&db).fetch(profile.id).await? {
+    bot
+      .send_message(chat_id, progress(&profile, &test))
+      .reply_markup(abort_keyboard(profile.id))
+      .await?;
+    return Ok(());
+  }
+  let meals = SkippedMeal::ALL.map(|meal| {
+    Choice {
+      profile_id: profile.id,
+      start: Some(meal),
+    }
+    .button()
+  });
+  bot
+    .send_message(
+      chat_id,
+      format!(
+        "🧪 Базальный тест{} показывает, подходит ли доза длинного \
+        инсулина. Во время теста пропустите прием пищи, не вводите \
+        короткий инсулин и измеряйте сахар по напоминаниям. Без \
+        завтрака или обеда тест длится 6 часов с измерением каждый \
+        час, без ужина — 10 часов с измерением каждые 2 часа.\n\n\
+        Начните тест в то время, когда обычно едите, и не раньше \
+        чем через 4 часа после болюса. При низком сахаре тест \
+        прерывается.\n\nКакой прием пищи пропускаете?",
+        profile.tag()
+      ),
+    )
+    .reply_markup(InlineKeyboardMarkup::new([meals]))
+    .await?;
+  Ok(())
+}
+
+fn progress(profile: &Profile, test: &BasalTest) -> String {
+  format!(
+    "🧪 Идет базальный тест{} (пропуск {}) до {}. Следующее \
+    измерение в {}",
+    profile.tag(),
+    test.skipped_meal.genitive(),
+    local_time(test.ends_at),
+    local_time(test.next_prompt_at.min(test.ends_at))
+  )
+}
+
+async fn choose(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  choice: Choice,
+  query: CallbackQuery,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  let profile = profiles(&db).fetch(choice.profile_id).await?;
+  let Some(profile) =
+    profile.filter(|profile| profile.user_id == user_id)
+  else {
+    return Ok(());
+  };
+  let mut tests = basal_tests(&db);
+  let Some(skipped_meal) = choice.start else {
+    let text = if tests.remove(profile.id).await? {
+      "Базальный тест прерван"
+    } else {
+      "Тест уже не идет"
+    };
+    bot.edit_message_text(msg.chat.id, msg.id, text).await?;
+    return Ok(());
+  };
+  if let Some(test) = tests.fetch(profile.id).await? {
+    bot
+      .edit_message_text(
+        msg.chat.id,
+        msg.id,
+        progress(&profile, &test),
+      )
+      .reply_markup(abort_keyboard(profile.id))
+      .await?;
+    return Ok(());
+  }
+  let now = Utc::now();
+  let injections = insulin_injections(&db, profile.id)
+    .fetch_between(action_start(now), now)
+    .await?;
+  let on_board = insulin_on_board(&injections, now);
+  if on_board > MAX_INSULIN_ON_BOARD {
+    bot
+      .edit_message_text(
+        msg.chat.id,
+        msg.id,
+        format!(
+          "Еще действует {on_board:.1} ЕД короткого инсулина, \
+          результат теста будет неточным. Начните тест позже: \
+          /basal_test"
+        ),
+      )
+      .await?;
+    return Ok(());
+  }
+  let test = BasalTest {
+    profile_id: profile.id,
+    skipped_meal,
+    started_at: now,
+    ends_at: now + Duration::hours(skipped_meal.hours()),
+    next_prompt_at: now + Duration::minutes(skipped_meal.interval()),
+  };
+  tests.start(&test).await?;
+  bot
+    .edit_message_text(
+      msg.chat.id,
+      msg.id,
+      format!(
+        "🧪 Базальный тест{} начат. Измерьте сахар сейчас: \
+        /sugar_level\n\nСледующее напоминание в {}, тест закончится в \
+        {}. Не ешьте и не вводите короткий инсулин до конца теста",
+        profile.tag(),
+        local_time(test.next_prompt_at),
+        local_time(test.ends_at)
+      ),
+    )
+    .reply_markup(abort_keyboard(profile.id))
+    .await?;
+  Ok(())
+}
+
+/// Stops test of profile on hypo, it's unsafe to continue fasting
+async fn abort_on_hypo(
+  bot: Bot,
+  db: Arc<Db>,
+  event: SugarMeasurementAdded,
+) -> Result<()> {
+  let SugarMeasurementAdded {
+    profile,
+    measurement,
+  } = event;
+  if !measurement.level.is_low()
+    || !basal_tests(&db).remove(profile.id).await?
+  {
+    return Ok(());
+  }
+  bot
+    .send_message(
+      profile.user_id,
+      format!(
+        "⛔️ Базальный тест{} прерван из-за низкого сахара. Примите \
+        быстрые углеводы. Гипогликемия без еды может означать, что \
+        базального инсулина слишком много — обсудите это с врачом",
+        profile.tag()
+      ),
+    )
+    .await?;
+  Ok(())
+}
+
+/// Sends due measurement reminders and summaries of finished tests
+async fn send_due(bot: Bot, db: Arc<Db>) -> Result<()> {
+  let now = Utc::now();
+  let mut tests = basal_tests(&db);
+  for test in tests.fetch_due(now).await? {
+    let Some(profile) = profiles(&db).fetch(test.profile_id).await?
+    else {
+      continue;
+    };
+    let tag = profile.tag();
+    let finished = test.next_prompt_at > test.ends_at;
+    let text = if finished {
+      tests.remove(profile.id).await?;
+      let readings = sugar_measurements(&db, profile.id)
+        .fetch_between(test.started_at, test.next_prompt_at)
+        .await?;
+      let verdict = judge(&readings, test.started_at, test.ends_at);
+      render(test.skipped_meal, &readings, verdict, &tag, &Local)
+    } else if test.next_prompt_at < test.ends_at {
+      let next =
+        now + Duration::minutes(test.skipped_meal.interval());
+      tests.reschedule(profile.id, next.min(test.ends_at)).await?;
+      format!(
+        "🧪 Базальный тест{tag}: пора измерить сахар — /sugar_level"
+      )
+    } else {
+      let next = now + Duration::minutes(LAST_READING_MINUTES);
+      tests.reschedule(profile.id, next).await?;
+      format!(
+        "🧪 Базальный тест{tag}: последнее измерение — /sugar_level. \
+        Итоги пришлю через {LAST_READING_MINUTES} мин."
+      )
+    };
+    let mut request = bot.send_message(profile.user_id, text);
+    if !finished {
+      request = request.reply_markup(abort_keyboard(profile.id));
+    }
+    match request.await {
+      Ok(_) => {}
+      Err(RequestError::Api(ApiError::BotBlocked)) => {
+        tests.remove(profile.id).await?;
+        users(&db).disable(profile.user_id).await?;
+      }
+      Err(err) => log::error!(
+        "Basal test prompt of {} isn't sent: {err}",
+        profile.user_id
+      ),
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,179 @@
+use chrono::{DateTime, Utc};
+
+use crate::{
+  app::profile::ProfileId,
+  db::{txn::ExecutorHolder, Db},
+};
+
+use super::{BasalTest, SkippedMeal};
+
+pub fn basal_tests(db: &Db) -> Repository {
+  Repository { exec: db.exec() }
+}
+
+pub struct Repository {
+  exec: ExecutorHolder,
+}
+
+impl Repository {
+  /// Running test of profile
+  pub async fn fetch(
+    &self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<Option<BasalTest>> {
+    sqlx::query!(
+      r#"
+        SELECT
+          skipped_meal AS "skipped_meal: SkippedMeal",
+          started_at,
+          ends_at,
+          next_prompt_at
+        FROM basal_tests
+        WHERE profile_id = ?
+      "#,
+      profile_id.0
+    )
+    .map(|rec| BasalTest {
+      profile_id,
+      skipped_meal: rec.skipped_meal,
+      started_at: rec.started_at.and_utc(),
+      ends_at: rec.ends_at.and_utc(),
+      next_prompt_at: rec.next_prompt_at.and_utc(),
+    })
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Starts test replacing running one
+  pub async fn start(
+    &mut self,
+    test: &BasalTest,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      r#"
+        REPLACE INTO basal_tests (
+          profile_id, skipped_meal, started_at, ends_at, next_prompt_at
+        )
+        VALUES (?, ?, ?, ?, ?)
+      "#,
+      test.profile_id.0,
+      test.skipped_meal,
+      test.started_at,
+      test.ends_at,
+      test.next_prompt_at
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  /// Stops running test. Returns `false` if there was none.
+  pub async fn remove(
+    &mut self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<bool> {
+    let res = sqlx::query!(
+      "DELETE FROM basal_tests WHERE profile_id = ?",
+      profile_id.0
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(res.rows_affected() > 0)
+  }
+
+  /// Tests which prompt should be sent at `now`
+  pub async fn fetch_due(
+    &self,
+    now: DateTime<Utc>,
+  ) -> sqlx::Result<Vec<BasalTest>> {
+    sqlx::query!(
+      r#"
+        SELECT
+          profile_id,
+          skipped_meal AS "skipped_meal: SkippedMeal",
+          started_at,
+          ends_at,
+          next_prompt_at
+        FROM basal_tests
+        WHERE next_prompt_at <= ?
+      "#,
+      now
+    )
+    .map(|rec| BasalTest {
+      profile_id: ProfileId(rec.profile_id),
+      skipped_meal: rec.skipped_meal,
+      started_at: rec.started_at.and_utc(),
+      ends_at: rec.ends_at.and_utc(),
+      next_prompt_at: rec.next_prompt_at.and_utc(),
+    })
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  pub async fn reschedule(
+    &mut self,
+    profile_id: ProfileId,
+    next_prompt_at: DateTime<Utc>,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      "UPDATE basal_tests SET next_prompt_at = ? WHERE profile_id = ?",
+      next_prompt_at,
+      profile_id.0
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::Duration;
+  use teloxide::types::UserId;
+
+  use crate::{
+    app::{profile::repository::profiles, user::repository::users},
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn start_prompt_and_stop() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      users(&test_db).add(user).await.unwrap();
+      let profile_id =
+        profiles(&test_db).add(user, None).await.unwrap().id;
+      let now: DateTime<Utc> =
+        "2024-03-03T07:00:00Z".parse().unwrap();
+      let test = BasalTest {
+        profile_id,
+        skipped_meal: SkippedMeal::Breakfast,
+        started_at: now,
+        ends_at: now + Duration::hours(6),
+        next_prompt_at: now + Duration::hours(1),
+      };
+      let mut tests = basal_tests(&test_db);
+      assert_eq!(tests.fetch(profile_id).await.unwrap(), None);
+      tests.start(&test).await.unwrap();
+      assert_eq!(
+        tests.fetch(profile_id).await.unwrap(),
+        Some(test.clone())
+      );
+      assert!(tests.fetch_due(now).await.unwrap().is_empty());
+      let next = test.next_prompt_at;
+      assert_eq!(tests.fetch_due(next).await.unwrap(), vec![test]);
+      tests
+        .reschedule(profile_id, next + Duration::hours(1))
+        .await
+        .unwrap();
+      assert!(tests.fetch_due(next).await.unwrap().is_empty());
+      assert!(tests.remove(profile_id).await.unwrap());
+      assert!(!tests.remove(profile_id).await.unwrap());
+    })
+    .await
+    .unwrap();
+  }
+}
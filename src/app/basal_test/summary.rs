@@ -0,0 +1,186 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+
+use crate::app::sugar_measurement::SugarMeasurement;
+
+use super::SkippedMeal;
+
+/// Fewer readings can't show drift
+const MIN_READINGS: usize = 3;
+/// First and last readings are taken this close to start and end of
+/// test, minutes
+const EDGE_MINUTES: i64 = 60;
+/// Larger change of level over test means basal should be adjusted,
+/// mmol/L
+pub const MAX_DRIFT: f64 = 1.7;
+
+/// Judgement on basal dose from drift of level over test
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+  /// Level rose, change in mmol/L
+  TooLow(f64),
+  /// Level fell, change in mmol/L
+  TooHigh(f64),
+  Fine(f64),
+  NotEnoughReadings,
+}
+
+/// Judges basal dose from readings of test running from `start`
+/// until `end`
+pub fn judge(
+  readings: &[SugarMeasurement],
+  start: DateTime<Utc>,
+  end: DateTime<Utc>,
+) -> Verdict {
+  let edge = Duration::minutes(EDGE_MINUTES);
+  let (Some(first), Some(last)) = (readings.first(), readings.last())
+  else {
+    return Verdict::NotEnoughReadings;
+  };
+  if readings.len() < MIN_READINGS
+    || first.date_time > start + edge
+    || last.date_time < end - edge
+  {
+    return Verdict::NotEnoughReadings;
+  }
+  let drift = last.level.as_millimoles_per_liter()
+    - first.level.as_millimoles_per_liter();
+  if readings.iter().any(|r| r.level.is_low()) || drift < -MAX_DRIFT {
+    Verdict::TooHigh(drift)
+  } else if drift > MAX_DRIFT {
+    Verdict::TooLow(drift)
+  } else {
+    Verdict::Fine(drift)
+  }
+}
+
+/// Readings of test with verdict. Times are shown in `tz`.
+pub fn render<Tz: TimeZone>(
+  skipped_meal: SkippedMeal,
+  readings: &[SugarMeasurement],
+  verdict: Verdict,
+  tag: &str,
+  tz: &Tz,
+) -> String
+where
+  Tz::Offset: std::fmt::Display,
+{
+  let mut text = format!(
+    "📋 Итоги базального теста (пропуск {}){tag}\n\n",
+    skipped_meal.genitive()
+  );
+  for r in readings {
+    let _ = writeln!(
+      text,
+      "{} — {:.1} ммоль/л",
+      r.date_time.with_timezone(tz).format("%H:%M"),
+      r.level.as_millimoles_per_liter()
+    );
+  }
+  let verdict = match verdict {
+    Verdict::TooLow(drift) => format!(
+      "\n⬆️ Сахар вырос на {drift:.1} ммоль/л — похоже, базального \
+      инсулина недостаточно."
+    ),
+    Verdict::TooHigh(drift) => format!(
+      "\n⬇️ Сахар изменился на {drift:+.1} ммоль/л и опускался \
+      низко — похоже, базального инсулина слишком много."
+    ),
+    Verdict::Fine(drift) => format!(
+      "\n✅ Сахар держался ровно ({drift:+.1} ммоль/л, допустимо \
+      ±{MAX_DRIFT:.1}) — базальный инсулин подобран хорошо."
+    ),
+    Verdict::NotEnoughReadings => format!(
+      "\nНедостаточно измерений для вывода: нужно хотя бы \
+      {MIN_READINGS}, в том числе в начале и в конце теста."
+    ),
+  };
+  text.push_str(&verdict);
+  text.push_str(
+    "\n\nПокажите результаты врачу, прежде чем менять дозу. Для \
+    надежного вывода тест обычно повторяют несколько раз.",
+  );
+  text
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::Utc;
+
+  use crate::app::sugar_measurement::SugarLevel;
+
+  use super::*;
+
+  fn at(time: &str) -> DateTime<Utc> {
+    format!("2024-03-01T{time}:00Z").parse().unwrap()
+  }
+
+  fn readings(levels: &[(&str, f64)]) -> Vec<SugarMeasurement> {
+    levels
+      .iter()
+      .map(|&(time, level)| SugarMeasurement {
+        date_time: at(time),
+        level: SugarLevel::from_millimoles_per_liter(level),
+      })
+      .collect()
+  }
+
+  fn verdict(levels: &[(&str, f64)]) -> Verdict {
+    judge(&readings(levels), at("07:00"), at("13:00"))
+  }
+
+  #[test]
+  fn judges_by_drift() {
+    assert_eq!(
+      verdict(&[("07:05", 6.0), ("10:00", 6.5), ("13:00", 7.0)]),
+      Verdict::Fine(1.0)
+    );
+    assert_eq!(
+      verdict(&[("07:05", 6.0), ("10:00", 7.5), ("12:30", 9.0)]),
+      Verdict::TooLow(3.0)
+    );
+    assert_eq!(
+      verdict(&[("07:05", 8.0), ("10:00", 7.0), ("12:30", 6.0)]),
+      Verdict::TooHigh(-2.0)
+    );
+    assert_eq!(
+      verdict(&[("07:05", 6.0), ("10:00", 3.5), ("12:30", 5.0)]),
+      Verdict::TooHigh(-1.0)
+    );
+  }
+
+  #[test]
+  fn needs_readings_at_start_and_end() {
+    assert_eq!(
+      verdict(&[("07:05", 6.0), ("13:00", 7.0)]),
+      Verdict::NotEnoughReadings
+    );
+    assert_eq!(
+      verdict(&[("08:30", 6.0), ("10:00", 6.5), ("13:00", 7.0)]),
+      Verdict::NotEnoughReadings
+    );
+    assert_eq!(
+      verdict(&[("07:05", 6.0), ("08:00", 6.5), ("11:00", 7.0)]),
+      Verdict::NotEnoughReadings
+    );
+  }
+
+  #[test]
+  fn renders_readings_and_verdict() {
+    let readings =
+      readings(&[("07:05", 6.0), ("10:00", 6.5), ("13:00", 7.0)]);
+    let text = render(
+      SkippedMeal::Breakfast,
+      &readings,
+      Verdict::Fine(1.0),
+      "",
+      &Utc,
+    );
+    assert!(text.starts_with(
+      "📋 Итоги базального теста (пропуск завтрака)\n\n\
+      07:05 — 6.0 ммоль/л\n10:00 — 6.5 ммоль/л\n13:00 — 7.0 ммоль/л\n\
+      \n✅ Сахар держался ровно (+1.0 ммоль/л, допустимо ±1.7)"
+    ));
+  }
+}
@@ -0,0 +1,567 @@
+pub mod repository;
+
+use std::sync::Arc;
+
+use teloxide::{
+  dptree::case,
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup, Me},
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::Result,
+  db::Db,
+  event_handler::{filter_event, handler, EventHandler},
+  utils::{filter_callback_query, filter_message, full_name},
+};
+
+use self::repository::user_links;
+
+use super::{
+  hypo_recheck::HypoRecheckUnanswered,
+  insulin_injection::InsulinInjectionAdded,
+  invite::{invite_link, repository::invites, InviteKind},
+  long_insulin::BasalDoseMissed,
+  meal::MealAdded,
+  preferences::repository::preferences,
+  profile::{
+    current_profile, repository::profiles, Profile, ProfileId,
+  },
+  sugar_measurement::SugarMeasurementAdded,
+  user::UserStarted,
+  UpdateHandler,
+};
+
+const CALLBACK_PREFIX: &str = "caregiver:";
+
+/// Access level of caregiver to patient's data
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type)]
+#[sqlx(rename_all = "snake_case")]
+pub enum Permission {
+  /// Receives notifications only
+  Notify,
+  /// Receives notifications and reads logbook
+  Read,
+}
+
+/// Which patient's entries caregiver is notified about
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::Type)]
+#[sqlx(rename_all = "snake_case")]
+pub enum EntryNotifications {
+  All,
+  OutOfRange,
+  Off,
+}
+
+impl EntryNotifications {
+  fn next(self) -> Self {
+    match self {
+      Self::All => Self::OutOfRange,
+      Self::OutOfRange => Self::Off,
+      Self::Off => Self::All,
+    }
+  }
+
+  fn label(self) -> &'static str {
+    match self {
+      Self::All => "все записи",
+      Self::OutOfRange => "вне диапазона",
+      Self::Off => "выкл",
+    }
+  }
+}
+
+/// Caregiver's access to patient profile
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UserLink {
+  pub profile_id: ProfileId,
+  pub caregiver_id: UserId,
+  pub permission: Permission,
+  pub entry_notifications: EntryNotifications,
+  pub missed_reminder_notifications: bool,
+}
+
+impl UserLink {
+  pub fn new(
+    profile_id: ProfileId,
+    caregiver_id: UserId,
+    permission: Permission,
+  ) -> Self {
+    Self {
+      profile_id,
+      caregiver_id,
+      permission,
+      entry_notifications: EntryNotifications::OutOfRange,
+      missed_reminder_notifications: true,
+    }
+  }
+}
+
+/// Profiles which logbook user may read: own ones and patients'
+/// linked with [`Permission::Read`]
+pub async fn readable_profiles(
+  db: &Db,
+  user_id: UserId,
+) -> sqlx::Result<Vec<Profile>> {
+  let profiles = profiles(db);
+  let mut readable = profiles.fetch_all(user_id).await?;
+  for link in user_links(db).fetch_patients(user_id).await? {
+    if link.permission != Permission::Read {
+      continue;
+    }
+    if let Some(profile) = profiles.fetch(link.profile_id).await? {
+      readable.push(profile);
+    }
+  }
+  Ok(readable)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Callback {
+  Invite(Permission),
+  CycleEntries(ProfileId),
+  ToggleMissedReminders(ProfileId),
+}
+
+impl Callback {
+  fn parse(data: &str) -> Option<Self> {
+    let data = data.strip_prefix(CALLBACK_PREFIX)?;
+    let (action, arg) = data.split_once(':')?;
+    match action {
+      "invite" if arg == "notify" => {
+        Some(Self::Invite(Permission::Notify))
+      }
+      "invite" if arg == "read" => {
+        Some(Self::Invite(Permission::Read))
+      }
+      "entries" => {
+        Some(Self::CycleEntries(ProfileId(arg.parse().ok()?)))
+      }
+      "missed" => Some(Self::ToggleMissedReminders(ProfileId(
+        arg.parse().ok()?,
+      ))),
+      _ => None,
+    }
+  }
+
+  fn data(self) -> String {
+    let (action, arg) = match self {
+      Self::Invite(Permission::Notify) => ("invite", "notify".into()),
+      Self::Invite(Permission::Read) => ("invite", "read".into()),
+      Self::CycleEntries(ProfileId(id)) => {
+        ("entries", id.to_string())
+      }
+      Self::ToggleMissedReminders(ProfileId(id)) => {
+        ("missed", id.to_string())
+      }
+    };
+    format!("{CALLBACK_PREFIX}{action}:{arg}")
+  }
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    dptree::entry()
+      .branch(
+        filter_message()
+          .filter_command::<MenuCommand>()
+          .branch(
+            case![MenuCommand::Caregiver].endpoint(ask_permission),
+          )
+          .branch(
+            case![MenuCommand::Patients].endpoint(send_patients),
+          ),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            Callback::parse(query.data.as_deref()?)
+          })
+          .branch(
+            case![Callback::Invite(permission)].endpoint(invite),
+          )
+          .branch(
+            case![Callback::CycleEntries(profile_id)]
+              .endpoint(cycle_entry_notifications),
+          )
+          .branch(
+            case![Callback::ToggleMissedReminders(profile_id)]
+              .endpoint(toggle_missed_reminder_notifications),
+          ),
+      )
+  }
+
+  fn event_handler(&self) -> EventHandler {
+    dptree::entry()
+      .branch(filter_event::<UserStarted>().chain(handler(redeem)))
+      .branch(
+        filter_event::<SugarMeasurementAdded>()
+          .chain(handler(notify_sugar_measurement)),
+      )
+      .branch(
+        filter_event::<InsulinInjectionAdded>()
+          .chain(handler(notify_insulin_injection)),
+      )
+      .branch(filter_event::<MealAdded>().chain(handler(notify_meal)))
+      .branch(
+        filter_event::<HypoRecheckUnanswered>()
+          .chain(handler(notify_missed_hypo_recheck)),
+      )
+      .branch(
+        filter_event::<BasalDoseMissed>()
+          .chain(handler(notify_missed_basal_dose)),
+      )
+  }
+}
+
+async fn ask_permission(bot: Bot, chat_id: ChatId) -> Result<()> {
+  let keyboard = InlineKeyboardMarkup::new([
+    [InlineKeyboardButton::callback(
+      "🔔 Только уведомления",
+      Callback::Invite(Permission::Notify).data(),
+    )],
+    [InlineKeyboardButton::callback(
+      "👁 Уведомления и просмотр дневника",
+      Callback::Invite(Permission::Read).data(),
+    )],
+  ]);
+  bot
+    .send_message(chat_id, "Какой доступ дать опекуну?")
+    .reply_markup(keyboard)
+    .await?;
+  Ok(())
+}
+
+async fn invite(
+  bot: Bot,
+  me: Me,
+  db: Arc<Db>,
+  user_id: UserId,
+  permission: Permission,
+  query: CallbackQuery,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let profile = current_profile(&db, user_id).await?;
+  let code = invites(&db)
+    .create(profile.id, InviteKind::Caregiver(permission))
+    .await?;
+  let link = invite_link(&me, &code);
+  let text = format!(
+    "Отправьте эту ссылку опекуну{}. \
+    Ссылка одноразовая и действует сутки:\n\n{link}",
+    profile.tag()
+  );
+  if let Some(msg) = query.message {
+    bot.edit_message_text(msg.chat.id, msg.id, text).await?;
+  }
+  Ok(())
+}
+
+async fn redeem(
+  bot: Bot,
+  db: Arc<Db>,
+  event: UserStarted,
+) -> Result<()> {
+  let UserStarted { user_id, payload } = event;
+  if payload.is_empty() {
+    return Ok(());
+  }
+  let mut invites = invites(&db);
+  for permission in [Permission::Notify, Permission::Read] {
+    let kind = InviteKind::Caregiver(permission);
+    let Some(profile_id) = invites.redeem(&payload, kind).await?
+    else {
+      continue;
+    };
+    let Some(profile) = profiles(&db).fetch(profile_id).await? else {
+      continue;
+    };
+    if profile.user_id == user_id {
+      bot
+        .send_message(user_id, "Нельзя стать опекуном своего профиля")
+        .await?;
+      return Ok(());
+    }
+    let link = UserLink::new(profile.id, user_id, permission);
+    user_links(&db).add(link).await?;
+    let patient_name = profile.display_name(&bot).await?;
+    let caregiver_name = full_name(&bot, user_id).await?;
+    bot
+      .send_message(
+        user_id,
+        format!(
+          "Вы стали опекуном {patient_name}. \
+          Настроить уведомления: /patients"
+        ),
+      )
+      .await?;
+    bot
+      .send_message(
+        profile.user_id,
+        format!("{caregiver_name} теперь опекун{}", profile.tag()),
+      )
+      .await?;
+  }
+  Ok(())
+}
+
+async fn send_patients(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+) -> Result<()> {
+  let links = user_links(&db).fetch_patients(user_id).await?;
+  if links.is_empty() {
+    bot
+      .send_message(
+        chat_id,
+        "У вас нет подопечных. Пациент может пригласить вас \
+        командой /caregiver",
+      )
+      .await?;
+    return Ok(());
+  }
+  let keyboard = patients_keyboard(&bot, &db, &links).await?;
+  bot
+    .send_message(chat_id, "Уведомления о подопечных:")
+    .reply_markup(keyboard)
+    .await?;
+  Ok(())
+}
+
+async fn patients_keyboard(
+  bot: &Bot,
+  db: &Db,
+  links: &[UserLink],
+) -> Result<InlineKeyboardMarkup> {
+  let profiles = profiles(db);
+  let mut rows = Vec::new();
+  for link in links {
+    let Some(profile) = profiles.fetch(link.profile_id).await? else {
+      continue;
+    };
+    let name = profile.display_name(bot).await?;
+    let missed = if link.missed_reminder_notifications {
+      "вкл"
+    } else {
+      "выкл"
+    };
+    rows.push(vec![InlineKeyboardButton::callback(
+      format!("{name}: {}", link.entry_notifications.label()),
+      Callback::CycleEntries(link.profile_id).data(),
+    )]);
+    rows.push(vec![InlineKeyboardButton::callback(
+      format!("{name}: пропуски — {missed}"),
+      Callback::ToggleMissedReminders(link.profile_id).data(),
+    )]);
+  }
+  Ok(InlineKeyboardMarkup::new(rows))
+}
+
+async fn cycle_entry_notifications(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  profile_id: ProfileId,
+  query: CallbackQuery,
+) -> Result<()> {
+  update_notifications(bot, db, user_id, profile_id, query, |link| {
+    link.entry_notifications = link.entry_notifications.next();
+  })
+  .await
+}
+
+async fn toggle_missed_reminder_notifications(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  profile_id: ProfileId,
+  query: CallbackQuery,
+) -> Result<()> {
+  update_notifications(bot, db, user_id, profile_id, query, |link| {
+    link.missed_reminder_notifications =
+      !link.missed_reminder_notifications;
+  })
+  .await
+}
+
+async fn update_notifications(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  profile_id: ProfileId,
+  query: CallbackQuery,
+  update: impl FnOnce(&mut UserLink),
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let mut repository = user_links(&db);
+  let mut links = repository.fetch_patients(user_id).await?;
+  let Some(link) =
+    links.iter_mut().find(|link| link.profile_id == profile_id)
+  else {
+    return Ok(());
+  };
+  update(link);
+  repository.update_notifications(*link).await?;
+  if let Some(msg) = query.message {
+    let keyboard = patients_keyboard(&bot, &db, &links).await?;
+    bot
+      .edit_message_reply_markup(msg.chat.id, msg.id)
+      .reply_markup(keyboard)
+      .await?;
+  }
+  Ok(())
+}
+
+async fn notify_sugar_measurement(
+  bot: Bot,
+  db: Arc<Db>,
+  event: SugarMeasurementAdded,
+) -> Result<()> {
+  let level = event.measurement.level;
+  let links =
+    user_links(&db).fetch_caregivers(event.profile.id).await?;
+  let caregivers: Vec<_> = links
+    .into_iter()
+    .filter(|link| match link.entry_notifications {
+      EntryNotifications::All => true,
+      EntryNotifications::OutOfRange => !level.is_in_range(),
+      EntryNotifications::Off => false,
+    })
+    .collect();
+  let sign = if level.is_low() {
+    "⬇️ "
+  } else if level.is_high() {
+    "⬆️ "
+  } else {
+    ""
+  };
+  notify(&bot, &event.profile, &caregivers, |name| {
+    format!(
+      "🩸 {name}: {sign}сахар {:.1} ммоль/л",
+      level.as_millimoles_per_liter()
+    )
+  })
+  .await
+}
+
+async fn notify_insulin_injection(
+  bot: Bot,
+  db: Arc<Db>,
+  event: InsulinInjectionAdded,
+) -> Result<()> {
+  let links =
+    user_links(&db).fetch_caregivers(event.profile.id).await?;
+  let caregivers: Vec<_> = links
+    .into_iter()
+    .filter(|link| {
+      link.entry_notifications == EntryNotifications::All
+    })
+    .collect();
+  notify(&bot, &event.profile, &caregivers, |name| {
+    format!(
+      "💉 {name}: инсулин {} ЕД",
+      event.injection.volume.as_cubic_centimeters()
+    )
+  })
+  .await
+}
+
+async fn notify_meal(
+  bot: Bot,
+  db: Arc<Db>,
+  event: MealAdded,
+) -> Result<()> {
+  let links =
+    user_links(&db).fetch_caregivers(event.profile.id).await?;
+  let caregivers: Vec<_> = links
+    .into_iter()
+    .filter(|link| {
+      link.entry_notifications == EntryNotifications::All
+    })
+    .collect();
+  notify(&bot, &event.profile, &caregivers, |name| {
+    let mut text =
+      format!("🍽 {name}: углеводы {} г", event.meal.carbs_grams);
+    if let Some(note) = &event.meal.note {
+      text.push_str(&format!(" — {note}"));
+    }
+    text
+  })
+  .await
+}
+
+async fn notify_missed_hypo_recheck(
+  bot: Bot,
+  db: Arc<Db>,
+  event: HypoRecheckUnanswered,
+) -> Result<()> {
+  let links =
+    user_links(&db).fetch_caregivers(event.profile.id).await?;
+  let caregivers: Vec<_> = links
+    .into_iter()
+    .filter(|link| link.missed_reminder_notifications)
+    .collect();
+  notify(&bot, &event.profile, &caregivers, |name| {
+    format!(
+      "⏰ {name} не ответил(а) на напоминание \
+      перепроверить сахар после гипогликемии"
+    )
+  })
+  .await
+}
+
+async fn notify_missed_basal_dose(
+  bot: Bot,
+  db: Arc<Db>,
+  event: BasalDoseMissed,
+) -> Result<()> {
+  let links =
+    user_links(&db).fetch_caregivers(event.profile.id).await?;
+  let caregivers: Vec<_> = links
+    .into_iter()
+    .filter(|link| link.missed_reminder_notifications)
+    .collect();
+  let tz = preferences(&db)
+    .fetch(event.profile.id)
+    .await?
+    .unwrap_or_default()
+    .timezone;
+  let reminded_at = event.reminded_at.with_timezone(&tz);
+  notify(&bot, &event.profile, &caregivers, |name| {
+    format!(
+      "⏰ {name} не записал(а) длинный инсулин после напоминания \
+      в {}",
+      reminded_at.format("%H:%M")
+    )
+  })
+  .await
+}
+
+async fn notify(
+  bot: &Bot,
+  profile: &Profile,
+  caregivers: &[UserLink],
+  text: impl FnOnce(&str) -> String,
+) -> Result<()> {
+  if caregivers.is_empty() {
+    return Ok(());
+  }
+  let text = text(&profile.display_name(bot).await?);
+  // Every caregiver is tried even if some are unreachable
+  for link in caregivers {
+    let caregiver_id = link.caregiver_id;
+    if let Err(err) = bot.send_message(caregiver_id, &text).await {
+      log::error!(
+        "Notification of {:?} isn't sent to {caregiver_id}: {err}",
+        profile.id
+      );
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,170 @@
+use teloxide::types::UserId;
+
+use crate::{
+  app::profile::ProfileId,
+  db::{txn::ExecutorHolder, Db},
+};
+
+use super::{EntryNotifications, Permission, UserLink};
+
+pub fn user_links(db: &Db) -> Repository {
+  Repository { exec: db.exec() }
+}
+
+pub struct Repository {
+  exec: ExecutorHolder,
+}
+
+impl Repository {
+  /// Links caregiver to patient. Relinking resets notifications.
+  #[allow(clippy::cast_possible_wrap)]
+  pub async fn add(&mut self, link: UserLink) -> sqlx::Result<()> {
+    let caregiver_id = link.caregiver_id.0 as i64;
+    sqlx::query!(
+      r#"
+        REPLACE INTO user_links (
+          profile_id,
+          caregiver_id,
+          permission,
+          entry_notifications,
+          missed_reminder_notifications
+        )
+        VALUES (?, ?, ?, ?, ?)
+      "#,
+      link.profile_id.0,
+      caregiver_id,
+      link.permission as _,
+      link.entry_notifications as _,
+      link.missed_reminder_notifications
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  #[allow(clippy::cast_sign_loss)]
+  pub async fn fetch_caregivers(
+    &self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<Vec<UserLink>> {
+    sqlx::query!(
+      r#"
+        SELECT
+          profile_id,
+          caregiver_id,
+          permission AS "permission: Permission",
+          entry_notifications AS "entry_notifications: EntryNotifications",
+          missed_reminder_notifications
+        FROM user_links
+        WHERE profile_id = ?
+      "#,
+      profile_id.0
+    )
+    .map(|rec| UserLink {
+      profile_id: ProfileId(rec.profile_id),
+      caregiver_id: UserId(rec.caregiver_id as _),
+      permission: rec.permission,
+      entry_notifications: rec.entry_notifications,
+      missed_reminder_notifications: rec.missed_reminder_notifications,
+    })
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+  pub async fn fetch_patients(
+    &self,
+    caregiver_id: UserId,
+  ) -> sqlx::Result<Vec<UserLink>> {
+    let caregiver_id = caregiver_id.0 as i64;
+    sqlx::query!(
+      r#"
+        SELECT
+          profile_id,
+          caregiver_id,
+          permission AS "permission: Permission",
+          entry_notifications AS "entry_notifications: EntryNotifications",
+          missed_reminder_notifications
+        FROM user_links
+        WHERE caregiver_id = ?
+      "#,
+      caregiver_id
+    )
+    .map(|rec| UserLink {
+      profile_id: ProfileId(rec.profile_id),
+      caregiver_id: UserId(rec.caregiver_id as _),
+      permission: rec.permission,
+      entry_notifications: rec.entry_notifications,
+      missed_reminder_notifications: rec.missed_reminder_notifications,
+    })
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  #[allow(clippy::cast_possible_wrap)]
+  pub async fn update_notifications(
+    &mut self,
+    link: UserLink,
+  ) -> sqlx::Result<()> {
+    let caregiver_id = link.caregiver_id.0 as i64;
+    sqlx::query!(
+      r#"
+        UPDATE user_links
+        SET entry_notifications = ?, missed_reminder_notifications = ?
+        WHERE profile_id = ? AND caregiver_id = ?
+      "#,
+      link.entry_notifications as _,
+      link.missed_reminder_notifications,
+      link.profile_id.0,
+      caregiver_id
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    app::{profile::repository::profiles, user::repository::users},
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn add_update_and_fetch() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let patient = UserId(1);
+      let caregiver = UserId(2);
+      users(&test_db).add(patient).await.unwrap();
+      users(&test_db).add(caregiver).await.unwrap();
+      let profile =
+        profiles(&test_db).add(patient, None).await.unwrap();
+      let mut links = user_links(&test_db);
+      let mut link =
+        UserLink::new(profile.id, caregiver, Permission::Read);
+      links.add(link).await.unwrap();
+      link.entry_notifications = EntryNotifications::All;
+      link.missed_reminder_notifications = false;
+      links.update_notifications(link).await.unwrap();
+      assert_eq!(
+        links.fetch_caregivers(profile.id).await.unwrap(),
+        [link]
+      );
+      assert_eq!(
+        links.fetch_patients(caregiver).await.unwrap(),
+        [link]
+      );
+      assert!(links
+        .fetch_patients(patient)
+        .await
+        .unwrap()
+        .is_empty());
+    })
+    .await
+    .unwrap();
+  }
+}
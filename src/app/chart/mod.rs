@@ -0,0 +1,128 @@
+pub mod render;
+
+use std::sync::Arc;
+
+use chrono::Local;
+use teloxide::{
+  dptree::case,
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile},
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::Db,
+  utils::{filter_callback_query, filter_message},
+};
+
+use self::render::Chart;
+
+use super::{
+  insulin_injection::repository::insulin_injections,
+  meal::repository::meals, profile::current_profile, stats::Period,
+  sugar_measurement::repository::sugar_measurements, UpdateHandler,
+};
+
+const CALLBACK_PREFIX: &str = "chart:";
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    dptree::entry()
+      .branch(
+        filter_message()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::Chart].endpoint(ask_period)),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            let data = query.data.as_deref()?;
+            Period::parse(data.strip_prefix(CALLBACK_PREFIX)?)
+          })
+          .endpoint(send_chart),
+      )
+  }
+}
+
+async fn ask_period(bot: Bot, chat_id: ChatId) -> Result<()> {
+  let periods = Period::ALL
+    .into_iter()
+    .map(|period| {
+      InlineKeyboardButton::callback(
+        period.label(),
+        format!("{CALLBACK_PREFIX}{}", period.data()),
+      )
+    })
+    .collect::<Vec<_>>();
+  bot
+    .send_message(chat_id, "Выберите период графика:")
+    .reply_markup(InlineKeyboardMarkup::new([periods]))
+    .await?;
+  Ok(())
+}
+
+async fn send_chart(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  period: Period,
+  query: CallbackQuery,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  let profile = current_profile(&db, user_id).await?;
+  let now = Local::now();
+  let start = period.start(now);
+  let end = now.to_utc();
+  let local = |dt: chrono::DateTime<chrono::Utc>| {
+    dt.with_timezone(&Local).naive_local()
+  };
+  // CGM readings of a long period go straight into points
+  let mut levels = Vec::new();
+  sugar_measurements(&db, profile.id)
+    .for_each_between(start, end, |m| {
+      levels.push((
+        local(m.date_time),
+        m.level.as_millimoles_per_liter(),
+      ));
+    })
+    .await?;
+  let injections = insulin_injections(&db, profile.id)
+    .fetch_between(start, end)
+    .await?
+    .into_iter()
+    .map(|i| {
+      (local(i.date_time), i.volume.as_cubic_centimeters(), i.kind)
+    })
+    .collect();
+  let meals = meals(&db, profile.id)
+    .fetch_between(start, end)
+    .await?
+    .into_iter()
+    .map(|m| (local(m.date_time), m.carbs_grams))
+    .collect();
+  let chart = Chart {
+    title: format!("Сахар за {}{}", period.title(), profile.tag()),
+    start: local(start),
+    end: now.naive_local(),
+    levels,
+    injections,
+    meals,
+  };
+  let png = tokio::task::spawn_blocking(move || chart.render())
+    .await
+    .map_err(any)??;
+  bot
+    .send_photo(
+      msg.chat.id,
+      InputFile::memory(png).file_name("chart.png"),
+    )
+    .await?;
+  Ok(())
+}
@@ -0,0 +1,286 @@
+use std::sync::Once;
+
+use chrono::NaiveDateTime;
+use plotters::{
+  coord::types::RangedDateTime,
+  prelude::*,
+  style::{register_font, FontStyle},
+};
+
+use crate::{
+  app::{
+    insulin_injection::InsulinKind,
+    sugar_measurement::{HIGH_LEVEL, LOW_LEVEL},
+  },
+  common::Result,
+};
+
+pub const WIDTH: u32 = 1000;
+pub const HEIGHT: u32 = 600;
+/// Family of bundled font
+pub const FONT: &str = "sans-serif";
+const FONT_BYTES: &[u8] =
+  include_bytes!("../../../assets/fonts/DejaVuSans.ttf");
+/// Minimal upper bound of level axis, mmol/L
+const MIN_MAX_LEVEL: f64 = 15.0;
+
+const RANGE_COLOR: RGBColor = RGBColor(220, 245, 220);
+const LEVEL_COLOR: RGBColor = RGBColor(30, 90, 200);
+const BOLUS_COLOR: RGBColor = RGBColor(230, 120, 20);
+const BASAL_COLOR: RGBColor = RGBColor(140, 60, 170);
+const UNSPECIFIED_COLOR: RGBColor = RGBColor(120, 120, 120);
+const MEAL_COLOR: RGBColor = RGBColor(40, 150, 60);
+
+static REGISTER_FONT: Once = Once::new();
+
+/// Makes bundled font available to plotters as [`FONT`]
+pub fn register_bundled_font() {
+  REGISTER_FONT.call_once(|| {
+    register_font(FONT, FontStyle::Normal, FONT_BYTES)
+      .unwrap_or_else(|_| panic!("bundled font is invalid"));
+  });
+}
+
+/// Data of chart in local time of the patient
+pub struct Chart {
+  pub title: String,
+  pub start: NaiveDateTime,
+  pub end: NaiveDateTime,
+  /// Time and level in mmol/L
+  pub levels: Vec<(NaiveDateTime, f64)>,
+  /// Time, volume and kind of injection
+  pub injections: Vec<(NaiveDateTime, f64, Option<InsulinKind>)>,
+  /// Time and carbs of meal, g
+  pub meals: Vec<(NaiveDateTime, f64)>,
+}
+
+impl Chart {
+  /// Renders chart to PNG
+  pub fn render(&self) -> Result<Vec<u8>> {
+    encode_png(&self.render_pixels()?, WIDTH, HEIGHT)
+  }
+
+  /// Renders chart to RGB pixels of [`WIDTH`] x [`HEIGHT`] image
+  pub fn render_pixels(&self) -> Result<Vec<u8>> {
+    register_bundled_font();
+    let mut pixels = vec![0; (WIDTH * HEIGHT * 3) as usize];
+    self.draw(&mut pixels).map_err(|err| err.to_string())?;
+    Ok(pixels)
+  }
+
+  fn draw(
+    &self,
+    pixels: &mut [u8],
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let root = BitMapBackend::with_buffer(pixels, (WIDTH, HEIGHT))
+      .into_drawing_area();
+    root.fill(&WHITE)?;
+    let max_level = self
+      .levels
+      .iter()
+      .map(|&(_, level)| level + 1.0)
+      .fold(MIN_MAX_LEVEL, f64::max);
+    let mut chart = ChartBuilder::on(&root)
+      .caption(&self.title, (FONT, 24))
+      .margin(16)
+      .x_label_area_size(40)
+      .y_label_area_size(50)
+      .build_cartesian_2d(
+        RangedDateTime::from(self.start..self.end),
+        0.0..max_level,
+      )?;
+    chart
+      .configure_mesh()
+      .x_labels(8)
+      .x_label_formatter(&|dt| dt.format("%d.%m %H:%M").to_string())
+      .y_desc("ммоль/л")
+      .label_style((FONT, 14))
+      .axis_desc_style((FONT, 16))
+      .light_line_style(WHITE.mix(0.0))
+      .draw()?;
+
+    chart
+      .draw_series([Rectangle::new(
+        [(self.start, LOW_LEVEL), (self.end, HIGH_LEVEL)],
+        RANGE_COLOR.filled(),
+      )])?
+      .label(format!("Целевой диапазон {LOW_LEVEL}–{HIGH_LEVEL:.1}"))
+      .legend(|(x, y)| {
+        Rectangle::new(
+          [(x, y - 5), (x + 16, y + 5)],
+          RANGE_COLOR.filled(),
+        )
+      });
+
+    chart
+      .draw_series(LineSeries::new(
+        self.levels.iter().copied(),
+        LEVEL_COLOR.stroke_width(2),
+      ))?
+      .label("Сахар")
+      .legend(|(x, y)| {
+        PathElement::new(
+          [(x, y), (x + 16, y)],
+          LEVEL_COLOR.stroke_width(2),
+        )
+      });
+    chart.draw_series(
+      self
+        .levels
+        .iter()
+        .map(|&point| Circle::new(point, 4, LEVEL_COLOR.filled())),
+    )?;
+
+    for (kind, color, label) in [
+      (Some(InsulinKind::Bolus), BOLUS_COLOR, "Болюсный инсулин"),
+      (Some(InsulinKind::Basal), BASAL_COLOR, "Базальный инсулин"),
+      (None, UNSPECIFIED_COLOR, "Инсулин"),
+    ] {
+      let injections: Vec<_> = self
+        .injections
+        .iter()
+        .filter(|&&(_, _, k)| k == kind)
+        .collect();
+      if injections.is_empty() {
+        continue;
+      }
+      chart
+        .draw_series(injections.into_iter().map(
+          |&(dt, volume, _)| {
+            EmptyElement::at((dt, 0.0))
+              + TriangleMarker::new((0, -10), 7, color.filled())
+              + Text::new(
+                format!("{volume}"),
+                (-6, -34),
+                (FONT, 13).into_font().color(&color),
+              )
+          },
+        ))?
+        .label(label)
+        .legend(move |(x, y)| {
+          TriangleMarker::new((x + 8, y), 6, color.filled())
+        });
+    }
+
+    if !self.meals.is_empty() {
+      chart
+        .draw_series(self.meals.iter().map(|&(dt, carbs)| {
+          EmptyElement::at((dt, 0.0))
+            + Circle::new((0, -58), 6, MEAL_COLOR.filled())
+            + Text::new(
+              format!("{carbs}"),
+              (-8, -82),
+              (FONT, 13).into_font().color(&MEAL_COLOR),
+            )
+        }))?
+        .label("Еда, г углеводов")
+        .legend(|(x, y)| {
+          Circle::new((x + 8, y), 5, MEAL_COLOR.filled())
+        });
+    }
+
+    chart
+      .configure_series_labels()
+      .label_font((FONT, 14))
+      .background_style(WHITE.mix(0.8))
+      .border_style(BLACK)
+      .position(SeriesLabelPosition::UpperLeft)
+      .draw()?;
+    root.present()?;
+    Ok(())
+  }
+}
+
+/// Encodes RGB `pixels` of image `width` x `height` to PNG
+pub fn encode_png(
+  pixels: &[u8],
+  width: u32,
+  height: u32,
+) -> Result<Vec<u8>> {
+  let mut png = Vec::new();
+  let mut encoder = png::Encoder::new(&mut png, width, height);
+  encoder.set_color(png::ColorType::Rgb);
+  encoder.set_depth(png::BitDepth::Eight);
+  let mut writer =
+    encoder.write_header().map_err(|err| err.to_string())?;
+  writer
+    .write_image_data(pixels)
+    .map_err(|err| err.to_string())?;
+  writer.finish().map_err(|err| err.to_string())?;
+  Ok(png)
+}
+
+/// Compares `png` with golden image at `path` relative to crate
+/// root. Set `UPDATE_GOLDEN=1` to overwrite golden images after
+/// intentional changes of look.
+#[cfg(test)]
+pub fn assert_matches_golden(png: &[u8], path: &str) {
+  use std::{env, fs, path::Path};
+
+  fn decode(png: &[u8]) -> Vec<u8> {
+    let decoder = png::Decoder::new(png);
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+    pixels
+  }
+
+  let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+  if env::var_os("UPDATE_GOLDEN").is_some() {
+    fs::write(&golden, png).unwrap();
+  }
+  let expected = fs::read(&golden).unwrap();
+  assert!(
+    decode(png) == decode(&expected),
+    "image differs from {path}"
+  );
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const GOLDEN: &str = "src/app/chart/testdata/chart.png";
+
+  fn at(s: &str) -> NaiveDateTime {
+    s.parse().unwrap()
+  }
+
+  fn chart() -> Chart {
+    Chart {
+      title: "Сахар за 7 дней".into(),
+      start: at("2024-03-01T00:00:00"),
+      end: at("2024-03-08T00:00:00"),
+      levels: [
+        ("2024-03-01T08:00:00", 6.2),
+        ("2024-03-01T13:00:00", 11.4),
+        ("2024-03-02T08:00:00", 3.5),
+        ("2024-03-03T20:00:00", 8.1),
+        ("2024-03-05T08:00:00", 16.3),
+        ("2024-03-07T22:00:00", 5.4),
+      ]
+      .map(|(dt, level)| (at(dt), level))
+      .into(),
+      injections: vec![
+        (at("2024-03-01T08:00:00"), 12.0, Some(InsulinKind::Basal)),
+        (at("2024-03-01T13:00:00"), 6.0, Some(InsulinKind::Bolus)),
+        (at("2024-03-04T13:00:00"), 4.0, None),
+      ],
+      meals: vec![
+        (at("2024-03-01T13:00:00"), 60.0),
+        (at("2024-03-06T09:00:00"), 35.0),
+      ],
+    }
+  }
+
+  #[test]
+  fn renders_deterministically() {
+    let chart = chart();
+    assert_eq!(chart.render().unwrap(), chart.render().unwrap());
+  }
+
+  #[test]
+  fn matches_golden_image() {
+    assert_matches_golden(&chart().render().unwrap(), GOLDEN);
+  }
+}
@@ -0,0 +1,262 @@
+pub mod repository;
+pub mod summary;
+
+use std::sync::Arc;
+
+use chrono::{
+  DateTime, Days, Duration, NaiveDate, NaiveTime, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use clokwerk::{AsyncScheduler, TimeUnits};
+use teloxide::{
+  dispatching::dialogue::InMemStorage, dptree::case, prelude::*,
+  ApiError, RequestError,
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::Db,
+  event_handler::{filter_event, handler, EventHandler},
+  utils::{event_publisher::EventPublisher, filter_message},
+};
+
+use self::{
+  repository::{daily_summaries, DueSummary},
+  summary::DailySummary,
+};
+
+use super::{
+  insulin_injection::{repository::insulin_injections, InsulinKind},
+  long_insulin::repository::basal_reminders,
+  preferences::repository::preferences,
+  profile::{current_profile, Profile, ProfileId},
+  quiet_hours::repository::quiet_hours,
+  stats::{
+    midnight_in,
+    statistics::{hypo_episodes, GlucoseStats, InsulinStats},
+  },
+  sugar_measurement::repository::sugar_measurements,
+  user::repository::users,
+  UpdateHandler,
+};
+
+/// Summaries delayed longer, e.g. while user disabled bot, are
+/// skipped
+const MAX_DELAY_HOURS: i64 = 12;
+
+type Dialog = Dialogue<State, InMemStorage<State>>;
+
+#[derive(Debug, Clone)]
+struct DailySummaryTick;
+
+#[derive(Default, Clone)]
+enum State {
+  #[default]
+  Ignoring,
+  Accepting(ProfileId),
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn prepare(&self, di: &mut DependencyMap) {
+    di.insert(InMemStorage::<State>::new());
+  }
+
+  fn update_handler(&self) -> UpdateHandler {
+    filter_message()
+      .enter_dialogue::<Message, InMemStorage<State>, State>()
+      .branch(
+        dptree::entry()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::DailySummary].endpoint(ask)),
+      )
+      .branch(case![State::Accepting(profile_id)].endpoint(accept))
+  }
+
+  fn event_handler(&self) -> EventHandler {
+    filter_event::<DailySummaryTick>().chain(handler(send_due))
+  }
+
+  fn schedule(
+    &self,
+    scheduler: &mut AsyncScheduler,
+    event_publisher: Arc<EventPublisher>,
+  ) {
+    scheduler.every(1.minute()).run(move || {
+      event_publisher.send(DailySummaryTick);
+      async {}
+    });
+  }
+}
+
+async fn ask(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+  dialogue: Dialog,
+) -> Result<()> {
+  let profile = current_profile(&db, user_id).await?;
+  let current = match daily_summaries(&db).fetch(profile.id).await? {
+    Some(time) => format!("приходит в {}", time.format("%H:%M")),
+    None => "выключена".into(),
+  };
+  bot
+    .send_message(
+      chat_id,
+      format!(
+        "Ежедневная сводка{} {current}. В ней сахар за день, время \
+        в диапазоне, гипогликемии и инсулин.\n\n\
+        Отправьте время, например «21:30», или «выкл», чтобы \
+        отключить",
+        profile.tag()
+      ),
+    )
+    .await?;
+  dialogue
+    .update(State::Accepting(profile.id))
+    .await
+    .map_err(any)?;
+  Ok(())
+}
+
+async fn accept(
+  bot: Bot,
+  msg: Message,
+  db: Arc<Db>,
+  dialogue: Dialog,
+  profile_id: ProfileId,
+) -> Result<()> {
+  let text = msg.text().unwrap_or_default().trim();
+  let mut summaries = daily_summaries(&db);
+  let reply = if text.to_lowercase() == "выкл" {
+    summaries.remove(profile_id).await?;
+    "Ежедневная сводка отключена".into()
+  } else if let Ok(time) = NaiveTime::parse_from_str(text, "%H:%M") {
+    let tz = preferences(&db)
+      .fetch(profile_id)
+      .await?
+      .unwrap_or_default()
+      .timezone;
+    let next_at =
+      next_occurrence(time, Utc::now().with_timezone(&tz));
+    summaries.set(profile_id, time, next_at).await?;
+    format!(
+      "✅ Сводка будет приходить каждый день в {}. Тихие часы: \
+      /quiet_hours",
+      time.format("%H:%M")
+    )
+  } else {
+    "Неправильный формат. Пример: «21:30»".into()
+  };
+  bot.send_message(msg.chat.id, reply).await?;
+  dialogue.reset().await.map_err(any)?;
+  Ok(())
+}
+
+async fn send_due(bot: Bot, db: Arc<Db>) -> Result<()> {
+  let now = Utc::now();
+  let mut summaries = daily_summaries(&db);
+  for due in summaries.fetch_due(now).await? {
+    let DueSummary {
+      profile,
+      time,
+      due_at,
+    } = due;
+    let tz = preferences(&db)
+      .fetch(profile.id)
+      .await?
+      .unwrap_or_default()
+      .timezone;
+    let local_now = now.with_timezone(&tz);
+    let is_stale = now - due_at > Duration::hours(MAX_DELAY_HOURS);
+    if !is_stale {
+      let quiet = quiet_hours(&db).fetch(profile.user_id).await?;
+      if quiet.is_some_and(|quiet| quiet.contains(local_now.time())) {
+        // Sent once quiet hours are over
+        continue;
+      }
+      let date = due_at.with_timezone(&tz).date_naive();
+      let summary = daily_summary(&db, &profile, date, tz).await?;
+      let text = summary.render(&profile.tag(), &tz);
+      match bot.send_message(profile.user_id, text).await {
+        Ok(_) => {}
+        Err(RequestError::Api(ApiError::BotBlocked)) => {
+          users(&db).disable(profile.user_id).await?;
+        }
+        // Rescheduled anyway so summary isn't resent every tick
+        Err(err) => log::error!(
+          "Daily summary of {} isn't sent: {err}",
+          profile.user_id
+        ),
+      }
+    }
+    summaries
+      .reschedule(profile.id, next_occurrence(time, local_now))
+      .await?;
+  }
+  Ok(())
+}
+
+async fn daily_summary(
+  db: &Db,
+  profile: &Profile,
+  date: NaiveDate,
+  tz: Tz,
+) -> Result<DailySummary> {
+  let start = midnight_in(date, &tz);
+  let end = midnight_in(date + Days::new(1), &tz);
+  let measurements = sugar_measurements(db, profile.id);
+  let sugar = measurements.summary_between(start, end).await?;
+  let readings = measurements.fetch_between(start, end).await?;
+  let mut insulin = InsulinStats {
+    basal: 0.0,
+    bolus: 0.0,
+    unspecified: 0.0,
+  };
+  let mut has_injections = false;
+  insulin_injections(db, profile.id)
+    .for_each_between(start, end, |injection| {
+      has_injections = true;
+      let volume = injection.volume.as_cubic_centimeters();
+      match injection.kind {
+        Some(InsulinKind::Basal) => insulin.basal += volume,
+        Some(InsulinKind::Bolus) => insulin.bolus += volume,
+        None => insulin.unspecified += volume,
+      }
+    })
+    .await?;
+  let yesterday = midnight_in(date - Days::new(1), &tz);
+  let missed_basal = basal_reminders(db)
+    .fetch_missed_between(profile.id, yesterday, start)
+    .await?;
+  Ok(DailySummary {
+    date,
+    sugar,
+    glucose: GlucoseStats::calculate(&readings),
+    hypos: hypo_episodes(&readings),
+    insulin: has_injections.then_some(insulin),
+    missed_basal,
+  })
+}
+
+/// Next moment `time` in timezone of `now` comes after `now`
+pub fn next_occurrence<Tz: TimeZone>(
+  time: NaiveTime,
+  now: DateTime<Tz>,
+) -> DateTime<Utc> {
+  let today = now.date_naive().and_time(time);
+  let date = if today > now.naive_local() {
+    now.date_naive()
+  } else {
+    now.date_naive() + Days::new(1)
+  };
+  let next = date.and_time(time);
+  next
+    .and_local_timezone(now.timezone())
+    .earliest()
+    .map_or_else(|| next.and_utc(), |next| next.to_utc())
+}
@@ -0,0 +1,199 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use teloxide::types::UserId;
+
+use crate::{
+  app::profile::{Profile, ProfileId},
+  db::{txn::ExecutorHolder, Db},
+};
+
+pub fn daily_summaries(db: &Db) -> Repository {
+  Repository { exec: db.exec() }
+}
+
+pub struct Repository {
+  exec: ExecutorHolder,
+}
+
+/// Summary which should be sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct DueSummary {
+  pub profile: Profile,
+  /// Local time summary is sent at
+  pub time: NaiveTime,
+  pub due_at: DateTime<Utc>,
+}
+
+impl Repository {
+  /// Local time summary of profile is sent at, `None` if disabled
+  pub async fn fetch(
+    &self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<Option<NaiveTime>> {
+    sqlx::query!(
+      r#"SELECT time AS "time: NaiveTime" FROM daily_summaries WHERE profile_id = ?"#,
+      profile_id.0
+    )
+    .map(|rec| rec.time)
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+
+  /// When summary of profile is sent next, `None` if disabled
+  pub async fn fetch_next_at(
+    &self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<Option<DateTime<Utc>>> {
+    sqlx::query!(
+      "SELECT next_at FROM daily_summaries WHERE profile_id = ?",
+      profile_id.0
+    )
+    .map(|rec| rec.next_at.and_utc())
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Enables summary at local `time` replacing previous schedule
+  pub async fn set(
+    &mut self,
+    profile_id: ProfileId,
+    time: NaiveTime,
+    next_at: DateTime<Utc>,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      r#"
+        REPLACE INTO daily_summaries (profile_id, time, next_at)
+        VALUES (?, ?, ?)
+      "#,
+      profile_id.0,
+      time,
+      next_at
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  pub async fn remove(
+    &mut self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      "DELETE FROM daily_summaries WHERE profile_id = ?",
+      profile_id.0
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  /// Summaries due at `now` of users who haven't disabled the bot
+  #[allow(clippy::cast_sign_loss)]
+  pub async fn fetch_due(
+    &self,
+    now: DateTime<Utc>,
+  ) -> sqlx::Result<Vec<DueSummary>> {
+    sqlx::query!(
+      r#"
+        SELECT
+          profiles.id AS profile_id,
+          profiles.user_id,
+          profiles.name,
+          daily_summaries.time AS "time: NaiveTime",
+          daily_summaries.next_at
+        FROM daily_summaries
+        JOIN profiles ON profiles.id = daily_summaries.profile_id
+        JOIN users ON users.id = profiles.user_id
+        WHERE users.disabled = FALSE AND daily_summaries.next_at <= ?
+      "#,
+      now
+    )
+    .map(|rec| DueSummary {
+      profile: Profile {
+        id: ProfileId(rec.profile_id),
+        user_id: UserId(rec.user_id as _),
+        name: rec.name,
+      },
+      time: rec.time,
+      due_at: rec.next_at.and_utc(),
+    })
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  pub async fn reschedule(
+    &mut self,
+    profile_id: ProfileId,
+    next_at: DateTime<Utc>,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      "UPDATE daily_summaries SET next_at = ? WHERE profile_id = ?",
+      next_at,
+      profile_id.0
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::Duration;
+
+  use crate::{
+    app::{profile::repository::profiles, user::repository::users},
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn fetch_due_skips_future_and_disabled() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      let disabled = UserId(2);
+      users(&test_db).add(user).await.unwrap();
+      users(&test_db).add(disabled).await.unwrap();
+      users(&test_db).disable(disabled).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let child = profiles(&test_db)
+        .add(user, Some("Маша".into()))
+        .await
+        .unwrap();
+      let other =
+        profiles(&test_db).add(disabled, None).await.unwrap();
+      let now: DateTime<Utc> =
+        "2024-03-01T18:00:00Z".parse().unwrap();
+      let time = NaiveTime::from_hms_opt(21, 0, 0).unwrap();
+      let mut summaries = daily_summaries(&test_db);
+      summaries.set(profile.id, time, now).await.unwrap();
+      summaries
+        .set(child.id, time, now + Duration::minutes(1))
+        .await
+        .unwrap();
+      summaries.set(other.id, time, now).await.unwrap();
+      assert_eq!(
+        summaries.fetch(child.id).await.unwrap(),
+        Some(time)
+      );
+      assert_eq!(
+        summaries.fetch_due(now).await.unwrap(),
+        vec![DueSummary {
+          profile: profile.clone(),
+          time,
+          due_at: now,
+        }]
+      );
+      summaries
+        .reschedule(profile.id, now + Duration::days(1))
+        .await
+        .unwrap();
+      assert!(summaries.fetch_due(now).await.unwrap().is_empty());
+      summaries.remove(child.id).await.unwrap();
+      assert_eq!(summaries.fetch(child.id).await.unwrap(), None);
+    })
+    .await
+    .unwrap();
+  }
+}
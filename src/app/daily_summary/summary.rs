@@ -0,0 +1,191 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+use crate::app::{
+  stats::statistics::{GlucoseStats, HypoEpisode, InsulinStats},
+  sugar_measurement::{repository::Summary, HIGH_LEVEL, LOW_LEVEL},
+};
+
+/// Results of a day sent in the evening
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailySummary {
+  pub date: NaiveDate,
+  /// Count, mean, min and max of readings
+  pub sugar: Summary,
+  /// `None` if there are no readings
+  pub glucose: Option<GlucoseStats>,
+  pub hypos: Vec<HypoEpisode>,
+  /// `None` if there are no injections
+  pub insulin: Option<InsulinStats>,
+  /// Reminders of previous day after which long-acting dose wasn't
+  /// logged
+  pub missed_basal: Vec<DateTime<Utc>>,
+}
+
+impl DailySummary {
+  /// Message text, `tag` marks not own profile. Times are shown in
+  /// `tz`.
+  pub fn render<Tz: TimeZone>(&self, tag: &str, tz: &Tz) -> String
+  where
+    Tz::Offset: std::fmt::Display,
+  {
+    let mut text =
+      format!("🌙 Сводка за {}{tag}\n", self.date.format("%d.%m.%Y"));
+    match (
+      self.glucose,
+      self.sugar.mean,
+      self.sugar.min,
+      self.sugar.max,
+    ) {
+      (Some(glucose), Some(mean), Some(min), Some(max)) => {
+        let percent = |share: f64| share * 100.0;
+        let _ = write!(
+          text,
+          "\nИзмерений: {}\n\
+          Средний сахар: {:.1} ммоль/л\n\
+          Минимум: {:.1}, максимум: {:.1} ммоль/л\n\
+          В диапазоне ({LOW_LEVEL}–{HIGH_LEVEL:.1}): {:.0} %\n",
+          self.sugar.count,
+          mean.as_millimoles_per_liter(),
+          min.as_millimoles_per_liter(),
+          max.as_millimoles_per_liter(),
+          percent(glucose.in_range),
+        );
+      }
+      _ => text.push_str(
+        "\nЗа день не записано ни одного измерения сахара. \
+        Регулярные измерения помогают вовремя заметить гипо- и \
+        гипергликемию: /sugar_level\n",
+      ),
+    }
+    if !self.hypos.is_empty() {
+      let _ = writeln!(text, "\nГипогликемии: {}", self.hypos.len());
+      for hypo in &self.hypos {
+        let time = |dt: DateTime<Utc>| {
+          dt.with_timezone(tz).format("%H:%M").to_string()
+        };
+        let _ = writeln!(
+          text,
+          "• {}–{}, минимум {:.1} ммоль/л",
+          time(hypo.start),
+          time(hypo.end),
+          hypo.min_level
+        );
+      }
+    }
+    match self.insulin {
+      Some(insulin) => {
+        let _ = write!(
+          text,
+          "\nИнсулин за день: {:.1} ЕД\n\
+          • базальный: {:.1} ЕД\n\
+          • болюсный: {:.1} ЕД\n",
+          insulin.total(),
+          insulin.basal,
+          insulin.bolus,
+        );
+        if insulin.unspecified > 0.0 {
+          let _ = writeln!(
+            text,
+            "• не указан: {:.1} ЕД",
+            insulin.unspecified
+          );
+        }
+      }
+      None => text.push_str("\nИнъекций инсулина не записано\n"),
+    }
+    for reminded_at in &self.missed_basal {
+      let _ = writeln!(
+        text,
+        "⚠️ Пропущен длинный инсулин: напоминание {} осталось без \
+        записи",
+        reminded_at.with_timezone(tz).format("%d.%m в %H:%M")
+      );
+    }
+    text
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::app::sugar_measurement::SugarLevel;
+
+  use super::*;
+
+  fn at(s: &str) -> DateTime<Utc> {
+    s.parse().unwrap()
+  }
+
+  #[test]
+  fn renders_day() {
+    let level =
+      |value| Some(SugarLevel::from_millimoles_per_liter(value));
+    let summary = DailySummary {
+      date: "2024-03-01".parse().unwrap(),
+      sugar: Summary {
+        count: 4,
+        mean: level(6.5),
+        min: level(3.4),
+        max: level(11.0),
+      },
+      glucose: Some(GlucoseStats {
+        count: 4,
+        mean: 6.5,
+        std_dev: 3.0,
+        below_range: 0.25,
+        in_range: 0.5,
+        above_range: 0.25,
+      }),
+      hypos: vec![HypoEpisode {
+        start: at("2024-03-01T14:05:00Z"),
+        end: at("2024-03-01T14:40:00Z"),
+        min_level: 3.4,
+      }],
+      insulin: Some(InsulinStats {
+        basal: 12.0,
+        bolus: 18.0,
+        unspecified: 0.0,
+      }),
+      missed_basal: vec![at("2024-02-29T22:00:00Z")],
+    };
+    assert_eq!(
+      summary.render(" [Маша]", &Utc),
+      "🌙 Сводка за 01.03.2024 [Маша]\n\
+      \n\
+      Измерений: 4\n\
+      Средний сахар: 6.5 ммоль/л\n\
+      Минимум: 3.4, максимум: 11.0 ммоль/л\n\
+      В диапазоне (3.9–10.0): 50 %\n\
+      \n\
+      Гипогликемии: 1\n\
+      • 14:05–14:40, минимум 3.4 ммоль/л\n\
+      \n\
+      Инсулин за день: 30.0 ЕД\n\
+      • базальный: 12.0 ЕД\n\
+      • болюсный: 18.0 ЕД\n\
+      ⚠️ Пропущен длинный инсулин: напоминание 29.02 в 22:00 \
+      осталось без записи\n"
+    );
+  }
+
+  #[test]
+  fn nudges_without_readings() {
+    let summary = DailySummary {
+      date: "2024-03-01".parse().unwrap(),
+      sugar: Summary {
+        count: 0,
+        mean: None,
+        min: None,
+        max: None,
+      },
+      glucose: None,
+      hypos: Vec::new(),
+      insulin: None,
+      missed_basal: Vec::new(),
+    };
+    let text = summary.render("", &Utc);
+    assert!(text.contains("не записано ни одного измерения"));
+    assert!(text.ends_with("Инъекций инсулина не записано\n"));
+  }
+}
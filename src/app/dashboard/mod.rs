@@ -0,0 +1,288 @@
+pub mod repository;
+pub mod view;
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use clokwerk::{AsyncScheduler, TimeUnits};
+use teloxide::{dptree::case, prelude::*, ApiError, RequestError};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::Result,
+  db::Db,
+  event_handler::{filter_event, handler, EventHandler},
+  utils::{event_publisher::EventPublisher, filter_message},
+};
+
+use self::{
+  repository::{dashboards, Dashboard},
+  view::{Reminder, Today},
+};
+
+use super::{
+  daily_summary::repository::daily_summaries,
+  hypo_recheck::repository::hypo_rechecks,
+  insulin_injection::{
+    on_board::{action_start, insulin_on_board},
+    repository::insulin_injections,
+    InsulinInjectionAdded,
+  },
+  meal::{repository::meals, MealAdded},
+  preferences::repository::preferences,
+  profile::{current_profile, Profile},
+  stats::{midnight_in, statistics::InsulinStats},
+  sugar_measurement::{
+    repository::sugar_measurements, SugarMeasurementAdded,
+  },
+  user::repository::users,
+  weekly_digest::repository::weekly_digests,
+  UpdateHandler,
+};
+
+/// Readings shown on dashboard, enough for trend
+const LATEST_READINGS: u32 = 2;
+
+#[derive(Debug, Clone)]
+struct DashboardTick;
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    filter_message()
+      .filter_command::<MenuCommand>()
+      .branch(case![MenuCommand::Dashboard].endpoint(recreate))
+  }
+
+  fn event_handler(&self) -> EventHandler {
+    dptree::entry()
+      .branch(
+        filter_event::<SugarMeasurementAdded>()
+          .map(|event: SugarMeasurementAdded| event.profile)
+          .chain(handler(refresh)),
+      )
+      .branch(
+        filter_event::<InsulinInjectionAdded>()
+          .map(|event: InsulinInjectionAdded| event.profile)
+          .chain(handler(refresh)),
+      )
+      .branch(
+        filter_event::<MealAdded>()
+          .map(|event: MealAdded| event.profile)
+          .chain(handler(refresh)),
+      )
+      .branch(
+        filter_event::<DashboardTick>()
+          .chain(handler(recreate_outdated)),
+      )
+  }
+
+  fn schedule(
+    &self,
+    scheduler: &mut AsyncScheduler,
+    event_publisher: Arc<EventPublisher>,
+  ) {
+    scheduler.every(1.minute()).run(move || {
+      event_publisher.send(DashboardTick);
+      async {}
+    });
+  }
+}
+
+async fn recreate(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+) -> Result<()> {
+  let old = dashboards(&db).fetch(user_id).await?;
+  let profile = current_profile(&db, user_id).await?;
+  let now = profile_now(&db, &profile).await?;
+  let text = render(&db, &profile, now).await?;
+  replace(&bot, &db, user_id, old, text).await
+}
+
+/// Updates dashboard of user managing profile entry is added to
+async fn refresh(
+  bot: Bot,
+  db: Arc<Db>,
+  profile: Profile,
+) -> Result<()> {
+  update(&bot, &db, profile.user_id).await
+}
+
+/// Replaces dashboards left from previous day
+async fn recreate_outdated(bot: Bot, db: Arc<Db>) -> Result<()> {
+  let outdated = dashboards(&db).fetch_outdated(Utc::now()).await?;
+  for user_id in outdated {
+    if let Err(err) = update(&bot, &db, user_id).await {
+      log::error!("Dashboard of {user_id} isn't replaced: {err}");
+    }
+  }
+  Ok(())
+}
+
+/// Edits dashboard in place. New one is sent if there is none, it's
+/// from previous day or can't be edited, e.g. it's deleted.
+async fn update(bot: &Bot, db: &Db, user_id: UserId) -> Result<()> {
+  let profile = current_profile(db, user_id).await?;
+  let now = profile_now(db, &profile).await?;
+  let text = render(db, &profile, now).await?;
+  let old = dashboards(db).fetch(user_id).await?;
+  let midnight = midnight_in(now.date_naive(), &now.timezone());
+  let Some(dashboard) =
+    old.filter(|dashboard| dashboard.created_at >= midnight)
+  else {
+    return replace(bot, db, user_id, old, text).await;
+  };
+  let edited = bot
+    .edit_message_text(user_id, dashboard.message_id, &text)
+    .await;
+  match edited {
+    Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {
+      Ok(())
+    }
+    Err(RequestError::Api(ApiError::BotBlocked)) => {
+      users(db).disable(user_id).await?;
+      Ok(())
+    }
+    Err(RequestError::Api(err)) => {
+      log::info!("Dashboard of {user_id} isn't edited: {err}");
+      replace(bot, db, user_id, old, text).await
+    }
+    Err(err) => Err(err.into()),
+  }
+}
+
+/// Sends and pins new dashboard unpinning `old` one
+async fn replace(
+  bot: &Bot,
+  db: &Db,
+  user_id: UserId,
+  old: Option<Dashboard>,
+  text: String,
+) -> Result<()> {
+  let msg = match bot.send_message(user_id, text).await {
+    Ok(msg) => msg,
+    Err(RequestError::Api(ApiError::BotBlocked)) => {
+      users(db).disable(user_id).await?;
+      return Ok(());
+    }
+    Err(err) => return Err(err.into()),
+  };
+  dashboards(db)
+    .set(
+      user_id,
+      Dashboard {
+        message_id: msg.id,
+        created_at: Utc::now(),
+      },
+    )
+    .await?;
+  // Dashboard is usable without pin, so pin errors aren't fatal
+  if let Some(old) = old {
+    if let Err(err) = bot
+      .unpin_chat_message(user_id)
+      .message_id(old.message_id)
+      .await
+    {
+      log::info!("Old dashboard of {user_id} isn't unpinned: {err}");
+    }
+  }
+  if let Err(err) = bot
+    .pin_chat_message(user_id, msg.id)
+    .disable_notification(true)
+    .await
+  {
+    log::error!("Dashboard of {user_id} isn't pinned: {err}");
+  }
+  Ok(())
+}
+
+/// Current time in timezone of `profile`
+async fn profile_now(
+  db: &Db,
+  profile: &Profile,
+) -> Result<DateTime<Tz>> {
+  let tz = preferences(db)
+    .fetch(profile.id)
+    .await?
+    .unwrap_or_default()
+    .timezone;
+  Ok(Utc::now().with_timezone(&tz))
+}
+
+/// Dashboard text of `profile` as of its local `now`
+async fn render(
+  db: &Db,
+  profile: &Profile,
+  now: DateTime<Tz>,
+) -> Result<String> {
+  let tz = now.timezone();
+  let midnight = midnight_in(now.date_naive(), &tz);
+  let end = now.to_utc();
+  let measurements = sugar_measurements(db, profile.id);
+  let latest = measurements.fetch_latest(LATEST_READINGS).await?;
+  let sugar = measurements.summary_between(midnight, end).await?;
+  let injections = insulin_injections(db, profile.id);
+  let last_injection =
+    injections.fetch_latest(1).await?.into_iter().next();
+  let injections = injections
+    .fetch_between(midnight.min(action_start(end)), end)
+    .await?;
+  let todays = injections
+    .iter()
+    .filter(|i| i.date_time >= midnight)
+    .copied()
+    .collect::<Vec<_>>();
+  let carbs = meals(db, profile.id)
+    .fetch_between(midnight, end)
+    .await?
+    .iter()
+    .map(|meal| meal.carbs_grams)
+    .sum();
+  let today = Today {
+    date: now.date_naive(),
+    latest,
+    sugar,
+    insulin_on_board: insulin_on_board(&injections, end),
+    last_injection,
+    // Daily average of a single day is its total
+    insulin: InsulinStats::calculate(&todays, &tz),
+    carbs,
+    next_reminder: next_reminder(db, profile).await?,
+  };
+  Ok(today.render(&profile.tag(), end, &tz))
+}
+
+/// Earliest message scheduled for profile
+async fn next_reminder(
+  db: &Db,
+  profile: &Profile,
+) -> Result<Option<Reminder>> {
+  let reminder = |label| {
+    move |at: Option<DateTime<Utc>>| {
+      at.map(|at| Reminder { at, label })
+    }
+  };
+  let recheck = hypo_rechecks(db)
+    .fetch_prompt_at(profile.id)
+    .await
+    .map(reminder("Повторное измерение после гипо"))?;
+  let summary = daily_summaries(db)
+    .fetch_next_at(profile.id)
+    .await
+    .map(reminder("Ежедневная сводка"))?;
+  let digest = weekly_digests(db)
+    .fetch_next_at(profile.id)
+    .await
+    .map(reminder("Еженедельный отчет"))?;
+  Ok(
+    [recheck, summary, digest]
+      .into_iter()
+      .flatten()
+      .min_by_key(|reminder| reminder.at),
+  )
+}
@@ -0,0 +1,180 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use teloxide::types::{MessageId, UserId};
+
+use crate::{
+  app::{preferences::server_timezone, stats::midnight_in},
+  db::{txn::ExecutorHolder, Db},
+};
+
+pub fn dashboards(db: &Db) -> Repository {
+  Repository { exec: db.exec() }
+}
+
+pub struct Repository {
+  exec: ExecutorHolder,
+}
+
+/// Pinned dashboard message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dashboard {
+  pub message_id: MessageId,
+  pub created_at: DateTime<Utc>,
+}
+
+impl Repository {
+  #[allow(clippy::cast_possible_wrap)]
+  pub async fn fetch(
+    &self,
+    user_id: UserId,
+  ) -> sqlx::Result<Option<Dashboard>> {
+    let user_id = user_id.0 as i64;
+    sqlx::query!(
+      r#"
+        SELECT message_id, created_at
+        FROM dashboards
+        WHERE user_id = ?
+      "#,
+      user_id
+    )
+    .map(|rec| Dashboard {
+      message_id: MessageId(rec.message_id as _),
+      created_at: rec.created_at.and_utc(),
+    })
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Saves dashboard replacing previous one
+  #[allow(clippy::cast_possible_wrap)]
+  pub async fn set(
+    &mut self,
+    user_id: UserId,
+    dashboard: Dashboard,
+  ) -> sqlx::Result<()> {
+    let user_id = user_id.0 as i64;
+    sqlx::query!(
+      r#"
+        REPLACE INTO dashboards (user_id, message_id, created_at)
+        VALUES (?, ?, ?)
+      "#,
+      user_id,
+      dashboard.message_id.0,
+      dashboard.created_at
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  /// Users whose dashboard is created before the day of `now` in
+  /// timezone of their current profile and who haven't disabled the
+  /// bot
+  #[allow(clippy::cast_sign_loss)]
+  pub async fn fetch_outdated(
+    &self,
+    now: DateTime<Utc>,
+  ) -> sqlx::Result<Vec<UserId>> {
+    let server_timezone = server_timezone();
+    let recs = sqlx::query!(
+      r#"
+        SELECT dashboards.user_id, dashboards.created_at,
+          profile_preferences.timezone AS "timezone?"
+        FROM dashboards
+        JOIN users ON users.id = dashboards.user_id
+        LEFT JOIN profile_preferences
+          ON profile_preferences.profile_id = users.current_profile_id
+        WHERE users.disabled = FALSE
+      "#
+    )
+    .fetch_all(&mut self.exec.borrow())
+    .await?;
+    let outdated = recs
+      .into_iter()
+      .filter(|rec| {
+        let tz = rec
+          .timezone
+          .as_deref()
+          .and_then(|tz| tz.parse::<Tz>().ok())
+          .unwrap_or(server_timezone);
+        let today = now.with_timezone(&tz).date_naive();
+        rec.created_at.and_utc() < midnight_in(today, &tz)
+      })
+      .map(|rec| UserId(rec.user_id as _))
+      .collect();
+    Ok(outdated)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::Duration;
+
+  use crate::{
+    app::{
+      export::logbook::Units,
+      preferences::{repository::preferences, Preferences},
+      profile::repository::profiles,
+      user::repository::users,
+    },
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn set_and_fetch_outdated() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      users(&test_db).add(user).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      profiles(&test_db).switch(user, profile.id).await.unwrap();
+      preferences(&test_db)
+        .set(
+          profile.id,
+          Preferences {
+            timezone: chrono_tz::Asia::Vladivostok,
+            units: Units::default(),
+          },
+        )
+        .await
+        .unwrap();
+      let mut repository = dashboards(&test_db);
+      assert_eq!(repository.fetch(user).await.unwrap(), None);
+      // Midnight of March 1 in Vladivostok
+      let midnight: DateTime<Utc> =
+        "2024-02-29T14:00:00Z".parse().unwrap();
+      let now = midnight + Duration::hours(1);
+      let dashboard = Dashboard {
+        message_id: MessageId(10),
+        created_at: midnight - Duration::hours(1),
+      };
+      repository.set(user, dashboard).await.unwrap();
+      assert_eq!(
+        repository.fetch(user).await.unwrap(),
+        Some(dashboard)
+      );
+      assert_eq!(
+        repository.fetch_outdated(now).await.unwrap(),
+        vec![user]
+      );
+      let dashboard = Dashboard {
+        message_id: MessageId(11),
+        created_at: midnight,
+      };
+      repository.set(user, dashboard).await.unwrap();
+      assert!(repository
+        .fetch_outdated(now)
+        .await
+        .unwrap()
+        .is_empty());
+      assert_eq!(
+        repository.fetch(user).await.unwrap(),
+        Some(dashboard)
+      );
+    })
+    .await
+    .unwrap();
+  }
+}
@@ -0,0 +1,258 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+use crate::app::{
+  insulin_injection::{InsulinInjection, InsulinKind},
+  stats::statistics::InsulinStats,
+  sugar_measurement::{repository::Summary, SugarMeasurement},
+};
+
+/// Older previous reading doesn't show trend
+const TREND_MAX_MINUTES: i64 = 180;
+
+/// Scheduled message shown on dashboard
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reminder {
+  pub at: DateTime<Utc>,
+  pub label: &'static str,
+}
+
+/// Data of dashboard of a day
+#[derive(Debug, Clone, PartialEq)]
+pub struct Today {
+  pub date: NaiveDate,
+  /// Newest readings first, at most two
+  pub latest: Vec<SugarMeasurement>,
+  /// Readings since midnight
+  pub sugar: Summary,
+  /// Units of bolus insulin still acting
+  pub insulin_on_board: f64,
+  /// Newest injection, even if it's from previous days
+  pub last_injection: Option<InsulinInjection>,
+  /// Totals since midnight, `None` if there are no injections
+  pub insulin: Option<InsulinStats>,
+  /// Carbs since midnight, g
+  pub carbs: f64,
+  pub next_reminder: Option<Reminder>,
+}
+
+impl Today {
+  /// Message text at `now`, `tag` marks not own profile. Times are
+  /// shown in `tz`.
+  pub fn render<Tz: TimeZone>(
+    &self,
+    tag: &str,
+    now: DateTime<Utc>,
+    tz: &Tz,
+  ) -> String
+  where
+    Tz::Offset: std::fmt::Display,
+  {
+    let time = |dt: DateTime<Utc>| {
+      dt.with_timezone(tz).format("%H:%M").to_string()
+    };
+    let mut text =
+      format!("📋 Сегодня, {}{tag}\n\n", self.date.format("%d.%m"));
+    match self.latest.first() {
+      Some(last) => {
+        let _ = writeln!(
+          text,
+          "🩸 {:.1} ммоль/л в {} ({})",
+          last.level.as_millimoles_per_liter(),
+          time(last.date_time),
+          age((now - last.date_time).num_minutes())
+        );
+        if let Some(trend) = trend(&self.latest) {
+          let _ = writeln!(text, "Тренд: {trend}");
+        }
+      }
+      None => text.push_str("🩸 Измерений пока нет: /sugar_level\n"),
+    }
+    let _ = writeln!(
+      text,
+      "💉 Активный инсулин: {:.1} ЕД",
+      self.insulin_on_board
+    );
+    if let Some(last) = self.last_injection {
+      let kind = match last.kind {
+        Some(InsulinKind::Bolus) => ", короткий",
+        Some(InsulinKind::Basal) => ", длинный",
+        None => "",
+      };
+      let _ = writeln!(
+        text,
+        "Последняя доза: {} ЕД{kind}, в {} ({})",
+        last.volume.as_cubic_centimeters(),
+        time(last.date_time),
+        age((now - last.date_time).num_minutes())
+      );
+    }
+    text.push_str("\nЗа сегодня:\n");
+    match self.sugar.mean {
+      Some(mean) => {
+        let _ = writeln!(
+          text,
+          "• измерений: {}, средний сахар {:.1} ммоль/л",
+          self.sugar.count,
+          mean.as_millimoles_per_liter()
+        );
+      }
+      None => text.push_str("• измерений: 0\n"),
+    }
+    match self.insulin {
+      Some(insulin) => {
+        let _ = writeln!(
+          text,
+          "• инсулин: {:.1} ЕД (базальный {:.1}, болюсный {:.1})",
+          insulin.total(),
+          insulin.basal,
+          insulin.bolus
+        );
+      }
+      None => text.push_str("• инсулин: 0 ЕД\n"),
+    }
+    let _ = writeln!(text, "• углеводы: {:.0} г", self.carbs);
+    if let Some(reminder) = self.next_reminder {
+      let _ = writeln!(
+        text,
+        "\n⏰ {} — в {}",
+        reminder.label,
+        time(reminder.at)
+      );
+    }
+    let _ = write!(text, "\nОбновлено в {}", time(now));
+    text
+  }
+}
+
+/// "12 мин назад"
+fn age(minutes: i64) -> String {
+  match minutes {
+    ..=0 => "только что".into(),
+    1..=59 => format!("{minutes} мин назад"),
+    60..=1439 => {
+      format!("{} ч {} мин назад", minutes / 60, minutes % 60)
+    }
+    _ => "больше суток назад".into(),
+  }
+}
+
+/// Arrow and change between two newest readings
+#[allow(clippy::cast_precision_loss)]
+fn trend(latest: &[SugarMeasurement]) -> Option<String> {
+  let [last, previous, ..] = latest else {
+    return None;
+  };
+  let minutes = (last.date_time - previous.date_time).num_minutes();
+  if !(1..=TREND_MAX_MINUTES).contains(&minutes) {
+    return None;
+  }
+  let change = last.level.as_millimoles_per_liter()
+    - previous.level.as_millimoles_per_liter();
+  let per_hour = change / minutes as f64 * 60.0;
+  let arrow = match per_hour {
+    rate if rate >= 3.0 => "⬆️",
+    rate if rate >= 1.0 => "↗️",
+    rate if rate <= -3.0 => "⬇️",
+    rate if rate <= -1.0 => "↘️",
+    _ => "➡️",
+  };
+  Some(format!("{arrow} {change:+.1} ммоль/л за {minutes} мин"))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::app::{
+    insulin_injection::Insulin, sugar_measurement::SugarLevel,
+  };
+
+  use super::*;
+
+  fn at(s: &str) -> DateTime<Utc> {
+    s.parse().unwrap()
+  }
+
+  fn reading(date_time: &str, level: f64) -> SugarMeasurement {
+    SugarMeasurement {
+      date_time: at(date_time),
+      level: SugarLevel::from_millimoles_per_liter(level),
+    }
+  }
+
+  #[test]
+  fn renders_today() {
+    let today = Today {
+      date: "2024-03-01".parse().unwrap(),
+      latest: vec![
+        reading("2024-03-01T14:08:00Z", 6.8),
+        reading("2024-03-01T13:33:00Z", 6.0),
+      ],
+      sugar: Summary {
+        count: 5,
+        mean: Some(SugarLevel::from_millimoles_per_liter(7.1)),
+        min: None,
+        max: None,
+      },
+      insulin_on_board: 2.34,
+      last_injection: Some(InsulinInjection {
+        date_time: at("2024-03-01T13:00:00Z"),
+        volume: Insulin::from_cubic_centimeters(4.0),
+        kind: Some(InsulinKind::Bolus),
+      }),
+      insulin: Some(InsulinStats {
+        basal: 10.0,
+        bolus: 8.0,
+        unspecified: 0.0,
+      }),
+      carbs: 120.0,
+      next_reminder: Some(Reminder {
+        at: at("2024-03-01T14:35:00Z"),
+        label: "Повторное измерение после гипо",
+      }),
+    };
+    assert_eq!(
+      today.render(" [Маша]", at("2024-03-01T14:20:00Z"), &Utc),
+      "📋 Сегодня, 01.03 [Маша]\n\
+      \n\
+      🩸 6.8 ммоль/л в 14:08 (12 мин назад)\n\
+      Тренд: ↗️ +0.8 ммоль/л за 35 мин\n\
+      💉 Активный инсулин: 2.3 ЕД\n\
+      Последняя доза: 4 ЕД, короткий, в 13:00 (1 ч 20 мин назад)\n\
+      \n\
+      За сегодня:\n\
+      • измерений: 5, средний сахар 7.1 ммоль/л\n\
+      • инсулин: 18.0 ЕД (базальный 10.0, болюсный 8.0)\n\
+      • углеводы: 120 г\n\
+      \n\
+      ⏰ Повторное измерение после гипо — в 14:35\n\
+      \n\
+      Обновлено в 14:20"
+    );
+  }
+
+  #[test]
+  fn shows_trend_of_recent_readings_only() {
+    let rising = [
+      reading("2024-03-01T12:20:00Z", 9.0),
+      reading("2024-03-01T12:00:00Z", 7.0),
+    ];
+    assert_eq!(
+      trend(&rising).as_deref(),
+      Some("⬆️ +2.0 ммоль/л за 20 мин")
+    );
+    let stale = [
+      reading("2024-03-01T12:00:00Z", 9.0),
+      reading("2024-03-01T08:00:00Z", 7.0),
+    ];
+    assert_eq!(trend(&stale), None);
+    assert_eq!(trend(&rising[..1]), None);
+  }
+
+  #[test]
+  fn formats_age() {
+    assert_eq!(age(0), "только что");
+    assert_eq!(age(75), "1 ч 15 мин назад");
+    assert_eq!(age(2000), "больше суток назад");
+  }
+}
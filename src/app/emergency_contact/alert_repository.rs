@@ -0,0 +1,137 @@
+use chrono::{DateTime, Utc};
+use teloxide::types::UserId;
+
+use crate::{
+  app::profile::ProfileId,
+  db::{txn::ExecutorHolder, Db},
+};
+
+use super::{AlertId, Reason};
+
+pub fn emergency_alerts(db: &Db) -> Repository {
+  Repository { exec: db.exec() }
+}
+
+pub struct Repository {
+  exec: ExecutorHolder,
+}
+
+impl Repository {
+  pub async fn add(
+    &mut self,
+    profile_id: ProfileId,
+    date_time: DateTime<Utc>,
+    reason: Reason,
+  ) -> sqlx::Result<AlertId> {
+    let (reason, millimoles_per_liter) = match reason {
+      Reason::CriticalLow(level) => {
+        ("critical_low", Some(level.as_millimoles_per_liter()))
+      }
+      Reason::UnansweredRecheck => ("unanswered_recheck", None),
+    };
+    let res = sqlx::query!(
+      r#"
+        INSERT INTO emergency_alerts (
+          profile_id,
+          date_time,
+          reason,
+          millimoles_per_liter
+        )
+        VALUES (?, ?, ?, ?)
+      "#,
+      profile_id.0,
+      date_time,
+      reason,
+      millimoles_per_liter
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(AlertId(res.last_insert_rowid()))
+  }
+
+  /// Profile whom alert is about
+  pub async fn fetch_profile(
+    &self,
+    alert_id: AlertId,
+  ) -> sqlx::Result<Option<ProfileId>> {
+    sqlx::query!(
+      "SELECT profile_id FROM emergency_alerts WHERE id = ?",
+      alert_id.0
+    )
+    .map(|rec| ProfileId(rec.profile_id))
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Logs acknowledgement. Returns `false` if contact already did it.
+  #[allow(clippy::cast_possible_wrap)]
+  pub async fn acknowledge(
+    &mut self,
+    alert_id: AlertId,
+    contact_id: UserId,
+    date_time: DateTime<Utc>,
+  ) -> sqlx::Result<bool> {
+    let contact_id = contact_id.0 as i64;
+    let res = sqlx::query!(
+      r#"
+        INSERT OR IGNORE INTO emergency_alert_acknowledgements (
+          alert_id,
+          contact_id,
+          date_time
+        )
+        VALUES (?, ?, ?)
+      "#,
+      alert_id.0,
+      contact_id,
+      date_time
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(res.rows_affected() > 0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    app::{
+      profile::repository::profiles, sugar_measurement::SugarLevel,
+      user::repository::users,
+    },
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn add_and_acknowledge_once() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      let contact = UserId(2);
+      let now = Utc::now();
+      users(&test_db).add(user).await.unwrap();
+      users(&test_db).add(contact).await.unwrap();
+      let profile =
+        profiles(&test_db).add(user, None).await.unwrap().id;
+      let mut alerts = emergency_alerts(&test_db);
+      let level = SugarLevel::from_millimoles_per_liter(2.5);
+      let alert_id = alerts
+        .add(profile, now, Reason::CriticalLow(level))
+        .await
+        .unwrap();
+      let alerted = alerts.fetch_profile(alert_id).await.unwrap();
+      assert_eq!(alerted, Some(profile));
+      assert!(alerts
+        .acknowledge(alert_id, contact, now)
+        .await
+        .unwrap());
+      assert!(!alerts
+        .acknowledge(alert_id, contact, now)
+        .await
+        .unwrap());
+    })
+    .await
+    .unwrap();
+  }
+}
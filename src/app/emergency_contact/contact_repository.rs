@@ -0,0 +1,82 @@
+use teloxide::types::UserId;
+
+use crate::{
+  app::profile::ProfileId,
+  db::{txn::ExecutorHolder, Db},
+};
+
+pub fn emergency_contacts(
+  db: &Db,
+  profile_id: ProfileId,
+) -> Repository {
+  let exec = db.exec();
+  Repository { profile_id, exec }
+}
+
+pub struct Repository {
+  profile_id: ProfileId,
+  exec: ExecutorHolder,
+}
+
+impl Repository {
+  #[allow(clippy::cast_sign_loss)]
+  pub async fn fetch_all(&self) -> sqlx::Result<Vec<UserId>> {
+    let profile_id = self.profile_id.0;
+    sqlx::query!(
+      "SELECT contact_id FROM emergency_contacts WHERE profile_id = ?",
+      profile_id
+    )
+    .map(|rec| UserId(rec.contact_id as _))
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  #[allow(clippy::cast_possible_wrap)]
+  pub async fn add(
+    &mut self,
+    contact_id: UserId,
+  ) -> sqlx::Result<()> {
+    let profile_id = self.profile_id.0;
+    let contact_id = contact_id.0 as i64;
+    sqlx::query!(
+      r#"
+        INSERT OR IGNORE INTO emergency_contacts (profile_id, contact_id)
+        VALUES (?, ?)
+      "#,
+      profile_id,
+      contact_id
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    app::{profile::repository::profiles, user::repository::users},
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn add_and_fetch_all() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      let contact = UserId(2);
+      users(&test_db).add(user).await.unwrap();
+      users(&test_db).add(contact).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let mut contacts = emergency_contacts(&test_db, profile.id);
+      contacts.add(contact).await.unwrap();
+      contacts.add(contact).await.unwrap();
+      let recs = contacts.fetch_all().await.unwrap();
+      assert_eq!(recs, vec![contact]);
+    })
+    .await
+    .unwrap();
+  }
+}
@@ -0,0 +1,283 @@
+pub mod alert_repository;
+pub mod contact_repository;
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use teloxide::{
+  dptree::case,
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup, Me},
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::Result,
+  db::Db,
+  event_handler::{filter_event, handler, EventHandler},
+  utils::{filter_callback_query, filter_message, full_name},
+};
+
+use self::{
+  alert_repository::emergency_alerts,
+  contact_repository::emergency_contacts,
+};
+
+use super::{
+  hypo_recheck::HypoRecheckUnanswered,
+  invite::{invite_link, repository::invites, InviteKind},
+  profile::{current_profile, repository::profiles, Profile},
+  sugar_measurement::{SugarLevel, SugarMeasurementAdded},
+  user::UserStarted,
+  UpdateHandler,
+};
+
+/// Sugar level which alerts emergency contacts immediately, mmol/L
+const CRITICAL_LEVEL: f64 = 3.0;
+const ACK_CALLBACK_PREFIX: &str = "emergency_ack:";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlertId(i64);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+  CriticalLow(SugarLevel),
+  UnansweredRecheck,
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    dptree::entry()
+      .branch(
+        filter_message().filter_command::<MenuCommand>().branch(
+          case![MenuCommand::EmergencyContact].endpoint(invite),
+        ),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| parse_ack(query.data?))
+          .endpoint(acknowledge),
+      )
+  }
+
+  fn event_handler(&self) -> EventHandler {
+    dptree::entry()
+      .branch(filter_event::<UserStarted>().chain(handler(redeem)))
+      .branch(
+        filter_event::<SugarMeasurementAdded>()
+          .chain(handler(check_critical_level)),
+      )
+      .branch(
+        filter_event::<HypoRecheckUnanswered>()
+          .chain(handler(alert_unanswered_recheck)),
+      )
+  }
+}
+
+fn parse_ack(data: String) -> Option<AlertId> {
+  let id = data.strip_prefix(ACK_CALLBACK_PREFIX)?.parse().ok()?;
+  Some(AlertId(id))
+}
+
+async fn invite(
+  bot: Bot,
+  me: Me,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+) -> Result<()> {
+  let profile = current_profile(&db, user_id).await?;
+  let code = invites(&db)
+    .create(profile.id, InviteKind::EmergencyContact)
+    .await?;
+  let link = invite_link(&me, &code);
+  bot
+    .send_message(
+      chat_id,
+      format!(
+        "Отправьте эту ссылку человеку, которого нужно оповещать \
+        при тяжёлой гипогликемии{}. \
+        Ссылка одноразовая и действует сутки:\n\n{link}",
+        profile.tag()
+      ),
+    )
+    .await?;
+  Ok(())
+}
+
+async fn redeem(
+  bot: Bot,
+  db: Arc<Db>,
+  event: UserStarted,
+) -> Result<()> {
+  let UserStarted { user_id, payload } = event;
+  if payload.is_empty() {
+    return Ok(());
+  }
+  let Some(profile_id) = invites(&db)
+    .redeem(&payload, InviteKind::EmergencyContact)
+    .await?
+  else {
+    return Ok(());
+  };
+  let Some(profile) = profiles(&db).fetch(profile_id).await? else {
+    return Ok(());
+  };
+  if profile.user_id == user_id {
+    bot
+      .send_message(
+        user_id,
+        "Нельзя стать экстренным контактом своего профиля",
+      )
+      .await?;
+    return Ok(());
+  }
+  emergency_contacts(&db, profile.id).add(user_id).await?;
+  let patient_name = profile.display_name(&bot).await?;
+  let contact_name = full_name(&bot, user_id).await?;
+  bot
+    .send_message(
+      user_id,
+      format!(
+        "Вы стали экстренным контактом для {patient_name}. \
+        Я сообщу вам, если понадобится помощь"
+      ),
+    )
+    .await?;
+  bot
+    .send_message(
+      profile.user_id,
+      format!(
+        "{contact_name} теперь экстренный контакт{}",
+        profile.tag()
+      ),
+    )
+    .await?;
+  Ok(())
+}
+
+async fn check_critical_level(
+  bot: Bot,
+  db: Arc<Db>,
+  event: SugarMeasurementAdded,
+) -> Result<()> {
+  let level = event.measurement.level;
+  if level.as_millimoles_per_liter() < CRITICAL_LEVEL {
+    alert(&bot, &db, &event.profile, Reason::CriticalLow(level))
+      .await?;
+  }
+  Ok(())
+}
+
+async fn alert_unanswered_recheck(
+  bot: Bot,
+  db: Arc<Db>,
+  event: HypoRecheckUnanswered,
+) -> Result<()> {
+  alert(&bot, &db, &event.profile, Reason::UnansweredRecheck).await
+}
+
+async fn alert(
+  bot: &Bot,
+  db: &Db,
+  profile: &Profile,
+  reason: Reason,
+) -> Result<()> {
+  let contacts =
+    emergency_contacts(db, profile.id).fetch_all().await?;
+  if contacts.is_empty() {
+    log::info!("No emergency contacts of {:?} to alert", profile.id);
+    return Ok(());
+  }
+  let alert_id = emergency_alerts(db)
+    .add(profile.id, Utc::now(), reason)
+    .await?;
+  let name = profile.display_name(bot).await?;
+  let text = match reason {
+    Reason::CriticalLow(level) => format!(
+      "🆘 У {name} критически низкий сахар: {:.1} ммоль/л",
+      level.as_millimoles_per_liter()
+    ),
+    Reason::UnansweredRecheck => format!(
+      "🆘 {name} не отвечает на просьбу перепроверить сахар \
+      после гипогликемии"
+    ),
+  };
+  let keyboard =
+    InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
+      "✅ Принято",
+      format!("{ACK_CALLBACK_PREFIX}{}", alert_id.0),
+    )]]);
+  // Every contact is tried even if some are unreachable
+  let mut reached = 0;
+  for &contact in &contacts {
+    let res = bot
+      .send_message(contact, &text)
+      .reply_markup(keyboard.clone())
+      .await;
+    match res {
+      Ok(_) => reached += 1,
+      Err(err) => log::error!(
+        "Emergency alert {alert_id:?} isn't sent to {contact}: {err}"
+      ),
+    }
+  }
+  let report = if reached == 0 {
+    format!(
+      "⚠️{} Не удалось оповестить экстренные контакты",
+      profile.tag()
+    )
+  } else {
+    format!(
+      "🆘{} Экстренные контакты оповещены: {reached} из {}",
+      profile.tag(),
+      contacts.len()
+    )
+  };
+  bot.send_message(profile.user_id, report).await?;
+  Ok(())
+}
+
+async fn acknowledge(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  alert_id: AlertId,
+  query: CallbackQuery,
+) -> Result<()> {
+  let mut alerts = emergency_alerts(&db);
+  let Some(profile_id) = alerts.fetch_profile(alert_id).await? else {
+    bot.answer_callback_query(query.id).await?;
+    return Ok(());
+  };
+  let contacts =
+    emergency_contacts(&db, profile_id).fetch_all().await?;
+  let profile = profiles(&db).fetch(profile_id).await?;
+  let Some(profile) = profile.filter(|_| contacts.contains(&user_id))
+  else {
+    bot.answer_callback_query(query.id).await?;
+    return Ok(());
+  };
+  let first_time =
+    alerts.acknowledge(alert_id, user_id, Utc::now()).await?;
+  log::info!(
+    "Emergency alert {alert_id:?} acknowledged by {user_id}"
+  );
+  bot.answer_callback_query(query.id).text("Спасибо!").await?;
+  if let Some(msg) = query.message {
+    bot.edit_message_reply_markup(msg.chat.id, msg.id).await?;
+  }
+  if first_time {
+    let name = full_name(&bot, user_id).await?;
+    bot
+      .send_message(
+        profile.user_id,
+        format!("✅{} {name} получил(а) оповещение", profile.tag()),
+      )
+      .await?;
+  }
+  Ok(())
+}
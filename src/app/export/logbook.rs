@@ -0,0 +1,293 @@
+use std::io;
+
+use chrono::{NaiveDateTime, TimeZone};
+use rust_xlsxwriter::{Format, Workbook};
+
+use crate::{
+  app::{
+    insulin_injection::{InsulinInjection, InsulinKind},
+    meal::Meal,
+    sugar_measurement::{SugarLevel, SugarMeasurement},
+  },
+  common::{any, Result},
+};
+
+/// Units of exported sugar levels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Units {
+  #[default]
+  MillimolesPerLiter,
+  MilligramsPerDeciliter,
+}
+
+impl Units {
+  pub const ALL: [Self; 2] =
+    [Self::MillimolesPerLiter, Self::MilligramsPerDeciliter];
+
+  pub fn parse(s: &str) -> Option<Self> {
+    match s {
+      "mmol" => Some(Self::MillimolesPerLiter),
+      "mg" => Some(Self::MilligramsPerDeciliter),
+      _ => None,
+    }
+  }
+
+  pub fn data(self) -> &'static str {
+    match self {
+      Self::MillimolesPerLiter => "mmol",
+      Self::MilligramsPerDeciliter => "mg",
+    }
+  }
+
+  pub fn label(self) -> &'static str {
+    match self {
+      Self::MillimolesPerLiter => "ммоль/л",
+      Self::MilligramsPerDeciliter => "мг/дл",
+    }
+  }
+
+  pub fn format(self, level: SugarLevel) -> String {
+    match self {
+      Self::MillimolesPerLiter => {
+        format!("{:.1}", level.as_millimoles_per_liter())
+      }
+      Self::MilligramsPerDeciliter => {
+        format!("{:.0}", level.as_milligrams_per_deciliter())
+      }
+    }
+  }
+
+  fn convert(self, level: SugarLevel) -> f64 {
+    match self {
+      Self::MillimolesPerLiter => level.as_millimoles_per_liter(),
+      Self::MilligramsPerDeciliter => {
+        level.as_milligrams_per_deciliter().round()
+      }
+    }
+  }
+}
+
+/// Logbook record
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+  Sugar(SugarLevel),
+  Insulin(f64, Option<InsulinKind>),
+  Meal(f64, Option<String>),
+}
+
+/// Records of a profile in local time, in chronological order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Logbook {
+  pub records: Vec<(NaiveDateTime, Record)>,
+}
+
+impl Logbook {
+  /// Collects records with time taken in `tz`
+  pub fn new<Tz: TimeZone>(
+    measurements: &[SugarMeasurement],
+    injections: &[InsulinInjection],
+    meals: &[Meal],
+    tz: &Tz,
+  ) -> Self {
+    let local = |dt: chrono::DateTime<chrono::Utc>| {
+      dt.with_timezone(tz).naive_local()
+    };
+    let mut records: Vec<_> = measurements
+      .iter()
+      .map(|m| (local(m.date_time), Record::Sugar(m.level)))
+      .chain(injections.iter().map(|i| {
+        let volume = i.volume.as_cubic_centimeters();
+        (local(i.date_time), Record::Insulin(volume, i.kind))
+      }))
+      .chain(meals.iter().map(|m| {
+        let record = Record::Meal(m.carbs_grams, m.note.clone());
+        (local(m.date_time), record)
+      }))
+      .collect();
+    records.sort_by_key(|(time, _)| *time);
+    Self { records }
+  }
+
+  fn header(units: Units) -> [String; 7] {
+    [
+      "Дата".into(),
+      "Время".into(),
+      format!("Сахар, {}", units.label()),
+      "Инсулин, ЕД".into(),
+      "Тип инсулина".into(),
+      "Углеводы, г".into(),
+      "Заметка".into(),
+    ]
+  }
+
+  /// Writes CSV with header and one record per row
+  pub fn write_csv<W: io::Write>(
+    &self,
+    units: Units,
+    writer: W,
+  ) -> Result<()> {
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record(Self::header(units)).map_err(any)?;
+    for (time, record) in &self.records {
+      let mut row = [
+        time.format("%Y-%m-%d").to_string(),
+        time.format("%H:%M").to_string(),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+      ];
+      match record {
+        Record::Sugar(level) => row[2] = units.format(*level),
+        Record::Insulin(volume, kind) => {
+          row[3] = volume.to_string();
+          row[4] = kind_label(*kind).into();
+        }
+        Record::Meal(carbs, note) => {
+          row[5] = carbs.to_string();
+          row[6] = note.clone().unwrap_or_default();
+        }
+      }
+      csv.write_record(&row).map_err(any)?;
+    }
+    csv.flush().map_err(any)?;
+    Ok(())
+  }
+
+  /// Builds XLSX workbook with one sheet laid out like CSV
+  pub fn to_xlsx(&self, units: Units) -> Result<Vec<u8>> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Дневник").map_err(any)?;
+    let bold = Format::new().set_bold();
+    let date = Format::new().set_num_format("dd.mm.yyyy");
+    let time = Format::new().set_num_format("hh:mm");
+    let level = Format::new().set_num_format(match units {
+      Units::MillimolesPerLiter => "0.0",
+      Units::MilligramsPerDeciliter => "0",
+    });
+    for (col, title) in (0..).zip(Self::header(units)) {
+      sheet.write_with_format(0, col, title, &bold).map_err(any)?;
+    }
+    for (row, (date_time, record)) in (1..).zip(&self.records) {
+      sheet
+        .write_datetime_with_format(row, 0, date_time.date(), &date)
+        .map_err(any)?;
+      sheet
+        .write_datetime_with_format(row, 1, date_time.time(), &time)
+        .map_err(any)?;
+      match record {
+        Record::Sugar(value) => {
+          sheet
+            .write_number_with_format(
+              row,
+              2,
+              units.convert(*value),
+              &level,
+            )
+            .map_err(any)?;
+        }
+        Record::Insulin(volume, kind) => {
+          sheet.write_number(row, 3, *volume).map_err(any)?;
+          sheet
+            .write_string(row, 4, kind_label(*kind))
+            .map_err(any)?;
+        }
+        Record::Meal(carbs, note) => {
+          sheet.write_number(row, 5, *carbs).map_err(any)?;
+          if let Some(note) = note {
+            sheet.write_string(row, 6, note).map_err(any)?;
+          }
+        }
+      }
+    }
+    sheet.set_column_width(0, 12).map_err(any)?;
+    sheet.set_column_width(2, 16).map_err(any)?;
+    sheet.set_column_width(4, 14).map_err(any)?;
+    sheet.set_column_width(6, 40).map_err(any)?;
+    sheet.set_freeze_panes(1, 0).map_err(any)?;
+    workbook.save_to_buffer().map_err(any)
+  }
+}
+
+fn kind_label(kind: Option<InsulinKind>) -> &'static str {
+  match kind {
+    Some(InsulinKind::Basal) => "базальный",
+    Some(InsulinKind::Bolus) => "болюсный",
+    None => "",
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{FixedOffset, Utc};
+
+  use crate::app::insulin_injection::Insulin;
+
+  use super::*;
+
+  const FIXTURE: &str = "src/app/export/testdata/logbook.csv";
+  const FIXTURE_MG_DL: &str =
+    "src/app/export/testdata/logbook_mg_dl.csv";
+
+  fn at(s: &str) -> chrono::DateTime<Utc> {
+    s.parse().unwrap()
+  }
+
+  fn logbook() -> Logbook {
+    let measurements = [
+      SugarMeasurement {
+        date_time: at("2024-03-01T05:10:00Z"),
+        level: SugarLevel::from_millimoles_per_liter(6.4),
+      },
+      SugarMeasurement {
+        date_time: at("2024-03-01T21:30:00Z"),
+        level: SugarLevel::from_millimoles_per_liter(3.6),
+      },
+    ];
+    let injections = [
+      InsulinInjection {
+        date_time: at("2024-03-01T05:15:00Z"),
+        volume: Insulin::from_cubic_centimeters(4.5),
+        kind: Some(InsulinKind::Bolus),
+      },
+      InsulinInjection {
+        date_time: at("2024-03-01T19:00:00Z"),
+        volume: Insulin::from_cubic_centimeters(12.0),
+        kind: None,
+      },
+    ];
+    let meals = [Meal {
+      date_time: at("2024-03-01T05:20:00Z"),
+      carbs_grams: 45.0,
+      note: Some("овсянка, \"с ягодами\"".into()),
+    }];
+    let tz = FixedOffset::east_opt(3 * 3600).unwrap();
+    Logbook::new(&measurements, &injections, &meals, &tz)
+  }
+
+  fn csv(units: Units) -> String {
+    let mut buf = Vec::new();
+    logbook().write_csv(units, &mut buf).unwrap();
+    String::from_utf8(buf).unwrap()
+  }
+
+  #[test]
+  fn writes_csv_in_local_time() {
+    let expected = std::fs::read_to_string(FIXTURE).unwrap();
+    assert_eq!(csv(Units::MillimolesPerLiter), expected);
+  }
+
+  #[test]
+  fn writes_csv_in_milligrams_per_deciliter() {
+    let expected = std::fs::read_to_string(FIXTURE_MG_DL).unwrap();
+    assert_eq!(csv(Units::MilligramsPerDeciliter), expected);
+  }
+
+  #[test]
+  fn builds_xlsx() {
+    let xlsx = logbook().to_xlsx(Units::MillimolesPerLiter).unwrap();
+    assert!(xlsx.starts_with(b"PK\x03\x04"));
+  }
+}
@@ -0,0 +1,210 @@
+pub mod logbook;
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use teloxide::{
+  dptree::case,
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile},
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::Db,
+  utils::{filter_callback_query, filter_message},
+};
+
+use self::logbook::Logbook;
+
+use super::{
+  insulin_injection::repository::insulin_injections,
+  meal::repository::meals, preferences::repository::preferences,
+  profile::current_profile, stats::Period,
+  sugar_measurement::repository::sugar_measurements, UpdateHandler,
+};
+
+const CALLBACK_PREFIX: &str = "export:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+  Csv,
+  Xlsx,
+}
+
+impl Format {
+  fn extension(self) -> &'static str {
+    match self {
+      Self::Csv => "csv",
+      Self::Xlsx => "xlsx",
+    }
+  }
+}
+
+/// Period and file format of export
+#[derive(Debug, Clone, Copy)]
+struct Selection {
+  period: Period,
+  format: Format,
+}
+
+impl Selection {
+  fn parse(data: &str) -> Option<Self> {
+    let data = data.strip_prefix(CALLBACK_PREFIX)?;
+    let mut parts = data.split(':');
+    let period = Period::parse(parts.next()?)?;
+    let format = match parts.next()? {
+      "csv" => Format::Csv,
+      "xlsx" => Format::Xlsx,
+      _ => return None,
+    };
+    Some(Self { period, format })
+  }
+
+  fn data(self) -> String {
+    format!(
+      "{CALLBACK_PREFIX}{}:{}",
+      self.period.data(),
+      self.format.extension()
+    )
+  }
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    dptree::entry()
+      .branch(
+        filter_message()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::Export].endpoint(ask_period)),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            Selection::parse(query.data.as_deref()?)
+          })
+          .endpoint(send_export),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            let data = query.data.as_deref()?;
+            Period::parse(data.strip_prefix(CALLBACK_PREFIX)?)
+          })
+          .endpoint(ask_format),
+      )
+  }
+}
+
+async fn ask_period(bot: Bot, chat_id: ChatId) -> Result<()> {
+  let periods = Period::ALL
+    .into_iter()
+    .map(|period| {
+      InlineKeyboardButton::callback(
+        period.label(),
+        format!("{CALLBACK_PREFIX}{}", period.data()),
+      )
+    })
+    .collect::<Vec<_>>();
+  bot
+    .send_message(chat_id, "Выберите период выгрузки дневника:")
+    .reply_markup(InlineKeyboardMarkup::new([periods]))
+    .await?;
+  Ok(())
+}
+
+async fn ask_format(
+  bot: Bot,
+  period: Period,
+  query: CallbackQuery,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  let keyboard = [Format::Csv, Format::Xlsx].map(|format| {
+    InlineKeyboardButton::callback(
+      format.extension().to_uppercase(),
+      Selection { period, format }.data(),
+    )
+  });
+  bot
+    .edit_message_text(
+      msg.chat.id,
+      msg.id,
+      format!(
+        "Выгрузка за {}. Выберите формат файла, единицы сахара \
+        и часовой пояс задаются в /preferences:",
+        period.title()
+      ),
+    )
+    .reply_markup(InlineKeyboardMarkup::new([keyboard]))
+    .await?;
+  Ok(())
+}
+
+async fn send_export(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  selection: Selection,
+  query: CallbackQuery,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  let profile = current_profile(&db, user_id).await?;
+  let prefs = preferences(&db)
+    .fetch(profile.id)
+    .await?
+    .unwrap_or_default();
+  let now = Utc::now().with_timezone(&prefs.timezone);
+  let start = selection.period.start(now);
+  let end = now.to_utc();
+  let measurements = sugar_measurements(&db, profile.id)
+    .fetch_between(start, end)
+    .await?;
+  let injections = insulin_injections(&db, profile.id)
+    .fetch_between(start, end)
+    .await?;
+  let meals =
+    meals(&db, profile.id).fetch_between(start, end).await?;
+  let logbook =
+    Logbook::new(&measurements, &injections, &meals, &prefs.timezone);
+  if logbook.records.is_empty() {
+    bot
+      .send_message(msg.chat.id, "Нет записей за период")
+      .await?;
+    return Ok(());
+  }
+  let units = prefs.units;
+  let file =
+    tokio::task::spawn_blocking(move || match selection.format {
+      Format::Csv => {
+        let mut buf = Vec::new();
+        logbook.write_csv(units, &mut buf)?;
+        Ok(buf)
+      }
+      Format::Xlsx => logbook.to_xlsx(units),
+    })
+    .await
+    .map_err(any)??;
+  let file_name = format!(
+    "logbook_{}_{}.{}",
+    start.with_timezone(&prefs.timezone).format("%Y-%m-%d"),
+    now.format("%Y-%m-%d"),
+    selection.format.extension()
+  );
+  bot
+    .send_document(
+      msg.chat.id,
+      InputFile::memory(file).file_name(file_name),
+    )
+    .await?;
+  Ok(())
+}
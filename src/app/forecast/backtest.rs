@@ -0,0 +1,275 @@
+//! Accuracy of forecast replayed over history
+
+use chrono::{DateTime, Duration, Timelike, Utc};
+
+use crate::app::{
+  insulin_injection::{
+    on_board::remaining_share, Insulin, InsulinInjection, InsulinKind,
+  },
+  meal::Meal,
+  sugar_measurement::{SugarLevel, SugarMeasurement, LOW_LEVEL},
+};
+
+use super::model::{Forecast, Sensitivity};
+
+/// Forecast errors at horizon against keeping latest level
+#[derive(Debug, Clone, Copy)]
+pub struct Accuracy {
+  pub samples: usize,
+  /// Mean absolute error of forecast, mmol/L
+  pub error: f64,
+  /// Mean absolute error of latest level taken as forecast, mmol/L
+  pub naive_error: f64,
+  /// Share of hypos within horizon that were warned about
+  pub lows_warned: f64,
+}
+
+/// Forecasts level after every reading from entries known at that
+/// moment and compares it with reading taken `horizon` minutes later
+#[allow(clippy::cast_precision_loss)]
+pub fn backtest(
+  readings: &[SugarMeasurement],
+  meals: &[Meal],
+  injections: &[InsulinInjection],
+  sensitivity: Sensitivity,
+  horizon: i64,
+) -> Accuracy {
+  let (mut samples, mut error, mut naive_error) = (0, 0.0, 0.0);
+  let (mut lows, mut warned) = (0, 0);
+  for (index, reading) in readings.iter().enumerate() {
+    let target = reading.date_time + Duration::minutes(horizon);
+    let Some(actual) =
+      readings.iter().find(|r| r.date_time == target)
+    else {
+      continue;
+    };
+    let forecast = Forecast::calculate(
+      &readings[..=index],
+      meals,
+      injections,
+      sensitivity,
+    )
+    .unwrap();
+    let actual_level = actual.level.as_millimoles_per_liter();
+    samples += 1;
+    error +=
+      (forecast.level_after(horizon).unwrap() - actual_level).abs();
+    naive_error += (forecast.level - actual_level).abs();
+    let goes_low = forecast.level >= LOW_LEVEL
+      && readings.iter().any(|r| {
+        reading.date_time < r.date_time
+          && r.date_time <= target
+          && r.level.is_low()
+      });
+    if goes_low {
+      lows += 1;
+      warned += usize::from(forecast.low_after().is_some());
+    }
+  }
+  let samples_f = samples as f64;
+  Accuracy {
+    samples,
+    error: error / samples_f,
+    naive_error: naive_error / samples_f,
+    lows_warned: warned as f64 / lows.max(1) as f64,
+  }
+}
+
+/// History of simulated patient which physiology differs from the
+/// model: carbs absorb unevenly and both factors are off by 10-15 %
+struct Simulation {
+  readings: Vec<SugarMeasurement>,
+  meals: Vec<Meal>,
+  injections: Vec<InsulinInjection>,
+}
+
+/// Deterministic pseudo-random numbers in [0, 1)
+struct Lcg(u64);
+
+impl Lcg {
+  #[allow(clippy::cast_precision_loss)]
+  fn next(&mut self) -> f64 {
+    self.0 = self
+      .0
+      .wrapping_mul(6_364_136_223_846_793_005)
+      .wrapping_add(1_442_695_040_888_963_407);
+    (self.0 >> 11) as f64 / (1u64 << 53) as f64
+  }
+
+  fn between(&mut self, low: f64, high: f64) -> f64 {
+    low + (high - low) * self.next()
+  }
+}
+
+/// Share of carbs absorbed after `minutes` with triangular rate
+/// peaking at 60 minutes and ending at 180
+fn absorbed(minutes: f64) -> f64 {
+  const PEAK: f64 = 60.0;
+  const END: f64 = 180.0;
+  match minutes {
+    m if m <= 0.0 => 0.0,
+    m if m <= PEAK => m * m / (PEAK * END),
+    m if m < END => 1.0 - (END - m).powi(2) / ((END - PEAK) * END),
+    _ => 1.0,
+  }
+}
+
+impl Simulation {
+  const ISF: f64 = 2.3;
+  const CARB_RATIO: f64 = 11.0;
+  const STEP_MINUTES: i64 = 5;
+  const READING_MINUTES: i64 = 15;
+
+  #[allow(clippy::cast_precision_loss)]
+  fn run(days: i64, seed: u64) -> Self {
+    let mut rng = Lcg(seed);
+    let start: DateTime<Utc> =
+      "2024-03-01T00:00:00Z".parse().unwrap();
+    let mut sim = Self {
+      readings: Vec::new(),
+      meals: Vec::new(),
+      injections: Vec::new(),
+    };
+    let mut level = 6.0;
+    let steps = days * 24 * 60 / Self::STEP_MINUTES;
+    for step in 0..steps {
+      let now = start + Duration::minutes(step * Self::STEP_MINUTES);
+      let (hour, minute) = (now.hour(), now.minute());
+      if minute == 0 && [8, 13, 19].contains(&hour) {
+        let carbs = rng.between(30.0, 80.0).round();
+        sim.eat(now, carbs);
+        // Boluses are a bit off like real ones
+        let units = carbs / Self::CARB_RATIO * rng.between(0.8, 1.2);
+        sim.inject(now, (units * 2.0).round() / 2.0);
+      }
+      if minute == 30 && hour % 3 == 0 && level > 11.0 {
+        let units = ((level - 7.0) / Self::ISF * 2.0).round() / 2.0;
+        sim.inject(now, units);
+      }
+      if level < 3.5
+        && sim
+          .meals
+          .last()
+          .is_none_or(|m| now - m.date_time >= Duration::minutes(20))
+      {
+        sim.eat(now, 15.0);
+      }
+      if step % (Self::READING_MINUTES / Self::STEP_MINUTES) == 0 {
+        let noise = rng.between(-0.3, 0.3);
+        sim.readings.push(SugarMeasurement {
+          date_time: now,
+          level: SugarLevel::from_millimoles_per_liter(
+            (level + noise).max(2.0),
+          ),
+        });
+      }
+      level =
+        (level + sim.change(now) + rng.between(-0.05, 0.05)).max(2.0);
+    }
+    sim
+  }
+
+  fn eat(&mut self, date_time: DateTime<Utc>, carbs_grams: f64) {
+    self.meals.push(Meal {
+      date_time,
+      carbs_grams,
+      note: None,
+    });
+  }
+
+  fn inject(&mut self, date_time: DateTime<Utc>, units: f64) {
+    self.injections.push(InsulinInjection {
+      date_time,
+      volume: Insulin::from_cubic_centimeters(units),
+      kind: Some(InsulinKind::Bolus),
+    });
+  }
+
+  /// Change of level within step after `now`
+  #[allow(clippy::cast_precision_loss)]
+  fn change(&self, now: DateTime<Utc>) -> f64 {
+    let step = Self::STEP_MINUTES as f64;
+    let age =
+      |at: DateTime<Utc>| (now - at).num_seconds() as f64 / 60.0;
+    let insulin = self
+      .injections
+      .iter()
+      .map(|i| {
+        let age = age(i.date_time);
+        i.volume.as_cubic_centimeters()
+          * (remaining_share(age) - remaining_share(age + step))
+      })
+      .sum::<f64>();
+    let carbs = self
+      .meals
+      .iter()
+      .map(|m| {
+        let age = age(m.date_time);
+        m.carbs_grams * (absorbed(age + step) - absorbed(age))
+      })
+      .sum::<f64>();
+    // Dawn phenomenon rises level early in the morning
+    let dawn = if (4..8).contains(&now.hour()) {
+      0.04
+    } else {
+      0.0
+    };
+    Self::ISF * (carbs / Self::CARB_RATIO - insulin) + dawn
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Factors estimated from diary rather than true ones
+  const ESTIMATED: Sensitivity = Sensitivity {
+    insulin: 2.0,
+    carb_ratio: 10.0,
+  };
+
+  fn accuracy(horizon: i64) -> Accuracy {
+    let sim = Simulation::run(14, 42);
+    backtest(
+      &sim.readings,
+      &sim.meals,
+      &sim.injections,
+      ESTIMATED,
+      horizon,
+    )
+  }
+
+  #[test]
+  fn beats_naive_forecast_in_an_hour() {
+    let accuracy = accuracy(60);
+    assert!(accuracy.samples > 1000, "{accuracy:?}");
+    assert!(accuracy.error < 1.2, "{accuracy:?}");
+    assert!(
+      accuracy.error < 0.85 * accuracy.naive_error,
+      "{accuracy:?}"
+    );
+  }
+
+  #[test]
+  fn beats_naive_forecast_in_two_hours() {
+    let accuracy = accuracy(120);
+    assert!(accuracy.error < 2.0, "{accuracy:?}");
+    assert!(
+      accuracy.error < 0.85 * accuracy.naive_error,
+      "{accuracy:?}"
+    );
+  }
+
+  #[test]
+  fn warns_about_most_hypos() {
+    let accuracy = accuracy(60);
+    assert!(accuracy.lows_warned > 0.7, "{accuracy:?}");
+  }
+
+  #[test]
+  fn simulation_is_deterministic() {
+    let (a, b) = (Simulation::run(2, 7), Simulation::run(2, 7));
+    assert_eq!(a.readings, b.readings);
+    assert_eq!(a.meals, b.meals);
+  }
+}
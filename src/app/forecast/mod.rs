@@ -0,0 +1,115 @@
+#[cfg(test)]
+mod backtest;
+pub mod model;
+
+use std::sync::Arc;
+
+use chrono::{Days, Duration, Local, Timelike, Utc};
+use teloxide::prelude::*;
+
+use crate::{
+  app,
+  common::Result,
+  db::Db,
+  event_handler::{filter_event, handler, EventHandler},
+};
+
+use self::model::{Forecast, Sensitivity};
+
+use super::{
+  insulin_injection::{
+    repository::insulin_injections, InsulinInjectionAdded,
+  },
+  meal::{repository::meals, MealAdded},
+  profile::Profile,
+  ratios::{
+    estimate::{Ratios, BUCKETS},
+    DAYS,
+  },
+  stats::statistics::InsulinStats,
+  sugar_measurement::{
+    repository::sugar_measurements, SugarMeasurementAdded,
+  },
+};
+
+/// Forecast is shown only after reading this recent, minutes
+const FRESH_MINUTES: i64 = 20;
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn event_handler(&self) -> EventHandler {
+    dptree::entry()
+      .branch(
+        filter_event::<SugarMeasurementAdded>()
+          .map(|event: SugarMeasurementAdded| event.profile)
+          .chain(handler(send_forecast)),
+      )
+      .branch(
+        filter_event::<InsulinInjectionAdded>()
+          .map(|event: InsulinInjectionAdded| event.profile)
+          .chain(handler(send_forecast)),
+      )
+      .branch(
+        filter_event::<MealAdded>()
+          .map(|event: MealAdded| event.profile)
+          .chain(handler(send_forecast)),
+      )
+  }
+}
+
+/// Sends forecast after entry if there is fresh reading and enough
+/// history to know how insulin and carbs act
+async fn send_forecast(
+  bot: Bot,
+  db: Arc<Db>,
+  profile: Profile,
+) -> Result<()> {
+  let now = Utc::now();
+  let start = now - Days::new(DAYS);
+  let readings = sugar_measurements(&db, profile.id)
+    .fetch_between(start, now)
+    .await?;
+  let fresh = now - Duration::minutes(FRESH_MINUTES);
+  if readings.last().is_none_or(|r| r.date_time < fresh) {
+    return Ok(());
+  }
+  let meals =
+    meals(&db, profile.id).fetch_between(start, now).await?;
+  let injections = insulin_injections(&db, profile.id)
+    .fetch_between(start, now)
+    .await?;
+  // Factors of current part of day, rules of thumb if not estimated
+  let ratios =
+    Ratios::estimate(&meals, &injections, &readings, &Local);
+  let bucket = &ratios.buckets[BUCKETS.index(Local::now().hour())];
+  let rules = InsulinStats::calculate(&injections, &Local)
+    .map(|stats| stats.total())
+    .filter(|&units| units > 0.0)
+    .map(Sensitivity::from_daily_dose);
+  let insulin = bucket
+    .sensitivity()
+    .map(|estimate| estimate.mean)
+    .or(rules.map(|rules| rules.insulin));
+  let carb_ratio = bucket
+    .carb_ratio()
+    .map(|estimate| estimate.mean)
+    .or(rules.map(|rules| rules.carb_ratio));
+  let (Some(insulin), Some(carb_ratio)) = (insulin, carb_ratio)
+  else {
+    return Ok(());
+  };
+  let sensitivity = Sensitivity {
+    insulin,
+    carb_ratio,
+  };
+  let Some(forecast) =
+    Forecast::calculate(&readings, &meals, &injections, sensitivity)
+  else {
+    return Ok(());
+  };
+  bot
+    .send_message(profile.user_id, forecast.render(&profile.tag()))
+    .await?;
+  Ok(())
+}
@@ -0,0 +1,361 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::app::{
+  insulin_injection::{
+    on_board::{insulin_on_board, remaining_share},
+    InsulinInjection, InsulinKind,
+  },
+  meal::Meal,
+  sugar_measurement::{SugarMeasurement, LOW_LEVEL},
+};
+
+/// Forecast covers, minutes
+pub const HORIZON_MINUTES: i64 = 120;
+/// Distance between forecast points, minutes
+pub const STEP_MINUTES: i64 = 10;
+/// Readings within that before latest one give trend, minutes
+const TREND_MINUTES: i64 = 45;
+/// Trend carries on for, minutes
+const MOMENTUM_MINUTES: f64 = 30.0;
+/// Steeper trends are likely noise, mmol/L per minute
+const MAX_SLOPE: f64 = 0.1;
+/// Carbs are absorbed evenly within, minutes
+const CARB_ABSORPTION_MINUTES: f64 = 180.0;
+
+/// How insulin and carbs change level of patient
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sensitivity {
+  /// Drop of level by unit of insulin, mmol/L
+  pub insulin: f64,
+  /// Grams of carbs covered by unit of insulin
+  pub carb_ratio: f64,
+}
+
+impl Sensitivity {
+  /// Rules of 100 and 500 from total daily dose in units
+  pub fn from_daily_dose(units: f64) -> Self {
+    Self {
+      insulin: 100.0 / units,
+      carb_ratio: 500.0 / units,
+    }
+  }
+
+  /// Rise of level by gram of carbs, mmol/L
+  fn carbs(self) -> f64 {
+    self.insulin / self.carb_ratio
+  }
+}
+
+/// Level expected after latest reading with contributions to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Forecast {
+  pub start: DateTime<Utc>,
+  /// Latest reading, mmol/L
+  pub level: f64,
+  /// Trend of latest readings, mmol/L per hour
+  pub trend: f64,
+  /// Units of bolus insulin acting at start
+  pub insulin_on_board: f64,
+  /// Grams of carbs not absorbed at start
+  pub carbs_on_board: f64,
+  pub sensitivity: Sensitivity,
+  /// Minutes after start with expected level, mmol/L
+  pub points: Vec<(i64, f64)>,
+}
+
+impl Forecast {
+  /// Forecasts level after latest reading using entries until it
+  /// only. `None` if there are no readings.
+  #[allow(clippy::cast_precision_loss)]
+  pub fn calculate(
+    readings: &[SugarMeasurement],
+    meals: &[Meal],
+    injections: &[InsulinInjection],
+    sensitivity: Sensitivity,
+  ) -> Option<Self> {
+    let latest = readings.iter().max_by_key(|r| r.date_time)?;
+    let start = latest.date_time;
+    let level = latest.level.as_millimoles_per_liter();
+    let injections = injections
+      .iter()
+      .filter(|i| i.date_time <= start)
+      .cloned()
+      .collect::<Vec<_>>();
+    let meals = meals
+      .iter()
+      .filter(|m| m.date_time <= start)
+      .collect::<Vec<_>>();
+    let minutes = |from: DateTime<Utc>, to: DateTime<Utc>| {
+      (to - from).num_seconds() as f64 / 60.0
+    };
+    let absorbed = |at: DateTime<Utc>| {
+      meals
+        .iter()
+        .map(|m| {
+          let share =
+            minutes(m.date_time, at) / CARB_ABSORPTION_MINUTES;
+          m.carbs_grams * share.clamp(0.0, 1.0)
+        })
+        .sum::<f64>()
+    };
+    let acted = |at: DateTime<Utc>| {
+      injections
+        .iter()
+        .filter(|i| i.kind == Some(InsulinKind::Bolus))
+        .map(|i| {
+          let share = remaining_share(minutes(i.date_time, at));
+          i.volume.as_cubic_centimeters() * (1.0 - share)
+        })
+        .sum::<f64>()
+    };
+    // Change of level by carbs and insulin since long before `at`
+    let effect = |at| {
+      sensitivity.carbs() * absorbed(at)
+        - sensitivity.insulin * acted(at)
+    };
+    let trend = slope(readings, start, |_| 0.0);
+    // Only trend not explained by insulin and carbs carries on
+    let momentum = slope(readings, start, effect);
+    let points = (1..=HORIZON_MINUTES / STEP_MINUTES)
+      .map(|step| {
+        let minutes = step * STEP_MINUTES;
+        let at = start + Duration::minutes(minutes);
+        let momentum =
+          momentum * (minutes as f64).min(MOMENTUM_MINUTES);
+        let expected = level + momentum + effect(at) - effect(start);
+        (minutes, expected.max(0.0))
+      })
+      .collect();
+    let carbs = meals.iter().map(|m| m.carbs_grams).sum::<f64>();
+    Some(Self {
+      start,
+      level,
+      trend: trend * 60.0,
+      insulin_on_board: insulin_on_board(&injections, start),
+      carbs_on_board: carbs - absorbed(start),
+      sensitivity,
+      points,
+    })
+  }
+
+  /// Expected level `minutes` after start, mmol/L
+  pub fn level_after(&self, minutes: i64) -> Option<f64> {
+    self
+      .points
+      .iter()
+      .find(|&&(point, _)| point == minutes)
+      .map(|&(_, level)| level)
+  }
+
+  /// Minutes after start until expected hypo, `None` if level
+  /// stays above low or is already low
+  pub fn low_after(&self) -> Option<i64> {
+    if self.level < LOW_LEVEL {
+      return None;
+    }
+    self
+      .points
+      .iter()
+      .find(|&&(_, level)| level < LOW_LEVEL)
+      .map(|&(minutes, _)| minutes)
+  }
+
+  /// Change of level still expected from acting insulin, mmol/L
+  pub fn insulin_effect(&self) -> f64 {
+    -self.sensitivity.insulin * self.insulin_on_board
+  }
+
+  /// Change of level still expected from carbs on board, mmol/L
+  pub fn carbs_effect(&self) -> f64 {
+    self.sensitivity.carbs() * self.carbs_on_board
+  }
+
+  /// Expected levels with contributions explained
+  pub fn render(&self, tag: &str) -> String {
+    let level =
+      |minutes| self.level_after(minutes).unwrap_or(self.level);
+    let mut text = format!(
+      "🔮 Прогноз{tag}: через 1 ч ≈ {:.1}, через 2 ч ≈ {:.1} ммоль/л\n\n\
+      Сейчас {:.1} ммоль/л, тренд {:+.1} ммоль/л в час.",
+      level(60),
+      level(120),
+      self.level,
+      self.trend
+    );
+    if self.insulin_on_board >= 0.1 {
+      let _ = write!(
+        text,
+        " Активный инсулин {:.1} ЕД снизит сахар еще на {:.1} ммоль/л.",
+        self.insulin_on_board,
+        -self.insulin_effect()
+      );
+    }
+    if self.carbs_on_board >= 1.0 {
+      let _ = write!(
+        text,
+        " Неусвоенные углеводы {:.0} г поднимут его на {:.1} ммоль/л.",
+        self.carbs_on_board,
+        self.carbs_effect()
+      );
+    }
+    if let Some(minutes) = self.low_after() {
+      let _ = write!(
+        text,
+        "\n\n⚠️ Возможна гипогликемия примерно через {minutes} мин. \
+        Подумайте о перекусе и перепроверьте сахар."
+      );
+    }
+    text.push_str(
+      "\n\nПрогноз приблизительный и не заменяет измерений.",
+    );
+    text
+  }
+}
+
+/// Least squares slope of readings shortly before `start` less
+/// `effect` known at their time, mmol/L per minute
+#[allow(clippy::cast_precision_loss)]
+fn slope(
+  readings: &[SugarMeasurement],
+  start: DateTime<Utc>,
+  effect: impl Fn(DateTime<Utc>) -> f64,
+) -> f64 {
+  let since = start - Duration::minutes(TREND_MINUTES);
+  let points = readings
+    .iter()
+    .filter(|r| since <= r.date_time && r.date_time <= start)
+    .map(|r| {
+      let minutes = (r.date_time - start).num_seconds() as f64 / 60.0;
+      let level = r.level.as_millimoles_per_liter();
+      (minutes, level - effect(r.date_time))
+    })
+    .collect::<Vec<_>>();
+  if points.len() < 2 {
+    return 0.0;
+  }
+  let n = points.len() as f64;
+  let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+  let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+  let (cov, var) =
+    points.iter().fold((0.0, 0.0), |(cov, var), (x, y)| {
+      (
+        cov + (x - mean_x) * (y - mean_y),
+        var + (x - mean_x).powi(2),
+      )
+    });
+  if var == 0.0 {
+    return 0.0;
+  }
+  (cov / var).clamp(-MAX_SLOPE, MAX_SLOPE)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::app::{
+    insulin_injection::{Insulin, InsulinKind},
+    sugar_measurement::SugarLevel,
+  };
+
+  use super::*;
+
+  const SENSITIVITY: Sensitivity = Sensitivity {
+    insulin: 2.0,
+    carb_ratio: 10.0,
+  };
+
+  fn at(time: &str) -> DateTime<Utc> {
+    format!("2024-03-01T{time}:00Z").parse().unwrap()
+  }
+
+  fn reading(time: &str, level: f64) -> SugarMeasurement {
+    SugarMeasurement {
+      date_time: at(time),
+      level: SugarLevel::from_millimoles_per_liter(level),
+    }
+  }
+
+  #[test]
+  fn flat_without_insulin_and_carbs() {
+    let readings = [reading("11:40", 6.0), reading("12:00", 6.0)];
+    let forecast =
+      Forecast::calculate(&readings, &[], &[], SENSITIVITY).unwrap();
+    assert_eq!(forecast.points.len(), 12);
+    assert!(forecast
+      .points
+      .iter()
+      .all(|&(_, level)| (level - 6.0).abs() < 1e-9));
+    assert_eq!(forecast.low_after(), None);
+    assert_eq!(
+      forecast.render(" (Маша)"),
+      "🔮 Прогноз (Маша): через 1 ч ≈ 6.0, через 2 ч ≈ 6.0 ммоль/л\n\n\
+      Сейчас 6.0 ммоль/л, тренд +0.0 ммоль/л в час.\n\n\
+      Прогноз приблизительный и не заменяет измерений."
+    );
+  }
+
+  #[test]
+  fn trend_carries_on_for_half_an_hour() {
+    let readings = [reading("11:30", 9.0), reading("12:00", 7.5)];
+    let forecast =
+      Forecast::calculate(&readings, &[], &[], SENSITIVITY).unwrap();
+    assert!((forecast.trend + 3.0).abs() < 1e-9);
+    let level = |minutes| forecast.level_after(minutes).unwrap();
+    assert!((level(30) - 6.0).abs() < 1e-9);
+    assert!((level(120) - 6.0).abs() < 1e-9);
+  }
+
+  #[test]
+  fn insulin_and_carbs_act_over_time() {
+    let readings = [reading("12:00", 6.0)];
+    let meals = [Meal {
+      date_time: at("12:00"),
+      carbs_grams: 60.0,
+      note: None,
+    }];
+    let injections = [InsulinInjection {
+      date_time: at("12:00"),
+      volume: Insulin::from_cubic_centimeters(6.0),
+      kind: Some(InsulinKind::Bolus),
+    }];
+    let carbs =
+      Forecast::calculate(&readings, &meals, &[], SENSITIVITY)
+        .unwrap();
+    assert!((carbs.level_after(60).unwrap() - 10.0).abs() < 1e-9);
+    assert!((carbs.carbs_effect() - 12.0).abs() < 1e-9);
+    let both = Forecast::calculate(
+      &readings,
+      &meals,
+      &injections,
+      SENSITIVITY,
+    )
+    .unwrap();
+    // Carbs act sooner than insulin
+    assert!(both.level_after(60).unwrap() > 6.0);
+    assert!((both.insulin_effect() + 12.0).abs() < 1e-9);
+  }
+
+  #[test]
+  fn warns_about_coming_hypo() {
+    let readings = [reading("12:00", 5.0)];
+    let injections = [InsulinInjection {
+      date_time: at("11:30"),
+      volume: Insulin::from_cubic_centimeters(3.0),
+      kind: Some(InsulinKind::Bolus),
+    }];
+    let forecast =
+      Forecast::calculate(&readings, &[], &injections, SENSITIVITY)
+        .unwrap();
+    let minutes = forecast.low_after().unwrap();
+    assert!((10..=60).contains(&minutes), "{minutes}");
+    assert!(forecast.render("").contains(&format!(
+      "⚠️ Возможна гипогликемия примерно через {minutes} мин."
+    )));
+    let low = [reading("12:00", 3.5)];
+    let forecast =
+      Forecast::calculate(&low, &[], &injections, SENSITIVITY)
+        .unwrap();
+    assert_eq!(forecast.low_after(), None);
+  }
+}
@@ -0,0 +1,281 @@
+pub mod repository;
+
+use std::{fmt::Write, sync::Arc};
+
+use chrono::{
+  DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc,
+};
+use teloxide::{
+  dispatching::dialogue::InMemStorage, dptree::case, prelude::*,
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::Db,
+  utils::filter_message,
+};
+
+use self::repository::hba1c_results;
+
+use super::{
+  preferences::repository::preferences,
+  profile::current_profile,
+  stats::statistics::{
+    glucose_management_indicator, GlucoseStats, GMI_MAX_DAYS,
+    GMI_MIN_DAYS,
+  },
+  sugar_measurement::{
+    repository::sugar_measurements, SugarMeasurement,
+  },
+  UpdateHandler,
+};
+
+/// Plausible range of HbA1c, %
+const PERCENT_RANGE: std::ops::RangeInclusive<f64> = 3.0..=20.0;
+
+type Dialog = Dialogue<State, InMemStorage<State>>;
+
+/// HbA1c measured by laboratory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabHba1c {
+  pub date_time: DateTime<Utc>,
+  pub percent: f64,
+}
+
+#[derive(Default, Clone)]
+enum State {
+  #[default]
+  Ignoring,
+  Accepting,
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn prepare(&self, di: &mut DependencyMap) {
+    di.insert(InMemStorage::<State>::new());
+  }
+
+  fn update_handler(&self) -> UpdateHandler {
+    filter_message()
+      .enter_dialogue::<Message, InMemStorage<State>, State>()
+      .branch(
+        dptree::entry()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::Hba1c].endpoint(ask)),
+      )
+      .branch(case![State::Accepting].endpoint(accept))
+  }
+}
+
+async fn ask(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+  dialogue: Dialog,
+) -> Result<()> {
+  let profile = current_profile(&db, user_id).await?;
+  let tz = preferences(&db)
+    .fetch(profile.id)
+    .await?
+    .unwrap_or_default()
+    .timezone;
+  let results = hba1c_results(&db, profile.id).fetch_all().await?;
+  let measurements = match results.iter().map(|r| r.date_time).min() {
+    Some(oldest) => {
+      let start = oldest - Duration::days(GMI_MAX_DAYS.into());
+      sugar_measurements(&db, profile.id)
+        .fetch_between(start, Utc::now())
+        .await?
+    }
+    None => Vec::new(),
+  };
+  let mut text = format!("🧪 HbA1c{}\n", profile.tag());
+  if results.is_empty() {
+    text.push_str("\nЛабораторных результатов пока нет\n");
+  }
+  for result in &results {
+    let date = result.date_time.with_timezone(&tz).format("%d.%m.%Y");
+    let _ = write!(text, "\n{date}: лаб. {:.1} %", result.percent);
+    match estimate_at(&measurements, result.date_time) {
+      Some(gmi) => {
+        let diff = gmi - result.percent;
+        let _ = write!(text, ", GMI {gmi:.1} % ({diff:+.1})");
+      }
+      None => text.push_str(", GMI — мало данных"),
+    }
+  }
+  text.push_str(
+    "\n\nОтправьте результат анализа в %, например «6.8» \
+    или «6.8 01.03.2024»",
+  );
+  bot.send_message(chat_id, text).await?;
+  dialogue.update(State::Accepting).await.map_err(any)?;
+  Ok(())
+}
+
+async fn accept(
+  bot: Bot,
+  msg: Message,
+  user_id: UserId,
+  db: Arc<Db>,
+  dialogue: Dialog,
+) -> Result<()> {
+  let profile = current_profile(&db, user_id).await?;
+  let tz = preferences(&db)
+    .fetch(profile.id)
+    .await?
+    .unwrap_or_default()
+    .timezone;
+  if let Some(result) = parse(msg.text(), Utc::now(), &tz) {
+    hba1c_results(&db, profile.id).add(result).await?;
+    bot
+      .send_message(msg.chat.id, format!("✅{}", profile.tag()))
+      .await?;
+  } else {
+    bot
+      .send_message(
+        msg.chat.id,
+        "Неправильный формат. Пример: «6.8» или «6.8 01.03.2024»",
+      )
+      .await?;
+  }
+  dialogue.reset().await.map_err(any)?;
+  Ok(())
+}
+
+/// GMI from readings over up to 90 days before `date_time`. `None` if
+/// readings span less than 14 days.
+fn estimate_at(
+  measurements: &[SugarMeasurement],
+  date_time: DateTime<Utc>,
+) -> Option<f64> {
+  let start = date_time - Duration::days(GMI_MAX_DAYS.into());
+  let window: Vec<_> = measurements
+    .iter()
+    .filter(|m| (start..=date_time).contains(&m.date_time))
+    .copied()
+    .collect();
+  let first = window.iter().map(|m| m.date_time).min()?;
+  let last = window.iter().map(|m| m.date_time).max()?;
+  if last - first < Duration::days(GMI_MIN_DAYS.into()) {
+    return None;
+  }
+  let stats = GlucoseStats::calculate(&window)?;
+  Some(glucose_management_indicator(stats.mean))
+}
+
+/// Parses "percent [dd.mm.yyyy]". Result without date is taken at
+/// `now`, lab date is midnight in `tz`.
+fn parse<Tz: TimeZone>(
+  s: Option<&str>,
+  now: DateTime<Utc>,
+  tz: &Tz,
+) -> Option<LabHba1c> {
+  let mut parts = s?.split_whitespace();
+  let percent: f64 = parts.next()?.replace(',', ".").parse().ok()?;
+  if !PERCENT_RANGE.contains(&percent) {
+    return None;
+  }
+  let date_time = match parts.next() {
+    Some(date) => NaiveDate::parse_from_str(date, "%d.%m.%Y")
+      .ok()?
+      .and_time(NaiveTime::MIN)
+      .and_local_timezone(tz.clone())
+      .earliest()?
+      .to_utc(),
+    None => now,
+  };
+  if parts.next().is_some() || date_time > now {
+    return None;
+  }
+  Some(LabHba1c { date_time, percent })
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::app::sugar_measurement::SugarLevel;
+
+  use super::*;
+
+  fn at(s: &str) -> DateTime<Utc> {
+    s.parse().unwrap()
+  }
+
+  /// Daily readings of `level` for `days` days before `end`
+  fn daily(
+    end: DateTime<Utc>,
+    days: i64,
+    level: f64,
+  ) -> Vec<SugarMeasurement> {
+    (0..days)
+      .map(|day| SugarMeasurement {
+        date_time: end - Duration::days(day),
+        level: SugarLevel::from_millimoles_per_liter(level),
+      })
+      .collect()
+  }
+
+  #[test]
+  fn parses_result_taken_now() {
+    let now = at("2024-03-10T09:00:00Z");
+    assert_eq!(
+      parse(Some(" 6,8 "), now, &Utc),
+      Some(LabHba1c {
+        date_time: now,
+        percent: 6.8,
+      })
+    );
+  }
+
+  #[test]
+  fn parses_lab_date_as_midnight_in_timezone() {
+    let now = at("2024-03-10T09:00:00Z");
+    let tz = chrono_tz::Asia::Vladivostok;
+    let result = parse(Some("7.1 01.03.2024"), now, &tz).unwrap();
+    assert_eq!(result.date_time, at("2024-02-29T14:00:00Z"));
+    assert!((result.percent - 7.1).abs() < 1e-9);
+  }
+
+  #[test]
+  fn rejects_malformed_and_implausible_results() {
+    let now = at("2024-03-10T09:00:00Z");
+    let parse = |s| parse(Some(s), now, &Utc);
+    assert_eq!(parse("2.9"), None);
+    assert_eq!(parse("20.5"), None);
+    assert_eq!(parse("шесть"), None);
+    assert_eq!(parse("6.8 32.01.2024"), None);
+    assert_eq!(parse("6.8 01.03.2024 утром"), None);
+    // Lab date in the future
+    assert_eq!(parse("6.8 01.04.2024"), None);
+    assert_eq!(super::parse(None, now, &Utc), None);
+  }
+
+  #[test]
+  fn estimates_only_with_two_weeks_of_readings() {
+    let now = at("2024-03-10T09:00:00Z");
+    assert_eq!(estimate_at(&daily(now, 14, 8.0), now), None);
+    let estimate = estimate_at(&daily(now, 15, 8.0), now).unwrap();
+    let expected = glucose_management_indicator(8.0);
+    assert!((estimate - expected).abs() < 1e-9);
+  }
+
+  #[test]
+  fn estimate_ignores_readings_outside_window() {
+    let now = at("2024-03-10T09:00:00Z");
+    let mut readings = daily(now, 15, 8.0);
+    // Older than 90 days and after the result
+    readings.extend(daily(now - Duration::days(100), 10, 20.0));
+    readings.extend(daily(now + Duration::days(5), 3, 20.0));
+    let estimate = estimate_at(&readings, now).unwrap();
+    let expected = glucose_management_indicator(8.0);
+    assert!((estimate - expected).abs() < 1e-9);
+    assert_eq!(
+      estimate_at(&readings, now - Duration::days(91)),
+      None
+    );
+  }
+}
@@ -0,0 +1,93 @@
+use crate::{
+  app::profile::ProfileId,
+  db::{txn::ExecutorHolder, Db},
+};
+
+use super::LabHba1c;
+
+pub fn hba1c_results(db: &Db, profile_id: ProfileId) -> Repository {
+  let exec = db.exec();
+  Repository { profile_id, exec }
+}
+
+pub struct Repository {
+  profile_id: ProfileId,
+  exec: ExecutorHolder,
+}
+
+impl Repository {
+  /// Results from the latest
+  pub async fn fetch_all(&self) -> sqlx::Result<Vec<LabHba1c>> {
+    let profile_id = self.profile_id.0;
+    sqlx::query!(
+      r#"
+        SELECT date_time, percent
+        FROM hba1c_results
+        WHERE profile_id = ?
+        ORDER BY date_time DESC
+      "#,
+      profile_id
+    )
+    .map(|rec| LabHba1c {
+      date_time: rec.date_time.and_utc(),
+      percent: rec.percent,
+    })
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Adds result replacing one at the same time
+  pub async fn add(&mut self, result: LabHba1c) -> sqlx::Result<()> {
+    let profile_id = self.profile_id.0;
+    sqlx::query!(
+      r#"
+        REPLACE INTO hba1c_results (profile_id, date_time, percent)
+        VALUES (?, ?, ?)
+      "#,
+      profile_id,
+      result.date_time,
+      result.percent
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{Duration, Utc};
+  use teloxide::types::UserId;
+
+  use crate::{
+    app::{profile::repository::profiles, user::repository::users},
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn add_and_fetch_latest_first() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      let now = Utc::now();
+      users(&test_db).add(user).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let older = LabHba1c {
+        date_time: now - Duration::days(90),
+        percent: 7.4,
+      };
+      let newer = LabHba1c {
+        date_time: now,
+        percent: 6.9,
+      };
+      let mut results = hba1c_results(&test_db, profile.id);
+      results.add(older).await.unwrap();
+      results.add(newer).await.unwrap();
+      assert_eq!(results.fetch_all().await.unwrap(), [newer, older]);
+    })
+    .await
+    .unwrap();
+  }
+}
@@ -22,9 +22,28 @@ impl app::Plugin for Plugin {
 async fn send_help(bot: Bot, chat_id: ChatId) -> Result<()> {
   let help_message = format!("
 Этот бот имеет следующие возможности:
-- сохранение и просмотр ваших показаний уровня сахара
+- сохранение показаний уровня сахара и просмотр дневника по дням
 - отправка напоминаний о необходимости измерения сахара и/или инъекции инсулина
-- просмотр среднего количества инсулина за период времени.
+- напоминание о длинном инсулине с повтором, отметкой о пропуске в сводке и уведомлением опекунов
+- закрепленная сводка за сегодня, которая обновляется после каждой записи
+- прогноз сахара на 1–2 часа после записи с предупреждением о возможной гипогликемии
+- поиск повторяющихся закономерностей: утренние подъемы, ночные гипо, подъемы после обеда
+- оценка углеводного коэффициента и чувствительности к инсулину по дневнику — только как подсказка
+- подтверждение больших доз инсулина и предупреждения о наложении болюсов и двойном базале
+- базальный тест с пропуском еды, напоминаниями об измерениях и итоговой оценкой дозы
+- ежедневная вечерняя сводка и тихие часы без уведомлений
+- еженедельный отчет со сравнением с прошлой неделей
+- статистика за период: средний сахар, вариабельность, время в диапазоне и суточная доза инсулина; разбивка по времени суток и по часам с тепловой картой
+- расчетный HbA1c (GMI) и сравнение с лабораторными результатами
+- графики сахара с отметками введенного инсулина
+- амбулаторный профиль глюкозы (AGP) для эндокринолога
+- учет углеводов и заметок, PDF-отчет для врача
+- свой часовой пояс и единицы сахара у каждого профиля
+- выгрузка дневника в CSV и XLSX, импорт из LibreView, mySugr, xDrip+, Dexcom Clarity и Apple Health
+- оповещение экстренных контактов при тяжёлой гипогликемии
+- уведомления для опекунов о записях подопечного
+- ведение дневников нескольких пациентов из одного аккаунта
+- выгрузка всех ваших данных в JSON и полное удаление аккаунта.
 
 Функциональность постепенно увеличивается, бот находится в активной разработке. 
 
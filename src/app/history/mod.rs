@@ -0,0 +1,414 @@
+use std::sync::Arc;
+
+use chrono::{Days, NaiveDate, NaiveDateTime, Utc};
+use teloxide::{
+  dptree::case,
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::Result,
+  db::Db,
+  utils::{filter_callback_query, filter_message},
+};
+
+use super::{
+  caregiver::readable_profiles,
+  export::logbook::{Logbook, Record, Units},
+  insulin_injection::{repository::insulin_injections, InsulinKind},
+  meal::repository::meals,
+  preferences::repository::preferences,
+  profile::{current_profile, Profile, ProfileId},
+  stats::midnight_in,
+  sugar_measurement::repository::sugar_measurements,
+  UpdateHandler,
+};
+
+const CALLBACK_PREFIX: &str = "history:";
+/// Records per message, keeps CGM days under message length limit
+const RECORDS_PER_PAGE: usize = 60;
+
+/// Part of day's records of profile shown in one message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Page {
+  /// `None` for current profile
+  profile_id: Option<ProfileId>,
+  date: NaiveDate,
+  part: usize,
+}
+
+impl Page {
+  fn parse(data: &str) -> Option<Self> {
+    let data = data.strip_prefix(CALLBACK_PREFIX)?;
+    let fields = data.split(':').collect::<Vec<_>>();
+    let (profile_id, date, part) = match fields[..] {
+      // Buttons sent before profile picker was added have no profile
+      [date, part] => (None, date, part),
+      [profile_id, date, part] => {
+        (Some(ProfileId(profile_id.parse().ok()?)), date, part)
+      }
+      _ => return None,
+    };
+    Some(Self {
+      profile_id,
+      date: date.parse().ok()?,
+      part: part.parse().ok()?,
+    })
+  }
+
+  fn button(self, label: impl Into<String>) -> InlineKeyboardButton {
+    let profile_id =
+      self.profile_id.map(|ProfileId(id)| format!("{id}:"));
+    InlineKeyboardButton::callback(
+      label,
+      format!(
+        "{CALLBACK_PREFIX}{}{}:{}",
+        profile_id.unwrap_or_default(),
+        self.date,
+        self.part
+      ),
+    )
+  }
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    dptree::entry()
+      .branch(
+        filter_message()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::History].endpoint(show_today)),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            Page::parse(query.data.as_deref()?)
+          })
+          .endpoint(turn_page),
+      )
+  }
+}
+
+async fn show_today(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+) -> Result<()> {
+  let profile = current_profile(&db, user_id).await?;
+  let tz = preferences(&db)
+    .fetch(profile.id)
+    .await?
+    .unwrap_or_default()
+    .timezone;
+  let page = Page {
+    profile_id: Some(profile.id),
+    date: Utc::now().with_timezone(&tz).date_naive(),
+    part: 0,
+  };
+  let Some((text, keyboard)) =
+    render_page(&bot, &db, user_id, page).await?
+  else {
+    return Ok(());
+  };
+  bot
+    .send_message(chat_id, text)
+    .reply_markup(keyboard)
+    .await?;
+  Ok(())
+}
+
+async fn turn_page(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  page: Page,
+  query: CallbackQuery,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  let Some((text, keyboard)) =
+    render_page(&bot, &db, user_id, page).await?
+  else {
+    return Ok(());
+  };
+  bot
+    .edit_message_text(msg.chat.id, msg.id, text)
+    .reply_markup(keyboard)
+    .await?;
+  Ok(())
+}
+
+/// `None` if profile of page isn't readable by user
+async fn render_page(
+  bot: &Bot,
+  db: &Db,
+  user_id: UserId,
+  page: Page,
+) -> Result<Option<(String, InlineKeyboardMarkup)>> {
+  let readable = readable_profiles(db, user_id).await?;
+  let profile = match page.profile_id {
+    Some(id) => readable.iter().find(|p| p.id == id).cloned(),
+    None => Some(current_profile(db, user_id).await?),
+  };
+  let Some(profile) = profile else {
+    return Ok(None);
+  };
+  let page = Page {
+    profile_id: Some(profile.id),
+    ..page
+  };
+  let prefs =
+    preferences(db).fetch(profile.id).await?.unwrap_or_default();
+  let tz = prefs.timezone;
+  let start = midnight_in(page.date, &tz);
+  let end = midnight_in(page.date + Days::new(1), &tz);
+  let measurements = sugar_measurements(db, profile.id)
+    .fetch_between(start, end)
+    .await?;
+  let injections = insulin_injections(db, profile.id)
+    .fetch_between(start, end)
+    .await?;
+  let meals = meals(db, profile.id).fetch_between(start, end).await?;
+  let logbook = Logbook::new(&measurements, &injections, &meals, &tz);
+  let parts = logbook.records.len().div_ceil(RECORDS_PER_PAGE).max(1);
+  let part = page.part.min(parts - 1);
+  let date = page.date.format("%d.%m.%Y");
+  let mut text = if profile.user_id == user_id {
+    format!("Дневник за {date}{}", profile.tag())
+  } else {
+    let name = profile.display_name(bot).await?;
+    format!("{name}: дневник за {date}")
+  };
+  if parts > 1 {
+    text += &format!(" (часть {} из {parts})", part + 1);
+  }
+  text += "\n\n";
+  text += &render_records(&logbook, part, prefs.units);
+  let today = Utc::now().with_timezone(&tz).date_naive();
+  let page = Page { part, ..page };
+  let keyboard =
+    keyboard(bot, user_id, &readable, page, parts, today).await?;
+  Ok(Some((text, keyboard)))
+}
+
+/// Lines of records of `part` of day with sugar in `units`
+fn render_records(
+  logbook: &Logbook,
+  part: usize,
+  units: Units,
+) -> String {
+  let records = logbook
+    .records
+    .chunks(RECORDS_PER_PAGE)
+    .nth(part)
+    .unwrap_or_default();
+  if records.is_empty() {
+    return "Нет записей".into();
+  }
+  records
+    .iter()
+    .map(|(time, record)| render_record(*time, record, units))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+fn render_record(
+  time: NaiveDateTime,
+  record: &Record,
+  units: Units,
+) -> String {
+  let time = time.format("%H:%M");
+  match record {
+    Record::Sugar(level) => {
+      format!("{time} 🩸 {} {}", units.format(*level), units.label())
+    }
+    Record::Insulin(volume, kind) => {
+      let kind = match kind {
+        Some(InsulinKind::Bolus) => ", короткий",
+        Some(InsulinKind::Basal) => ", длинный",
+        None => "",
+      };
+      format!("{time} 💉 {volume} ЕД{kind}")
+    }
+    Record::Meal(carbs, note) => {
+      let mut line = format!("{time} 🍽 {carbs} г углеводов");
+      if let Some(note) = note {
+        line += &format!(" — {note}");
+      }
+      line
+    }
+  }
+}
+
+async fn keyboard(
+  bot: &Bot,
+  user_id: UserId,
+  readable: &[Profile],
+  page: Page,
+  parts: usize,
+  today: NaiveDate,
+) -> Result<InlineKeyboardMarkup> {
+  let Page { date, part, .. } = page;
+  let mut rows = Vec::new();
+  if parts > 1 {
+    let mut row = Vec::new();
+    if part > 0 {
+      row.push(
+        Page {
+          part: part - 1,
+          ..page
+        }
+        .button("⬆️ Раньше"),
+      );
+    }
+    if part + 1 < parts {
+      row.push(
+        Page {
+          part: part + 1,
+          ..page
+        }
+        .button("Позже ⬇️"),
+      );
+    }
+    rows.push(row);
+  }
+  let mut row = Vec::new();
+  let previous = date - Days::new(1);
+  row.push(
+    Page {
+      date: previous,
+      part: 0,
+      ..page
+    }
+    .button(format!("◀️ {}", previous.format("%d.%m"))),
+  );
+  let next = date + Days::new(1);
+  if next <= today {
+    row.push(
+      Page {
+        date: next,
+        part: 0,
+        ..page
+      }
+      .button(format!("{} ▶️", next.format("%d.%m"))),
+    );
+  }
+  rows.push(row);
+  if readable.len() > 1 {
+    for profile in readable {
+      let name = if profile.user_id == user_id {
+        profile.label().to_string()
+      } else {
+        profile.display_name(bot).await?
+      };
+      let selected = Some(profile.id) == page.profile_id;
+      rows.push(vec![Page {
+        profile_id: Some(profile.id),
+        part: 0,
+        ..page
+      }
+      .button(format!(
+        "{}{name}",
+        if selected { "✅ " } else { "" }
+      ))]);
+    }
+  }
+  Ok(InlineKeyboardMarkup::new(rows))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::app::sugar_measurement::SugarLevel;
+
+  use super::*;
+
+  fn at(s: &str) -> NaiveDateTime {
+    s.parse().unwrap()
+  }
+
+  #[test]
+  fn renders_records() {
+    let logbook = Logbook {
+      records: vec![
+        (
+          at("2024-03-01T07:05:00"),
+          Record::Sugar(SugarLevel::from_millimoles_per_liter(6.43)),
+        ),
+        (
+          at("2024-03-01T07:10:00"),
+          Record::Insulin(4.5, Some(InsulinKind::Bolus)),
+        ),
+        (
+          at("2024-03-01T07:15:00"),
+          Record::Meal(45.0, Some("Овсянка".into())),
+        ),
+        (at("2024-03-01T22:00:00"), Record::Insulin(12.0, None)),
+      ],
+    };
+    assert_eq!(
+      render_records(&logbook, 0, Units::MillimolesPerLiter),
+      "07:05 🩸 6.4 ммоль/л\n\
+      07:10 💉 4.5 ЕД, короткий\n\
+      07:15 🍽 45 г углеводов — Овсянка\n\
+      22:00 💉 12 ЕД"
+    );
+    let mg =
+      render_records(&logbook, 0, Units::MilligramsPerDeciliter);
+    assert_eq!(mg.lines().next(), Some("07:05 🩸 116 мг/дл"));
+  }
+
+  #[test]
+  fn parses_pages_with_and_without_profile() {
+    let date = "2024-03-01".parse().unwrap();
+    let page = Page {
+      profile_id: Some(ProfileId(7)),
+      date,
+      part: 2,
+    };
+    assert_eq!(Page::parse("history:7:2024-03-01:2"), Some(page));
+    assert_eq!(
+      Page::parse("history:2024-03-01:0"),
+      Some(Page {
+        profile_id: None,
+        date,
+        part: 0,
+      })
+    );
+    assert_eq!(Page::parse("history:x:2024-03-01:0"), None);
+  }
+
+  #[test]
+  fn splits_dense_day_into_parts() {
+    let records = (0..RECORDS_PER_PAGE + 1)
+      .map(|minutes| {
+        let time = at("2024-03-01T00:00:00")
+          + chrono::Duration::minutes(minutes as i64 * 5);
+        let level = SugarLevel::from_millimoles_per_liter(5.0);
+        (time, Record::Sugar(level))
+      })
+      .collect();
+    let logbook = Logbook { records };
+    assert_eq!(
+      render_records(&logbook, 0, Units::MillimolesPerLiter)
+        .lines()
+        .count(),
+      RECORDS_PER_PAGE
+    );
+    assert_eq!(
+      render_records(&logbook, 1, Units::MillimolesPerLiter),
+      "05:00 🩸 5.0 ммоль/л"
+    );
+    assert_eq!(
+      render_records(&logbook, 2, Units::MillimolesPerLiter),
+      "Нет записей"
+    );
+  }
+}
@@ -0,0 +1,174 @@
+pub mod repository;
+
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use clokwerk::{AsyncScheduler, TimeUnits};
+use teloxide::{
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::{
+  app,
+  common::Result,
+  db::Db,
+  event_handler::{filter_event, handler, EventHandler},
+  utils::{event_publisher::EventPublisher, filter_callback_query},
+};
+
+use self::repository::hypo_rechecks;
+
+use super::{
+  profile::{repository::profiles, Profile, ProfileId},
+  sugar_measurement::SugarMeasurementAdded,
+  UpdateHandler,
+};
+
+const RECHECK_DELAY_MINUTES: i64 = 15;
+/// Time to answer recheck prompt before emergency contacts alerted
+const ANSWER_TIMEOUT_MINUTES: i64 = 15;
+const OK_CALLBACK_PREFIX: &str = "hypo_recheck_ok:";
+
+#[derive(Debug, Clone)]
+struct HypoRecheckTick;
+
+/// User didn't answer hypo recheck prompt in time
+#[derive(Debug, Clone)]
+pub struct HypoRecheckUnanswered {
+  pub profile: Profile,
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    filter_callback_query()
+      .filter_map(|query: CallbackQuery| {
+        let data = query.data?;
+        let id =
+          data.strip_prefix(OK_CALLBACK_PREFIX)?.parse().ok()?;
+        Some(ProfileId(id))
+      })
+      .endpoint(confirm_ok)
+  }
+
+  fn event_handler(&self) -> EventHandler {
+    dptree::entry()
+      .branch(
+        filter_event::<SugarMeasurementAdded>()
+          .chain(handler(handle_measurement)),
+      )
+      .branch(
+        filter_event::<HypoRecheckTick>()
+          .chain(handler(check_rechecks)),
+      )
+  }
+
+  fn schedule(
+    &self,
+    scheduler: &mut AsyncScheduler,
+    event_publisher: Arc<EventPublisher>,
+  ) {
+    scheduler.every(1.minute()).run(move || {
+      event_publisher.send(HypoRecheckTick);
+      async {}
+    });
+  }
+}
+
+async fn handle_measurement(
+  bot: Bot,
+  db: Arc<Db>,
+  event: SugarMeasurementAdded,
+) -> Result<()> {
+  let SugarMeasurementAdded {
+    profile,
+    measurement,
+  } = event;
+  let mut rechecks = hypo_rechecks(&db);
+  rechecks.remove(profile.id).await?;
+  if measurement.level.is_low() {
+    let prompt_at = measurement.date_time
+      + Duration::minutes(RECHECK_DELAY_MINUTES);
+    rechecks.schedule(profile.id, prompt_at).await?;
+    bot
+      .send_message(
+        profile.user_id,
+        format!(
+          "⚠️{} Низкий сахар! Примите быстрые углеводы. \
+          Напомню перепроверить сахар через {RECHECK_DELAY_MINUTES} мин.",
+          profile.tag()
+        ),
+      )
+      .await?;
+  }
+  Ok(())
+}
+
+async fn check_rechecks(
+  bot: Bot,
+  db: Arc<Db>,
+  ep: Arc<EventPublisher>,
+) -> Result<()> {
+  let now = Utc::now();
+  let mut rechecks = hypo_rechecks(&db);
+  let profiles = profiles(&db);
+  for profile_id in rechecks.fetch_due(now).await? {
+    rechecks.mark_prompted(profile_id, now).await?;
+    if let Some(profile) = profiles.fetch(profile_id).await? {
+      send_prompt(&bot, &profile).await?;
+    }
+  }
+  let deadline = now - Duration::minutes(ANSWER_TIMEOUT_MINUTES);
+  for profile_id in rechecks.fetch_unanswered(deadline).await? {
+    rechecks.remove(profile_id).await?;
+    if let Some(profile) = profiles.fetch(profile_id).await? {
+      ep.send(HypoRecheckUnanswered { profile });
+    }
+  }
+  Ok(())
+}
+
+async fn send_prompt(bot: &Bot, profile: &Profile) -> Result<()> {
+  let keyboard =
+    InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
+      "👌 Я в порядке",
+      format!("{OK_CALLBACK_PREFIX}{}", profile.id.0),
+    )]]);
+  bot
+    .send_message(
+      profile.user_id,
+      format!(
+        "Пора перепроверить сахар{}: /sugar_level\n\n\
+        Если не ответите в течение {ANSWER_TIMEOUT_MINUTES} мин., \
+        я оповещу экстренные контакты",
+        profile.tag()
+      ),
+    )
+    .reply_markup(keyboard)
+    .await?;
+  Ok(())
+}
+
+async fn confirm_ok(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  profile_id: ProfileId,
+  query: CallbackQuery,
+) -> Result<()> {
+  let profile = profiles(&db).fetch(profile_id).await?;
+  let own = profile.is_some_and(|profile| profile.user_id == user_id);
+  let answered = own && hypo_rechecks(&db).remove(profile_id).await?;
+  let text = if answered {
+    "Хорошо 👌"
+  } else {
+    "Уже неактуально"
+  };
+  bot.answer_callback_query(query.id).text(text).await?;
+  if let Some(msg) = query.message {
+    bot.edit_message_reply_markup(msg.chat.id, msg.id).await?;
+  }
+  Ok(())
+}
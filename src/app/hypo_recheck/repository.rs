@@ -0,0 +1,207 @@
+use chrono::{DateTime, Utc};
+
+use crate::{
+  app::profile::ProfileId,
+  db::{txn::ExecutorHolder, Db},
+};
+
+pub fn hypo_rechecks(db: &Db) -> Repository {
+  Repository { exec: db.exec() }
+}
+
+pub struct Repository {
+  exec: ExecutorHolder,
+}
+
+impl Repository {
+  /// Schedules recheck prompt replacing pending one
+  pub async fn schedule(
+    &mut self,
+    profile_id: ProfileId,
+    prompt_at: DateTime<Utc>,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      r#"
+        REPLACE INTO hypo_rechecks (profile_id, prompt_at, prompted)
+        VALUES (?, ?, FALSE)
+      "#,
+      profile_id.0,
+      prompt_at
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  /// Removes pending recheck. Returns `false` if there was none.
+  pub async fn remove(
+    &mut self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<bool> {
+    let res = sqlx::query!(
+      "DELETE FROM hypo_rechecks WHERE profile_id = ?",
+      profile_id.0
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(res.rows_affected() > 0)
+  }
+
+  /// Time of recheck prompt which is not sent yet
+  pub async fn fetch_prompt_at(
+    &self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<Option<DateTime<Utc>>> {
+    sqlx::query!(
+      r#"
+        SELECT prompt_at FROM hypo_rechecks
+        WHERE profile_id = ? AND prompted = FALSE
+      "#,
+      profile_id.0
+    )
+    .map(|rec| rec.prompt_at.and_utc())
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Rechecks which prompt is not sent yet but should be at `now`
+  pub async fn fetch_due(
+    &self,
+    now: DateTime<Utc>,
+  ) -> sqlx::Result<Vec<ProfileId>> {
+    sqlx::query!(
+      r#"
+        SELECT profile_id FROM hypo_rechecks
+        WHERE prompted = FALSE AND prompt_at <= ?
+      "#,
+      now
+    )
+    .map(|rec| ProfileId(rec.profile_id))
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Records that prompt was sent at `now`
+  pub async fn mark_prompted(
+    &mut self,
+    profile_id: ProfileId,
+    now: DateTime<Utc>,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      r#"
+        UPDATE hypo_rechecks SET prompted = TRUE, prompted_at = ?
+        WHERE profile_id = ?
+      "#,
+      now,
+      profile_id.0
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  /// Rechecks which prompt was sent before `deadline` and not
+  /// answered since
+  pub async fn fetch_unanswered(
+    &self,
+    deadline: DateTime<Utc>,
+  ) -> sqlx::Result<Vec<ProfileId>> {
+    sqlx::query!(
+      r#"
+        SELECT profile_id FROM hypo_rechecks
+        WHERE prompted = TRUE AND prompted_at <= ?
+      "#,
+      deadline
+    )
+    .map(|rec| ProfileId(rec.profile_id))
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::Duration;
+
+  use teloxide::types::UserId;
+
+  use crate::{
+    app::{profile::repository::profiles, user::repository::users},
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn schedule_prompt_and_expire() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      let now = Utc::now();
+      users(&test_db).add(user).await.unwrap();
+      let profile =
+        profiles(&test_db).add(user, None).await.unwrap().id;
+      let mut rechecks = hypo_rechecks(&test_db);
+      rechecks.schedule(profile, now).await.unwrap();
+      assert_eq!(
+        rechecks.fetch_due(now).await.unwrap(),
+        vec![profile]
+      );
+      assert!(rechecks
+        .fetch_unanswered(now)
+        .await
+        .unwrap()
+        .is_empty());
+      rechecks.mark_prompted(profile, now).await.unwrap();
+      assert!(rechecks.fetch_due(now).await.unwrap().is_empty());
+      let before = now - Duration::minutes(1);
+      assert!(rechecks
+        .fetch_unanswered(before)
+        .await
+        .unwrap()
+        .is_empty());
+      assert_eq!(
+        rechecks.fetch_unanswered(now).await.unwrap(),
+        vec![profile]
+      );
+      assert!(rechecks.remove(profile).await.unwrap());
+      assert!(!rechecks.remove(profile).await.unwrap());
+    })
+    .await
+    .unwrap();
+  }
+
+  #[tokio::test]
+  async fn late_prompt_is_not_escalated_at_once() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      let now = Utc::now();
+      users(&test_db).add(user).await.unwrap();
+      let profile =
+        profiles(&test_db).add(user, None).await.unwrap().id;
+      let mut rechecks = hypo_rechecks(&test_db);
+      // Bot was down when prompt was due
+      rechecks
+        .schedule(profile, now - Duration::hours(1))
+        .await
+        .unwrap();
+      rechecks.mark_prompted(profile, now).await.unwrap();
+      let deadline = now - Duration::minutes(15);
+      assert!(rechecks
+        .fetch_unanswered(deadline)
+        .await
+        .unwrap()
+        .is_empty());
+      assert_eq!(
+        rechecks
+          .fetch_unanswered(now + Duration::minutes(15))
+          .await
+          .unwrap(),
+        vec![profile]
+      );
+    })
+    .await
+    .unwrap();
+  }
+}
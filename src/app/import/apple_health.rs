@@ -0,0 +1,243 @@
+use std::io::{BufRead, BufReader, Cursor};
+
+use chrono::{DateTime, Utc};
+use quick_xml::{
+  events::{BytesStart, Event},
+  Reader,
+};
+use zip::ZipArchive;
+
+use crate::app::{
+  insulin_injection::{Insulin, InsulinInjection, InsulinKind},
+  sugar_measurement::{SugarLevel, SugarMeasurement},
+};
+
+use super::formats::{Parsed, Source};
+
+const BLOOD_GLUCOSE: &str = "HKQuantityTypeIdentifierBloodGlucose";
+const INSULIN_DELIVERY: &str =
+  "HKQuantityTypeIdentifierInsulinDelivery";
+const INSULIN_DELIVERY_REASON: &str = "HKInsulinDeliveryReason";
+
+/// Reads glucose and insulin records from `export.zip` or
+/// `export.xml` made by Health app. `progress` is called with share
+/// of processed XML, 0..=1. `None` if data is not a Health export.
+pub fn parse(
+  data: &[u8],
+  progress: impl FnMut(f64),
+) -> Option<Parsed> {
+  if data.starts_with(b"PK\x03\x04") {
+    let mut archive = ZipArchive::new(Cursor::new(data)).ok()?;
+    let name = archive
+      .file_names()
+      .find(|name| {
+        *name == "export.xml" || name.ends_with("/export.xml")
+      })?
+      .to_string();
+    let file = archive.by_name(&name).ok()?;
+    let size = file.size();
+    parse_xml(BufReader::new(file), size, progress)
+  } else {
+    parse_xml(data, data.len() as u64, progress)
+  }
+}
+
+fn parse_xml<R: BufRead>(
+  reader: R,
+  size: u64,
+  mut progress: impl FnMut(f64),
+) -> Option<Parsed> {
+  let mut reader = Reader::from_reader(reader);
+  let mut parsed = Parsed {
+    source: Source::AppleHealth,
+    measurements: Vec::new(),
+    injections: Vec::new(),
+    meals: Vec::new(),
+    skipped: 0,
+  };
+  let mut is_health_data = false;
+  // Insulin record waiting for its metadata
+  let mut insulin: Option<(DateTime<Utc>, f64)> = None;
+  let mut kind = None;
+  let mut buf = Vec::new();
+  loop {
+    let event = reader.read_event_into(&mut buf).ok()?;
+    match &event {
+      Event::Start(e) | Event::Empty(e) => match e.name().as_ref() {
+        b"HealthData" => is_health_data = true,
+        b"Record" => {
+          let is_empty = matches!(event, Event::Empty(_));
+          match record(e) {
+            Some(Record::Sugar(measurement)) => {
+              parsed.measurements.push(measurement);
+            }
+            Some(Record::Insulin(date_time, units)) if is_empty => {
+              parsed
+                .injections
+                .push(injection(date_time, units, None));
+            }
+            Some(Record::Insulin(date_time, units)) => {
+              insulin = Some((date_time, units));
+              kind = None;
+            }
+            Some(Record::Other) => {}
+            None => parsed.skipped += 1,
+          }
+        }
+        b"MetadataEntry" if insulin.is_some() => {
+          let key = attribute(e, b"key");
+          if key.as_deref() == Some(INSULIN_DELIVERY_REASON) {
+            kind = match attribute(e, b"value").as_deref() {
+              Some("1") => Some(InsulinKind::Basal),
+              Some("2") => Some(InsulinKind::Bolus),
+              _ => None,
+            };
+          }
+        }
+        _ => {}
+      },
+      Event::End(e) if e.name().as_ref() == b"Record" => {
+        if let Some((date_time, units)) = insulin.take() {
+          parsed.injections.push(injection(date_time, units, kind));
+        }
+      }
+      Event::Eof => break,
+      _ => {}
+    }
+    buf.clear();
+    #[allow(clippy::cast_precision_loss)]
+    progress(reader.buffer_position() as f64 / size.max(1) as f64);
+  }
+  progress(1.0);
+  is_health_data.then_some(parsed)
+}
+
+enum Record {
+  Sugar(SugarMeasurement),
+  Insulin(DateTime<Utc>, f64),
+  /// Record of other type
+  Other,
+}
+
+/// `None` if record of imported type is unreadable
+fn record(e: &BytesStart) -> Option<Record> {
+  let kind = attribute(e, b"type")?;
+  if kind != BLOOD_GLUCOSE && kind != INSULIN_DELIVERY {
+    return Some(Record::Other);
+  }
+  let date_time = DateTime::parse_from_str(
+    &attribute(e, b"startDate")?,
+    "%Y-%m-%d %H:%M:%S %z",
+  )
+  .ok()?
+  .to_utc();
+  let value: f64 = attribute(e, b"value")?.parse().ok()?;
+  if value <= 0.0 {
+    return None;
+  }
+  let unit = attribute(e, b"unit")?;
+  if kind == INSULIN_DELIVERY {
+    return (unit == "IU")
+      .then_some(Record::Insulin(date_time, value));
+  }
+  let level = if unit == "mg/dL" {
+    SugarLevel::from_milligrams_per_deciliter(value)
+  } else if unit.starts_with("mmol") {
+    SugarLevel::from_millimoles_per_liter(value)
+  } else {
+    return None;
+  };
+  Some(Record::Sugar(SugarMeasurement { date_time, level }))
+}
+
+fn injection(
+  date_time: DateTime<Utc>,
+  units: f64,
+  kind: Option<InsulinKind>,
+) -> InsulinInjection {
+  InsulinInjection {
+    date_time,
+    volume: Insulin::from_cubic_centimeters(units),
+    kind,
+  }
+}
+
+fn attribute(e: &BytesStart, key: &[u8]) -> Option<String> {
+  let attribute = e.try_get_attribute(key).ok()??;
+  Some(attribute.unescape_value().ok()?.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+
+  use zip::{write::SimpleFileOptions, ZipWriter};
+
+  use super::*;
+
+  const FIXTURE: &str = "src/app/import/testdata/export.xml";
+
+  fn at(s: &str) -> DateTime<Utc> {
+    s.parse().unwrap()
+  }
+
+  fn check(parsed: &Parsed) {
+    let levels: Vec<_> = parsed
+      .measurements
+      .iter()
+      .map(|m| {
+        let level = m.level.as_millimoles_per_liter();
+        (m.date_time, (level * 10.0).round() / 10.0)
+      })
+      .collect();
+    assert_eq!(
+      levels,
+      [
+        (at("2024-03-01T05:10:00Z"), 6.4),
+        (at("2024-03-01T18:30:00Z"), 3.6),
+      ]
+    );
+    let injections: Vec<_> = parsed
+      .injections
+      .iter()
+      .map(|i| (i.date_time, i.volume.as_cubic_centimeters(), i.kind))
+      .collect();
+    assert_eq!(
+      injections,
+      [
+        (at("2024-03-01T05:15:00Z"), 4.0, Some(InsulinKind::Bolus)),
+        (at("2024-03-01T19:00:00Z"), 12.0, Some(InsulinKind::Basal)),
+        (at("2024-03-01T20:00:00Z"), 2.0, None),
+      ]
+    );
+    assert_eq!(parsed.skipped, 1);
+  }
+
+  #[test]
+  fn parses_xml() {
+    let data = std::fs::read(FIXTURE).unwrap();
+    let mut last = 0.0;
+    let parsed = parse(&data, |share| last = share).unwrap();
+    check(&parsed);
+    assert_eq!(last, 1.0);
+  }
+
+  #[test]
+  fn parses_zip() {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip
+      .start_file(
+        "apple_health_export/export.xml",
+        SimpleFileOptions::default(),
+      )
+      .unwrap();
+    zip.write_all(&std::fs::read(FIXTURE).unwrap()).unwrap();
+    let data = zip.finish().unwrap().into_inner();
+    check(&parse(&data, |_| {}).unwrap());
+  }
+
+  #[test]
+  fn rejects_other_xml() {
+    assert_eq!(parse(b"<?xml version=\"1.0\"?><a/>", |_| {}), None);
+  }
+}
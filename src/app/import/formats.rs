@@ -0,0 +1,520 @@
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+
+use crate::app::{
+  insulin_injection::{Insulin, InsulinInjection, InsulinKind},
+  meal::Meal,
+  sugar_measurement::{SugarLevel, SugarMeasurement},
+};
+
+/// Lines searched for the header row
+const HEADER_LINES: usize = 5;
+
+/// Application the logbook was exported from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+  LibreView,
+  MySugr,
+  XDrip,
+  Clarity,
+  AppleHealth,
+}
+
+impl Source {
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::LibreView => "LibreView",
+      Self::MySugr => "mySugr",
+      Self::XDrip => "xDrip+",
+      Self::Clarity => "Dexcom Clarity",
+      Self::AppleHealth => "Apple Health",
+    }
+  }
+
+  fn detect(header: &str) -> Option<Self> {
+    if header.contains("Device Timestamp") {
+      Some(Self::LibreView)
+    } else if header.contains("Blood Sugar Measurement") {
+      Some(Self::MySugr)
+    } else if header.starts_with("DAY;TIME") {
+      Some(Self::XDrip)
+    } else if header.contains("Timestamp (YYYY-MM-DDThh:mm:ss)") {
+      Some(Self::Clarity)
+    } else {
+      None
+    }
+  }
+
+  fn delimiter(self) -> u8 {
+    match self {
+      Self::XDrip => b';',
+      _ => b',',
+    }
+  }
+}
+
+/// Records read from an exported logbook
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parsed {
+  pub source: Source,
+  pub measurements: Vec<SugarMeasurement>,
+  pub injections: Vec<InsulinInjection>,
+  pub meals: Vec<Meal>,
+  /// Rows with unreadable time or values
+  pub skipped: usize,
+}
+
+impl Parsed {
+  /// Earliest and latest time of records
+  pub fn date_range(
+    &self,
+  ) -> Option<(chrono::DateTime<Utc>, chrono::DateTime<Utc>)> {
+    let times = self
+      .measurements
+      .iter()
+      .map(|m| m.date_time)
+      .chain(self.injections.iter().map(|i| i.date_time))
+      .chain(self.meals.iter().map(|m| m.date_time));
+    times.fold(None, |range, dt| match range {
+      None => Some((dt, dt)),
+      Some((start, end)) => Some((start.min(dt), end.max(dt))),
+    })
+  }
+}
+
+/// Detects format of CSV export and reads its records. Times in
+/// the file are taken in `tz`. `None` if format is unknown.
+pub fn parse<Tz: TimeZone>(data: &[u8], tz: &Tz) -> Option<Parsed> {
+  let text = String::from_utf8_lossy(data);
+  let text = text.trim_start_matches('\u{feff}');
+  let (offset, source) = text
+    .split_inclusive('\n')
+    .take(HEADER_LINES)
+    .scan(0, |offset, line| {
+      let start = *offset;
+      *offset += line.len();
+      Some((start, line))
+    })
+    .find_map(|(start, line)| Some((start, Source::detect(line)?)))?;
+  let mut reader = csv::ReaderBuilder::new()
+    .delimiter(source.delimiter())
+    .flexible(true)
+    .from_reader(&text.as_bytes()[offset..]);
+  let columns = Columns(reader.headers().ok()?.clone());
+  let mut parsed = Parsed {
+    source,
+    measurements: Vec::new(),
+    injections: Vec::new(),
+    meals: Vec::new(),
+    skipped: 0,
+  };
+  let local = |time: NaiveDateTime| {
+    Some(tz.from_local_datetime(&time).earliest()?.to_utc())
+  };
+  for row in reader.records() {
+    let Ok(row) = row else {
+      parsed.skipped += 1;
+      continue;
+    };
+    let row = Row {
+      columns: &columns,
+      row: &row,
+    };
+    let records = match source {
+      Source::LibreView => libre_view(&row),
+      Source::MySugr => my_sugr(&row),
+      Source::XDrip => x_drip(&row),
+      Source::Clarity => clarity(&row),
+      Source::AppleHealth => unreachable!("Not detected in CSV"),
+    };
+    match records {
+      Some((None, _)) => {}
+      Some((Some(time), records)) => match local(time) {
+        Some(date_time) => parsed.push(date_time, records),
+        None => parsed.skipped += 1,
+      },
+      None => parsed.skipped += 1,
+    }
+  }
+  Some(parsed)
+}
+
+impl Parsed {
+  fn push(
+    &mut self,
+    date_time: chrono::DateTime<Utc>,
+    records: Vec<Record>,
+  ) {
+    for record in records {
+      match record {
+        Record::Sugar(level) => {
+          self
+            .measurements
+            .push(SugarMeasurement { date_time, level });
+        }
+        Record::Insulin(units, kind) => {
+          self.injections.push(InsulinInjection {
+            date_time,
+            volume: Insulin::from_cubic_centimeters(units),
+            kind,
+          });
+        }
+        Record::Meal(carbs_grams, note) => self.meals.push(Meal {
+          date_time,
+          carbs_grams,
+          note,
+        }),
+      }
+    }
+  }
+}
+
+enum Record {
+  Sugar(SugarLevel),
+  Insulin(f64, Option<InsulinKind>),
+  Meal(f64, Option<String>),
+}
+
+/// Local time of row, `None` for rows without time, and its records.
+/// `None` if row is unreadable.
+type RowRecords = Option<(Option<NaiveDateTime>, Vec<Record>)>;
+
+struct Columns(csv::StringRecord);
+
+impl Columns {
+  /// Index of first column whose title starts with `prefix`
+  fn find(&self, prefix: &str) -> Option<usize> {
+    self
+      .0
+      .iter()
+      .position(|title| title.trim().starts_with(prefix))
+  }
+
+  fn title(&self, index: usize) -> &str {
+    self.0.get(index).unwrap_or_default()
+  }
+}
+
+struct Row<'a> {
+  columns: &'a Columns,
+  row: &'a csv::StringRecord,
+}
+
+impl Row<'_> {
+  /// Trimmed value of column starting with `prefix`, `None` if empty
+  fn get(&self, prefix: &str) -> Option<&str> {
+    let value = self.row.get(self.columns.find(prefix)?)?.trim();
+    (!value.is_empty()).then_some(value)
+  }
+
+  /// Number in column, `Some(None)` if empty, `None` if unreadable
+  fn number(&self, prefix: &str) -> Option<Option<f64>> {
+    let Some(value) = self.get(prefix) else {
+      return Some(None);
+    };
+    let number: f64 = value.replace(',', ".").parse().ok()?;
+    Some((number > 0.0).then_some(number))
+  }
+
+  /// Sugar level in column with units in its title
+  fn level(&self, prefix: &str) -> Option<Option<SugarLevel>> {
+    let index = self.columns.find(prefix);
+    let title = index.map_or("", |i| self.columns.title(i));
+    Some(self.number(prefix)?.map(|value| {
+      if title.contains("mg/dL") {
+        SugarLevel::from_milligrams_per_deciliter(value)
+      } else {
+        SugarLevel::from_millimoles_per_liter(value)
+      }
+    }))
+  }
+}
+
+fn date_time(s: &str, formats: &[&str]) -> Option<NaiveDateTime> {
+  formats
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+}
+
+fn date_and_time(
+  date: &str,
+  time: &str,
+  date_formats: &[&str],
+  time_formats: &[&str],
+) -> Option<NaiveDateTime> {
+  let date = date_formats.iter().find_map(|format| {
+    NaiveDate::parse_from_str(date, format).ok()
+  })?;
+  let time = time_formats.iter().find_map(|format| {
+    NaiveTime::parse_from_str(time, format).ok()
+  })?;
+  Some(date.and_time(time))
+}
+
+fn note(s: Option<&str>) -> Option<String> {
+  s.map(String::from)
+}
+
+/// FreeStyle Libre export: glucose of one kind per row
+fn libre_view(row: &Row) -> RowRecords {
+  let Some(time) = row.get("Device Timestamp") else {
+    return Some((None, Vec::new()));
+  };
+  let time = date_time(
+    time,
+    &[
+      "%d-%m-%Y %H:%M",
+      "%m-%d-%Y %I:%M %p",
+      "%Y-%m-%d %H:%M",
+      "%d.%m.%Y %H:%M",
+    ],
+  )?;
+  let mut records = Vec::new();
+  let level = row
+    .level("Historic Glucose")?
+    .or(row.level("Scan Glucose")?)
+    .or(row.level("Strip Glucose")?);
+  records.extend(level.map(Record::Sugar));
+  if let Some(units) = row.number("Rapid-Acting Insulin (units)")? {
+    records.push(Record::Insulin(units, Some(InsulinKind::Bolus)));
+  }
+  if let Some(units) = row.number("Long-Acting Insulin (units)")? {
+    records.push(Record::Insulin(units, Some(InsulinKind::Basal)));
+  }
+  if let Some(carbs) = row.number("Carbohydrates (grams)")? {
+    records.push(Record::Meal(carbs, note(row.get("Notes"))));
+  }
+  Some((Some(time), records))
+}
+
+/// mySugr export: pen bolus and basal in separate columns
+fn my_sugr(row: &Row) -> RowRecords {
+  let (Some(date), Some(time)) = (row.get("Date"), row.get("Time"))
+  else {
+    return Some((None, Vec::new()));
+  };
+  let time = date_and_time(
+    date,
+    time,
+    &["%b %d, %Y", "%d.%m.%Y", "%Y-%m-%d"],
+    &["%H:%M:%S", "%I:%M:%S %p", "%H:%M"],
+  )?;
+  let mut records = Vec::new();
+  records
+    .extend(row.level("Blood Sugar Measurement")?.map(Record::Sugar));
+  if let Some(units) = row.number("Insulin Injection Units (Pen)")? {
+    records.push(Record::Insulin(units, Some(InsulinKind::Bolus)));
+  }
+  if let Some(units) = row.number("Basal Injection Units")? {
+    records.push(Record::Insulin(units, Some(InsulinKind::Basal)));
+  }
+  let description = row.get("Meal Descriptions").or(row.get("Note"));
+  if let Some(carbs) = row.number("Meal Carbohydrates")? {
+    records.push(Record::Meal(carbs, note(description)));
+  }
+  Some((Some(time), records))
+}
+
+/// xDrip+ export for SiDiary: levels in mg/dL
+fn x_drip(row: &Row) -> RowRecords {
+  let (Some(date), Some(time)) = (row.get("DAY"), row.get("TIME"))
+  else {
+    return Some((None, Vec::new()));
+  };
+  let time =
+    date_and_time(date, time, &["%d.%m.%Y"], &["%H:%M", "%H:%M:%S"])?;
+  let mut records = Vec::new();
+  let level = row.number("UDT_CGMS")?.or(row.number("BG_LEVEL")?);
+  records.extend(level.map(|x| {
+    Record::Sugar(SugarLevel::from_milligrams_per_deciliter(x))
+  }));
+  if let Some(units) = row.number("BOLUS")? {
+    records.push(Record::Insulin(units, Some(InsulinKind::Bolus)));
+  }
+  if let Some(carbs) = row.number("CH_GR")? {
+    records.push(Record::Meal(carbs, note(row.get("REMARK"))));
+  }
+  Some((Some(time), records))
+}
+
+/// Dexcom Clarity export: one event per row, patient info rows
+/// without time
+fn clarity(row: &Row) -> RowRecords {
+  let Some(time) = row.get("Timestamp") else {
+    return Some((None, Vec::new()));
+  };
+  let time = date_time(time, &["%Y-%m-%dT%H:%M:%S"])?;
+  let records = match row.get("Event Type") {
+    Some("EGV" | "Calibration") => match row.get("Glucose Value") {
+      // Out of sensor range
+      Some("Low" | "High") => Vec::new(),
+      _ => row
+        .level("Glucose Value")?
+        .map(Record::Sugar)
+        .into_iter()
+        .collect(),
+    },
+    Some("Insulin") => {
+      let kind = match row.get("Event Subtype") {
+        Some("Long-Acting") => Some(InsulinKind::Basal),
+        Some("Fast-Acting") => Some(InsulinKind::Bolus),
+        _ => None,
+      };
+      row
+        .number("Insulin Value")?
+        .map(|units| Record::Insulin(units, kind))
+        .into_iter()
+        .collect()
+    }
+    Some("Carbs") => row
+      .number("Carb Value")?
+      .map(|carbs| Record::Meal(carbs, None))
+      .into_iter()
+      .collect(),
+    _ => Vec::new(),
+  };
+  Some((Some(time), records))
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{DateTime, FixedOffset};
+
+  use super::*;
+
+  fn parse_fixture(name: &str) -> Parsed {
+    let data =
+      std::fs::read(format!("src/app/import/testdata/{name}"))
+        .unwrap();
+    let tz = FixedOffset::east_opt(3 * 3600).unwrap();
+    parse(&data, &tz).unwrap()
+  }
+
+  fn at(s: &str) -> DateTime<Utc> {
+    s.parse().unwrap()
+  }
+
+  fn levels(parsed: &Parsed) -> Vec<(DateTime<Utc>, f64)> {
+    parsed
+      .measurements
+      .iter()
+      .map(|m| {
+        let level = m.level.as_millimoles_per_liter();
+        (m.date_time, (level * 10.0).round() / 10.0)
+      })
+      .collect()
+  }
+
+  fn injections(
+    parsed: &Parsed,
+  ) -> Vec<(DateTime<Utc>, f64, Option<InsulinKind>)> {
+    parsed
+      .injections
+      .iter()
+      .map(|i| (i.date_time, i.volume.as_cubic_centimeters(), i.kind))
+      .collect()
+  }
+
+  #[test]
+  fn parses_libre_view() {
+    let parsed = parse_fixture("libreview.csv");
+    assert_eq!(parsed.source, Source::LibreView);
+    assert_eq!(
+      levels(&parsed),
+      [
+        (at("2024-03-01T05:10:00Z"), 6.4),
+        (at("2024-03-01T05:25:00Z"), 7.2),
+        (at("2024-03-01T05:40:00Z"), 8.1),
+      ]
+    );
+    assert_eq!(
+      injections(&parsed),
+      [
+        (at("2024-03-01T05:15:00Z"), 4.0, Some(InsulinKind::Bolus)),
+        (at("2024-03-01T19:00:00Z"), 12.0, Some(InsulinKind::Basal)),
+      ]
+    );
+    assert_eq!(
+      parsed.meals,
+      [Meal {
+        date_time: at("2024-03-01T05:20:00Z"),
+        carbs_grams: 45.0,
+        note: Some("овсянка".into()),
+      }]
+    );
+    assert_eq!(parsed.skipped, 1);
+    assert_eq!(
+      parsed.date_range(),
+      Some((at("2024-03-01T05:10:00Z"), at("2024-03-01T19:00:00Z")))
+    );
+  }
+
+  #[test]
+  fn parses_my_sugr() {
+    let parsed = parse_fixture("mysugr.csv");
+    assert_eq!(parsed.source, Source::MySugr);
+    assert_eq!(
+      levels(&parsed),
+      [
+        (at("2024-03-01T05:10:00Z"), 6.4),
+        (at("2024-03-01T18:30:00Z"), 3.6),
+      ]
+    );
+    assert_eq!(
+      injections(&parsed),
+      [
+        (at("2024-03-01T05:15:00Z"), 4.0, Some(InsulinKind::Bolus)),
+        (at("2024-03-01T19:00:00Z"), 12.0, Some(InsulinKind::Basal)),
+      ]
+    );
+    assert_eq!(parsed.meals.len(), 1);
+    assert_eq!(parsed.meals[0].note.as_deref(), Some("Oatmeal"));
+    assert_eq!(parsed.skipped, 0);
+  }
+
+  #[test]
+  fn parses_x_drip() {
+    let parsed = parse_fixture("xdrip.csv");
+    assert_eq!(parsed.source, Source::XDrip);
+    assert_eq!(
+      levels(&parsed),
+      [
+        (at("2024-03-01T05:10:00Z"), 6.4),
+        (at("2024-03-01T05:15:00Z"), 6.9),
+        (at("2024-03-01T05:20:00Z"), 7.0),
+      ]
+    );
+    assert_eq!(
+      injections(&parsed),
+      [(at("2024-03-01T05:15:00Z"), 4.0, Some(InsulinKind::Bolus))]
+    );
+    assert_eq!(parsed.meals[0].carbs_grams, 45.0);
+  }
+
+  #[test]
+  fn parses_clarity() {
+    let parsed = parse_fixture("clarity.csv");
+    assert_eq!(parsed.source, Source::Clarity);
+    assert_eq!(
+      levels(&parsed),
+      [
+        (at("2024-03-01T05:10:00Z"), 6.4),
+        (at("2024-03-01T05:15:00Z"), 6.6),
+      ]
+    );
+    assert_eq!(
+      injections(&parsed),
+      [
+        (at("2024-03-01T05:15:00Z"), 4.0, Some(InsulinKind::Bolus)),
+        (at("2024-03-01T19:00:00Z"), 12.0, Some(InsulinKind::Basal)),
+      ]
+    );
+    assert_eq!(parsed.meals[0].carbs_grams, 45.0);
+    assert_eq!(parsed.skipped, 0);
+  }
+
+  #[test]
+  fn rejects_unknown_format() {
+    let tz = FixedOffset::east_opt(0).unwrap();
+    assert_eq!(parse(b"date,value\n2024-03-01,5.5\n", &tz), None);
+  }
+}
@@ -0,0 +1,340 @@
+pub mod apple_health;
+pub mod formats;
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::Local;
+use teloxide::{
+  dispatching::dialogue::InMemStorage,
+  dptree::case,
+  net::Download,
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+use tokio::sync::watch;
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::{txn, Db},
+  utils::{filter_callback_query, filter_message},
+};
+
+use self::formats::Parsed;
+
+use super::{
+  insulin_injection::repository::insulin_injections,
+  meal::repository::meals,
+  profile::{current_profile, ProfileId},
+  sugar_measurement::repository::sugar_measurements,
+  UpdateHandler,
+};
+
+const CALLBACK_PREFIX: &str = "import:";
+/// Largest file bots can download, bytes
+const MAX_FILE_SIZE: u32 = 20 * 1024 * 1024;
+/// Minimal interval between progress message edits
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(3);
+
+type Dialog = Dialogue<State, InMemStorage<State>>;
+
+#[derive(Default, Clone)]
+enum State {
+  #[default]
+  Ignoring,
+  AwaitingFile,
+  Confirming(ProfileId, Arc<Parsed>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decision {
+  Import,
+  Cancel,
+}
+
+impl Decision {
+  fn parse(data: &str) -> Option<Self> {
+    match data.strip_prefix(CALLBACK_PREFIX)? {
+      "import" => Some(Self::Import),
+      "cancel" => Some(Self::Cancel),
+      _ => None,
+    }
+  }
+
+  fn button(self) -> InlineKeyboardButton {
+    let (label, data) = match self {
+      Self::Import => ("Импортировать", "import"),
+      Self::Cancel => ("Отмена", "cancel"),
+    };
+    InlineKeyboardButton::callback(
+      label,
+      format!("{CALLBACK_PREFIX}{data}"),
+    )
+  }
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn prepare(&self, di: &mut DependencyMap) {
+    di.insert(InMemStorage::<State>::new());
+  }
+
+  fn update_handler(&self) -> UpdateHandler {
+    dptree::entry()
+      .branch(
+        filter_message()
+          .enter_dialogue::<Message, InMemStorage<State>, State>()
+          .branch(
+            dptree::entry()
+              .filter_command::<MenuCommand>()
+              .branch(case![MenuCommand::Import].endpoint(ask)),
+          )
+          .branch(case![State::AwaitingFile].endpoint(preview)),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            Decision::parse(query.data.as_deref()?)
+          })
+          .endpoint(decide),
+      )
+  }
+}
+
+async fn ask(
+  bot: Bot,
+  chat_id: ChatId,
+  dialogue: Dialog,
+) -> Result<()> {
+  bot
+    .send_message(
+      chat_id,
+      "Отправьте CSV-файл, выгруженный из LibreView, mySugr, xDrip+ \
+      или Dexcom Clarity, либо export.zip из приложения «Здоровье». \
+      Время в CSV-файле считается местным",
+    )
+    .await?;
+  dialogue.update(State::AwaitingFile).await.map_err(any)?;
+  Ok(())
+}
+
+async fn preview(
+  bot: Bot,
+  msg: Message,
+  user_id: UserId,
+  db: Arc<Db>,
+  dialogue: Dialog,
+) -> Result<()> {
+  let Some(document) = msg.document() else {
+    bot
+      .send_message(msg.chat.id, "Импорт отменен: ожидался файл")
+      .await?;
+    dialogue.reset().await.map_err(any)?;
+    return Ok(());
+  };
+  if document.file.size > MAX_FILE_SIZE {
+    bot
+      .send_message(msg.chat.id, "Файл больше 20 МБ, импорт отменен")
+      .await?;
+    dialogue.reset().await.map_err(any)?;
+    return Ok(());
+  }
+  let file = bot.get_file(&document.file.id).await?;
+  let mut data = Vec::new();
+  bot
+    .download_file(&file.path, &mut data)
+    .await
+    .map_err(any)?;
+  let is_health_export =
+    document.file_name.as_deref().is_some_and(|name| {
+      name.ends_with(".zip") || name.ends_with(".xml")
+    });
+  let parsed = if is_health_export {
+    parse_health_export(&bot, msg.chat.id, data).await?
+  } else {
+    tokio::task::spawn_blocking(move || formats::parse(&data, &Local))
+      .await
+      .map_err(any)?
+  };
+  let Some(parsed) = parsed else {
+    bot
+      .send_message(
+        msg.chat.id,
+        "Не удалось распознать формат. Поддерживаются выгрузки \
+        LibreView, mySugr, xDrip+, Dexcom Clarity и Apple Health",
+      )
+      .await?;
+    dialogue.reset().await.map_err(any)?;
+    return Ok(());
+  };
+  let Some((start, end)) = parsed.date_range() else {
+    bot
+      .send_message(msg.chat.id, "В файле нет записей для импорта")
+      .await?;
+    dialogue.reset().await.map_err(any)?;
+    return Ok(());
+  };
+  let profile = current_profile(&db, user_id).await?;
+  let date = |dt: chrono::DateTime<chrono::Utc>| {
+    dt.with_timezone(&Local).format("%d.%m.%Y").to_string()
+  };
+  let mut text = format!(
+    "Формат: {}{}\n\
+    Период: {} – {}\n\
+    Измерений сахара: {}\n\
+    Инъекций инсулина: {}\n\
+    Приемов пищи: {}",
+    parsed.source.name(),
+    profile.tag(),
+    date(start),
+    date(end),
+    parsed.measurements.len(),
+    parsed.injections.len(),
+    parsed.meals.len(),
+  );
+  if parsed.skipped > 0 {
+    text += &format!("\nНераспознанных строк: {}", parsed.skipped);
+  }
+  text += "\n\nЗаписи на время, которое уже есть в дневнике, \
+    будут пропущены.";
+  let keyboard = InlineKeyboardMarkup::new([[
+    Decision::Import.button(),
+    Decision::Cancel.button(),
+  ]]);
+  bot
+    .send_message(msg.chat.id, text)
+    .reply_markup(keyboard)
+    .await?;
+  dialogue
+    .update(State::Confirming(profile.id, Arc::new(parsed)))
+    .await
+    .map_err(any)?;
+  Ok(())
+}
+
+/// Parses Health app export in background, reporting progress by
+/// editing status message
+async fn parse_health_export(
+  bot: &Bot,
+  chat_id: ChatId,
+  data: Vec<u8>,
+) -> Result<Option<Parsed>> {
+  let (tx, mut rx) = watch::channel(0);
+  let task = tokio::task::spawn_blocking(move || {
+    apple_health::parse(&data, |share| {
+      #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+      )]
+      let percent = (share * 100.0) as u8;
+      tx.send_if_modified(|current| {
+        std::mem::replace(current, percent) != percent
+      });
+    })
+  });
+  let status =
+    |percent| format!("Обработка выгрузки Apple Health: {percent} %");
+  let status_msg = bot.send_message(chat_id, status(0)).await?;
+  let mut shown = 0;
+  while rx.changed().await.is_ok() {
+    let percent = *rx.borrow_and_update();
+    if percent != shown {
+      shown = percent;
+      bot
+        .edit_message_text(chat_id, status_msg.id, status(percent))
+        .await?;
+    }
+    tokio::time::sleep(PROGRESS_INTERVAL).await;
+  }
+  let parsed = task.await.map_err(any)?;
+  bot
+    .edit_message_text(
+      chat_id,
+      status_msg.id,
+      "Обработка выгрузки Apple Health завершена",
+    )
+    .await?;
+  Ok(parsed)
+}
+
+async fn decide(
+  bot: Bot,
+  db: Arc<Db>,
+  decision: Decision,
+  query: CallbackQuery,
+  storage: Arc<InMemStorage<State>>,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  let dialogue = Dialog::new(storage, msg.chat.id);
+  let state = dialogue.get().await.map_err(any)?;
+  dialogue.reset().await.map_err(any)?;
+  let Some(State::Confirming(profile_id, parsed)) = state else {
+    bot
+      .edit_message_text(msg.chat.id, msg.id, "Импорт уже завершен")
+      .await?;
+    return Ok(());
+  };
+  let text = match decision {
+    Decision::Import => {
+      let added =
+        txn::begin(db.pool(), import(&db, profile_id, &parsed))
+          .await??;
+      let total = parsed.measurements.len()
+        + parsed.injections.len()
+        + parsed.meals.len();
+      format!(
+        "Импортировано: сахар — {}, инсулин — {}, еда — {}. \
+        Пропущено совпадающих записей: {}",
+        added.measurements,
+        added.injections,
+        added.meals,
+        total - added.measurements - added.injections - added.meals
+      )
+    }
+    Decision::Cancel => "Импорт отменен".into(),
+  };
+  bot.edit_message_text(msg.chat.id, msg.id, text).await?;
+  Ok(())
+}
+
+/// Numbers of records added by import
+#[derive(Default)]
+struct Added {
+  measurements: usize,
+  injections: usize,
+  meals: usize,
+}
+
+/// Adds records in shared transaction and commits it
+async fn import(
+  db: &Db,
+  profile_id: ProfileId,
+  parsed: &Parsed,
+) -> Result<Added> {
+  let mut added = Added::default();
+  let mut repository = sugar_measurements(db, profile_id);
+  for &measurement in &parsed.measurements {
+    if repository.add_if_absent(measurement).await? {
+      added.measurements += 1;
+    }
+  }
+  let mut repository = insulin_injections(db, profile_id);
+  for &injection in &parsed.injections {
+    if repository.add_if_absent(injection).await? {
+      added.injections += 1;
+    }
+  }
+  let mut repository = meals(db, profile_id);
+  for meal in &parsed.meals {
+    if repository.add_if_absent(meal).await? {
+      added.meals += 1;
+    }
+  }
+  txn::commit().await?;
+  Ok(added)
+}
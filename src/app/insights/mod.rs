@@ -0,0 +1,153 @@
+pub mod patterns;
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Days, Utc};
+use chrono_tz::Tz;
+use clokwerk::{AsyncScheduler, Interval, Job};
+use teloxide::{dptree::case, prelude::*, ApiError, RequestError};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::Result,
+  db::Db,
+  event_handler::{filter_event, handler, EventHandler},
+  utils::{event_publisher::EventPublisher, filter_message},
+};
+
+use self::patterns::{find_patterns, Finding};
+
+use super::{
+  insulin_injection::repository::insulin_injections,
+  preferences::repository::preferences,
+  profile::{current_profile, repository::profiles, Profile},
+  quiet_hours::repository::quiet_hours,
+  sugar_measurement::repository::sugar_measurements,
+  user::repository::users,
+  UpdateHandler,
+};
+
+/// Days of history searched for patterns
+const DAYS: u64 = 14;
+
+#[derive(Debug, Clone)]
+struct InsightsTick;
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    filter_message()
+      .filter_command::<MenuCommand>()
+      .branch(case![MenuCommand::Insights].endpoint(send_insights))
+  }
+
+  fn event_handler(&self) -> EventHandler {
+    filter_event::<InsightsTick>().chain(handler(send_weekly))
+  }
+
+  fn schedule(
+    &self,
+    scheduler: &mut AsyncScheduler,
+    event_publisher: Arc<EventPublisher>,
+  ) {
+    scheduler.every(Interval::Monday).at("10:00").run(move || {
+      event_publisher.send(InsightsTick);
+      async {}
+    });
+  }
+}
+
+async fn send_insights(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+) -> Result<()> {
+  let profile = current_profile(&db, user_id).await?;
+  let tz = preferences(&db)
+    .fetch(profile.id)
+    .await?
+    .unwrap_or_default()
+    .timezone;
+  let findings = find(&db, &profile, Utc::now(), tz).await?;
+  let text = render(&findings, &profile.tag()).unwrap_or_else(|| {
+    format!(
+      "Повторяющихся закономерностей за {DAYS} дней{} не найдено. \
+      Чем регулярнее измерения утром, днем и ночью, тем точнее \
+      анализ",
+      profile.tag()
+    )
+  });
+  bot.send_message(chat_id, text).await?;
+  Ok(())
+}
+
+/// Sends findings to everyone who has them. Users in quiet hours
+/// get them next week.
+async fn send_weekly(bot: Bot, db: Arc<Db>) -> Result<()> {
+  let now = Utc::now();
+  let since = now - Days::new(DAYS);
+  for profile in profiles(&db).fetch_measured_since(since).await? {
+    let tz = preferences(&db)
+      .fetch(profile.id)
+      .await?
+      .unwrap_or_default()
+      .timezone;
+    let quiet = quiet_hours(&db).fetch(profile.user_id).await?;
+    let local_time = now.with_timezone(&tz).time();
+    if quiet.is_some_and(|quiet| quiet.contains(local_time)) {
+      continue;
+    }
+    let findings = find(&db, &profile, now, tz).await?;
+    let Some(text) = render(&findings, &profile.tag()) else {
+      continue;
+    };
+    match bot.send_message(profile.user_id, text).await {
+      Ok(_) => {}
+      Err(RequestError::Api(ApiError::BotBlocked)) => {
+        users(&db).disable(profile.user_id).await?;
+      }
+      Err(err) => log::error!(
+        "Insights of {} aren't sent: {err}",
+        profile.user_id
+      ),
+    }
+  }
+  Ok(())
+}
+
+/// Patterns in history of profile until `now`, parts of day are in
+/// `tz`
+async fn find(
+  db: &Db,
+  profile: &Profile,
+  now: DateTime<Utc>,
+  tz: Tz,
+) -> Result<Vec<Finding>> {
+  let start = now - Days::new(DAYS);
+  let readings = sugar_measurements(db, profile.id)
+    .fetch_between(start, now)
+    .await?;
+  let injections = insulin_injections(db, profile.id)
+    .fetch_between(start, now)
+    .await?;
+  Ok(find_patterns(&readings, &injections, &tz))
+}
+
+/// `None` if nothing is found
+fn render(findings: &[Finding], tag: &str) -> Option<String> {
+  if findings.is_empty() {
+    return None;
+  }
+  let findings = findings
+    .iter()
+    .map(Finding::explain)
+    .collect::<Vec<_>>()
+    .join("\n\n");
+  Some(format!(
+    "🔎 Закономерности за {DAYS} дней{tag}\n\n{findings}\n\n\
+    Это подсказки для разговора с врачом, а не назначение"
+  ))
+}
@@ -0,0 +1,474 @@
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  ops::Range,
+};
+
+use chrono::{Days, NaiveDate, TimeZone, Timelike};
+
+use crate::app::{
+  insulin_injection::{InsulinInjection, InsulinKind},
+  stats::statistics::hypo_episodes,
+  sugar_measurement::{SugarMeasurement, HIGH_LEVEL},
+};
+
+/// Fewer days with a finding aren't a pattern
+const MIN_DAYS: usize = 3;
+/// Minimum share of observed days with a finding
+const MIN_SHARE: f64 = 0.5;
+/// Fasting readings, local hours
+const MORNING: Range<u32> = 5..9;
+/// Readings before dawn rise, local hours
+const NIGHT: Range<u32> = 0..4;
+/// Night hypos start within, local hours
+const HYPO_NIGHT: Range<u32> = 0..6;
+/// Readings after lunch, local hours
+const AFTERNOON: Range<u32> = 13..17;
+/// Minimum night hypos making a pattern
+const MIN_NIGHT_HYPOS: usize = 2;
+/// Minimum days after missed basal making a pattern
+const MIN_MISSED_BASAL: usize = 2;
+/// Mean level after missed basal should exceed other days by, mmol/L
+const MISSED_BASAL_RISE: f64 = 1.5;
+
+/// Recurring pattern found in history
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Finding {
+  /// Fasting readings are high
+  MorningHighs {
+    days: usize,
+    /// Days with fasting readings
+    observed_days: usize,
+    /// Mean of high fasting readings, mmol/L
+    mean: f64,
+    /// Mean of readings before dawn, mmol/L
+    night_mean: Option<f64>,
+  },
+  NightHypos {
+    episodes: usize,
+    nights: usize,
+    /// Lowest level, mmol/L
+    min_level: f64,
+  },
+  PostLunchSpikes {
+    days: usize,
+    /// Days with readings after lunch
+    observed_days: usize,
+    /// Mean of daily peaks above range, mmol/L
+    mean_peak: f64,
+  },
+  HighsAfterMissedBasal {
+    /// Days after missed basal dose with readings
+    days: usize,
+    /// Mean level on these days, mmol/L
+    mean: f64,
+    /// Mean level on other days, mmol/L
+    usual_mean: f64,
+  },
+}
+
+impl Finding {
+  /// Plain-language explanation with supporting numbers
+  pub fn explain(&self) -> String {
+    let hours = |range: &Range<u32>| {
+      format!("{:02}:00–{:02}:00", range.start, range.end)
+    };
+    match *self {
+      Self::MorningHighs {
+        days,
+        observed_days,
+        mean,
+        night_mean,
+      } => {
+        let night = night_mean.map_or_else(String::new, |night| {
+          format!(
+            " Ночью ({}) сахар в среднем {night:.1} ммоль/л, значит \
+            он поднимается под утро — это похоже на феномен \
+            утренней зари.",
+            hours(&NIGHT)
+          )
+        });
+        format!(
+          "🌅 Высокий сахар утром: в {days} из {observed_days} дней \
+          натощак ({}) он был выше {HIGH_LEVEL:.1} ммоль/л, в \
+          среднем {mean:.1} ммоль/л.{night} Обсудите с врачом дозу и \
+          время базального инсулина.",
+          hours(&MORNING)
+        )
+      }
+      Self::NightHypos {
+        episodes,
+        nights,
+        min_level,
+      } => format!(
+        "🌙 Ночные гипогликемии ({}): эпизодов — {episodes}, ночей \
+        с гипо — {nights}, минимум {min_level:.1} ммоль/л. Ночью гипо \
+        легко не заметить; обсудите с врачом дозу вечернего инсулина и \
+        перекус перед сном.",
+        hours(&HYPO_NIGHT)
+      ),
+      Self::PostLunchSpikes {
+        days,
+        observed_days,
+        mean_peak,
+      } => format!(
+        "🍽 Подъемы после обеда: в {days} из {observed_days} дней с \
+        {} сахар поднимался выше {HIGH_LEVEL:.1} ммоль/л, в среднем \
+        до {mean_peak:.1} ммоль/л. Возможно, болюса на обед \
+        недостаточно или он введен слишком поздно.",
+        hours(&AFTERNOON)
+      ),
+      Self::HighsAfterMissedBasal {
+        days,
+        mean,
+        usual_mean,
+      } => format!(
+        "💉 Высокий сахар после пропуска базального инсулина: в дни \
+        после пропущенной дозы (их {days}) средний сахар был \
+        {mean:.1} ммоль/л, а в остальные дни {usual_mean:.1} \
+        ммоль/л. Старайтесь не пропускать базальный инсулин."
+      ),
+    }
+  }
+}
+
+/// All patterns found in readings and injections. Times of day are
+/// taken in `tz`.
+pub fn find_patterns<Tz: TimeZone>(
+  readings: &[SugarMeasurement],
+  injections: &[InsulinInjection],
+  tz: &Tz,
+) -> Vec<Finding> {
+  [
+    morning_highs(readings, tz),
+    night_hypos(readings, tz),
+    post_lunch_spikes(readings, tz),
+    highs_after_missed_basal(readings, injections, tz),
+  ]
+  .into_iter()
+  .flatten()
+  .collect()
+}
+
+/// Levels by local date of readings taken within `hours`
+fn daily_levels<Tz: TimeZone>(
+  readings: &[SugarMeasurement],
+  tz: &Tz,
+  hours: &Range<u32>,
+) -> BTreeMap<NaiveDate, Vec<f64>> {
+  let mut readings = readings.to_vec();
+  readings.sort_by_key(|m| m.date_time);
+  let mut days = BTreeMap::<_, Vec<_>>::new();
+  for m in readings {
+    let local = m.date_time.with_timezone(tz);
+    if hours.contains(&local.hour()) {
+      days
+        .entry(local.date_naive())
+        .or_default()
+        .push(m.level.as_millimoles_per_liter());
+    }
+  }
+  days
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn mean(levels: &[f64]) -> Option<f64> {
+  (!levels.is_empty())
+    .then(|| levels.iter().sum::<f64>() / levels.len() as f64)
+}
+
+/// Whether `days` out of `observed_days` make a pattern
+#[allow(clippy::cast_precision_loss)]
+fn is_recurring(days: usize, observed_days: usize) -> bool {
+  days >= MIN_DAYS && days as f64 >= MIN_SHARE * observed_days as f64
+}
+
+fn morning_highs<Tz: TimeZone>(
+  readings: &[SugarMeasurement],
+  tz: &Tz,
+) -> Option<Finding> {
+  let mornings = daily_levels(readings, tz, &MORNING);
+  let highs = mornings
+    .values()
+    .filter_map(|levels| levels.first().copied())
+    .filter(|&fasting| fasting > HIGH_LEVEL)
+    .collect::<Vec<_>>();
+  if !is_recurring(highs.len(), mornings.len()) {
+    return None;
+  }
+  let nights = daily_levels(readings, tz, &NIGHT)
+    .into_values()
+    .flatten()
+    .collect::<Vec<_>>();
+  Some(Finding::MorningHighs {
+    days: highs.len(),
+    observed_days: mornings.len(),
+    mean: mean(&highs)?,
+    night_mean: mean(&nights),
+  })
+}
+
+fn night_hypos<Tz: TimeZone>(
+  readings: &[SugarMeasurement],
+  tz: &Tz,
+) -> Option<Finding> {
+  let episodes = hypo_episodes(readings)
+    .into_iter()
+    .filter(|episode| {
+      HYPO_NIGHT.contains(&episode.start.with_timezone(tz).hour())
+    })
+    .collect::<Vec<_>>();
+  if episodes.len() < MIN_NIGHT_HYPOS {
+    return None;
+  }
+  let nights = episodes
+    .iter()
+    .map(|episode| episode.start.with_timezone(tz).date_naive())
+    .collect::<BTreeSet<_>>();
+  Some(Finding::NightHypos {
+    episodes: episodes.len(),
+    nights: nights.len(),
+    min_level: episodes
+      .iter()
+      .map(|episode| episode.min_level)
+      .fold(f64::INFINITY, f64::min),
+  })
+}
+
+fn post_lunch_spikes<Tz: TimeZone>(
+  readings: &[SugarMeasurement],
+  tz: &Tz,
+) -> Option<Finding> {
+  let afternoons = daily_levels(readings, tz, &AFTERNOON);
+  let peaks = afternoons
+    .values()
+    .map(|levels| levels.iter().copied().fold(f64::MIN, f64::max))
+    .filter(|&peak| peak > HIGH_LEVEL)
+    .collect::<Vec<_>>();
+  if !is_recurring(peaks.len(), afternoons.len()) {
+    return None;
+  }
+  Some(Finding::PostLunchSpikes {
+    days: peaks.len(),
+    observed_days: afternoons.len(),
+    mean_peak: mean(&peaks)?,
+  })
+}
+
+/// Compares days after ones without basal dose to other days. Only
+/// regular basal logging is analyzed, otherwise missed doses can't
+/// be told from unlogged ones.
+fn highs_after_missed_basal<Tz: TimeZone>(
+  readings: &[SugarMeasurement],
+  injections: &[InsulinInjection],
+  tz: &Tz,
+) -> Option<Finding> {
+  let basal_days = injections
+    .iter()
+    .filter(|i| i.kind == Some(InsulinKind::Basal))
+    .map(|i| i.date_time.with_timezone(tz).date_naive())
+    .collect::<BTreeSet<_>>();
+  let levels = daily_levels(readings, tz, &(0..24));
+  let first = *basal_days.first()?;
+  let last = *levels.keys().last()?;
+  let missed = first
+    .iter_days()
+    .take_while(|&day| day < last)
+    .filter(|day| !basal_days.contains(day))
+    .collect::<Vec<_>>();
+  if basal_days.len() < MIN_DAYS
+    || !is_recurring(
+      basal_days.len(),
+      basal_days.len() + missed.len(),
+    )
+  {
+    return None;
+  }
+  let after_missed = missed
+    .iter()
+    .filter_map(|&day| day.checked_add_days(Days::new(1)))
+    .collect::<BTreeSet<_>>();
+  let (after, usual): (Vec<_>, Vec<_>) = levels
+    .iter()
+    .filter(|(&day, _)| day >= first)
+    .partition(|(day, _)| after_missed.contains(day));
+  if after.len() < MIN_MISSED_BASAL {
+    return None;
+  }
+  let flatten = |days: Vec<(&NaiveDate, &Vec<f64>)>| {
+    days
+      .into_iter()
+      .flat_map(|(_, levels)| levels.iter().copied())
+      .collect::<Vec<_>>()
+  };
+  let days = after.len();
+  let mean_after = mean(&flatten(after))?;
+  let usual_mean = mean(&flatten(usual))?;
+  (mean_after - usual_mean >= MISSED_BASAL_RISE).then_some(
+    Finding::HighsAfterMissedBasal {
+      days,
+      mean: mean_after,
+      usual_mean,
+    },
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{DateTime, Datelike, NaiveTime, Utc};
+
+  use crate::app::{
+    insulin_injection::Insulin, sugar_measurement::SugarLevel,
+  };
+
+  use super::*;
+
+  /// Time on `day` of synthetic history
+  fn at(day: u64, time: &str) -> DateTime<Utc> {
+    let date =
+      NaiveDate::from_ymd_opt(2024, 3, 1).unwrap() + Days::new(day);
+    let time = NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+    date.and_time(time).and_utc()
+  }
+
+  fn reading(day: u64, time: &str, level: f64) -> SugarMeasurement {
+    SugarMeasurement {
+      date_time: at(day, time),
+      level: SugarLevel::from_millimoles_per_liter(level),
+    }
+  }
+
+  fn basal(day: u64) -> InsulinInjection {
+    InsulinInjection {
+      date_time: at(day, "21:00"),
+      volume: Insulin::from_cubic_centimeters(14.0),
+      kind: Some(InsulinKind::Basal),
+    }
+  }
+
+  /// Readings in range at breakfast, lunch and dinner
+  fn steady_days(days: Range<u64>) -> Vec<SugarMeasurement> {
+    days
+      .flat_map(|day| {
+        ["07:00", "14:00", "19:00"]
+          .map(|time| reading(day, time, 7.0))
+      })
+      .collect()
+  }
+
+  #[test]
+  fn finds_nothing_in_steady_history() {
+    let readings = steady_days(0..14);
+    let injections = (0..14).map(basal).collect::<Vec<_>>();
+    assert!(find_patterns(&readings, &injections, &Utc).is_empty());
+  }
+
+  #[test]
+  fn finds_morning_highs() {
+    let mut readings = Vec::new();
+    for day in 0..7 {
+      readings.push(reading(day, "02:00", 7.0));
+      let fasting = if day < 5 { 11.5 } else { 6.0 };
+      readings.push(reading(day, "06:30", fasting));
+      // Later morning correction doesn't hide fasting high
+      readings.push(reading(day, "08:30", 6.5));
+    }
+    assert_eq!(
+      morning_highs(&readings, &Utc),
+      Some(Finding::MorningHighs {
+        days: 5,
+        observed_days: 7,
+        mean: 11.5,
+        night_mean: Some(7.0),
+      })
+    );
+    let occasional = &readings[..6];
+    assert_eq!(morning_highs(occasional, &Utc), None);
+  }
+
+  #[test]
+  fn finds_night_hypos() {
+    let mut readings = steady_days(0..7);
+    readings.extend([
+      reading(1, "03:00", 3.4),
+      reading(1, "03:20", 3.1),
+      reading(1, "03:45", 5.0),
+      reading(4, "02:10", 3.6),
+      reading(4, "02:40", 5.5),
+      // Daytime hypo isn't counted
+      reading(5, "15:00", 3.0),
+      reading(5, "15:20", 5.0),
+    ]);
+    assert_eq!(
+      night_hypos(&readings, &Utc),
+      Some(Finding::NightHypos {
+        episodes: 2,
+        nights: 2,
+        min_level: 3.1,
+      })
+    );
+    assert_eq!(night_hypos(&readings[..24], &Utc), None);
+  }
+
+  #[test]
+  fn finds_post_lunch_spikes() {
+    let mut readings = steady_days(0..6);
+    readings.extend([
+      reading(0, "15:00", 12.0),
+      reading(1, "14:30", 13.0),
+      reading(1, "16:00", 11.0),
+      reading(3, "15:00", 12.0),
+      reading(4, "15:30", 11.0),
+    ]);
+    assert_eq!(
+      post_lunch_spikes(&readings, &Utc),
+      Some(Finding::PostLunchSpikes {
+        days: 4,
+        observed_days: 6,
+        mean_peak: 12.0,
+      })
+    );
+    assert_eq!(post_lunch_spikes(&steady_days(0..6), &Utc), None);
+  }
+
+  #[test]
+  fn finds_highs_after_missed_basal() {
+    let mut readings = steady_days(0..11);
+    for reading in &mut readings {
+      let day = reading.date_time.day();
+      if day == 5 || day == 9 {
+        reading.level = SugarLevel::from_millimoles_per_liter(12.0);
+      }
+    }
+    let injections = (0..11)
+      .filter(|&day| day != 3 && day != 7)
+      .map(basal)
+      .collect::<Vec<_>>();
+    assert_eq!(
+      highs_after_missed_basal(&readings, &injections, &Utc),
+      Some(Finding::HighsAfterMissedBasal {
+        days: 2,
+        mean: 12.0,
+        usual_mean: 7.0,
+      })
+    );
+    // Without regular logging missed doses can't be detected
+    assert_eq!(
+      highs_after_missed_basal(&readings, &injections[..2], &Utc),
+      None
+    );
+  }
+
+  #[test]
+  fn explains_with_numbers() {
+    let text = Finding::NightHypos {
+      episodes: 2,
+      nights: 2,
+      min_level: 3.1,
+    }
+    .explain();
+    assert!(
+      text.contains("(00:00–06:00): эпизодов — 2, ночей с гипо — 2")
+    );
+    assert!(text.contains("минимум 3.1 ммоль/л"));
+  }
+}
@@ -1,16 +1,22 @@
-mod repository;
+pub mod on_board;
+pub mod repository;
+pub mod safety;
 
-use std::sync::Arc;
+use std::{fmt::Display, sync::Arc};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 use teloxide::{
   dispatching::{
     dialogue::{Dialogue, InMemStorage},
     HandlerExt,
   },
   dptree::{self, case, di::DependencyMap},
+  payloads::{AnswerCallbackQuerySetters, SendMessageSetters},
   requests::Requester,
-  types::{ChatId, Message, UserId},
+  types::{
+    CallbackQuery, ChatId, InlineKeyboardButton,
+    InlineKeyboardMarkup, Message, UserId,
+  },
   Bot,
 };
 
@@ -18,12 +24,34 @@ use crate::{
   bot_commands::MenuCommand,
   common::{any, Result},
   db::Db,
-  utils::filter_message,
+  utils::{
+    event_publisher::EventPublisher, filter_callback_query,
+    filter_message,
+  },
+};
+
+use self::{
+  repository::insulin_injections,
+  safety::{
+    earlier_since, repository::safety_rules, warnings, Choice,
+    MaxDose, SafetyRules,
+  },
 };
 
-use self::repository::insulin_injections;
+use super::{
+  plausibility::{parse_number, Confirmation, Plausibility},
+  preferences::repository::preferences,
+  profile::{
+    current_profile, repository::profiles, Profile, ProfileId,
+  },
+  UpdateHandler,
+};
 
-use super::UpdateHandler;
+const CALLBACK_PREFIX: &str = "insulin_kind:";
+const CONFIRM_CALLBACK_PREFIX: &str = "insulin_confirm:";
+const LOG_CALLBACK_PREFIX: &str = "insulin_log:";
+/// Pens and pumps can't deliver more at once, units
+const MAX_UNITS: f64 = 100.0;
 
 pub struct Plugin;
 
@@ -33,41 +61,172 @@ impl super::Plugin for Plugin {
   }
 
   fn update_handler(&self) -> UpdateHandler {
-    filter_message()
-      .enter_dialogue::<Message, InMemStorage<State>, State>()
+    dptree::entry()
+      .branch(
+        filter_message()
+          .enter_dialogue::<Message, InMemStorage<State>, State>()
+          .branch(
+            dptree::entry()
+              .filter_command::<MenuCommand>()
+              .branch(
+                case![MenuCommand::InsulinInjection]
+                  .endpoint(ask_kind),
+              )
+              .branch(
+                case![MenuCommand::InsulinSafety]
+                  .endpoint(safety::send_settings),
+              ),
+          )
+          .branch(case![State::Accepting(kind)].endpoint(accept))
+          .branch(
+            case![State::AcceptingMax(max)]
+              .endpoint(safety::accept_max),
+          ),
+      )
       .branch(
-        dptree::entry()
-          .filter_command::<MenuCommand>()
-          .branch(case![MenuCommand::InsulinInjection].endpoint(ask)),
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            InsulinKind::parse(query.data.as_deref()?)
+          })
+          .endpoint(ask_volume),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            Confirmation::parse(
+              CONFIRM_CALLBACK_PREFIX,
+              query.data.as_deref()?,
+            )
+          })
+          .endpoint(confirm),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            Choice::parse(query.data.as_deref()?)
+          })
+          .endpoint(safety::choose),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            LogRequest::parse(query.data.as_deref()?)
+          })
+          .endpoint(ask_volume_for_profile),
       )
-      .branch(case![State::Accepting].endpoint(accept))
   }
 }
 
+/// Logging dose to profile requested by button of another plugin
+#[derive(Debug, Clone, Copy)]
+struct LogRequest {
+  profile_id: ProfileId,
+  kind: InsulinKind,
+}
+
+impl LogRequest {
+  fn parse(data: &str) -> Option<Self> {
+    let data = data.strip_prefix(LOG_CALLBACK_PREFIX)?;
+    let (profile_id, kind) = data.split_once(':')?;
+    Some(Self {
+      profile_id: ProfileId(profile_id.parse().ok()?),
+      kind: InsulinKind::from_name(kind)?,
+    })
+  }
+}
+
+/// Button asking dose of `kind` for profile, which becomes current
+pub fn log_button(
+  label: &str,
+  profile_id: ProfileId,
+  kind: InsulinKind,
+) -> InlineKeyboardButton {
+  let ProfileId(id) = profile_id;
+  InlineKeyboardButton::callback(
+    label,
+    format!("{LOG_CALLBACK_PREFIX}{id}:{}", kind.name()),
+  )
+}
+
+#[derive(Debug, Clone)]
+pub struct InsulinInjectionAdded {
+  pub profile: Profile,
+  pub injection: InsulinInjection,
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
-struct InsulinInjection {
-  date_time: DateTime<Utc>,
-  volume: Insulin,
+pub struct InsulinInjection {
+  pub date_time: DateTime<Utc>,
+  pub volume: Insulin,
+  /// `None` for injections logged before kind was asked
+  pub kind: Option<InsulinKind>,
 }
 
 impl InsulinInjection {
-  fn from_now(volume: Insulin) -> Self {
+  pub fn from_now(volume: Insulin, kind: InsulinKind) -> Self {
     let date_time = Utc::now();
-    Self { date_time, volume }
+    Self {
+      date_time,
+      volume,
+      kind: Some(kind),
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, sqlx::Type)]
+#[sqlx(rename_all = "snake_case")]
+pub enum InsulinKind {
+  /// Long-acting insulin covering background needs
+  Basal,
+  /// Short-acting insulin covering meals and corrections
+  Bolus,
+}
+
+impl InsulinKind {
+  const ALL: [Self; 2] = [Self::Bolus, Self::Basal];
+
+  fn label(self) -> &'static str {
+    match self {
+      Self::Basal => "Длинный (базальный)",
+      Self::Bolus => "Короткий (болюсный)",
+    }
+  }
+
+  fn parse(data: &str) -> Option<Self> {
+    Self::from_name(data.strip_prefix(CALLBACK_PREFIX)?)
+  }
+
+  fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "basal" => Some(Self::Basal),
+      "bolus" => Some(Self::Bolus),
+      _ => None,
+    }
+  }
+
+  fn name(self) -> &'static str {
+    match self {
+      Self::Basal => "basal",
+      Self::Bolus => "bolus",
+    }
+  }
+
+  fn data(self) -> String {
+    format!("{CALLBACK_PREFIX}{}", self.name())
   }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
-struct Insulin {
+pub struct Insulin {
   cubic_centimeters: f64,
 }
 
 impl Insulin {
-  fn from_cubic_centimeters(cubic_centimeters: f64) -> Self {
+  pub fn from_cubic_centimeters(cubic_centimeters: f64) -> Self {
     Self { cubic_centimeters }
   }
 
-  fn as_cubic_centimeters(self) -> f64 {
+  pub fn as_cubic_centimeters(self) -> f64 {
     self.cubic_centimeters
   }
 }
@@ -76,20 +235,87 @@ impl Insulin {
 enum State {
   #[default]
   Ignoring,
-  Accepting,
+  Accepting(InsulinKind),
+  /// Maximum dose of safety rules is being changed
+  AcceptingMax(MaxDose),
+  /// Doubtful injection into profile waits for confirmation with
+  /// its warnings
+  Confirming(ProfileId, InsulinInjection, String),
 }
 
 type Dialog = Dialogue<State, InMemStorage<State>>;
 
-async fn ask(
+async fn ask_kind(
   bot: Bot,
   chat_id: ChatId,
   dialogue: Dialog,
 ) -> Result<()> {
+  let keyboard = InsulinKind::ALL.map(|kind| {
+    vec![InlineKeyboardButton::callback(kind.label(), kind.data())]
+  });
+  bot
+    .send_message(chat_id, "Какой инсулин?")
+    .reply_markup(InlineKeyboardMarkup::new(keyboard))
+    .await?;
+  dialogue.reset().await.map_err(any)?;
+  Ok(())
+}
+
+async fn ask_volume(
+  bot: Bot,
+  kind: InsulinKind,
+  query: CallbackQuery,
+  storage: Arc<InMemStorage<State>>,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
   bot
-    .send_message(chat_id, "Отправьте инсулин в см³ (ЕД)")
+    .edit_message_text(
+      msg.chat.id,
+      msg.id,
+      format!("{}. Отправьте инсулин в см³ (ЕД)", kind.label()),
+    )
     .await?;
-  dialogue.update(State::Accepting).await.map_err(any)?;
+  Dialog::new(storage, msg.chat.id)
+    .update(State::Accepting(kind))
+    .await
+    .map_err(any)?;
+  Ok(())
+}
+
+async fn ask_volume_for_profile(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  request: LogRequest,
+  query: CallbackQuery,
+  storage: Arc<InMemStorage<State>>,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  if !profiles(&db).switch(user_id, request.profile_id).await? {
+    return Ok(());
+  }
+  bot.edit_message_reply_markup(msg.chat.id, msg.id).await?;
+  let profile = current_profile(&db, user_id).await?;
+  bot
+    .send_message(
+      msg.chat.id,
+      format!(
+        "{}{}. Отправьте инсулин в см³ (ЕД)",
+        request.kind.label(),
+        profile.tag()
+      ),
+    )
+    .await?;
+  Dialog::new(storage, msg.chat.id)
+    .update(State::Accepting(request.kind))
+    .await
+    .map_err(any)?;
   Ok(())
 }
 
@@ -97,23 +323,213 @@ async fn accept(
   bot: Bot,
   msg: Message,
   user_id: UserId,
+  kind: InsulinKind,
   db: Arc<Db>,
+  ep: Arc<EventPublisher>,
   dialogue: Dialog,
 ) -> Result<()> {
-  if let Some(insulin) = parse(msg.text()) {
-    insulin_injections(&db, user_id)
-      .add(InsulinInjection::from_now(insulin))
+  let Some(insulin) = parse(msg.text()) else {
+    bot
+      .send_message(
+        msg.chat.id,
+        "Неправильный формат. Отправьте число, например 4.5",
+      )
       .await?;
-    bot.send_message(msg.chat.id, "✅").await?;
-  } else {
+    dialogue.reset().await.map_err(any)?;
+    return Ok(());
+  };
+  let profile = current_profile(&db, user_id).await?;
+  let injection = InsulinInjection::from_now(insulin, kind);
+  let rules = safety_rules(&db)
+    .fetch(profile.id)
+    .await?
+    .unwrap_or_default();
+  let now = injection.date_time;
+  let earlier = insulin_injections(&db, profile.id)
+    .fetch_between(earlier_since(now), now)
+    .await?;
+  let tz = preferences(&db)
+    .fetch(profile.id)
+    .await?
+    .unwrap_or_default()
+    .timezone;
+  match check(injection, rules, &earlier, &tz) {
+    Plausibility::Plausible => {
+      save(&bot, &db, &ep, msg.chat.id, profile, injection, None)
+        .await?;
+      dialogue.reset().await.map_err(any)?;
+    }
+    Plausibility::Doubtful(reason) => {
+      bot
+        .send_message(msg.chat.id, format!("⚠️ {reason} Вы уверены?"))
+        .reply_markup(Confirmation::keyboard(CONFIRM_CALLBACK_PREFIX))
+        .await?;
+      dialogue
+        .update(State::Confirming(profile.id, injection, reason))
+        .await
+        .map_err(any)?;
+    }
+    Plausibility::Impossible(reason) => {
+      bot
+        .send_message(
+          msg.chat.id,
+          format!("{reason} Отправьте инсулин еще раз"),
+        )
+        .await?;
+    }
+  }
+  Ok(())
+}
+
+async fn confirm(
+  bot: Bot,
+  user_id: UserId,
+  db: Arc<Db>,
+  ep: Arc<EventPublisher>,
+  confirmation: Confirmation,
+  query: CallbackQuery,
+  storage: Arc<InMemStorage<State>>,
+) -> Result<()> {
+  let Some(msg) = query.message else {
+    bot.answer_callback_query(query.id).await?;
+    return Ok(());
+  };
+  let dialogue = Dialog::new(storage, msg.chat.id);
+  let Some(State::Confirming(profile_id, injection, warnings)) =
+    dialogue.get().await.map_err(any)?
+  else {
     bot
-      .send_message(msg.chat.id, "Неправильный формат (todo)")
+      .answer_callback_query(query.id)
+      .text("Уже неактуально")
+      .await?;
+    return Ok(());
+  };
+  bot.answer_callback_query(query.id).await?;
+  bot.edit_message_reply_markup(msg.chat.id, msg.id).await?;
+  match confirmation {
+    Confirmation::Save => {
+      dialogue.reset().await.map_err(any)?;
+      let profile = profiles(&db).fetch(profile_id).await?;
+      let Some(profile) =
+        profile.filter(|profile| profile.user_id == user_id)
+      else {
+        return Ok(());
+      };
+      save(
+        &bot,
+        &db,
+        &ep,
+        msg.chat.id,
+        profile,
+        injection,
+        Some(&warnings),
+      )
       .await?;
+    }
+    Confirmation::Retry => {
+      bot
+        .send_message(msg.chat.id, "Отправьте инсулин еще раз")
+        .await?;
+      let kind = injection.kind.unwrap_or(InsulinKind::Bolus);
+      dialogue.update(State::Accepting(kind)).await.map_err(any)?;
+    }
   }
-  dialogue.reset().await.map_err(any)?;
+  Ok(())
+}
+
+async fn save(
+  bot: &Bot,
+  db: &Db,
+  ep: &EventPublisher,
+  chat_id: ChatId,
+  profile: Profile,
+  injection: InsulinInjection,
+  warnings: Option<&str>,
+) -> Result<()> {
+  insulin_injections(db, profile.id)
+    .add_with_warnings(injection, warnings)
+    .await?;
+  bot
+    .send_message(chat_id, format!("✅{}", profile.tag()))
+    .await?;
+  ep.send(InsulinInjectionAdded { profile, injection });
   Ok(())
 }
 
 fn parse(s: Option<&str>) -> Option<Insulin> {
-  Some(Insulin::from_cubic_centimeters(s?.parse().ok()?))
+  Some(Insulin::from_cubic_centimeters(parse_number(s?)?))
+}
+
+/// Checks injected volume can be real and follows safety `rules`
+/// given `earlier` injections
+fn check<Tz: TimeZone>(
+  injection: InsulinInjection,
+  rules: SafetyRules,
+  earlier: &[InsulinInjection],
+  tz: &Tz,
+) -> Plausibility
+where
+  Tz::Offset: Display,
+{
+  let units = injection.volume.as_cubic_centimeters();
+  if units <= 0.0 || units > MAX_UNITS {
+    return Plausibility::Impossible(format!(
+      "Инсулин должен быть больше 0 и не больше {MAX_UNITS:.0} ЕД."
+    ));
+  }
+  let warnings = warnings(injection, rules, earlier, tz);
+  if warnings.is_empty() {
+    return Plausibility::Plausible;
+  }
+  let reasons: Vec<_> =
+    warnings.into_iter().map(|w| w.describe(tz)).collect();
+  Plausibility::Doubtful(reasons.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::Utc;
+
+  use super::*;
+
+  fn check_units(units: f64, kind: InsulinKind) -> Plausibility {
+    let volume = Insulin::from_cubic_centimeters(units);
+    let injection = InsulinInjection::from_now(volume, kind);
+    check(injection, SafetyRules::default(), &[], &Utc)
+  }
+
+  #[test]
+  fn rejects_impossible_volumes() {
+    use InsulinKind::Bolus;
+    assert_eq!(parse(Some("inf")), None);
+    assert!(matches!(
+      check_units(0.0, Bolus),
+      Plausibility::Impossible(_)
+    ));
+    assert!(matches!(
+      check_units(-2.0, Bolus),
+      Plausibility::Impossible(_)
+    ));
+    assert!(matches!(
+      check_units(150.0, InsulinKind::Basal),
+      Plausibility::Impossible(_)
+    ));
+  }
+
+  #[test]
+  fn asks_to_confirm_doses_above_rules() {
+    use InsulinKind::{Basal, Bolus};
+    assert_eq!(check_units(6.5, Bolus), Plausibility::Plausible);
+    assert_eq!(check_units(40.0, Basal), Plausibility::Plausible);
+    assert_eq!(
+      check_units(40.0, Bolus),
+      Plausibility::Doubtful(
+        "Доза больше заданного максимума 25 ЕД.".into()
+      )
+    );
+    assert!(matches!(
+      check_units(70.0, Basal),
+      Plausibility::Doubtful(_)
+    ));
+  }
 }
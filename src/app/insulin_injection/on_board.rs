@@ -0,0 +1,91 @@
+use chrono::{DateTime, Duration, Utc};
+
+use super::{InsulinInjection, InsulinKind};
+
+/// Duration of rapid-acting insulin action, minutes
+pub const ACTION_MINUTES: i64 = 360;
+/// Time of peak activity of rapid-acting insulin, minutes
+const PEAK_MINUTES: f64 = 75.0;
+
+/// Share of dose still acting `minutes` after injection, following
+/// exponential activity curve used by open-source closed loops
+#[allow(clippy::cast_precision_loss)]
+pub fn remaining_share(minutes: f64) -> f64 {
+  let duration = ACTION_MINUTES as f64;
+  if minutes <= 0.0 {
+    return 1.0;
+  }
+  if minutes >= duration {
+    return 0.0;
+  }
+  let peak = PEAK_MINUTES;
+  let tau =
+    peak * (1.0 - peak / duration) / (1.0 - 2.0 * peak / duration);
+  let a = 2.0 * tau / duration;
+  let s = 1.0 / (1.0 - a + (1.0 + a) * (-duration / tau).exp());
+  let t = minutes;
+  1.0
+    - s
+      * (1.0 - a)
+      * ((t.powi(2) / (tau * duration * (1.0 - a)) - t / tau - 1.0)
+        * (-t / tau).exp()
+        + 1.0)
+}
+
+/// Units of bolus insulin still acting at `now`. Basal and injections
+/// of unknown kind are ignored.
+#[allow(clippy::cast_precision_loss)]
+pub fn insulin_on_board(
+  injections: &[InsulinInjection],
+  now: DateTime<Utc>,
+) -> f64 {
+  injections
+    .iter()
+    .filter(|i| i.kind == Some(InsulinKind::Bolus))
+    .filter(|i| i.date_time <= now)
+    .map(|i| {
+      let minutes = (now - i.date_time).num_seconds() as f64 / 60.0;
+      i.volume.as_cubic_centimeters() * remaining_share(minutes)
+    })
+    .sum()
+}
+
+/// Earliest injection time affecting insulin on board at `now`
+pub fn action_start(now: DateTime<Utc>) -> DateTime<Utc> {
+  now - Duration::minutes(ACTION_MINUTES)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::app::insulin_injection::Insulin;
+
+  use super::*;
+
+  #[test]
+  fn share_decreases_to_zero() {
+    assert!((remaining_share(0.0) - 1.0).abs() < 1e-9);
+    let hour = remaining_share(60.0);
+    let three_hours = remaining_share(180.0);
+    assert!(0.7 < hour && hour < 0.85, "{hour}");
+    assert!(0.2 < three_hours && three_hours < 0.4, "{three_hours}");
+    assert!(remaining_share(359.0) < 0.01);
+    assert_eq!(remaining_share(360.0), 0.0);
+  }
+
+  #[test]
+  fn counts_only_bolus() {
+    let now: DateTime<Utc> = "2024-03-01T12:00:00Z".parse().unwrap();
+    let injection = |minutes_ago, units, kind| InsulinInjection {
+      date_time: now - Duration::minutes(minutes_ago),
+      volume: Insulin::from_cubic_centimeters(units),
+      kind,
+    };
+    let injections = [
+      injection(0, 4.0, Some(InsulinKind::Bolus)),
+      injection(400, 6.0, Some(InsulinKind::Bolus)),
+      injection(60, 20.0, Some(InsulinKind::Basal)),
+      injection(60, 3.0, None),
+    ];
+    assert!((insulin_on_board(&injections, now) - 4.0).abs() < 1e-9);
+  }
+}
@@ -1,94 +1,309 @@
-use teloxide::types::UserId;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
 
-use crate::db::{txn::ExecutorHolder, Db};
+use crate::{
+  app::profile::ProfileId,
+  db::{txn::ExecutorHolder, Db},
+};
 
-use super::{Insulin, InsulinInjection};
+use super::{Insulin, InsulinInjection, InsulinKind};
 
-pub fn insulin_injections(db: &Db, user_id: UserId) -> Repository {
+pub fn insulin_injections(
+  db: &Db,
+  profile_id: ProfileId,
+) -> Repository {
   let exec = db.exec();
-  Repository { user_id, exec }
+  Repository { profile_id, exec }
 }
 
 pub struct Repository {
-  user_id: UserId,
+  profile_id: ProfileId,
   exec: ExecutorHolder,
 }
 
+/// Aggregates of injection volumes calculated by database
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+  pub count: u32,
+  pub mean: Option<Insulin>,
+  pub min: Option<Insulin>,
+  pub max: Option<Insulin>,
+}
+
 impl Repository {
-  pub async fn fetch_all(
+  /// Injections in `[start, end)` in chronological order
+  pub async fn fetch_between(
     &self,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
   ) -> sqlx::Result<Vec<InsulinInjection>> {
-    let user_id = self.user_id();
+    let profile_id = self.profile_id.0;
     sqlx::query!(
       r#"
-        SELECT date_time, cubic_centimeters
+        SELECT
+          date_time,
+          cubic_centimeters,
+          kind AS "kind: InsulinKind"
         FROM insulin_injections
-        WHERE user_id = ?
+        WHERE profile_id = ? AND date_time >= ? AND date_time < ?
+        ORDER BY date_time
       "#,
-      user_id
+      profile_id,
+      start,
+      end
     )
     .map(|rec| InsulinInjection {
       date_time: rec.date_time.and_utc(),
       volume: Insulin::from_cubic_centimeters(rec.cubic_centimeters),
+      kind: rec.kind,
     })
     .fetch_all(&mut self.exec.borrow())
     .await
   }
 
-  fn user_id(&self) -> i64 {
-    self.user_id.0.try_into().unwrap()
+  /// Up to `limit` latest injections, newest first
+  pub async fn fetch_latest(
+    &self,
+    limit: u32,
+  ) -> sqlx::Result<Vec<InsulinInjection>> {
+    let profile_id = self.profile_id.0;
+    sqlx::query!(
+      r#"
+        SELECT
+          date_time,
+          cubic_centimeters,
+          kind AS "kind: InsulinKind"
+        FROM insulin_injections
+        WHERE profile_id = ?
+        ORDER BY date_time DESC
+        LIMIT ?
+      "#,
+      profile_id,
+      limit
+    )
+    .map(|rec| InsulinInjection {
+      date_time: rec.date_time.and_utc(),
+      volume: Insulin::from_cubic_centimeters(rec.cubic_centimeters),
+      kind: rec.kind,
+    })
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Passes injections in `[start, end)` to `f` in chronological
+  /// order as they are read, without collecting them
+  pub async fn for_each_between(
+    &self,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    mut f: impl FnMut(InsulinInjection),
+  ) -> sqlx::Result<()> {
+    let profile_id = self.profile_id.0;
+    let mut exec = self.exec.borrow();
+    let mut rows = sqlx::query!(
+      r#"
+        SELECT
+          date_time,
+          cubic_centimeters,
+          kind AS "kind: InsulinKind"
+        FROM insulin_injections
+        WHERE profile_id = ? AND date_time >= ? AND date_time < ?
+        ORDER BY date_time
+      "#,
+      profile_id,
+      start,
+      end
+    )
+    .map(|rec| InsulinInjection {
+      date_time: rec.date_time.and_utc(),
+      volume: Insulin::from_cubic_centimeters(rec.cubic_centimeters),
+      kind: rec.kind,
+    })
+    .fetch(&mut exec);
+    while let Some(injection) = rows.try_next().await? {
+      f(injection);
+    }
+    Ok(())
+  }
+
+  /// Count, mean, min and max volume of injections in `[start, end)`
+  #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+  pub async fn summary_between(
+    &self,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+  ) -> sqlx::Result<Summary> {
+    let profile_id = self.profile_id.0;
+    let rec = sqlx::query!(
+      r#"
+        SELECT
+          COUNT(*) AS "count!: i64",
+          AVG(cubic_centimeters) AS "mean: f64",
+          MIN(cubic_centimeters) AS "min: f64",
+          MAX(cubic_centimeters) AS "max: f64"
+        FROM insulin_injections
+        WHERE profile_id = ? AND date_time >= ? AND date_time < ?
+      "#,
+      profile_id,
+      start,
+      end
+    )
+    .fetch_one(&mut self.exec.borrow())
+    .await?;
+    let volume =
+      |value: Option<f64>| value.map(Insulin::from_cubic_centimeters);
+    Ok(Summary {
+      count: rec.count as u32,
+      mean: volume(rec.mean),
+      min: volume(rec.min),
+      max: volume(rec.max),
+    })
   }
 
-  pub async fn add(
+  /// Adds injection with safety `warnings` user confirmed
+  pub async fn add_with_warnings(
     &mut self,
     insulin_injection: InsulinInjection,
+    warnings: Option<&str>,
   ) -> sqlx::Result<()> {
-    let user_id = self.user_id();
+    let profile_id = self.profile_id.0;
     let date_time = insulin_injection.date_time;
     let cubic_centimeters =
       insulin_injection.volume.as_cubic_centimeters();
     sqlx::query!(
       r#"
         INSERT INTO insulin_injections (
-          user_id,
+          profile_id,
           date_time,
-          cubic_centimeters
+          cubic_centimeters,
+          kind,
+          warnings
         )
-        VALUES (?, ?, ?)
+        VALUES (?, ?, ?, ?, ?)
       "#,
-      user_id,
+      profile_id,
       date_time,
-      cubic_centimeters
+      cubic_centimeters,
+      insulin_injection.kind as _,
+      warnings
     )
     .execute(&mut self.exec.borrow())
     .await?;
     Ok(())
   }
+
+  /// Adds injection unless there is one at the same time. Returns
+  /// `true` if added.
+  pub async fn add_if_absent(
+    &mut self,
+    insulin_injection: InsulinInjection,
+  ) -> sqlx::Result<bool> {
+    let profile_id = self.profile_id.0;
+    let date_time = insulin_injection.date_time;
+    let cubic_centimeters =
+      insulin_injection.volume.as_cubic_centimeters();
+    let res = sqlx::query!(
+      r#"
+        INSERT OR IGNORE INTO insulin_injections (
+          profile_id,
+          date_time,
+          cubic_centimeters,
+          kind
+        )
+        VALUES (?, ?, ?, ?)
+      "#,
+      profile_id,
+      date_time,
+      cubic_centimeters,
+      insulin_injection.kind as _
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(res.rows_affected() > 0)
+  }
 }
 
 #[cfg(test)]
 mod tests {
+  use chrono::Duration;
+  use teloxide::types::UserId;
+
   use crate::{
-    app::user::repository::users,
+    app::{profile::repository::profiles, user::repository::users},
     db::{tests::test_db, txn},
   };
 
   use super::*;
 
   #[tokio::test]
-  async fn add_and_fetch_all() {
+  async fn add_and_fetch_between() {
     let test_db = test_db().await.unwrap();
     txn::begin(test_db.pool(), async {
       let user = UserId(1);
       let insulin = Insulin::from_cubic_centimeters(5.7);
-      let rec = InsulinInjection::from_now(insulin);
+      let rec =
+        InsulinInjection::from_now(insulin, InsulinKind::Basal);
       users(&test_db).add(user).await.unwrap();
-      let mut injections = insulin_injections(&test_db, user);
-      injections.add(rec).await.unwrap();
-      let recs = injections.fetch_all().await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let mut injections = insulin_injections(&test_db, profile.id);
+      injections.add_with_warnings(rec, None).await.unwrap();
+      let minute = Duration::minutes(1);
+      let recs = injections
+        .fetch_between(rec.date_time, rec.date_time + minute)
+        .await
+        .unwrap();
       assert_eq!(recs, vec![rec]);
     })
     .await
     .unwrap();
   }
+
+  #[tokio::test]
+  async fn latest_stream_and_summary() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      users(&test_db).add(user).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let mut injections = insulin_injections(&test_db, profile.id);
+      let rec = |s: &str, units, kind| InsulinInjection {
+        date_time: s.parse().unwrap(),
+        volume: Insulin::from_cubic_centimeters(units),
+        kind: Some(kind),
+      };
+      let recs = [
+        rec("2024-03-01T07:00:00Z", 4.0, InsulinKind::Bolus),
+        rec("2024-03-01T21:00:00Z", 12.0, InsulinKind::Basal),
+        rec("2024-03-02T07:00:00Z", 5.0, InsulinKind::Bolus),
+      ];
+      for rec in recs {
+        injections.add_with_warnings(rec, None).await.unwrap();
+      }
+      assert_eq!(
+        injections.fetch_latest(1).await.unwrap(),
+        [recs[2]]
+      );
+      let start = recs[0].date_time;
+      let end = recs[2].date_time;
+      let mut streamed = Vec::new();
+      injections
+        .for_each_between(start, end, |i| streamed.push(i))
+        .await
+        .unwrap();
+      assert_eq!(streamed, recs[..2]);
+      let volume =
+        |units| Some(Insulin::from_cubic_centimeters(units));
+      assert_eq!(
+        injections.summary_between(start, end).await.unwrap(),
+        Summary {
+          count: 2,
+          mean: volume(8.0),
+          min: volume(4.0),
+          max: volume(12.0),
+        }
+      );
+    })
+    .await
+    .unwrap();
+  }
 }
@@ -0,0 +1,511 @@
+//! Confirmation of large, stacked and repeated insulin doses
+
+pub mod repository;
+
+use std::{fmt::Display, sync::Arc};
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use teloxide::{
+  dispatching::dialogue::InMemStorage,
+  payloads::{EditMessageTextSetters, SendMessageSetters},
+  requests::Requester,
+  types::{
+    CallbackQuery, ChatId, InlineKeyboardButton,
+    InlineKeyboardMarkup, Message, UserId,
+  },
+  Bot,
+};
+
+use crate::{
+  app::profile::{
+    current_profile, repository::profiles, Profile, ProfileId,
+  },
+  common::{any, Result},
+  db::Db,
+};
+
+use self::repository::safety_rules;
+
+use super::{
+  on_board::insulin_on_board, parse, Dialog, InsulinInjection,
+  InsulinKind, State, MAX_UNITS,
+};
+
+const CALLBACK_PREFIX: &str = "insulin_safety:";
+/// Previous rapid dose within is still mostly acting, minutes
+const STACKING_MINUTES: i64 = 180;
+
+/// Per-profile limits of doses logged without confirmation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SafetyRules {
+  /// Largest rapid dose, units
+  pub max_bolus: f64,
+  /// Largest long-acting dose, units
+  pub max_basal: f64,
+  /// Warn about rapid dose while previous one is acting
+  pub stacking_warning: bool,
+  /// Warn about second long-acting dose on the same day
+  pub double_basal_warning: bool,
+}
+
+impl Default for SafetyRules {
+  fn default() -> Self {
+    Self {
+      max_bolus: 25.0,
+      max_basal: 60.0,
+      stacking_warning: true,
+      double_basal_warning: true,
+    }
+  }
+}
+
+impl SafetyRules {
+  fn max_dose(self, kind: InsulinKind) -> f64 {
+    match kind {
+      InsulinKind::Basal => self.max_basal,
+      InsulinKind::Bolus => self.max_bolus,
+    }
+  }
+}
+
+/// Reason to confirm injection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Warning {
+  LargeDose {
+    max: f64,
+  },
+  Stacking {
+    minutes_ago: i64,
+    insulin_on_board: f64,
+  },
+  DoubleBasal {
+    at: DateTime<Utc>,
+  },
+}
+
+impl Warning {
+  pub fn describe<Tz: TimeZone>(self, tz: &Tz) -> String
+  where
+    Tz::Offset: Display,
+  {
+    match self {
+      Self::LargeDose { max } => {
+        format!("Доза больше заданного максимума {max} ЕД.")
+      }
+      Self::Stacking {
+        minutes_ago,
+        insulin_on_board,
+      } => format!(
+        "Короткий инсулин уже вводился {minutes_ago} мин. назад, \
+        еще действует {insulin_on_board:.1} ЕД — дозы могут \
+        наложиться."
+      ),
+      Self::DoubleBasal { at } => format!(
+        "Длинный инсулин сегодня уже вводился в {} — возможно, \
+        это двойная доза.",
+        at.with_timezone(tz).format("%H:%M")
+      ),
+    }
+  }
+}
+
+/// Warnings about `injection` given `earlier` injections of last
+/// day in chronological order. Days start at midnight in `tz`.
+pub fn warnings<Tz: TimeZone>(
+  injection: InsulinInjection,
+  rules: SafetyRules,
+  earlier: &[InsulinInjection],
+  tz: &Tz,
+) -> Vec<Warning> {
+  let Some(kind) = injection.kind else {
+    return Vec::new();
+  };
+  let now = injection.date_time;
+  let mut warnings = Vec::new();
+  let max = rules.max_dose(kind);
+  if injection.volume.as_cubic_centimeters() > max {
+    warnings.push(Warning::LargeDose { max });
+  }
+  let mut same_kind = earlier
+    .iter()
+    .rev()
+    .filter(|i| i.kind == Some(kind) && i.date_time <= now);
+  match kind {
+    InsulinKind::Bolus if rules.stacking_warning => {
+      let recent = same_kind.find(|i| {
+        now - i.date_time <= Duration::minutes(STACKING_MINUTES)
+      });
+      if let Some(previous) = recent {
+        warnings.push(Warning::Stacking {
+          minutes_ago: (now - previous.date_time).num_minutes(),
+          insulin_on_board: insulin_on_board(earlier, now),
+        });
+      }
+    }
+    InsulinKind::Basal if rules.double_basal_warning => {
+      let today = now.with_timezone(tz).date_naive();
+      let same_day = same_kind.find(|i| {
+        i.date_time.with_timezone(tz).date_naive() == today
+      });
+      if let Some(previous) = same_day {
+        warnings.push(Warning::DoubleBasal {
+          at: previous.date_time,
+        });
+      }
+    }
+    _ => {}
+  }
+  warnings
+}
+
+/// Start of period of `earlier` injections for [`warnings`]
+pub fn earlier_since(now: DateTime<Utc>) -> DateTime<Utc> {
+  now - Duration::days(1)
+}
+
+/// Limit being changed
+#[derive(Debug, Clone, Copy)]
+pub struct MaxDose {
+  profile_id: ProfileId,
+  kind: InsulinKind,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Setting {
+  MaxDose(InsulinKind),
+  Stacking,
+  DoubleBasal,
+}
+
+/// Button of settings message
+#[derive(Debug, Clone, Copy)]
+pub struct Choice {
+  profile_id: ProfileId,
+  setting: Setting,
+}
+
+impl Choice {
+  pub fn parse(data: &str) -> Option<Self> {
+    let data = data.strip_prefix(CALLBACK_PREFIX)?;
+    let (profile_id, setting) = data.split_once(':')?;
+    let setting = match setting {
+      "max_bolus" => Setting::MaxDose(InsulinKind::Bolus),
+      "max_basal" => Setting::MaxDose(InsulinKind::Basal),
+      "stacking" => Setting::Stacking,
+      "double_basal" => Setting::DoubleBasal,
+      _ => return None,
+    };
+    Some(Self {
+      profile_id: ProfileId(profile_id.parse().ok()?),
+      setting,
+    })
+  }
+
+  fn data(self) -> String {
+    let setting = match self.setting {
+      Setting::MaxDose(InsulinKind::Bolus) => "max_bolus",
+      Setting::MaxDose(InsulinKind::Basal) => "max_basal",
+      Setting::Stacking => "stacking",
+      Setting::DoubleBasal => "double_basal",
+    };
+    let ProfileId(id) = self.profile_id;
+    format!("{CALLBACK_PREFIX}{id}:{setting}")
+  }
+}
+
+fn switch(enabled: bool) -> &'static str {
+  if enabled {
+    "вкл ✅"
+  } else {
+    "выкл"
+  }
+}
+
+fn settings_text(profile: &Profile, rules: SafetyRules) -> String {
+  format!(
+    "🛡 Правила безопасности инсулина{}. Бот попросит подтвердить \
+    запись, если:\n\
+    - болюс больше {} ЕД или базал больше {} ЕД\n\
+    - короткий инсулин вводится в течение {} ч после прошлого \
+    (наложение доз): {}\n\
+    - длинный инсулин вводится второй раз за день: {}\n\n\
+    Предупреждения сохраняются вместе с записью",
+    profile.tag(),
+    rules.max_bolus,
+    rules.max_basal,
+    STACKING_MINUTES / 60,
+    switch(rules.stacking_warning),
+    switch(rules.double_basal_warning)
+  )
+}
+
+fn settings_keyboard(
+  profile_id: ProfileId,
+  rules: SafetyRules,
+) -> InlineKeyboardMarkup {
+  let button = |label: String, setting| {
+    let choice = Choice {
+      profile_id,
+      setting,
+    };
+    [InlineKeyboardButton::callback(label, choice.data())]
+  };
+  InlineKeyboardMarkup::new([
+    button(
+      format!("Макс. болюс: {} ЕД", rules.max_bolus),
+      Setting::MaxDose(InsulinKind::Bolus),
+    ),
+    button(
+      format!("Макс. базал: {} ЕД", rules.max_basal),
+      Setting::MaxDose(InsulinKind::Basal),
+    ),
+    button(
+      format!("Наложение доз: {}", switch(rules.stacking_warning)),
+      Setting::Stacking,
+    ),
+    button(
+      format!(
+        "Двойной базал: {}",
+        switch(rules.double_basal_warning)
+      ),
+      Setting::DoubleBasal,
+    ),
+  ])
+}
+
+pub(super) async fn send_settings(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+) -> Result<()> {
+  let profile = current_profile(&db, user_id).await?;
+  let rules = safety_rules(&db)
+    .fetch(profile.id)
+    .await?
+    .unwrap_or_default();
+  bot
+    .send_message(chat_id, settings_text(&profile, rules))
+    .reply_markup(settings_keyboard(profile.id, rules))
+    .await?;
+  Ok(())
+}
+
+pub(super) async fn choose(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  choice: Choice,
+  query: CallbackQuery,
+  storage: Arc<InMemStorage<State>>,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  let profile = profiles(&db).fetch(choice.profile_id).await?;
+  let Some(profile) =
+    profile.filter(|profile| profile.user_id == user_id)
+  else {
+    return Ok(());
+  };
+  let mut repository = safety_rules(&db);
+  let mut rules =
+    repository.fetch(profile.id).await?.unwrap_or_default();
+  match choice.setting {
+    Setting::MaxDose(kind) => {
+      bot
+        .send_message(
+          msg.chat.id,
+          format!(
+            "Отправьте максимальную дозу инсулина «{}» в ЕД",
+            kind.label()
+          ),
+        )
+        .await?;
+      let max = MaxDose {
+        profile_id: profile.id,
+        kind,
+      };
+      Dialog::new(storage, msg.chat.id)
+        .update(State::AcceptingMax(max))
+        .await
+        .map_err(any)?;
+      return Ok(());
+    }
+    Setting::Stacking => {
+      rules.stacking_warning = !rules.stacking_warning;
+    }
+    Setting::DoubleBasal => {
+      rules.double_basal_warning = !rules.double_basal_warning;
+    }
+  }
+  repository.set(profile.id, rules).await?;
+  bot
+    .edit_message_text(
+      msg.chat.id,
+      msg.id,
+      settings_text(&profile, rules),
+    )
+    .reply_markup(settings_keyboard(profile.id, rules))
+    .await?;
+  Ok(())
+}
+
+pub(super) async fn accept_max(
+  bot: Bot,
+  msg: Message,
+  db: Arc<Db>,
+  max: MaxDose,
+  dialogue: Dialog,
+) -> Result<()> {
+  let units = parse(msg.text()).map(|i| i.as_cubic_centimeters());
+  let reply = match units {
+    Some(units) if 0.0 < units && units <= MAX_UNITS => {
+      let mut repository = safety_rules(&db);
+      let mut rules =
+        repository.fetch(max.profile_id).await?.unwrap_or_default();
+      match max.kind {
+        InsulinKind::Basal => rules.max_basal = units,
+        InsulinKind::Bolus => rules.max_bolus = units,
+      }
+      repository.set(max.profile_id, rules).await?;
+      format!(
+        "✅ Максимальная доза «{}»: {units} ЕД",
+        max.kind.label()
+      )
+    }
+    _ => format!(
+      "Неправильный формат. Отправьте число больше 0 и не больше \
+      {MAX_UNITS} ЕД: /insulin_safety"
+    ),
+  };
+  bot.send_message(msg.chat.id, reply).await?;
+  dialogue.reset().await.map_err(any)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::FixedOffset;
+
+  use crate::app::insulin_injection::Insulin;
+
+  use super::*;
+
+  fn injection(
+    time: &str,
+    units: f64,
+    kind: InsulinKind,
+  ) -> InsulinInjection {
+    InsulinInjection {
+      date_time: time.parse().unwrap(),
+      volume: Insulin::from_cubic_centimeters(units),
+      kind: Some(kind),
+    }
+  }
+
+  fn check(
+    injection: InsulinInjection,
+    rules: SafetyRules,
+    earlier: &[InsulinInjection],
+  ) -> Vec<Warning> {
+    let moscow = FixedOffset::east_opt(3 * 3600).unwrap();
+    warnings(injection, rules, earlier, &moscow)
+  }
+
+  #[test]
+  fn warns_about_dose_above_maximum() {
+    let rules = SafetyRules {
+      max_bolus: 10.0,
+      ..SafetyRules::default()
+    };
+    let dose = |units| {
+      injection("2024-03-01T12:00:00Z", units, InsulinKind::Bolus)
+    };
+    assert_eq!(check(dose(10.0), rules, &[]), []);
+    assert_eq!(
+      check(dose(12.0), rules, &[]),
+      [Warning::LargeDose { max: 10.0 }]
+    );
+  }
+
+  #[test]
+  fn warns_about_stacked_bolus() {
+    let earlier = [
+      injection("2024-03-01T07:00:00Z", 20.0, InsulinKind::Basal),
+      injection("2024-03-01T11:00:00Z", 4.0, InsulinKind::Bolus),
+    ];
+    let bolus = |time| injection(time, 3.0, InsulinKind::Bolus);
+    let warnings = check(
+      bolus("2024-03-01T12:00:00Z"),
+      SafetyRules::default(),
+      &earlier,
+    );
+    let [Warning::Stacking {
+      minutes_ago,
+      insulin_on_board,
+    }] = warnings[..]
+    else {
+      panic!("{warnings:?}");
+    };
+    assert_eq!(minutes_ago, 60);
+    assert!((insulin_on_board - 4.0 * 0.78).abs() < 0.1);
+    assert_eq!(
+      check(
+        bolus("2024-03-01T14:30:00Z"),
+        SafetyRules::default(),
+        &earlier
+      ),
+      []
+    );
+    let disabled = SafetyRules {
+      stacking_warning: false,
+      ..SafetyRules::default()
+    };
+    assert_eq!(
+      check(bolus("2024-03-01T12:00:00Z"), disabled, &earlier),
+      []
+    );
+  }
+
+  #[test]
+  fn warns_about_second_basal_on_local_day() {
+    // 22:00 and 23:30 in UTC+3
+    let earlier =
+      [injection("2024-03-01T19:00:00Z", 20.0, InsulinKind::Basal)];
+    let basal = |time| injection(time, 20.0, InsulinKind::Basal);
+    assert_eq!(
+      check(
+        basal("2024-03-01T20:30:00Z"),
+        SafetyRules::default(),
+        &earlier
+      ),
+      [Warning::DoubleBasal {
+        at: earlier[0].date_time
+      }]
+    );
+    // 00:30 of next local day although same UTC day
+    assert_eq!(
+      check(
+        basal("2024-03-01T21:30:00Z"),
+        SafetyRules::default(),
+        &earlier
+      ),
+      []
+    );
+  }
+
+  #[test]
+  fn describes_warning_in_local_time() {
+    let moscow = FixedOffset::east_opt(3 * 3600).unwrap();
+    let warning = Warning::DoubleBasal {
+      at: "2024-03-01T19:00:00Z".parse().unwrap(),
+    };
+    assert_eq!(
+      warning.describe(&moscow),
+      "Длинный инсулин сегодня уже вводился в 22:00 — возможно, это \
+      двойная доза."
+    );
+  }
+}
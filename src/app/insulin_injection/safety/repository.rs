@@ -0,0 +1,102 @@
+use crate::{
+  app::profile::ProfileId,
+  db::{txn::ExecutorHolder, Db},
+};
+
+use super::SafetyRules;
+
+pub fn safety_rules(db: &Db) -> Repository {
+  Repository { exec: db.exec() }
+}
+
+pub struct Repository {
+  exec: ExecutorHolder,
+}
+
+impl Repository {
+  /// Rules of profile, `None` if defaults weren't changed
+  pub async fn fetch(
+    &self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<Option<SafetyRules>> {
+    sqlx::query_as!(
+      SafetyRules,
+      r#"
+        SELECT
+          max_bolus,
+          max_basal,
+          stacking_warning,
+          double_basal_warning
+        FROM insulin_safety_rules
+        WHERE profile_id = ?
+      "#,
+      profile_id.0
+    )
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+
+  pub async fn set(
+    &mut self,
+    profile_id: ProfileId,
+    rules: SafetyRules,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      r#"
+        REPLACE INTO insulin_safety_rules (
+          profile_id,
+          max_bolus,
+          max_basal,
+          stacking_warning,
+          double_basal_warning
+        )
+        VALUES (?, ?, ?, ?, ?)
+      "#,
+      profile_id.0,
+      rules.max_bolus,
+      rules.max_basal,
+      rules.stacking_warning,
+      rules.double_basal_warning
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use teloxide::types::UserId;
+
+  use crate::{
+    app::{profile::repository::profiles, user::repository::users},
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn set_and_fetch() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      users(&test_db).add(user).await.unwrap();
+      let profile_id =
+        profiles(&test_db).add(user, None).await.unwrap().id;
+      let mut rules = safety_rules(&test_db);
+      assert_eq!(rules.fetch(profile_id).await.unwrap(), None);
+      let changed = SafetyRules {
+        max_bolus: 12.0,
+        double_basal_warning: false,
+        ..SafetyRules::default()
+      };
+      rules.set(profile_id, changed).await.unwrap();
+      assert_eq!(
+        rules.fetch(profile_id).await.unwrap(),
+        Some(changed)
+      );
+    })
+    .await
+    .unwrap();
+  }
+}
@@ -0,0 +1,26 @@
+pub mod repository;
+
+use teloxide::types::Me;
+
+use super::caregiver::Permission;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InviteKind {
+  EmergencyContact,
+  Caregiver(Permission),
+}
+
+impl InviteKind {
+  fn as_str(self) -> &'static str {
+    match self {
+      InviteKind::EmergencyContact => "emergency_contact",
+      InviteKind::Caregiver(Permission::Notify) => "caregiver_notify",
+      InviteKind::Caregiver(Permission::Read) => "caregiver_read",
+    }
+  }
+}
+
+/// Deep link which starts bot with invite `code` as payload
+pub fn invite_link(me: &Me, code: &str) -> String {
+  format!("https://t.me/{}?start={code}", me.username())
+}
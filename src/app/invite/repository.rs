@@ -0,0 +1,105 @@
+use chrono::{Duration, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::{
+  app::profile::ProfileId,
+  db::{txn::ExecutorHolder, Db},
+};
+
+use super::InviteKind;
+
+const CODE_LEN: usize = 16;
+
+pub fn invites(db: &Db) -> Repository {
+  Repository { exec: db.exec() }
+}
+
+pub struct Repository {
+  exec: ExecutorHolder,
+}
+
+impl Repository {
+  /// Creates one-time invite code to profile
+  pub async fn create(
+    &mut self,
+    profile_id: ProfileId,
+    kind: InviteKind,
+  ) -> sqlx::Result<String> {
+    let code: String = rand::thread_rng()
+      .sample_iter(&Alphanumeric)
+      .take(CODE_LEN)
+      .map(char::from)
+      .collect();
+    let kind = kind.as_str();
+    let created_at = Utc::now();
+    sqlx::query!(
+      r#"
+        INSERT INTO invites (code, profile_id, kind, created_at)
+        VALUES (?, ?, ?, ?)
+      "#,
+      code,
+      profile_id.0,
+      kind,
+      created_at
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(code)
+  }
+
+  /// Consumes invite and returns profile it invites to. Invites
+  /// expire in a day.
+  pub async fn redeem(
+    &mut self,
+    code: &str,
+    kind: InviteKind,
+  ) -> sqlx::Result<Option<ProfileId>> {
+    let kind = kind.as_str();
+    let expired_at = Utc::now() - Duration::days(1);
+    sqlx::query!(
+      r#"
+        DELETE FROM invites
+        WHERE code = ? AND kind = ? AND created_at > ?
+        RETURNING profile_id
+      "#,
+      code,
+      kind,
+      expired_at
+    )
+    .map(|rec| ProfileId(rec.profile_id))
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use teloxide::types::UserId;
+
+  use crate::{
+    app::{profile::repository::profiles, user::repository::users},
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn create_and_redeem_once() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      let kind = InviteKind::EmergencyContact;
+      users(&test_db).add(user).await.unwrap();
+      let profile =
+        profiles(&test_db).add(user, None).await.unwrap().id;
+      let mut invites = invites(&test_db);
+      let code = invites.create(profile, kind).await.unwrap();
+      let inviter = invites.redeem(&code, kind).await.unwrap();
+      assert_eq!(inviter, Some(profile));
+      let inviter = invites.redeem(&code, kind).await.unwrap();
+      assert_eq!(inviter, None);
+    })
+    .await
+    .unwrap();
+  }
+}
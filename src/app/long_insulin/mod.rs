@@ -0,0 +1,497 @@
+pub mod repository;
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use clokwerk::{AsyncScheduler, TimeUnits};
+use teloxide::{
+  dispatching::dialogue::InMemStorage, dptree::case, prelude::*,
+  types::InlineKeyboardMarkup, ApiError, RequestError,
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::Db,
+  event_handler::{filter_event, handler, EventHandler},
+  utils::{event_publisher::EventPublisher, filter_message},
+};
+
+use self::repository::{basal_reminders, DueReminder};
+
+use super::{
+  daily_summary::next_occurrence,
+  insulin_injection::{
+    log_button, repository::insulin_injections,
+    InsulinInjectionAdded, InsulinKind,
+  },
+  preferences::repository::preferences,
+  profile::{current_profile, Profile, ProfileId},
+  quiet_hours::repository::quiet_hours,
+  user::repository::users,
+  UpdateHandler,
+};
+
+/// Dose logged this long before reminder counts for it, minutes
+const EARLY_MINUTES: i64 = 120;
+/// Reminders delayed longer, e.g. while bot was down, are skipped
+const MAX_DELAY_HOURS: i64 = 12;
+const DEFAULT_FOLLOW_UP_HOURS: i64 = 2;
+const MAX_FOLLOW_UP_HOURS: i64 = 6;
+
+type Dialog = Dialogue<State, InMemStorage<State>>;
+
+#[derive(Debug, Clone)]
+struct BasalReminderTick;
+
+/// Long-acting dose wasn't logged after reminder and follow-up
+#[derive(Debug, Clone)]
+pub struct BasalDoseMissed {
+  pub profile: Profile,
+  pub reminded_at: DateTime<Utc>,
+}
+
+/// Step of escalation reminder is waiting for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "snake_case")]
+pub enum Stage {
+  /// Next daily reminder
+  Scheduled,
+  /// Follow-up after unanswered reminder
+  Reminded,
+  /// Marking dose as missed after unanswered follow-up
+  FollowedUp,
+}
+
+/// Daily reminder to log long-acting dose with escalation
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasalReminder {
+  pub profile_id: ProfileId,
+  /// Time of daily reminder in profile timezone
+  pub time: NaiveTime,
+  /// Time between escalation steps
+  pub follow_up_hours: i64,
+  pub stage: Stage,
+  /// When reminder of running escalation was sent
+  pub reminded_at: Option<DateTime<Utc>>,
+  pub next_at: DateTime<Utc>,
+}
+
+/// What is due after [`BasalReminder::advance`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+  Remind,
+  FollowUp,
+  /// Dose after reminder at given time is missed
+  Missed(DateTime<Utc>),
+  /// Dose is logged or reminder is stale
+  Skip,
+}
+
+impl BasalReminder {
+  fn new<Tz: TimeZone>(
+    profile_id: ProfileId,
+    time: NaiveTime,
+    follow_up_hours: i64,
+    now: DateTime<Tz>,
+  ) -> Self {
+    Self {
+      profile_id,
+      time,
+      follow_up_hours,
+      stage: Stage::Scheduled,
+      reminded_at: None,
+      next_at: next_occurrence(time, now),
+    }
+  }
+
+  /// Start of period in which logged dose answers reminder
+  fn answered_since(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+    self.reminded_at.unwrap_or(now) - Duration::minutes(EARLY_MINUTES)
+  }
+
+  /// Moves to step due at `now` given whether long-acting dose was
+  /// logged since [`Self::answered_since`]
+  fn advance<Tz: TimeZone>(
+    &mut self,
+    now: DateTime<Tz>,
+    dose_logged: bool,
+  ) -> Step {
+    let utc = now.to_utc();
+    let follow_up = Duration::hours(self.follow_up_hours);
+    let is_stale =
+      utc - self.next_at > Duration::hours(MAX_DELAY_HOURS);
+    let step = match self.stage {
+      _ if dose_logged => Step::Skip,
+      Stage::Scheduled if is_stale => Step::Skip,
+      Stage::Scheduled => {
+        self.stage = Stage::Reminded;
+        self.reminded_at = Some(utc);
+        self.next_at = utc + follow_up;
+        return Step::Remind;
+      }
+      Stage::Reminded => {
+        self.stage = Stage::FollowedUp;
+        self.next_at = utc + follow_up;
+        return Step::FollowUp;
+      }
+      Stage::FollowedUp => {
+        Step::Missed(self.reminded_at.unwrap_or(self.next_at))
+      }
+    };
+    self.reset(now);
+    step
+  }
+
+  /// Stops escalation until next daily reminder
+  fn reset<Tz: TimeZone>(&mut self, now: DateTime<Tz>) {
+    self.stage = Stage::Scheduled;
+    self.reminded_at = None;
+    self.next_at = next_occurrence(self.time, now);
+  }
+}
+
+#[derive(Default, Clone)]
+enum State {
+  #[default]
+  Ignoring,
+  Accepting(ProfileId),
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn prepare(&self, di: &mut DependencyMap) {
+    di.insert(InMemStorage::<State>::new());
+  }
+
+  fn update_handler(&self) -> UpdateHandler {
+    filter_message()
+      .enter_dialogue::<Message, InMemStorage<State>, State>()
+      .branch(
+        dptree::entry()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::BasalReminder].endpoint(ask)),
+      )
+      .branch(case![State::Accepting(profile_id)].endpoint(accept))
+  }
+
+  fn event_handler(&self) -> EventHandler {
+    dptree::entry()
+      .branch(
+        filter_event::<InsulinInjectionAdded>()
+          .chain(handler(cancel_escalation)),
+      )
+      .branch(
+        filter_event::<BasalReminderTick>().chain(handler(send_due)),
+      )
+  }
+
+  fn schedule(
+    &self,
+    scheduler: &mut AsyncScheduler,
+    event_publisher: Arc<EventPublisher>,
+  ) {
+    scheduler.every(1.minute()).run(move || {
+      event_publisher.send(BasalReminderTick);
+      async {}
+    });
+  }
+}
+
+async fn ask(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+  dialogue: Dialog,
+) -> Result<()> {
+  let profile = current_profile(&db, user_id).await?;
+  let reminder = basal_reminders(&db).fetch(profile.id).await?;
+  let current = match &reminder {
+    Some(reminder) => format!(
+      "приходит в {}, повтор через {} ч",
+      reminder.time.format("%H:%M"),
+      reminder.follow_up_hours
+    ),
+    None => "выключено".into(),
+  };
+  bot
+    .send_message(
+      chat_id,
+      format!(
+        "Напоминание о длинном инсулине{} {current}. Если доза не \
+        записана, напоминание повторится, а еще через столько же \
+        доза будет отмечена пропущенной в сводке и опекуны получат \
+        уведомление.\n\n\
+        Отправьте время и, если нужно, интервал повтора в часах, \
+        например «22:00» или «22:00 3», или «выкл», чтобы отключить",
+        profile.tag()
+      ),
+    )
+    .await?;
+  dialogue
+    .update(State::Accepting(profile.id))
+    .await
+    .map_err(any)?;
+  Ok(())
+}
+
+async fn accept(
+  bot: Bot,
+  msg: Message,
+  db: Arc<Db>,
+  dialogue: Dialog,
+  profile_id: ProfileId,
+) -> Result<()> {
+  let text = msg.text().unwrap_or_default().trim();
+  let mut reminders = basal_reminders(&db);
+  let reply = if text.to_lowercase() == "выкл" {
+    reminders.remove(profile_id).await?;
+    "Напоминание о длинном инсулине отключено".into()
+  } else if let Some((time, hours)) = parse_settings(text) {
+    let previous = reminders.fetch(profile_id).await?;
+    let follow_up_hours = hours
+      .or(previous.map(|reminder| reminder.follow_up_hours))
+      .unwrap_or(DEFAULT_FOLLOW_UP_HOURS);
+    let tz = preferences(&db)
+      .fetch(profile_id)
+      .await?
+      .unwrap_or_default()
+      .timezone;
+    let reminder = BasalReminder::new(
+      profile_id,
+      time,
+      follow_up_hours,
+      Utc::now().with_timezone(&tz),
+    );
+    reminders.set(&reminder).await?;
+    format!(
+      "✅ Напоминание будет приходить каждый день в {}, повтор \
+      через {follow_up_hours} ч. Тихие часы: /quiet_hours",
+      time.format("%H:%M")
+    )
+  } else {
+    format!(
+      "Неправильный формат. Пример: «22:00» или «22:00 3», повтор \
+      от 1 до {MAX_FOLLOW_UP_HOURS} ч"
+    )
+  };
+  bot.send_message(msg.chat.id, reply).await?;
+  dialogue.reset().await.map_err(any)?;
+  Ok(())
+}
+
+/// Parses "22:00" or "22:00 3" with follow-up interval in hours
+fn parse_settings(s: &str) -> Option<(NaiveTime, Option<i64>)> {
+  let mut parts = s.split_whitespace();
+  let time =
+    NaiveTime::parse_from_str(parts.next()?, "%H:%M").ok()?;
+  let hours = match parts.next() {
+    Some(hours) => {
+      let hours: i64 = hours.parse().ok()?;
+      (1..=MAX_FOLLOW_UP_HOURS)
+        .contains(&hours)
+        .then_some(hours)?;
+      Some(hours)
+    }
+    None => None,
+  };
+  parts.next().is_none().then_some((time, hours))
+}
+
+async fn cancel_escalation(
+  db: Arc<Db>,
+  event: InsulinInjectionAdded,
+) -> Result<()> {
+  if event.injection.kind != Some(InsulinKind::Basal) {
+    return Ok(());
+  }
+  let mut reminders = basal_reminders(&db);
+  let Some(mut reminder) = reminders.fetch(event.profile.id).await?
+  else {
+    return Ok(());
+  };
+  if reminder.stage != Stage::Scheduled {
+    let tz = preferences(&db)
+      .fetch(event.profile.id)
+      .await?
+      .unwrap_or_default()
+      .timezone;
+    reminder.reset(Utc::now().with_timezone(&tz));
+    reminders.set(&reminder).await?;
+  }
+  Ok(())
+}
+
+async fn send_due(
+  bot: Bot,
+  db: Arc<Db>,
+  ep: Arc<EventPublisher>,
+) -> Result<()> {
+  let now = Utc::now();
+  let mut reminders = basal_reminders(&db);
+  for due in reminders.fetch_due(now).await? {
+    let DueReminder {
+      profile,
+      mut reminder,
+    } = due;
+    let tz = preferences(&db)
+      .fetch(profile.id)
+      .await?
+      .unwrap_or_default()
+      .timezone;
+    let local_now = now.with_timezone(&tz);
+    if reminder.stage != Stage::FollowedUp {
+      let quiet = quiet_hours(&db).fetch(profile.user_id).await?;
+      if quiet.is_some_and(|quiet| quiet.contains(local_now.time())) {
+        // Sent once quiet hours are over
+        continue;
+      }
+    }
+    let since = reminder.answered_since(now);
+    let dose_logged = insulin_injections(&db, profile.id)
+      .fetch_between(since, now)
+      .await?
+      .iter()
+      .any(|injection| injection.kind == Some(InsulinKind::Basal));
+    let step = reminder.advance(local_now, dose_logged);
+    reminders.set(&reminder).await?;
+    let text = match step {
+      Step::Remind => {
+        format!("💉 Время ввести длинный инсулин{}", profile.tag())
+      }
+      Step::FollowUp => format!(
+        "⏰ Длинный инсулин{} еще не записан, напоминание было в \
+        {}. Если доза уже введена, запишите ее",
+        profile.tag(),
+        reminder
+          .reminded_at
+          .unwrap_or(reminder.next_at)
+          .with_timezone(&tz)
+          .format("%H:%M")
+      ),
+      Step::Missed(reminded_at) => {
+        reminders.add_missed(profile.id, reminded_at).await?;
+        ep.send(BasalDoseMissed {
+          profile,
+          reminded_at,
+        });
+        continue;
+      }
+      Step::Skip => continue,
+    };
+    let keyboard = InlineKeyboardMarkup::new([[log_button(
+      "💉 Записать дозу",
+      profile.id,
+      InsulinKind::Basal,
+    )]]);
+    let res = bot
+      .send_message(profile.user_id, text)
+      .reply_markup(keyboard)
+      .await;
+    match res {
+      Ok(_) => {}
+      Err(RequestError::Api(ApiError::BotBlocked)) => {
+        users(&db).disable(profile.user_id).await?;
+      }
+      // Other reminders due at the same tick are still sent
+      Err(err) => log::error!(
+        "Basal reminder of {} isn't sent: {err}",
+        profile.user_id
+      ),
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::NaiveDateTime;
+  use chrono_tz::{Asia::Vladivostok, Tz};
+
+  use super::*;
+
+  fn local(s: &str) -> DateTime<Tz> {
+    let naive =
+      NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+    naive.and_local_timezone(Vladivostok).unwrap()
+  }
+
+  fn reminder() -> BasalReminder {
+    let time = NaiveTime::from_hms_opt(22, 0, 0).unwrap();
+    BasalReminder::new(
+      ProfileId(1),
+      time,
+      2,
+      local("2024-03-01 12:00"),
+    )
+  }
+
+  #[test]
+  fn escalates_unanswered_reminder() {
+    let mut reminder = reminder();
+    assert_eq!(reminder.next_at, local("2024-03-01 22:00").to_utc());
+    assert_eq!(
+      reminder.next_at,
+      "2024-03-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap()
+    );
+    let reminded_at = local("2024-03-01 22:00");
+    assert_eq!(reminder.advance(reminded_at, false), Step::Remind);
+    assert_eq!(reminder.stage, Stage::Reminded);
+    assert_eq!(reminder.next_at, local("2024-03-02 00:00").to_utc());
+    assert_eq!(
+      reminder.advance(local("2024-03-02 00:00"), false),
+      Step::FollowUp
+    );
+    assert_eq!(reminder.next_at, local("2024-03-02 02:00").to_utc());
+    assert_eq!(
+      reminder.advance(local("2024-03-02 02:00"), false),
+      Step::Missed(reminded_at.to_utc())
+    );
+    assert_eq!(reminder.stage, Stage::Scheduled);
+    assert_eq!(reminder.reminded_at, None);
+    assert_eq!(reminder.next_at, local("2024-03-02 22:00").to_utc());
+  }
+
+  #[test]
+  fn logged_dose_stops_escalation() {
+    let mut reminder = reminder();
+    reminder.advance(local("2024-03-01 22:00"), false);
+    assert_eq!(
+      reminder.answered_since(Utc::now()),
+      local("2024-03-01 20:00").to_utc()
+    );
+    assert_eq!(
+      reminder.advance(local("2024-03-02 00:00"), true),
+      Step::Skip
+    );
+    assert_eq!(reminder.stage, Stage::Scheduled);
+    assert_eq!(reminder.next_at, local("2024-03-02 22:00").to_utc());
+  }
+
+  #[test]
+  fn skips_stale_reminder() {
+    let mut reminder = reminder();
+    assert_eq!(
+      reminder.advance(local("2024-03-02 11:00"), false),
+      Step::Skip
+    );
+    assert_eq!(reminder.next_at, local("2024-03-02 22:00").to_utc());
+  }
+
+  #[test]
+  fn parses_settings() {
+    let time = |s| NaiveTime::parse_from_str(s, "%H:%M").unwrap();
+    assert_eq!(parse_settings("22:00"), Some((time("22:00"), None)));
+    assert_eq!(
+      parse_settings(" 08:30  3 "),
+      Some((time("08:30"), Some(3)))
+    );
+    assert_eq!(parse_settings("22:00 0"), None);
+    assert_eq!(parse_settings("22:00 12"), None);
+    assert_eq!(parse_settings("22:00 3 ч"), None);
+    assert_eq!(parse_settings("25:00"), None);
+  }
+}
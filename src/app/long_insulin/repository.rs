@@ -0,0 +1,247 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use teloxide::types::UserId;
+
+use crate::{
+  app::profile::{Profile, ProfileId},
+  db::{txn::ExecutorHolder, Db},
+};
+
+use super::{BasalReminder, Stage};
+
+pub fn basal_reminders(db: &Db) -> Repository {
+  Repository { exec: db.exec() }
+}
+
+pub struct Repository {
+  exec: ExecutorHolder,
+}
+
+/// Reminder which next step is due
+#[derive(Debug, Clone, PartialEq)]
+pub struct DueReminder {
+  pub profile: Profile,
+  pub reminder: BasalReminder,
+}
+
+impl Repository {
+  /// Reminder of profile, `None` if disabled
+  pub async fn fetch(
+    &self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<Option<BasalReminder>> {
+    sqlx::query!(
+      r#"
+        SELECT
+          time AS "time: NaiveTime",
+          follow_up_hours,
+          stage AS "stage: Stage",
+          reminded_at,
+          next_at
+        FROM basal_reminders
+        WHERE profile_id = ?
+      "#,
+      profile_id.0
+    )
+    .map(|rec| BasalReminder {
+      profile_id,
+      time: rec.time,
+      follow_up_hours: rec.follow_up_hours,
+      stage: rec.stage,
+      reminded_at: rec.reminded_at.map(|dt| dt.and_utc()),
+      next_at: rec.next_at.and_utc(),
+    })
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Saves reminder replacing previous state
+  pub async fn set(
+    &mut self,
+    reminder: &BasalReminder,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      r#"
+        REPLACE INTO basal_reminders (
+          profile_id,
+          time,
+          follow_up_hours,
+          stage,
+          reminded_at,
+          next_at
+        )
+        VALUES (?, ?, ?, ?, ?, ?)
+      "#,
+      reminder.profile_id.0,
+      reminder.time,
+      reminder.follow_up_hours,
+      reminder.stage,
+      reminder.reminded_at,
+      reminder.next_at
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  pub async fn remove(
+    &mut self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      "DELETE FROM basal_reminders WHERE profile_id = ?",
+      profile_id.0
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  /// Reminders due at `now` of users who haven't disabled the bot
+  #[allow(clippy::cast_sign_loss)]
+  pub async fn fetch_due(
+    &self,
+    now: DateTime<Utc>,
+  ) -> sqlx::Result<Vec<DueReminder>> {
+    sqlx::query!(
+      r#"
+        SELECT
+          profiles.id AS profile_id,
+          profiles.user_id,
+          profiles.name,
+          basal_reminders.time AS "time: NaiveTime",
+          basal_reminders.follow_up_hours,
+          basal_reminders.stage AS "stage: Stage",
+          basal_reminders.reminded_at,
+          basal_reminders.next_at
+        FROM basal_reminders
+        JOIN profiles ON profiles.id = basal_reminders.profile_id
+        JOIN users ON users.id = profiles.user_id
+        WHERE users.disabled = FALSE AND basal_reminders.next_at <= ?
+      "#,
+      now
+    )
+    .map(|rec| DueReminder {
+      profile: Profile {
+        id: ProfileId(rec.profile_id),
+        user_id: UserId(rec.user_id as _),
+        name: rec.name,
+      },
+      reminder: BasalReminder {
+        profile_id: ProfileId(rec.profile_id),
+        time: rec.time,
+        follow_up_hours: rec.follow_up_hours,
+        stage: rec.stage,
+        reminded_at: rec.reminded_at.map(|dt| dt.and_utc()),
+        next_at: rec.next_at.and_utc(),
+      },
+    })
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Records that dose wasn't logged after reminder at `reminded_at`
+  pub async fn add_missed(
+    &mut self,
+    profile_id: ProfileId,
+    reminded_at: DateTime<Utc>,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      r#"
+        INSERT OR IGNORE INTO missed_basal_doses (profile_id, reminded_at)
+        VALUES (?, ?)
+      "#,
+      profile_id.0,
+      reminded_at
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  /// Reminder times of doses missed in `[start, end)` in
+  /// chronological order
+  pub async fn fetch_missed_between(
+    &self,
+    profile_id: ProfileId,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+  ) -> sqlx::Result<Vec<DateTime<Utc>>> {
+    sqlx::query!(
+      r#"
+        SELECT reminded_at
+        FROM missed_basal_doses
+        WHERE profile_id = ? AND reminded_at >= ? AND reminded_at < ?
+        ORDER BY reminded_at
+      "#,
+      profile_id.0,
+      start,
+      end
+    )
+    .map(|rec| rec.reminded_at.and_utc())
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::Duration;
+
+  use crate::{
+    app::{profile::repository::profiles, user::repository::users},
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn set_fetch_due_and_record_missed() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      users(&test_db).add(user).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let now: DateTime<Utc> =
+        "2024-03-01T19:00:00Z".parse().unwrap();
+      let reminder = BasalReminder {
+        profile_id: profile.id,
+        time: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+        follow_up_hours: 2,
+        stage: Stage::Reminded,
+        reminded_at: Some(now - Duration::hours(2)),
+        next_at: now,
+      };
+      let mut reminders = basal_reminders(&test_db);
+      assert_eq!(reminders.fetch(profile.id).await.unwrap(), None);
+      reminders.set(&reminder).await.unwrap();
+      assert_eq!(
+        reminders.fetch(profile.id).await.unwrap(),
+        Some(reminder.clone())
+      );
+      let before = now - Duration::minutes(1);
+      assert!(reminders.fetch_due(before).await.unwrap().is_empty());
+      assert_eq!(
+        reminders.fetch_due(now).await.unwrap(),
+        vec![DueReminder {
+          profile: profile.clone(),
+          reminder: reminder.clone(),
+        }]
+      );
+      let reminded_at = reminder.reminded_at.unwrap();
+      reminders.add_missed(profile.id, reminded_at).await.unwrap();
+      reminders.add_missed(profile.id, reminded_at).await.unwrap();
+      let day = Duration::days(1);
+      assert_eq!(
+        reminders
+          .fetch_missed_between(profile.id, now - day, now)
+          .await
+          .unwrap(),
+        vec![reminded_at]
+      );
+      reminders.remove(profile.id).await.unwrap();
+      assert_eq!(reminders.fetch(profile.id).await.unwrap(), None);
+    })
+    .await
+    .unwrap();
+  }
+}
@@ -0,0 +1,143 @@
+pub mod repository;
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use teloxide::{
+  dispatching::dialogue::InMemStorage, dptree::case, prelude::*,
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::Db,
+  utils::{event_publisher::EventPublisher, filter_message},
+};
+
+use self::repository::meals;
+
+use super::{
+  profile::{current_profile, Profile},
+  UpdateHandler,
+};
+
+/// Upper bound of carbs in one meal, g
+const MAX_CARBS_GRAMS: f64 = 500.0;
+const NOTE_MAX_LEN: usize = 200;
+
+type Dialog = Dialogue<State, InMemStorage<State>>;
+
+#[derive(Debug, Clone)]
+pub struct MealAdded {
+  pub profile: Profile,
+  pub meal: Meal,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Meal {
+  pub date_time: DateTime<Utc>,
+  pub carbs_grams: f64,
+  pub note: Option<String>,
+}
+
+impl Meal {
+  pub fn from_now(carbs_grams: f64, note: Option<String>) -> Self {
+    let date_time = Utc::now();
+    Self {
+      date_time,
+      carbs_grams,
+      note,
+    }
+  }
+}
+
+#[derive(Default, Clone)]
+enum State {
+  #[default]
+  Ignoring,
+  Accepting,
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn prepare(&self, di: &mut DependencyMap) {
+    di.insert(InMemStorage::<State>::new());
+  }
+
+  fn update_handler(&self) -> UpdateHandler {
+    filter_message()
+      .enter_dialogue::<Message, InMemStorage<State>, State>()
+      .branch(
+        dptree::entry()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::Meal].endpoint(ask)),
+      )
+      .branch(case![State::Accepting].endpoint(accept))
+  }
+}
+
+async fn ask(
+  bot: Bot,
+  chat_id: ChatId,
+  dialogue: Dialog,
+) -> Result<()> {
+  bot
+    .send_message(
+      chat_id,
+      "Отправьте углеводы в граммах и, если нужно, заметку, \
+      например «45 овсянка». Для заметки без еды укажите 0",
+    )
+    .await?;
+  dialogue.update(State::Accepting).await.map_err(any)?;
+  Ok(())
+}
+
+async fn accept(
+  bot: Bot,
+  msg: Message,
+  user_id: UserId,
+  db: Arc<Db>,
+  ep: Arc<EventPublisher>,
+  dialogue: Dialog,
+) -> Result<()> {
+  if let Some((carbs_grams, note)) = parse(msg.text()) {
+    let profile = current_profile(&db, user_id).await?;
+    let meal = Meal::from_now(carbs_grams, note);
+    meals(&db, profile.id).add(&meal).await?;
+    bot
+      .send_message(msg.chat.id, format!("✅{}", profile.tag()))
+      .await?;
+    ep.send(MealAdded { profile, meal });
+  } else {
+    bot
+      .send_message(
+        msg.chat.id,
+        format!(
+          "Неправильный формат. Пример: «45 овсянка». Углеводы — \
+          до {MAX_CARBS_GRAMS} г, заметка — до {NOTE_MAX_LEN} \
+          символов"
+        ),
+      )
+      .await?;
+  }
+  dialogue.reset().await.map_err(any)?;
+  Ok(())
+}
+
+/// Parses "carbs [note]"
+fn parse(s: Option<&str>) -> Option<(f64, Option<String>)> {
+  let s = s?.trim();
+  let (carbs, note) =
+    s.split_once(char::is_whitespace).unwrap_or((s, ""));
+  let carbs: f64 = carbs.replace(',', ".").parse().ok()?;
+  if !(0.0..=MAX_CARBS_GRAMS).contains(&carbs) {
+    return None;
+  }
+  let note = note.trim();
+  if note.chars().count() > NOTE_MAX_LEN {
+    return None;
+  }
+  Some((carbs, (!note.is_empty()).then(|| note.to_string())))
+}
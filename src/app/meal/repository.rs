@@ -0,0 +1,125 @@
+use chrono::{DateTime, Utc};
+
+use crate::{
+  app::profile::ProfileId,
+  db::{txn::ExecutorHolder, Db},
+};
+
+use super::Meal;
+
+pub fn meals(db: &Db, profile_id: ProfileId) -> Repository {
+  let exec = db.exec();
+  Repository { profile_id, exec }
+}
+
+pub struct Repository {
+  profile_id: ProfileId,
+  exec: ExecutorHolder,
+}
+
+impl Repository {
+  /// Meals in `[start, end)` in chronological order
+  pub async fn fetch_between(
+    &self,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+  ) -> sqlx::Result<Vec<Meal>> {
+    let profile_id = self.profile_id.0;
+    sqlx::query!(
+      r#"
+        SELECT date_time, carbs_grams, note
+        FROM meals
+        WHERE profile_id = ? AND date_time >= ? AND date_time < ?
+        ORDER BY date_time
+      "#,
+      profile_id,
+      start,
+      end
+    )
+    .map(|rec| Meal {
+      date_time: rec.date_time.and_utc(),
+      carbs_grams: rec.carbs_grams,
+      note: rec.note,
+    })
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  pub async fn add(&mut self, meal: &Meal) -> sqlx::Result<()> {
+    let profile_id = self.profile_id.0;
+    sqlx::query!(
+      r#"
+        INSERT INTO meals (profile_id, date_time, carbs_grams, note)
+        VALUES (?, ?, ?, ?)
+      "#,
+      profile_id,
+      meal.date_time,
+      meal.carbs_grams,
+      meal.note
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  /// Adds meal unless there is one at the same time. Returns `true`
+  /// if added.
+  pub async fn add_if_absent(
+    &mut self,
+    meal: &Meal,
+  ) -> sqlx::Result<bool> {
+    let profile_id = self.profile_id.0;
+    let res = sqlx::query!(
+      r#"
+        INSERT OR IGNORE INTO meals (
+          profile_id,
+          date_time,
+          carbs_grams,
+          note
+        )
+        VALUES (?, ?, ?, ?)
+      "#,
+      profile_id,
+      meal.date_time,
+      meal.carbs_grams,
+      meal.note
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(res.rows_affected() > 0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::Duration;
+  use teloxide::types::UserId;
+
+  use crate::{
+    app::{profile::repository::profiles, user::repository::users},
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn add_and_fetch_between() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      let meal = Meal::from_now(45.0, Some("овсянка".into()));
+      users(&test_db).add(user).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let mut meals = meals(&test_db, profile.id);
+      meals.add(&meal).await.unwrap();
+      let (start, end) =
+        (meal.date_time, meal.date_time + Duration::minutes(1));
+      assert_eq!(
+        meals.fetch_between(start, end).await.unwrap(),
+        std::slice::from_ref(&meal)
+      );
+    })
+    .await
+    .unwrap();
+  }
+}
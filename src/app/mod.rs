@@ -1,8 +1,33 @@
+mod agp;
+mod basal_test;
+mod caregiver;
+mod chart;
+mod daily_summary;
+mod dashboard;
+mod emergency_contact;
+mod export;
+mod forecast;
+mod hba1c;
 mod help;
+mod history;
+mod hypo_recheck;
+mod import;
+mod insights;
 mod insulin_injection;
+mod invite;
 mod long_insulin;
+mod meal;
+mod personal_data;
+mod plausibility;
+mod preferences;
+mod profile;
+mod quiet_hours;
+mod ratios;
+mod report;
+mod stats;
 mod sugar_measurement;
 mod user;
+mod weekly_digest;
 
 use std::sync::Arc;
 
@@ -45,10 +70,33 @@ pub trait Plugin {
 
 pub fn plugins() -> Vec<Box<dyn Plugin>> {
   vec![
+    Box::new(agp::Plugin),
+    Box::new(basal_test::Plugin),
+    Box::new(caregiver::Plugin),
+    Box::new(chart::Plugin),
+    Box::new(daily_summary::Plugin),
+    Box::new(dashboard::Plugin),
+    Box::new(emergency_contact::Plugin),
+    Box::new(export::Plugin),
+    Box::new(forecast::Plugin),
+    Box::new(hba1c::Plugin),
     Box::new(help::Plugin),
+    Box::new(history::Plugin),
+    Box::new(hypo_recheck::Plugin),
+    Box::new(import::Plugin),
+    Box::new(insights::Plugin),
     Box::new(insulin_injection::Plugin),
     Box::new(long_insulin::Plugin),
+    Box::new(meal::Plugin),
+    Box::new(personal_data::Plugin),
+    Box::new(preferences::Plugin),
+    Box::new(profile::Plugin),
+    Box::new(quiet_hours::Plugin),
+    Box::new(ratios::Plugin),
+    Box::new(report::Plugin),
+    Box::new(stats::Plugin),
     Box::new(sugar_measurement::Plugin),
     Box::new(user::Plugin),
+    Box::new(weekly_digest::Plugin),
   ]
 }
@@ -0,0 +1,328 @@
+pub mod repository;
+
+use std::sync::Arc;
+
+use chrono::{NaiveDateTime, NaiveTime};
+use serde::Serialize;
+use teloxide::{
+  dptree::case,
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile},
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::{txn, Db},
+  utils::{filter_callback_query, filter_message},
+};
+
+use self::repository::personal_data;
+
+use super::UpdateHandler;
+
+const CALLBACK_PREFIX: &str = "delete_me:";
+
+/// Everything stored about user. Times are in UTC.
+#[derive(Debug, Serialize)]
+pub struct PersonalData {
+  pub user_id: u64,
+  pub disabled: bool,
+  pub profiles: Vec<ProfileData>,
+  /// Links to profiles of other users the user cares for
+  pub caregiver_of: Vec<CaregiverLink>,
+  /// Profiles of other users the user is emergency contact of
+  pub emergency_contact_of: Vec<i64>,
+  pub alert_acknowledgements: Vec<Acknowledgement>,
+  pub quiet_hours: Option<QuietHours>,
+  /// Pinned message with today's data
+  pub dashboard: Option<Dashboard>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfileData {
+  pub id: i64,
+  /// `None` for user's own profile
+  pub name: Option<String>,
+  pub current: bool,
+  pub sugar_measurements: Vec<SugarMeasurement>,
+  pub insulin_injections: Vec<InsulinInjection>,
+  pub meals: Vec<Meal>,
+  pub hba1c_results: Vec<Hba1cResult>,
+  pub caregivers: Vec<CaregiverLink>,
+  pub emergency_contacts: Vec<u64>,
+  pub emergency_alerts: Vec<EmergencyAlert>,
+  pub invites: Vec<Invite>,
+  pub hypo_recheck: Option<HypoRecheck>,
+  pub daily_summary: Option<DailySummary>,
+  /// Next weekly digest, `None` if disabled
+  pub weekly_digest_at: Option<NaiveDateTime>,
+  pub basal_test: Option<BasalTest>,
+  /// `None` if defaults weren't changed
+  pub insulin_safety_rules: Option<InsulinSafetyRules>,
+  pub basal_reminder: Option<BasalReminder>,
+  /// Reminders after which long-acting dose wasn't logged
+  pub missed_basal_doses: Vec<NaiveDateTime>,
+  /// `None` if defaults weren't changed
+  pub preferences: Option<Preferences>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SugarMeasurement {
+  pub date_time: NaiveDateTime,
+  pub millimoles_per_liter: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InsulinInjection {
+  pub date_time: NaiveDateTime,
+  pub cubic_centimeters: f64,
+  pub kind: Option<String>,
+  /// Safety warnings confirmed on entry
+  pub warnings: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Meal {
+  pub date_time: NaiveDateTime,
+  pub carbs_grams: f64,
+  pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Hba1cResult {
+  pub date_time: NaiveDateTime,
+  pub percent: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CaregiverLink {
+  pub profile_id: i64,
+  pub caregiver_id: u64,
+  pub permission: String,
+  pub entry_notifications: String,
+  pub missed_reminder_notifications: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmergencyAlert {
+  pub id: i64,
+  pub date_time: NaiveDateTime,
+  pub reason: String,
+  pub millimoles_per_liter: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Invite {
+  pub code: String,
+  pub kind: String,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HypoRecheck {
+  pub prompt_at: NaiveDateTime,
+  pub prompted: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Acknowledgement {
+  pub alert_id: i64,
+  pub date_time: NaiveDateTime,
+}
+
+/// Local time interval without scheduled messages
+#[derive(Debug, Serialize)]
+pub struct QuietHours {
+  pub start: NaiveTime,
+  pub end: NaiveTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Dashboard {
+  pub message_id: i64,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DailySummary {
+  /// Local time summary is sent at
+  pub time: NaiveTime,
+  pub next_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BasalTest {
+  pub skipped_meal: String,
+  pub started_at: NaiveDateTime,
+  pub ends_at: NaiveDateTime,
+  pub next_prompt_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InsulinSafetyRules {
+  pub max_bolus: f64,
+  pub max_basal: f64,
+  pub stacking_warning: bool,
+  pub double_basal_warning: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Preferences {
+  pub timezone: String,
+  pub units: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BasalReminder {
+  /// Local time reminder is sent at
+  pub time: NaiveTime,
+  pub follow_up_hours: i64,
+  pub stage: String,
+  pub reminded_at: Option<NaiveDateTime>,
+  pub next_at: NaiveDateTime,
+}
+
+/// Step of account deletion chosen by button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Deletion {
+  /// First confirmation
+  Confirm,
+  /// Second confirmation, deletes data
+  Delete,
+  Cancel,
+}
+
+impl Deletion {
+  fn parse(data: &str) -> Option<Self> {
+    match data.strip_prefix(CALLBACK_PREFIX)? {
+      "confirm" => Some(Self::Confirm),
+      "delete" => Some(Self::Delete),
+      "cancel" => Some(Self::Cancel),
+      _ => None,
+    }
+  }
+
+  fn button(self, label: &str) -> InlineKeyboardButton {
+    let data = match self {
+      Self::Confirm => "confirm",
+      Self::Delete => "delete",
+      Self::Cancel => "cancel",
+    };
+    InlineKeyboardButton::callback(
+      label,
+      format!("{CALLBACK_PREFIX}{data}"),
+    )
+  }
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    dptree::entry()
+      .branch(
+        filter_message()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::MyData].endpoint(send_data))
+          .branch(
+            case![MenuCommand::DeleteMe].endpoint(ask_deletion),
+          ),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            Deletion::parse(query.data.as_deref()?)
+          })
+          .endpoint(delete),
+      )
+  }
+}
+
+async fn send_data(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+) -> Result<()> {
+  let data = personal_data(&db, user_id).fetch().await?;
+  let json = serde_json::to_vec_pretty(&data).map_err(any)?;
+  bot
+    .send_document(
+      chat_id,
+      InputFile::memory(json).file_name("my_data.json"),
+    )
+    .caption(
+      "Все данные, которые бот хранит о вас. Время указано в UTC",
+    )
+    .await?;
+  Ok(())
+}
+
+async fn ask_deletion(bot: Bot, chat_id: ChatId) -> Result<()> {
+  let keyboard = InlineKeyboardMarkup::new([[
+    Deletion::Confirm.button("Удалить"),
+    Deletion::Cancel.button("Отмена"),
+  ]]);
+  bot
+    .send_message(
+      chat_id,
+      "Будут удалены ваш аккаунт, все профили с измерениями, \
+      инъекциями и заметками, а также связи с опекунами и \
+      экстренными контактами. Сохранить данные можно командой \
+      /my_data",
+    )
+    .reply_markup(keyboard)
+    .await?;
+  Ok(())
+}
+
+async fn delete(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  deletion: Deletion,
+  query: CallbackQuery,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  match deletion {
+    Deletion::Confirm => {
+      let keyboard = InlineKeyboardMarkup::new([[
+        Deletion::Delete.button("Удалить навсегда"),
+        Deletion::Cancel.button("Отмена"),
+      ]]);
+      bot
+        .edit_message_text(
+          msg.chat.id,
+          msg.id,
+          "Вы уверены? Удаление нельзя отменить",
+        )
+        .reply_markup(keyboard)
+        .await?;
+    }
+    Deletion::Delete => {
+      txn::begin(db.pool(), async {
+        personal_data(&db, user_id).delete().await?;
+        txn::commit().await
+      })
+      .await??;
+      bot
+        .edit_message_text(
+          msg.chat.id,
+          msg.id,
+          "Ваши данные удалены. Чтобы начать заново, отправьте /start",
+        )
+        .await?;
+    }
+    Deletion::Cancel => {
+      bot
+        .edit_message_text(msg.chat.id, msg.id, "Удаление отменено")
+        .await?;
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,644 @@
+use chrono::NaiveTime;
+use teloxide::types::UserId;
+
+use crate::db::{txn::ExecutorHolder, Db};
+
+use super::{
+  Acknowledgement, BasalReminder, BasalTest, CaregiverLink,
+  DailySummary, Dashboard, EmergencyAlert, Hba1cResult, HypoRecheck,
+  InsulinInjection, InsulinSafetyRules, Invite, Meal, PersonalData,
+  Preferences, ProfileData, QuietHours, SugarMeasurement,
+};
+
+pub fn personal_data(db: &Db, user_id: UserId) -> Repository {
+  let exec = db.exec();
+  Repository { user_id, exec }
+}
+
+pub struct Repository {
+  user_id: UserId,
+  exec: ExecutorHolder,
+}
+
+impl Repository {
+  /// Collects every row about user and their profiles
+  #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+  pub async fn fetch(&self) -> sqlx::Result<PersonalData> {
+    let user_id = self.user_id.0 as i64;
+    let user = sqlx::query!(
+      "SELECT disabled, current_profile_id FROM users WHERE id = ?",
+      user_id
+    )
+    .fetch_optional(&mut self.exec.borrow())
+    .await?;
+    let current_profile_id =
+      user.as_ref().and_then(|user| user.current_profile_id);
+    let profiles = sqlx::query!(
+      "SELECT id, name FROM profiles WHERE user_id = ?",
+      user_id
+    )
+    .fetch_all(&mut self.exec.borrow())
+    .await?;
+    let caregiver_of = self.fetch_caregiver_of().await?;
+    let emergency_contact_of = sqlx::query!(
+      "SELECT profile_id FROM emergency_contacts WHERE contact_id = ?",
+      user_id
+    )
+    .map(|rec| rec.profile_id)
+    .fetch_all(&mut self.exec.borrow())
+    .await?;
+    let alert_acknowledgements = sqlx::query_as!(
+      Acknowledgement,
+      r#"
+        SELECT alert_id, date_time
+        FROM emergency_alert_acknowledgements
+        WHERE contact_id = ?
+      "#,
+      user_id
+    )
+    .fetch_all(&mut self.exec.borrow())
+    .await?;
+    let quiet_hours = sqlx::query_as!(
+      QuietHours,
+      r#"
+        SELECT start AS "start: NaiveTime", end AS "end: NaiveTime"
+        FROM quiet_hours
+        WHERE user_id = ?
+      "#,
+      user_id
+    )
+    .fetch_optional(&mut self.exec.borrow())
+    .await?;
+    let dashboard = sqlx::query_as!(
+      Dashboard,
+      "SELECT message_id, created_at FROM dashboards WHERE user_id = ?",
+      user_id
+    )
+    .fetch_optional(&mut self.exec.borrow())
+    .await?;
+    let mut data = PersonalData {
+      user_id: self.user_id.0,
+      disabled: user.is_some_and(|user| user.disabled),
+      profiles: Vec::new(),
+      caregiver_of,
+      emergency_contact_of,
+      alert_acknowledgements,
+      quiet_hours,
+      dashboard,
+    };
+    for profile in profiles {
+      let profile = self
+        .fetch_profile(profile.id, profile.name, current_profile_id)
+        .await?;
+      data.profiles.push(profile);
+    }
+    Ok(data)
+  }
+
+  #[allow(clippy::cast_sign_loss)]
+  async fn fetch_caregiver_of(
+    &self,
+  ) -> sqlx::Result<Vec<CaregiverLink>> {
+    let user_id = self.user_id.0 as i64;
+    sqlx::query!(
+      r#"
+        SELECT
+          profile_id,
+          caregiver_id,
+          permission,
+          entry_notifications,
+          missed_reminder_notifications
+        FROM user_links
+        WHERE caregiver_id = ?
+      "#,
+      user_id
+    )
+    .map(|rec| CaregiverLink {
+      profile_id: rec.profile_id,
+      caregiver_id: rec.caregiver_id as _,
+      permission: rec.permission,
+      entry_notifications: rec.entry_notifications,
+      missed_reminder_notifications: rec
+        .missed_reminder_notifications,
+    })
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  #[allow(clippy::cast_sign_loss)]
+  async fn fetch_profile(
+    &self,
+    id: i64,
+    name: Option<String>,
+    current_profile_id: Option<i64>,
+  ) -> sqlx::Result<ProfileData> {
+    let exec = &self.exec;
+    let sugar_measurements = sqlx::query_as!(
+      SugarMeasurement,
+      r#"
+        SELECT date_time, millimoles_per_liter
+        FROM sugar_measurements
+        WHERE profile_id = ?
+        ORDER BY date_time
+      "#,
+      id
+    )
+    .fetch_all(&mut exec.borrow())
+    .await?;
+    let insulin_injections = sqlx::query_as!(
+      InsulinInjection,
+      r#"
+        SELECT date_time, cubic_centimeters, kind, warnings
+        FROM insulin_injections
+        WHERE profile_id = ?
+        ORDER BY date_time
+      "#,
+      id
+    )
+    .fetch_all(&mut exec.borrow())
+    .await?;
+    let meals = sqlx::query_as!(
+      Meal,
+      r#"
+        SELECT date_time, carbs_grams, note
+        FROM meals
+        WHERE profile_id = ?
+        ORDER BY date_time
+      "#,
+      id
+    )
+    .fetch_all(&mut exec.borrow())
+    .await?;
+    let hba1c_results = sqlx::query_as!(
+      Hba1cResult,
+      r#"
+        SELECT date_time, percent
+        FROM hba1c_results
+        WHERE profile_id = ?
+        ORDER BY date_time
+      "#,
+      id
+    )
+    .fetch_all(&mut exec.borrow())
+    .await?;
+    let caregivers = sqlx::query!(
+      r#"
+        SELECT
+          profile_id,
+          caregiver_id,
+          permission,
+          entry_notifications,
+          missed_reminder_notifications
+        FROM user_links
+        WHERE profile_id = ?
+      "#,
+      id
+    )
+    .map(|rec| CaregiverLink {
+      profile_id: rec.profile_id,
+      caregiver_id: rec.caregiver_id as _,
+      permission: rec.permission,
+      entry_notifications: rec.entry_notifications,
+      missed_reminder_notifications: rec
+        .missed_reminder_notifications,
+    })
+    .fetch_all(&mut exec.borrow())
+    .await?;
+    let emergency_contacts = sqlx::query!(
+        "SELECT contact_id FROM emergency_contacts WHERE profile_id = ?",
+        id
+      )
+      .map(|rec| rec.contact_id as _)
+      .fetch_all(&mut exec.borrow())
+      .await?;
+    let emergency_alerts = sqlx::query_as!(
+      EmergencyAlert,
+      r#"
+        SELECT id, date_time, reason, millimoles_per_liter
+        FROM emergency_alerts
+        WHERE profile_id = ?
+        ORDER BY date_time
+      "#,
+      id
+    )
+    .fetch_all(&mut exec.borrow())
+    .await?;
+    let invites = sqlx::query_as!(
+        Invite,
+        "SELECT code, kind, created_at FROM invites WHERE profile_id = ?",
+        id
+      )
+      .fetch_all(&mut exec.borrow())
+      .await?;
+    let hypo_recheck = sqlx::query_as!(
+      HypoRecheck,
+      r#"
+        SELECT prompt_at, prompted
+        FROM hypo_rechecks
+        WHERE profile_id = ?
+      "#,
+      id
+    )
+    .fetch_optional(&mut exec.borrow())
+    .await?;
+    let daily_summary = sqlx::query_as!(
+      DailySummary,
+      r#"
+        SELECT time AS "time: NaiveTime", next_at
+        FROM daily_summaries
+        WHERE profile_id = ?
+      "#,
+      id
+    )
+    .fetch_optional(&mut exec.borrow())
+    .await?;
+    let weekly_digest_at = sqlx::query!(
+      "SELECT next_at FROM weekly_digests WHERE profile_id = ?",
+      id
+    )
+    .map(|rec| rec.next_at)
+    .fetch_optional(&mut exec.borrow())
+    .await?;
+    let basal_test = sqlx::query_as!(
+      BasalTest,
+      r#"
+        SELECT skipped_meal, started_at, ends_at, next_prompt_at
+        FROM basal_tests
+        WHERE profile_id = ?
+      "#,
+      id
+    )
+    .fetch_optional(&mut exec.borrow())
+    .await?;
+    let insulin_safety_rules = sqlx::query_as!(
+      InsulinSafetyRules,
+      r#"
+        SELECT
+          max_bolus,
+          max_basal,
+          stacking_warning,
+          double_basal_warning
+        FROM insulin_safety_rules
+        WHERE profile_id = ?
+      "#,
+      id
+    )
+    .fetch_optional(&mut exec.borrow())
+    .await?;
+    let basal_reminder = sqlx::query_as!(
+      BasalReminder,
+      r#"
+        SELECT
+          time AS "time: NaiveTime",
+          follow_up_hours,
+          stage,
+          reminded_at,
+          next_at
+        FROM basal_reminders
+        WHERE profile_id = ?
+      "#,
+      id
+    )
+    .fetch_optional(&mut exec.borrow())
+    .await?;
+    let missed_basal_doses = sqlx::query!(
+      r#"
+        SELECT reminded_at
+        FROM missed_basal_doses
+        WHERE profile_id = ?
+        ORDER BY reminded_at
+      "#,
+      id
+    )
+    .map(|rec| rec.reminded_at)
+    .fetch_all(&mut exec.borrow())
+    .await?;
+    let preferences = sqlx::query_as!(
+      Preferences,
+      r#"
+        SELECT timezone, units
+        FROM profile_preferences
+        WHERE profile_id = ?
+      "#,
+      id
+    )
+    .fetch_optional(&mut exec.borrow())
+    .await?;
+    Ok(ProfileData {
+      id,
+      name,
+      current: current_profile_id == Some(id),
+      sugar_measurements,
+      insulin_injections,
+      meals,
+      hba1c_results,
+      caregivers,
+      emergency_contacts,
+      emergency_alerts,
+      invites,
+      hypo_recheck,
+      daily_summary,
+      weekly_digest_at,
+      basal_test,
+      insulin_safety_rules,
+      basal_reminder,
+      missed_basal_doses,
+      preferences,
+    })
+  }
+
+  /// Hard-deletes user, their profiles with all records and links to
+  /// other users' profiles. Call in shared transaction to delete
+  /// atomically.
+  #[allow(clippy::cast_possible_wrap)]
+  pub async fn delete(&mut self) -> sqlx::Result<()> {
+    let user_id = self.user_id.0 as i64;
+    let exec = &self.exec;
+    sqlx::query!(
+      r#"
+        DELETE FROM emergency_alert_acknowledgements
+        WHERE contact_id = ?1 OR alert_id IN (
+          SELECT emergency_alerts.id
+          FROM emergency_alerts
+          JOIN profiles ON profiles.id = emergency_alerts.profile_id
+          WHERE profiles.user_id = ?1
+        )
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM emergency_contacts
+        WHERE contact_id = ?1 OR profile_id IN (
+          SELECT id FROM profiles WHERE user_id = ?1
+        )
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM user_links
+        WHERE caregiver_id = ?1 OR profile_id IN (
+          SELECT id FROM profiles WHERE user_id = ?1
+        )
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM emergency_alerts
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM invites
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM basal_reminders
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM missed_basal_doses
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM profile_preferences
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM insulin_safety_rules
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM basal_tests
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM weekly_digests
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM daily_summaries
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM hypo_rechecks
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM sugar_measurements
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM insulin_injections
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM meals
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      r#"
+        DELETE FROM hba1c_results
+        WHERE profile_id IN (SELECT id FROM profiles WHERE user_id = ?)
+      "#,
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      "UPDATE users SET current_profile_id = NULL WHERE id = ?",
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!(
+      "DELETE FROM quiet_hours WHERE user_id = ?",
+      user_id
+    )
+    .execute(&mut exec.borrow())
+    .await?;
+    sqlx::query!("DELETE FROM dashboards WHERE user_id = ?", user_id)
+      .execute(&mut exec.borrow())
+      .await?;
+    sqlx::query!("DELETE FROM profiles WHERE user_id = ?", user_id)
+      .execute(&mut exec.borrow())
+      .await?;
+    sqlx::query!("DELETE FROM users WHERE id = ?", user_id)
+      .execute(&mut exec.borrow())
+      .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    app::{
+      caregiver::{
+        repository::user_links, EntryNotifications, Permission,
+        UserLink,
+      },
+      insulin_injection::{
+        self, repository::insulin_injections, Insulin, InsulinKind,
+      },
+      profile::repository::profiles,
+      sugar_measurement::{
+        self, repository::sugar_measurements, SugarLevel,
+      },
+      user::repository::users,
+    },
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn fetch_and_delete() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      let other = UserId(2);
+      users(&test_db).add(user).await.unwrap();
+      users(&test_db).add(other).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let other_profile =
+        profiles(&test_db).add(other, None).await.unwrap();
+      profiles(&test_db).switch(user, profile.id).await.unwrap();
+      sugar_measurements(&test_db, profile.id)
+        .add(sugar_measurement::SugarMeasurement::from_now(
+          SugarLevel::from_millimoles_per_liter(5.5),
+        ))
+        .await
+        .unwrap();
+      insulin_injections(&test_db, profile.id)
+        .add_with_warnings(
+          insulin_injection::InsulinInjection::from_now(
+            Insulin::from_cubic_centimeters(4.0),
+            InsulinKind::Bolus,
+          ),
+          Some("Доза больше заданного максимума 3 ЕД."),
+        )
+        .await
+        .unwrap();
+      user_links(&test_db)
+        .add(UserLink {
+          profile_id: other_profile.id,
+          caregiver_id: user,
+          permission: Permission::Read,
+          entry_notifications: EntryNotifications::All,
+          missed_reminder_notifications: true,
+        })
+        .await
+        .unwrap();
+      sugar_measurements(&test_db, other_profile.id)
+        .add(sugar_measurement::SugarMeasurement::from_now(
+          SugarLevel::from_millimoles_per_liter(7.0),
+        ))
+        .await
+        .unwrap();
+
+      let data = personal_data(&test_db, user).fetch().await.unwrap();
+      assert_eq!(data.profiles.len(), 1);
+      assert!(data.profiles[0].current);
+      assert_eq!(data.profiles[0].sugar_measurements.len(), 1);
+      assert_eq!(data.profiles[0].insulin_injections.len(), 1);
+      assert_eq!(
+        data.profiles[0].insulin_injections[0].kind.as_deref(),
+        Some("bolus")
+      );
+      assert!(data.profiles[0].insulin_injections[0]
+        .warnings
+        .is_some());
+      assert_eq!(data.caregiver_of.len(), 1);
+
+      personal_data(&test_db, user).delete().await.unwrap();
+      let data = personal_data(&test_db, user).fetch().await.unwrap();
+      assert!(data.profiles.is_empty());
+      assert!(data.caregiver_of.is_empty());
+      assert!(!users(&test_db)
+        .fetch_all()
+        .await
+        .unwrap()
+        .contains(&user));
+      let now = chrono::Utc::now();
+      let others = sugar_measurements(&test_db, other_profile.id)
+        .fetch_between(now - chrono::Duration::days(1), now)
+        .await
+        .unwrap();
+      assert_eq!(others.len(), 1);
+    })
+    .await
+    .unwrap();
+  }
+}
@@ -0,0 +1,74 @@
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+/// Verdict on entered value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Plausibility {
+  Plausible,
+  /// Unusual value user should confirm, with reason
+  Doubtful(String),
+  /// Value which can't be real, with reason
+  Impossible(String),
+}
+
+/// Answer to question whether doubtful value is right
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confirmation {
+  Save,
+  Retry,
+}
+
+impl Confirmation {
+  /// Parses button of plugin with callback `prefix`
+  pub fn parse(prefix: &str, data: &str) -> Option<Self> {
+    match data.strip_prefix(prefix)? {
+      "save" => Some(Self::Save),
+      "retry" => Some(Self::Retry),
+      _ => None,
+    }
+  }
+
+  /// Buttons of plugin with callback `prefix`
+  pub fn keyboard(prefix: &str) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[
+      InlineKeyboardButton::callback(
+        "Да, сохранить",
+        format!("{prefix}save"),
+      ),
+      InlineKeyboardButton::callback(
+        "Нет, исправить",
+        format!("{prefix}retry"),
+      ),
+    ]])
+  }
+}
+
+/// Parses finite number with either decimal separator
+pub fn parse_number(s: &str) -> Option<f64> {
+  let number: f64 = s.trim().replace(',', ".").parse().ok()?;
+  number.is_finite().then_some(number)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_finite_numbers_only() {
+    assert_eq!(parse_number(" 5,6 "), Some(5.6));
+    assert_eq!(parse_number("-1"), Some(-1.0));
+    assert_eq!(parse_number("NaN"), None);
+    assert_eq!(parse_number("inf"), None);
+    assert_eq!(parse_number("5.6 ммоль"), None);
+  }
+
+  #[test]
+  fn parses_confirmation() {
+    let parse = |data| Confirmation::parse("sugar_confirm:", data);
+    assert_eq!(parse("sugar_confirm:save"), Some(Confirmation::Save));
+    assert_eq!(
+      parse("sugar_confirm:retry"),
+      Some(Confirmation::Retry)
+    );
+    assert_eq!(parse("insulin_confirm:save"), None);
+  }
+}
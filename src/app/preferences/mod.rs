@@ -0,0 +1,215 @@
+pub mod repository;
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use chrono_tz::Tz;
+use teloxide::{
+  dispatching::dialogue::InMemStorage, dptree::case, prelude::*,
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::Db,
+  utils::filter_message,
+};
+
+use self::repository::preferences;
+
+use super::{
+  export::logbook::Units,
+  profile::{current_profile, ProfileId},
+  weekly_digest::{next_digest_at, repository::weekly_digests},
+  UpdateHandler,
+};
+
+type Dialog = Dialogue<State, InMemStorage<State>>;
+
+/// Timezone local dates and times of profile are in and units its
+/// sugar levels are shown and exported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preferences {
+  pub timezone: Tz,
+  pub units: Units,
+}
+
+impl Default for Preferences {
+  fn default() -> Self {
+    Self {
+      timezone: server_timezone(),
+      units: Units::default(),
+    }
+  }
+}
+
+/// Timezone of server, used until profile sets its own
+pub fn server_timezone() -> Tz {
+  iana_time_zone::get_timezone()
+    .ok()
+    .and_then(|name| name.parse().ok())
+    .unwrap_or(Tz::UTC)
+}
+
+/// Parses IANA name like "Europe/Moscow" or offset like "UTC+5"
+fn parse_timezone(s: &str) -> Option<Tz> {
+  let s = s.trim();
+  if let Ok(timezone) = s.parse() {
+    return Some(timezone);
+  }
+  let upper = s.to_uppercase();
+  let offset = upper
+    .strip_prefix("UTC")
+    .or_else(|| upper.strip_prefix("GMT"))
+    .unwrap_or(&upper)
+    .trim();
+  let hours: i32 = offset.parse().ok()?;
+  if hours == 0 {
+    return Some(Tz::UTC);
+  }
+  // Etc zones have sign inverted
+  format!("Etc/GMT{:+}", -hours).parse().ok()
+}
+
+fn parse_units(s: &str) -> Option<Units> {
+  let s = s.trim().to_lowercase();
+  Units::ALL.into_iter().find(|units| units.label() == s)
+}
+
+#[derive(Default, Clone)]
+enum State {
+  #[default]
+  Ignoring,
+  Accepting(ProfileId),
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn prepare(&self, di: &mut DependencyMap) {
+    di.insert(InMemStorage::<State>::new());
+  }
+
+  fn update_handler(&self) -> UpdateHandler {
+    filter_message()
+      .enter_dialogue::<Message, InMemStorage<State>, State>()
+      .branch(
+        dptree::entry()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::Preferences].endpoint(ask)),
+      )
+      .branch(case![State::Accepting(profile_id)].endpoint(accept))
+  }
+}
+
+async fn ask(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+  dialogue: Dialog,
+) -> Result<()> {
+  let profile = current_profile(&db, user_id).await?;
+  let prefs = preferences(&db)
+    .fetch(profile.id)
+    .await?
+    .unwrap_or_default();
+  let now = Utc::now().with_timezone(&prefs.timezone);
+  bot
+    .send_message(
+      chat_id,
+      format!(
+        "Часовой пояс{}: {} (сейчас {}), единицы сахара: {}.\n\n\
+        Отправьте часовой пояс, например «Asia/Yekaterinburg» \
+        или «UTC+5», либо единицы «ммоль/л» или «мг/дл»",
+        profile.tag(),
+        prefs.timezone.name(),
+        now.format("%H:%M"),
+        prefs.units.label()
+      ),
+    )
+    .await?;
+  dialogue
+    .update(State::Accepting(profile.id))
+    .await
+    .map_err(any)?;
+  Ok(())
+}
+
+async fn accept(
+  bot: Bot,
+  msg: Message,
+  db: Arc<Db>,
+  profile_id: ProfileId,
+  dialogue: Dialog,
+) -> Result<()> {
+  let text = msg.text().unwrap_or_default();
+  let mut repository = preferences(&db);
+  let mut prefs =
+    repository.fetch(profile_id).await?.unwrap_or_default();
+  let reply = if let Some(units) = parse_units(text) {
+    prefs.units = units;
+    format!("✅ Единицы сахара: {}", units.label())
+  } else if let Some(timezone) = parse_timezone(text) {
+    prefs.timezone = timezone;
+    let now = Utc::now().with_timezone(&timezone);
+    format!(
+      "✅ Часовой пояс: {} (сейчас {})",
+      timezone.name(),
+      now.format("%H:%M")
+    )
+  } else {
+    bot
+      .send_message(
+        msg.chat.id,
+        "Неизвестный часовой пояс. Пример: «Europe/Moscow» или «UTC+3»",
+      )
+      .await?;
+    dialogue.reset().await.map_err(any)?;
+    return Ok(());
+  };
+  repository.set(profile_id, prefs).await?;
+  let mut digests = weekly_digests(&db);
+  if digests.fetch_next_at(profile_id).await?.is_some() {
+    // Moves digest to Sunday evening in new timezone
+    let now = Utc::now().with_timezone(&prefs.timezone);
+    digests.enable(profile_id, next_digest_at(now)).await?;
+  }
+  bot.send_message(msg.chat.id, reply).await?;
+  dialogue.reset().await.map_err(any)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_names_and_offsets() {
+    let parse = |s| parse_timezone(s).map(|tz| tz.name());
+    assert_eq!(
+      parse("Asia/Yekaterinburg"),
+      Some("Asia/Yekaterinburg")
+    );
+    assert_eq!(parse("UTC+5"), Some("Etc/GMT-5"));
+    assert_eq!(parse("utc-3"), Some("Etc/GMT+3"));
+    assert_eq!(parse("+3"), Some("Etc/GMT-3"));
+    assert_eq!(parse("UTC+0"), Some("UTC"));
+    assert_eq!(parse("Mars/Olympus"), None);
+    assert_eq!(parse("UTC+25"), None);
+  }
+
+  #[test]
+  fn parses_units() {
+    assert_eq!(
+      parse_units(" мг/дл"),
+      Some(Units::MilligramsPerDeciliter)
+    );
+    assert_eq!(
+      parse_units("ММОЛЬ/Л"),
+      Some(Units::MillimolesPerLiter)
+    );
+    assert_eq!(parse_units("mg"), None);
+  }
+}
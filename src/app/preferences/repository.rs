@@ -0,0 +1,102 @@
+use chrono_tz::Tz;
+
+use crate::{
+  app::{export::logbook::Units, profile::ProfileId},
+  db::{txn::ExecutorHolder, Db},
+};
+
+use super::Preferences;
+
+pub fn preferences(db: &Db) -> Repository {
+  Repository { exec: db.exec() }
+}
+
+pub struct Repository {
+  exec: ExecutorHolder,
+}
+
+impl Repository {
+  /// Preferences of profile, `None` if defaults weren't changed.
+  /// Unknown values are replaced with defaults.
+  pub async fn fetch(
+    &self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<Option<Preferences>> {
+    sqlx::query!(
+      r#"
+        SELECT timezone, units
+        FROM profile_preferences
+        WHERE profile_id = ?
+      "#,
+      profile_id.0
+    )
+    .map(|rec| {
+      let defaults = Preferences::default();
+      Preferences {
+        timezone: rec
+          .timezone
+          .parse::<Tz>()
+          .unwrap_or(defaults.timezone),
+        units: Units::parse(&rec.units).unwrap_or(defaults.units),
+      }
+    })
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+
+  pub async fn set(
+    &mut self,
+    profile_id: ProfileId,
+    prefs: Preferences,
+  ) -> sqlx::Result<()> {
+    let timezone = prefs.timezone.name();
+    let units = prefs.units.data();
+    sqlx::query!(
+      r#"
+        REPLACE INTO profile_preferences (profile_id, timezone, units)
+        VALUES (?, ?, ?)
+      "#,
+      profile_id.0,
+      timezone,
+      units
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use teloxide::types::UserId;
+
+  use crate::{
+    app::{profile::repository::profiles, user::repository::users},
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn set_and_fetch() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      users(&test_db).add(user).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let mut repository = preferences(&test_db);
+      assert_eq!(repository.fetch(profile.id).await.unwrap(), None);
+      let prefs = Preferences {
+        timezone: chrono_tz::Asia::Yekaterinburg,
+        units: Units::MilligramsPerDeciliter,
+      };
+      repository.set(profile.id, prefs).await.unwrap();
+      assert_eq!(
+        repository.fetch(profile.id).await.unwrap(),
+        Some(prefs)
+      );
+    })
+    .await
+    .unwrap();
+  }
+}
@@ -0,0 +1,266 @@
+pub mod repository;
+
+use std::sync::Arc;
+
+use teloxide::{
+  dispatching::dialogue::InMemStorage,
+  dptree::case,
+  prelude::*,
+  requests::ResponseResult,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::Db,
+  utils::{filter_callback_query, filter_message, full_name},
+};
+
+use self::repository::profiles;
+
+use super::UpdateHandler;
+
+const CALLBACK_PREFIX: &str = "profile:";
+const NAME_MAX_LEN: usize = 32;
+
+type Dialog = Dialogue<State, InMemStorage<State>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProfileId(pub i64);
+
+/// Patient whose logbook is kept. Telegram user manages own profile
+/// and may add profiles of other patients, e.g. children.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+  pub id: ProfileId,
+  /// Managing user
+  pub user_id: UserId,
+  /// `None` for user's own profile
+  pub name: Option<String>,
+}
+
+impl Profile {
+  /// Name shown to other users: profile name or owner's full name
+  pub async fn display_name(
+    &self,
+    bot: &Bot,
+  ) -> ResponseResult<String> {
+    match &self.name {
+      Some(name) => Ok(name.clone()),
+      None => full_name(bot, self.user_id).await,
+    }
+  }
+
+  /// Marks messages for managing user about not own profile
+  pub fn tag(&self) -> String {
+    match &self.name {
+      Some(name) => format!(" [{name}]"),
+      None => String::new(),
+    }
+  }
+
+  /// Short name in managing user's keyboards
+  pub fn label(&self) -> &str {
+    self.name.as_deref().unwrap_or("Я")
+  }
+}
+
+/// Current profile of user. Own profile is created if user has none.
+pub async fn current_profile(
+  db: &Db,
+  user_id: UserId,
+) -> sqlx::Result<Profile> {
+  let mut profiles = profiles(db);
+  if let Some(profile) = profiles.fetch_current(user_id).await? {
+    return Ok(profile);
+  }
+  let profile = profiles.add(user_id, None).await?;
+  profiles.switch(user_id, profile.id).await?;
+  Ok(profile)
+}
+
+#[derive(Default, Clone)]
+enum State {
+  #[default]
+  Ignoring,
+  AcceptingName,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Callback {
+  Switch(ProfileId),
+  Add,
+}
+
+impl Callback {
+  fn parse(data: &str) -> Option<Self> {
+    match data.strip_prefix(CALLBACK_PREFIX)? {
+      "add" => Some(Self::Add),
+      id => Some(Self::Switch(ProfileId(id.parse().ok()?))),
+    }
+  }
+
+  fn data(self) -> String {
+    match self {
+      Self::Switch(ProfileId(id)) => format!("{CALLBACK_PREFIX}{id}"),
+      Self::Add => format!("{CALLBACK_PREFIX}add"),
+    }
+  }
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn prepare(&self, di: &mut DependencyMap) {
+    di.insert(InMemStorage::<State>::new());
+  }
+
+  fn update_handler(&self) -> UpdateHandler {
+    dptree::entry()
+      .branch(
+        filter_message()
+          .enter_dialogue::<Message, InMemStorage<State>, State>()
+          .branch(
+            dptree::entry().filter_command::<MenuCommand>().branch(
+              case![MenuCommand::Profiles].endpoint(send_profiles),
+            ),
+          )
+          .branch(case![State::AcceptingName].endpoint(accept_name)),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            Callback::parse(query.data.as_deref()?)
+          })
+          .branch(
+            case![Callback::Switch(profile_id)].endpoint(switch),
+          )
+          .branch(case![Callback::Add].endpoint(ask_name)),
+      )
+  }
+}
+
+async fn send_profiles(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+) -> Result<()> {
+  let keyboard = profiles_keyboard(&db, user_id).await?;
+  bot
+    .send_message(
+      chat_id,
+      "Выберите профиль, в который вести записи:",
+    )
+    .reply_markup(keyboard)
+    .await?;
+  Ok(())
+}
+
+async fn profiles_keyboard(
+  db: &Db,
+  user_id: UserId,
+) -> Result<InlineKeyboardMarkup> {
+  let current = current_profile(db, user_id).await?;
+  let mut rows: Vec<_> = profiles(db)
+    .fetch_all(user_id)
+    .await?
+    .into_iter()
+    .map(|profile| {
+      let mark = if profile.id == current.id { "✅ " } else { "" };
+      vec![InlineKeyboardButton::callback(
+        format!("{mark}{}", profile.label()),
+        Callback::Switch(profile.id).data(),
+      )]
+    })
+    .collect();
+  rows.push(vec![InlineKeyboardButton::callback(
+    "➕ Добавить",
+    Callback::Add.data(),
+  )]);
+  Ok(InlineKeyboardMarkup::new(rows))
+}
+
+async fn switch(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  profile_id: ProfileId,
+  query: CallbackQuery,
+) -> Result<()> {
+  profiles(&db).switch(user_id, profile_id).await?;
+  bot.answer_callback_query(query.id).await?;
+  if let Some(msg) = query.message {
+    let keyboard = profiles_keyboard(&db, user_id).await?;
+    bot
+      .edit_message_reply_markup(msg.chat.id, msg.id)
+      .reply_markup(keyboard)
+      .await?;
+  }
+  Ok(())
+}
+
+async fn ask_name(
+  bot: Bot,
+  storage: Arc<InMemStorage<State>>,
+  query: CallbackQuery,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  bot
+    .send_message(msg.chat.id, "Отправьте имя пациента")
+    .await?;
+  Dialog::new(storage, msg.chat.id)
+    .update(State::AcceptingName)
+    .await
+    .map_err(any)?;
+  Ok(())
+}
+
+async fn accept_name(
+  bot: Bot,
+  msg: Message,
+  db: Arc<Db>,
+  user_id: UserId,
+  dialogue: Dialog,
+) -> Result<()> {
+  match parse_name(msg.text()) {
+    Some(name) => {
+      let mut profiles = profiles(&db);
+      let profile = profiles.add(user_id, Some(name)).await?;
+      profiles.switch(user_id, profile.id).await?;
+      bot
+        .send_message(
+          msg.chat.id,
+          format!(
+            "✅ Профиль «{}» создан и выбран. \
+            Переключить профиль: /profiles",
+            profile.label()
+          ),
+        )
+        .await?;
+    }
+    None => {
+      bot
+        .send_message(
+          msg.chat.id,
+          format!(
+            "Имя должно быть не длиннее {NAME_MAX_LEN} символов"
+          ),
+        )
+        .await?;
+    }
+  }
+  dialogue.reset().await.map_err(any)?;
+  Ok(())
+}
+
+fn parse_name(s: Option<&str>) -> Option<String> {
+  let name = s?.trim();
+  let len = name.chars().count();
+  (1..=NAME_MAX_LEN).contains(&len).then(|| name.to_string())
+}
@@ -0,0 +1,226 @@
+use chrono::{DateTime, Utc};
+use teloxide::types::UserId;
+
+use crate::db::{txn::ExecutorHolder, Db};
+
+use super::{Profile, ProfileId};
+
+pub fn profiles(db: &Db) -> Repository {
+  Repository { exec: db.exec() }
+}
+
+pub struct Repository {
+  exec: ExecutorHolder,
+}
+
+impl Repository {
+  #[allow(clippy::cast_sign_loss)]
+  pub async fn fetch(
+    &self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<Option<Profile>> {
+    sqlx::query!(
+      "SELECT id, user_id, name FROM profiles WHERE id = ?",
+      profile_id.0
+    )
+    .map(|rec| Profile {
+      id: ProfileId(rec.id),
+      user_id: UserId(rec.user_id as _),
+      name: rec.name,
+    })
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+
+  #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+  pub async fn fetch_all(
+    &self,
+    user_id: UserId,
+  ) -> sqlx::Result<Vec<Profile>> {
+    let user_id = user_id.0 as i64;
+    sqlx::query!(
+      "SELECT id, user_id, name FROM profiles WHERE user_id = ?",
+      user_id
+    )
+    .map(|rec| Profile {
+      id: ProfileId(rec.id),
+      user_id: UserId(rec.user_id as _),
+      name: rec.name,
+    })
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Profiles with sugar readings since `since` of users who haven't
+  /// disabled the bot
+  #[allow(clippy::cast_sign_loss)]
+  pub async fn fetch_measured_since(
+    &self,
+    since: DateTime<Utc>,
+  ) -> sqlx::Result<Vec<Profile>> {
+    sqlx::query!(
+      r#"
+        SELECT profiles.id, profiles.user_id, profiles.name
+        FROM profiles
+        JOIN users ON users.id = profiles.user_id
+        WHERE users.disabled = FALSE AND EXISTS (
+          SELECT 1 FROM sugar_measurements
+          WHERE profile_id = profiles.id AND date_time >= ?
+        )
+      "#,
+      since
+    )
+    .map(|rec| Profile {
+      id: ProfileId(rec.id),
+      user_id: UserId(rec.user_id as _),
+      name: rec.name,
+    })
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+  pub async fn fetch_current(
+    &self,
+    user_id: UserId,
+  ) -> sqlx::Result<Option<Profile>> {
+    let user_id = user_id.0 as i64;
+    sqlx::query!(
+      r#"
+        SELECT profiles.id, profiles.user_id, profiles.name
+        FROM users
+        JOIN profiles ON profiles.id = users.current_profile_id
+        WHERE users.id = ?
+      "#,
+      user_id
+    )
+    .map(|rec| Profile {
+      id: ProfileId(rec.id),
+      user_id: UserId(rec.user_id as _),
+      name: rec.name,
+    })
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Adds profile managed by user. `name` is `None` for user's own.
+  #[allow(clippy::cast_possible_wrap)]
+  pub async fn add(
+    &mut self,
+    user_id: UserId,
+    name: Option<String>,
+  ) -> sqlx::Result<Profile> {
+    let id = user_id.0 as i64;
+    let res = sqlx::query!(
+      "INSERT INTO profiles (user_id, name) VALUES (?, ?)",
+      id,
+      name
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    let id = ProfileId(res.last_insert_rowid());
+    Ok(Profile { id, user_id, name })
+  }
+
+  /// Makes profile current. Ignores profiles of other users.
+  #[allow(clippy::cast_possible_wrap)]
+  pub async fn switch(
+    &mut self,
+    user_id: UserId,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<bool> {
+    let user_id = user_id.0 as i64;
+    let res = sqlx::query!(
+      r#"
+        UPDATE users SET current_profile_id = profiles.id
+        FROM profiles
+        WHERE users.id = ? AND profiles.id = ?
+          AND profiles.user_id = users.id
+      "#,
+      user_id,
+      profile_id.0
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(res.rows_affected() > 0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::Duration;
+
+  use crate::{
+    app::{
+      sugar_measurement::{
+        repository::sugar_measurements, SugarLevel, SugarMeasurement,
+      },
+      user::repository::users,
+    },
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn add_switch_and_fetch() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      let stranger = UserId(2);
+      users(&test_db).add(user).await.unwrap();
+      users(&test_db).add(stranger).await.unwrap();
+      let mut profiles = profiles(&test_db);
+      let own = profiles.add(user, None).await.unwrap();
+      let child =
+        profiles.add(user, Some("Маша".into())).await.unwrap();
+      let strangers = profiles.add(stranger, None).await.unwrap();
+      assert_eq!(profiles.fetch_current(user).await.unwrap(), None);
+      assert!(profiles.switch(user, child.id).await.unwrap());
+      assert!(!profiles.switch(user, strangers.id).await.unwrap());
+      let current = profiles.fetch_current(user).await.unwrap();
+      assert_eq!(current, Some(child.clone()));
+      let all = profiles.fetch_all(user).await.unwrap();
+      assert_eq!(all, vec![own.clone(), child]);
+      assert_eq!(profiles.fetch(own.id).await.unwrap(), Some(own));
+    })
+    .await
+    .unwrap();
+  }
+
+  #[tokio::test]
+  async fn fetch_measured_since() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      let disabled = UserId(2);
+      users(&test_db).add(user).await.unwrap();
+      users(&test_db).add(disabled).await.unwrap();
+      users(&test_db).disable(disabled).await.unwrap();
+      let mut profiles = profiles(&test_db);
+      let own = profiles.add(user, None).await.unwrap();
+      let child =
+        profiles.add(user, Some("Маша".into())).await.unwrap();
+      let other = profiles.add(disabled, None).await.unwrap();
+      let since: DateTime<Utc> =
+        "2024-03-01T00:00:00Z".parse().unwrap();
+      let level = SugarLevel::from_millimoles_per_liter(6.0);
+      for (profile, date_time) in [
+        (&own, since),
+        (&child, since - Duration::minutes(1)),
+        (&other, since),
+      ] {
+        sugar_measurements(&test_db, profile.id)
+          .add(SugarMeasurement { date_time, level })
+          .await
+          .unwrap();
+      }
+      assert_eq!(
+        profiles.fetch_measured_since(since).await.unwrap(),
+        vec![own]
+      );
+    })
+    .await
+    .unwrap();
+  }
+}
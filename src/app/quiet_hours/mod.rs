@@ -0,0 +1,165 @@
+pub mod repository;
+
+use std::{fmt, sync::Arc};
+
+use chrono::NaiveTime;
+use teloxide::{
+  dispatching::dialogue::InMemStorage, dptree::case, prelude::*,
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::Db,
+  utils::filter_message,
+};
+
+use self::repository::quiet_hours;
+
+use super::UpdateHandler;
+
+type Dialog = Dialogue<State, InMemStorage<State>>;
+
+/// Local time interval when bot doesn't send scheduled messages.
+/// Emergency alerts are sent regardless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+  pub start: NaiveTime,
+  pub end: NaiveTime,
+}
+
+impl QuietHours {
+  /// Whether `time` is in interval, which may span midnight
+  pub fn contains(self, time: NaiveTime) -> bool {
+    if self.start <= self.end {
+      self.start <= time && time < self.end
+    } else {
+      self.start <= time || time < self.end
+    }
+  }
+
+  /// Parses "23:00-07:00"
+  fn parse(s: &str) -> Option<Self> {
+    let (start, end) = s.split_once(['-', '–', '—'])?;
+    let time = |s: &str| NaiveTime::parse_from_str(s.trim(), "%H:%M");
+    let quiet_hours = Self {
+      start: time(start).ok()?,
+      end: time(end).ok()?,
+    };
+    (quiet_hours.start != quiet_hours.end).then_some(quiet_hours)
+  }
+}
+
+impl fmt::Display for QuietHours {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{}–{}",
+      self.start.format("%H:%M"),
+      self.end.format("%H:%M")
+    )
+  }
+}
+
+#[derive(Default, Clone)]
+enum State {
+  #[default]
+  Ignoring,
+  Accepting,
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn prepare(&self, di: &mut DependencyMap) {
+    di.insert(InMemStorage::<State>::new());
+  }
+
+  fn update_handler(&self) -> UpdateHandler {
+    filter_message()
+      .enter_dialogue::<Message, InMemStorage<State>, State>()
+      .branch(
+        dptree::entry()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::QuietHours].endpoint(ask)),
+      )
+      .branch(case![State::Accepting].endpoint(accept))
+  }
+}
+
+async fn ask(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+  dialogue: Dialog,
+) -> Result<()> {
+  let current = match quiet_hours(&db).fetch(user_id).await? {
+    Some(quiet_hours) => format!("Тихие часы: {quiet_hours}"),
+    None => "Тихие часы не заданы".into(),
+  };
+  bot
+    .send_message(
+      chat_id,
+      format!(
+        "{current}. В это время бот не присылает сводки и \
+        напоминания, экстренные оповещения приходят всегда.\n\n\
+        Отправьте интервал, например «23:00-07:00», или «выкл», \
+        чтобы отключить"
+      ),
+    )
+    .await?;
+  dialogue.update(State::Accepting).await.map_err(any)?;
+  Ok(())
+}
+
+async fn accept(
+  bot: Bot,
+  msg: Message,
+  user_id: UserId,
+  db: Arc<Db>,
+  dialogue: Dialog,
+) -> Result<()> {
+  let text = msg.text().unwrap_or_default().trim();
+  let reply = if text.to_lowercase() == "выкл" {
+    quiet_hours(&db).remove(user_id).await?;
+    "Тихие часы отключены".into()
+  } else if let Some(hours) = QuietHours::parse(text) {
+    quiet_hours(&db).set(user_id, hours).await?;
+    format!("✅ Тихие часы: {hours}")
+  } else {
+    "Неправильный формат. Пример: «23:00-07:00»".into()
+  };
+  bot.send_message(msg.chat.id, reply).await?;
+  dialogue.reset().await.map_err(any)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn time(s: &str) -> NaiveTime {
+    NaiveTime::parse_from_str(s, "%H:%M").unwrap()
+  }
+
+  #[test]
+  fn contains_interval_over_midnight() {
+    let night = QuietHours::parse("23:00 - 07:00").unwrap();
+    assert!(night.contains(time("23:00")));
+    assert!(night.contains(time("03:00")));
+    assert!(!night.contains(time("07:00")));
+    assert!(!night.contains(time("22:59")));
+    let day = QuietHours::parse("13:00–15:00").unwrap();
+    assert!(day.contains(time("14:00")));
+    assert!(!day.contains(time("23:00")));
+  }
+
+  #[test]
+  fn rejects_malformed_intervals() {
+    assert_eq!(QuietHours::parse("23:00"), None);
+    assert_eq!(QuietHours::parse("25:00-07:00"), None);
+    assert_eq!(QuietHours::parse("07:00-07:00"), None);
+  }
+}
@@ -0,0 +1,101 @@
+use chrono::NaiveTime;
+use teloxide::types::UserId;
+
+use crate::db::{txn::ExecutorHolder, Db};
+
+use super::QuietHours;
+
+pub fn quiet_hours(db: &Db) -> Repository {
+  Repository { exec: db.exec() }
+}
+
+pub struct Repository {
+  exec: ExecutorHolder,
+}
+
+impl Repository {
+  #[allow(clippy::cast_possible_wrap)]
+  pub async fn fetch(
+    &self,
+    user_id: UserId,
+  ) -> sqlx::Result<Option<QuietHours>> {
+    let user_id = user_id.0 as i64;
+    sqlx::query_as!(
+      QuietHours,
+      r#"
+        SELECT start AS "start: NaiveTime", end AS "end: NaiveTime"
+        FROM quiet_hours
+        WHERE user_id = ?
+      "#,
+      user_id
+    )
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Sets quiet hours replacing previous ones
+  #[allow(clippy::cast_possible_wrap)]
+  pub async fn set(
+    &mut self,
+    user_id: UserId,
+    quiet_hours: QuietHours,
+  ) -> sqlx::Result<()> {
+    let user_id = user_id.0 as i64;
+    sqlx::query!(
+      "REPLACE INTO quiet_hours (user_id, start, end) VALUES (?, ?, ?)",
+      user_id,
+      quiet_hours.start,
+      quiet_hours.end
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  #[allow(clippy::cast_possible_wrap)]
+  pub async fn remove(
+    &mut self,
+    user_id: UserId,
+  ) -> sqlx::Result<()> {
+    let user_id = user_id.0 as i64;
+    sqlx::query!(
+      "DELETE FROM quiet_hours WHERE user_id = ?",
+      user_id
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    app::user::repository::users,
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn set_fetch_and_remove() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      users(&test_db).add(user).await.unwrap();
+      let mut repository = quiet_hours(&test_db);
+      assert_eq!(repository.fetch(user).await.unwrap(), None);
+      let time = |h| NaiveTime::from_hms_opt(h, 0, 0).unwrap();
+      let hours = QuietHours {
+        start: time(23),
+        end: time(7),
+      };
+      repository.set(user, hours).await.unwrap();
+      assert_eq!(repository.fetch(user).await.unwrap(), Some(hours));
+      repository.remove(user).await.unwrap();
+      assert_eq!(repository.fetch(user).await.unwrap(), None);
+    })
+    .await
+    .unwrap();
+  }
+}
@@ -0,0 +1,553 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use chrono::{DateTime, Duration, TimeZone, Timelike, Utc};
+
+use crate::app::{
+  insulin_injection::{InsulinInjection, InsulinKind},
+  meal::Meal,
+  stats::time_of_day::Buckets,
+  sugar_measurement::SugarMeasurement,
+};
+
+/// Ratios are estimated for every part of day
+pub const BUCKETS: Buckets = Buckets::PartsOfDay;
+/// Fewer samples don't give an estimate
+const MIN_SAMPLES: usize = 3;
+/// Smaller meals are snacks and aren't used, g
+const MIN_CARBS_GRAMS: f64 = 10.0;
+/// Bolus this close to meal covers it, minutes
+const MEAL_BOLUS_MINUTES: i64 = 30;
+/// Reading before meal or correction is taken this early, minutes
+const BEFORE_MINUTES: i64 = 60;
+/// Reading after dose is taken within, hours
+const AFTER_HOURS: (i64, i64) = (3, 5);
+/// Other doses and meals this close make samples unreliable, hours
+const NEARBY_HOURS: i64 = 3;
+/// Meals with larger change of level aren't covered well, mmol/L
+const MAX_MEAL_DRIFT: f64 = 3.0;
+/// Doses at lower levels are rather unlogged meals, mmol/L
+const MIN_CORRECTION_LEVEL: f64 = 8.0;
+
+/// Why meal or correction isn't used for estimation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Exclusion {
+  NoBolus,
+  NoReadingBefore,
+  NoReadingAfter,
+  /// Meal within hours before or after
+  OtherMeal,
+  /// Other bolus within hours before or after
+  NearbyDose,
+  /// Hypo was likely treated with unlogged carbs
+  Hypo,
+  /// Level after meal differs too much from level before it
+  LargeDrift,
+  /// Correction at level within range
+  NotHigh,
+  /// Level didn't go down after correction
+  NoDrop,
+}
+
+impl Exclusion {
+  pub fn describe(self) -> String {
+    let (from, to) = AFTER_HOURS;
+    match self {
+      Self::NoBolus => "нет болюса к еде".into(),
+      Self::NoReadingBefore => "нет измерения до еды или дозы".into(),
+      Self::NoReadingAfter => {
+        format!("нет измерения через {from}–{to} часов")
+      }
+      Self::OtherMeal => {
+        format!("другая еда в пределах {NEARBY_HOURS} часов")
+      }
+      Self::NearbyDose => {
+        format!(
+          "коррекция или другая доза в пределах {NEARBY_HOURS} часов"
+        )
+      }
+      Self::Hypo => "гипогликемия после дозы".into(),
+      Self::LargeDrift => format!(
+        "сахар после еды изменился больше чем на {MAX_MEAL_DRIFT:.0} \
+        ммоль/л"
+      ),
+      Self::NotHigh => format!(
+        "доза без еды при сахаре ниже {MIN_CORRECTION_LEVEL:.0} \
+        ммоль/л"
+      ),
+      Self::NoDrop => "сахар не снизился после коррекции".into(),
+    }
+  }
+}
+
+/// Mean of samples with 95 % confidence interval
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+  pub mean: f64,
+  pub low: f64,
+  pub high: f64,
+}
+
+impl Estimate {
+  /// `None` if there are too few samples
+  #[allow(clippy::cast_precision_loss)]
+  fn from_samples(samples: &[f64]) -> Option<Self> {
+    let n = samples.len();
+    if n < MIN_SAMPLES {
+      return None;
+    }
+    let mean = samples.iter().sum::<f64>() / n as f64;
+    let variance = samples
+      .iter()
+      .map(|sample| (sample - mean).powi(2))
+      .sum::<f64>()
+      / (n - 1) as f64;
+    let half_width = t_quantile(n - 1) * (variance / n as f64).sqrt();
+    Some(Self {
+      mean,
+      low: (mean - half_width).max(0.0),
+      high: mean + half_width,
+    })
+  }
+}
+
+/// 97.5 % quantile of Student's t-distribution
+fn t_quantile(degrees: usize) -> f64 {
+  const TABLE: [f64; 10] =
+    [12.71, 4.30, 3.18, 2.78, 2.57, 2.45, 2.36, 2.31, 2.26, 2.23];
+  match degrees {
+    0 => f64::INFINITY,
+    1..=10 => TABLE[degrees - 1],
+    11..=20 => 2.09,
+    21..=30 => 2.04,
+    _ => 1.96,
+  }
+}
+
+/// Ratios within part of day
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BucketRatios {
+  /// Grams of carbs covered by unit of insulin
+  pub carb_ratio: Vec<f64>,
+  /// Drop of level by unit of insulin, mmol/L
+  pub sensitivity: Vec<f64>,
+}
+
+impl BucketRatios {
+  pub fn carb_ratio(&self) -> Option<Estimate> {
+    Estimate::from_samples(&self.carb_ratio)
+  }
+
+  pub fn sensitivity(&self) -> Option<Estimate> {
+    Estimate::from_samples(&self.sensitivity)
+  }
+}
+
+/// Estimated insulin-to-carb ratio and insulin sensitivity factor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ratios {
+  pub buckets: Vec<BucketRatios>,
+  /// Skipped meals and corrections by reason
+  pub excluded: BTreeMap<Exclusion, usize>,
+}
+
+impl Ratios {
+  /// Estimates ratios from history. Times of day are taken in `tz`.
+  pub fn estimate<Tz: TimeZone>(
+    meals: &[Meal],
+    injections: &[InsulinInjection],
+    readings: &[SugarMeasurement],
+    tz: &Tz,
+  ) -> Self {
+    let history = History {
+      meals,
+      boluses: injections
+        .iter()
+        .filter(|i| i.kind == Some(InsulinKind::Bolus))
+        .collect(),
+      readings,
+    };
+    let bucket =
+      |dt: &DateTime<Utc>| BUCKETS.index(dt.with_timezone(tz).hour());
+    let mut ratios = Self {
+      buckets: vec![BucketRatios::default(); BUCKETS.count()],
+      excluded: BTreeMap::new(),
+    };
+    for bolus in &history.boluses {
+      if history.meal_near(bolus.date_time).is_some() {
+        continue;
+      }
+      match history.sensitivity(bolus) {
+        Ok(sample) => ratios.buckets[bucket(&bolus.date_time)]
+          .sensitivity
+          .push(sample),
+        Err(exclusion) => ratios.exclude(exclusion),
+      }
+    }
+    // Sensitivity of the same part of day accounts for drift
+    let sensitivity = ratios
+      .buckets
+      .iter()
+      .map(|b| b.sensitivity().map(|estimate| estimate.mean))
+      .collect::<Vec<_>>();
+    for meal in meals {
+      if meal.carbs_grams < MIN_CARBS_GRAMS {
+        continue;
+      }
+      let index = bucket(&meal.date_time);
+      match history.carb_ratio(meal, sensitivity[index]) {
+        Ok(sample) => ratios.buckets[index].carb_ratio.push(sample),
+        Err(exclusion) => ratios.exclude(exclusion),
+      }
+    }
+    ratios
+  }
+
+  fn exclude(&mut self, exclusion: Exclusion) {
+    *self.excluded.entry(exclusion).or_default() += 1;
+  }
+
+  /// Suggestions by part of day with excluded entries
+  pub fn render(&self, days: u64, tag: &str) -> String {
+    let mut text =
+      format!("🧮 Оценка коэффициентов за {days} дней{tag}\n");
+    for (index, bucket) in self.buckets.iter().enumerate() {
+      let _ = write!(text, "\n{}\n", BUCKETS.label(index));
+      let _ = match bucket.carb_ratio() {
+        Some(Estimate { mean, low, high }) => writeln!(
+          text,
+          "УК: 1 ЕД на {mean:.0} г (95 % ДИ {low:.0}–{high:.0}), \
+          приемов пищи: {}",
+          bucket.carb_ratio.len()
+        ),
+        None => writeln!(
+          text,
+          "УК: мало данных (приемов пищи: {})",
+          bucket.carb_ratio.len()
+        ),
+      };
+      let _ = match bucket.sensitivity() {
+        Some(Estimate { mean, low, high }) => writeln!(
+          text,
+          "ФЧИ: 1 ЕД снижает на {mean:.1} ммоль/л (95 % ДИ \
+          {low:.1}–{high:.1}), коррекций: {}",
+          bucket.sensitivity.len()
+        ),
+        None => writeln!(
+          text,
+          "ФЧИ: мало данных (коррекций: {})",
+          bucket.sensitivity.len()
+        ),
+      };
+    }
+    if !self.excluded.is_empty() {
+      text.push_str("\nНе учтено:\n");
+      for (exclusion, count) in &self.excluded {
+        let _ =
+          writeln!(text, "• {} — {count}", exclusion.describe());
+      }
+    }
+    text.push_str(
+      "\nУК — сколько граммов углеводов покрывает 1 ЕД, ФЧИ — на \
+      сколько ммоль/л 1 ЕД снижает сахар. Это только подсказки по \
+      дневнику: бот ничего не меняет в ваших настройках. Изменения \
+      доз обсуждайте с врачом.",
+    );
+    text
+  }
+}
+
+struct History<'a> {
+  meals: &'a [Meal],
+  boluses: Vec<&'a InsulinInjection>,
+  readings: &'a [SugarMeasurement],
+}
+
+impl History<'_> {
+  /// Meal covered by bolus at `at`
+  fn meal_near(&self, at: DateTime<Utc>) -> Option<&Meal> {
+    let window = Duration::minutes(MEAL_BOLUS_MINUTES);
+    self
+      .meals
+      .iter()
+      .find(|m| (m.date_time - at).abs() <= window)
+  }
+
+  /// Latest reading shortly before `at`
+  fn reading_before(
+    &self,
+    at: DateTime<Utc>,
+  ) -> Result<&SugarMeasurement, Exclusion> {
+    let start = at - Duration::minutes(BEFORE_MINUTES);
+    self
+      .readings
+      .iter()
+      .filter(|r| start <= r.date_time && r.date_time <= at)
+      .max_by_key(|r| r.date_time)
+      .ok_or(Exclusion::NoReadingBefore)
+  }
+
+  /// Earliest reading when dose given at `at` has mostly acted
+  fn reading_after(
+    &self,
+    at: DateTime<Utc>,
+  ) -> Result<&SugarMeasurement, Exclusion> {
+    let (from, to) = AFTER_HOURS;
+    let (start, end) =
+      (at + Duration::hours(from), at + Duration::hours(to));
+    self
+      .readings
+      .iter()
+      .filter(|r| start <= r.date_time && r.date_time <= end)
+      .min_by_key(|r| r.date_time)
+      .ok_or(Exclusion::NoReadingAfter)
+  }
+
+  /// Checks nothing else affected level from hours before `at` until
+  /// `end` except own meals and boluses of sample
+  fn check_undisturbed(
+    &self,
+    at: DateTime<Utc>,
+    end: DateTime<Utc>,
+    is_own_meal: impl Fn(&Meal) -> bool,
+    is_own_bolus: impl Fn(&InsulinInjection) -> bool,
+  ) -> Result<(), Exclusion> {
+    let start = at - Duration::hours(NEARBY_HOURS);
+    let within = |dt: DateTime<Utc>| start <= dt && dt <= end;
+    if self
+      .meals
+      .iter()
+      .any(|m| within(m.date_time) && !is_own_meal(m))
+    {
+      return Err(Exclusion::OtherMeal);
+    }
+    if self
+      .boluses
+      .iter()
+      .any(|b| within(b.date_time) && !is_own_bolus(b))
+    {
+      return Err(Exclusion::NearbyDose);
+    }
+    if self.readings.iter().any(|r| {
+      at <= r.date_time && r.date_time <= end && r.level.is_low()
+    }) {
+      return Err(Exclusion::Hypo);
+    }
+    Ok(())
+  }
+
+  /// Grams of carbs covered by unit, corrected for change of level
+  /// with `sensitivity` if it is known
+  fn carb_ratio(
+    &self,
+    meal: &Meal,
+    sensitivity: Option<f64>,
+  ) -> Result<f64, Exclusion> {
+    let window = Duration::minutes(MEAL_BOLUS_MINUTES);
+    let is_meal_bolus = |b: &InsulinInjection| {
+      (b.date_time - meal.date_time).abs() <= window
+    };
+    let units = self
+      .boluses
+      .iter()
+      .filter(|b| is_meal_bolus(b))
+      .map(|b| b.volume.as_cubic_centimeters())
+      .sum::<f64>();
+    if units <= 0.0 {
+      return Err(Exclusion::NoBolus);
+    }
+    let before = self.reading_before(meal.date_time)?;
+    let after = self.reading_after(meal.date_time)?;
+    self.check_undisturbed(
+      meal.date_time,
+      after.date_time,
+      |m| m == meal,
+      is_meal_bolus,
+    )?;
+    let drift = after.level.as_millimoles_per_liter()
+      - before.level.as_millimoles_per_liter();
+    if drift.abs() > MAX_MEAL_DRIFT {
+      return Err(Exclusion::LargeDrift);
+    }
+    let needed = units + sensitivity.map_or(0.0, |isf| drift / isf);
+    if needed <= 0.0 {
+      return Err(Exclusion::LargeDrift);
+    }
+    Ok(meal.carbs_grams / needed)
+  }
+
+  /// Drop of level by unit of correction `bolus`
+  fn sensitivity(
+    &self,
+    bolus: &InsulinInjection,
+  ) -> Result<f64, Exclusion> {
+    let before = self.reading_before(bolus.date_time)?;
+    let before_level = before.level.as_millimoles_per_liter();
+    if before_level < MIN_CORRECTION_LEVEL {
+      return Err(Exclusion::NotHigh);
+    }
+    let after = self.reading_after(bolus.date_time)?;
+    self.check_undisturbed(
+      bolus.date_time,
+      after.date_time,
+      |_| false,
+      |b| b == bolus,
+    )?;
+    let drop = before_level - after.level.as_millimoles_per_liter();
+    if drop <= 0.0 {
+      return Err(Exclusion::NoDrop);
+    }
+    Ok(drop / bolus.volume.as_cubic_centimeters())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::app::{
+    insulin_injection::Insulin, sugar_measurement::SugarLevel,
+  };
+
+  use super::*;
+
+  fn at(day: u32, time: &str) -> DateTime<Utc> {
+    format!("2024-03-{day:02}T{time}:00Z").parse().unwrap()
+  }
+
+  fn reading(
+    date_time: DateTime<Utc>,
+    level: f64,
+  ) -> SugarMeasurement {
+    SugarMeasurement {
+      date_time,
+      level: SugarLevel::from_millimoles_per_liter(level),
+    }
+  }
+
+  fn bolus(date_time: DateTime<Utc>, units: f64) -> InsulinInjection {
+    InsulinInjection {
+      date_time,
+      volume: Insulin::from_cubic_centimeters(units),
+      kind: Some(InsulinKind::Bolus),
+    }
+  }
+
+  fn meal(date_time: DateTime<Utc>, carbs_grams: f64) -> Meal {
+    Meal {
+      date_time,
+      carbs_grams,
+      note: None,
+    }
+  }
+
+  /// Breakfasts at 08:00 covering 10 g by unit, corrections at 13:00
+  /// dropping level by 2 mmol/L per unit
+  fn history(
+  ) -> (Vec<Meal>, Vec<InsulinInjection>, Vec<SugarMeasurement>) {
+    let mut meals = Vec::new();
+    let mut injections = Vec::new();
+    let mut readings = Vec::new();
+    for (day, carbs) in [(1, 50.0), (2, 60.0), (3, 40.0), (4, 45.0)] {
+      meals.push(meal(at(day, "08:00"), carbs));
+      injections.push(bolus(at(day, "07:50"), carbs / 10.0));
+      readings.push(reading(at(day, "07:45"), 6.0));
+      readings.push(reading(at(day, "12:00"), 6.0));
+    }
+    for (day, units) in [(1, 2.0), (2, 3.0), (3, 2.0)] {
+      injections.push(bolus(at(day, "13:00"), units));
+      readings.push(reading(at(day, "12:55"), 12.0));
+      readings.push(reading(at(day, "16:30"), 12.0 - 2.0 * units));
+    }
+    (meals, injections, readings)
+  }
+
+  #[test]
+  fn estimates_by_part_of_day() {
+    let (meals, injections, readings) = history();
+    let ratios =
+      Ratios::estimate(&meals, &injections, &readings, &Utc);
+    let morning = &ratios.buckets[1];
+    let carb_ratio = morning.carb_ratio().unwrap();
+    assert!((carb_ratio.mean - 10.0).abs() < 1e-9);
+    assert!(carb_ratio.low <= 10.0 && 10.0 <= carb_ratio.high);
+    assert_eq!(morning.sensitivity(), None);
+    let afternoon = &ratios.buckets[2];
+    let sensitivity = afternoon.sensitivity().unwrap();
+    assert!((sensitivity.mean - 2.0).abs() < 1e-9);
+    assert_eq!(afternoon.carb_ratio, Vec::<f64>::new());
+    assert!(ratios.excluded.is_empty());
+  }
+
+  #[test]
+  fn excludes_disturbed_entries() {
+    let (mut meals, mut injections, mut readings) = history();
+    // Correction 2 hours before breakfast
+    injections.push(bolus(at(1, "06:00"), 1.0));
+    readings.push(reading(at(1, "05:55"), 7.0));
+    // Hypo after correction
+    readings.push(reading(at(2, "14:30"), 3.2));
+    // Breakfast without bolus and one without reading after it
+    meals.push(meal(at(5, "08:00"), 30.0));
+    meals.push(meal(at(6, "08:00"), 30.0));
+    injections.push(bolus(at(6, "08:00"), 3.0));
+    readings.push(reading(at(6, "07:30"), 6.0));
+    // Snack isn't counted at all
+    meals.push(meal(at(7, "08:00"), 5.0));
+    let ratios =
+      Ratios::estimate(&meals, &injections, &readings, &Utc);
+    assert_eq!(
+      ratios.excluded,
+      BTreeMap::from([
+        (Exclusion::NoBolus, 1),
+        (Exclusion::NoReadingAfter, 1),
+        (Exclusion::NearbyDose, 1),
+        (Exclusion::Hypo, 1),
+        (Exclusion::NotHigh, 1),
+      ])
+    );
+    assert_eq!(ratios.buckets[1].carb_ratio.len(), 3);
+    assert_eq!(ratios.buckets[2].sensitivity(), None);
+  }
+
+  #[test]
+  fn corrects_carb_ratio_for_drift() {
+    let (meals, mut injections, mut readings) = history();
+    // Lunch at 14:00 with 4 units for 40 g, level rises by 2 mmol/L
+    // which needs 1 more unit with sensitivity of 2 mmol/L
+    let lunch = [meal(at(5, "14:00"), 40.0)];
+    injections.push(bolus(at(5, "14:00"), 4.0));
+    readings.push(reading(at(5, "13:50"), 6.0));
+    readings.push(reading(at(5, "18:00"), 8.0));
+    let meals = [meals, lunch.to_vec()].concat();
+    let ratios =
+      Ratios::estimate(&meals, &injections, &readings, &Utc);
+    assert_eq!(ratios.buckets[2].carb_ratio, vec![8.0]);
+  }
+
+  #[test]
+  fn renders_suggestions() {
+    let (meals, injections, readings) = history();
+    let mut ratios =
+      Ratios::estimate(&meals, &injections, &readings, &Utc);
+    ratios.excluded.insert(Exclusion::NearbyDose, 2);
+    let text = ratios.render(30, "");
+    assert!(text.starts_with("🧮 Оценка коэффициентов за 30 дней\n"));
+    assert!(text.contains(
+      "Утро (06–12)\nУК: 1 ЕД на 10 г (95 % ДИ 10–10), приемов \
+      пищи: 4\nФЧИ: мало данных (коррекций: 0)\n"
+    ));
+    assert!(text.contains("ФЧИ: 1 ЕД снижает на 2.0 ммоль/л"));
+    assert!(text.contains(
+      "• коррекция или другая доза в пределах 3 часов — 2\n"
+    ));
+    assert!(text.contains("бот ничего не меняет"));
+  }
+
+  #[test]
+  fn confidence_interval_narrows_with_samples() {
+    let few = Estimate::from_samples(&[8.0, 10.0, 12.0]).unwrap();
+    let many =
+      Estimate::from_samples(&[8.0, 10.0, 12.0].repeat(4)).unwrap();
+    assert!((few.mean - 10.0).abs() < 1e-9);
+    assert!(many.high - many.low < few.high - few.low);
+    assert_eq!(Estimate::from_samples(&[10.0, 12.0]), None);
+  }
+}
@@ -0,0 +1,62 @@
+pub mod estimate;
+
+use std::sync::Arc;
+
+use chrono::{Days, Utc};
+use teloxide::{dptree::case, prelude::*};
+
+use crate::{
+  app, bot_commands::MenuCommand, common::Result, db::Db,
+  utils::filter_message,
+};
+
+use self::estimate::Ratios;
+
+use super::{
+  insulin_injection::repository::insulin_injections,
+  meal::repository::meals, preferences::repository::preferences,
+  profile::current_profile,
+  sugar_measurement::repository::sugar_measurements, UpdateHandler,
+};
+
+/// Days of history ratios are estimated from
+pub const DAYS: u64 = 30;
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    filter_message()
+      .filter_command::<MenuCommand>()
+      .branch(case![MenuCommand::Ratios].endpoint(send_ratios))
+  }
+}
+
+async fn send_ratios(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+) -> Result<()> {
+  let profile = current_profile(&db, user_id).await?;
+  let end = Utc::now();
+  let start = end - Days::new(DAYS);
+  let meals =
+    meals(&db, profile.id).fetch_between(start, end).await?;
+  let injections = insulin_injections(&db, profile.id)
+    .fetch_between(start, end)
+    .await?;
+  let readings = sugar_measurements(&db, profile.id)
+    .fetch_between(start, end)
+    .await?;
+  let tz = preferences(&db)
+    .fetch(profile.id)
+    .await?
+    .unwrap_or_default()
+    .timezone;
+  let ratios = Ratios::estimate(&meals, &injections, &readings, &tz);
+  bot
+    .send_message(chat_id, ratios.render(DAYS, &profile.tag()))
+    .await?;
+  Ok(())
+}
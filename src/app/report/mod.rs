@@ -0,0 +1,201 @@
+mod pdf;
+
+use std::{collections::HashSet, sync::Arc};
+
+use chrono::{DateTime, Local, Utc};
+use teloxide::{
+  dptree::case,
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile},
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::Db,
+  utils::{filter_callback_query, filter_message},
+};
+
+use self::pdf::{Entry, Picture, Report};
+
+use super::{
+  agp::{self, Agp},
+  chart::{self, render::Chart},
+  insulin_injection::repository::insulin_injections,
+  meal::repository::meals,
+  profile::current_profile,
+  stats::{
+    statistics::{hypo_episodes, GlucoseStats, InsulinStats},
+    Period,
+  },
+  sugar_measurement::repository::sugar_measurements,
+  UpdateHandler,
+};
+
+const CALLBACK_PREFIX: &str = "report:";
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    dptree::entry()
+      .branch(
+        filter_message()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::Report].endpoint(ask_period)),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            let data = query.data.as_deref()?;
+            Period::parse(data.strip_prefix(CALLBACK_PREFIX)?)
+          })
+          .endpoint(send_report),
+      )
+  }
+}
+
+async fn ask_period(bot: Bot, chat_id: ChatId) -> Result<()> {
+  let periods = Period::ALL
+    .into_iter()
+    .filter(|&period| period != Period::Today)
+    .map(|period| {
+      InlineKeyboardButton::callback(
+        period.label(),
+        format!("{CALLBACK_PREFIX}{}", period.data()),
+      )
+    })
+    .collect::<Vec<_>>();
+  bot
+    .send_message(chat_id, "Выберите период отчета для врача:")
+    .reply_markup(InlineKeyboardMarkup::new([periods]))
+    .await?;
+  Ok(())
+}
+
+async fn send_report(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  period: Period,
+  query: CallbackQuery,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  let profile = current_profile(&db, user_id).await?;
+  let now = Local::now();
+  let start = period.start(now);
+  let end = now.to_utc();
+  let local =
+    |dt: DateTime<Utc>| dt.with_timezone(&Local).naive_local();
+  let measurements = sugar_measurements(&db, profile.id)
+    .fetch_between(start, end)
+    .await?;
+  let injections = insulin_injections(&db, profile.id)
+    .fetch_between(start, end)
+    .await?;
+  let meals =
+    meals(&db, profile.id).fetch_between(start, end).await?;
+
+  let agp = Agp::generate(&measurements, start, end, &Local);
+  let chart = Chart {
+    title: format!("Сахар за {}", period.title()),
+    start: local(start),
+    end: now.naive_local(),
+    levels: measurements
+      .iter()
+      .map(|m| {
+        (local(m.date_time), m.level.as_millimoles_per_liter())
+      })
+      .collect(),
+    injections: injections
+      .iter()
+      .map(|i| {
+        (local(i.date_time), i.volume.as_cubic_centimeters(), i.kind)
+      })
+      .collect(),
+    meals: meals
+      .iter()
+      .map(|m| (local(m.date_time), m.carbs_grams))
+      .collect(),
+  };
+  let mut entries: Vec<_> = measurements
+    .iter()
+    .map(|m| {
+      let level = m.level.as_millimoles_per_liter();
+      (local(m.date_time), Entry::Sugar(level))
+    })
+    .chain(injections.iter().map(|i| {
+      let volume = i.volume.as_cubic_centimeters();
+      (local(i.date_time), Entry::Insulin(volume, i.kind))
+    }))
+    .chain(meals.iter().map(|m| {
+      (
+        local(m.date_time),
+        Entry::Meal(m.carbs_grams, m.note.clone()),
+      )
+    }))
+    .collect();
+  entries.sort_by_key(|(time, _)| *time);
+  let meal_days = meals
+    .iter()
+    .map(|m| local(m.date_time).date())
+    .collect::<HashSet<_>>()
+    .len();
+  #[allow(clippy::cast_precision_loss)]
+  let carbs_per_day = (meal_days > 0).then(|| {
+    meals.iter().map(|m| m.carbs_grams).sum::<f64>()
+      / meal_days as f64
+  });
+  let hypos = hypo_episodes(&measurements)
+    .into_iter()
+    .map(|episode| {
+      (local(episode.start), local(episode.end), episode)
+    })
+    .collect();
+
+  let mut report = Report {
+    patient: profile.display_name(&bot).await?,
+    start: local(start),
+    end: now.naive_local(),
+    glucose: GlucoseStats::calculate(&measurements),
+    insulin: InsulinStats::calculate(&injections, &Local),
+    carbs_per_day,
+    agp,
+    hypos,
+    pictures: Vec::new(),
+    entries,
+  };
+  let file_name = format!(
+    "report_{}_{}.pdf",
+    report.start.format("%Y-%m-%d"),
+    report.end.format("%Y-%m-%d")
+  );
+  let pdf = tokio::task::spawn_blocking(move || {
+    report.pictures.push(Picture {
+      pixels: chart.render_pixels()?,
+      width: chart::render::WIDTH,
+      height: chart::render::HEIGHT,
+    });
+    if let Some(agp) = &report.agp {
+      report.pictures.push(Picture {
+        pixels: agp::render::render_pixels(agp, "AGP", &Local)?,
+        width: agp::render::WIDTH,
+        height: agp::render::HEIGHT,
+      });
+    }
+    report.render()
+  })
+  .await
+  .map_err(any)??;
+  bot
+    .send_document(
+      msg.chat.id,
+      InputFile::memory(pdf).file_name(file_name),
+    )
+    .await?;
+  Ok(())
+}
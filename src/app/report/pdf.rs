@@ -0,0 +1,494 @@
+use std::io::Cursor;
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Weekday};
+use printpdf::{
+  Color, ColorBits, ColorSpace, Image, ImageTransform, ImageXObject,
+  IndirectFontRef, Line, Mm, PdfDocument, PdfDocumentReference,
+  PdfLayerReference, Point, Px, Rect, Rgb,
+};
+
+use crate::{
+  app::{
+    agp::{Agp, VERY_HIGH_LEVEL, VERY_LOW_LEVEL},
+    insulin_injection::InsulinKind,
+    stats::statistics::{GlucoseStats, HypoEpisode, InsulinStats},
+    sugar_measurement::{HIGH_LEVEL, LOW_LEVEL},
+  },
+  common::Result,
+};
+
+const FONT_BYTES: &[u8] =
+  include_bytes!("../../../assets/fonts/DejaVuSans.ttf");
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 15.0;
+const CONTENT_WIDTH: f32 = PAGE_WIDTH - 2.0 * MARGIN;
+const MM_PER_INCH: f32 = 25.4;
+/// Height of text line per point of font size, mm
+const LINE_HEIGHT: f32 = 0.5;
+
+/// Left edges of logbook columns relative to margin, mm
+const COLUMNS: [f32; 5] = [0.0, 20.0, 50.0, 85.0, 110.0];
+
+/// RGB pixels of rendered image
+pub struct Picture {
+  pub pixels: Vec<u8>,
+  pub width: u32,
+  pub height: u32,
+}
+
+/// Logbook record in local time
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+  Sugar(f64),
+  Insulin(f64, Option<InsulinKind>),
+  Meal(f64, Option<String>),
+}
+
+/// Report contents in local time of the patient
+pub struct Report {
+  pub patient: String,
+  pub start: NaiveDateTime,
+  pub end: NaiveDateTime,
+  pub glucose: Option<GlucoseStats>,
+  pub insulin: Option<InsulinStats>,
+  /// Average carbs per day with meals, g
+  pub carbs_per_day: Option<f64>,
+  pub agp: Option<Agp>,
+  /// Episodes with local start and end time
+  pub hypos: Vec<(NaiveDateTime, NaiveDateTime, HypoEpisode)>,
+  pub pictures: Vec<Picture>,
+  /// Records in chronological order
+  pub entries: Vec<(NaiveDateTime, Entry)>,
+}
+
+impl Report {
+  /// Renders multi-page A4 PDF
+  pub fn render(&self) -> Result<Vec<u8>> {
+    let mut writer = Writer::new(&format!(
+      "Дневник самоконтроля — {}",
+      self.patient
+    ))?;
+    self.write_summary(&mut writer);
+    self.write_time_in_ranges(&mut writer);
+    self.write_hypos(&mut writer);
+    self.write_pictures(&mut writer);
+    self.write_logbook(&mut writer);
+    writer
+      .doc
+      .save_to_bytes()
+      .map_err(|err| err.to_string().into())
+  }
+
+  fn write_summary(&self, w: &mut Writer) {
+    w.text("Дневник самоконтроля", 20.0);
+    w.text(&format!("Пациент: {}", self.patient), 12.0);
+    w.text(
+      &format!(
+        "Период: {} – {}",
+        self.start.format("%d.%m.%Y"),
+        self.end.format("%d.%m.%Y")
+      ),
+      12.0,
+    );
+    w.gap();
+    w.heading("Сводка");
+    match &self.glucose {
+      Some(stats) => {
+        w.text(&format!("Измерений сахара: {}", stats.count), 11.0);
+        w.text(
+          &format!("Средний сахар: {:.1} ммоль/л", stats.mean),
+          11.0,
+        );
+        w.text(
+          &format!(
+            "Стандартное отклонение: {:.1} ммоль/л",
+            stats.std_dev
+          ),
+          11.0,
+        );
+        w.text(
+          &format!(
+            "Коэффициент вариации: {:.1} %",
+            stats.coefficient_of_variation()
+          ),
+          11.0,
+        );
+      }
+      None => w.text("Нет измерений сахара", 11.0),
+    }
+    if let Some(agp) = &self.agp {
+      w.text(
+        &format!("GMI (расчетный HbA1c): {:.1} %", agp.gmi),
+        11.0,
+      );
+    }
+    match &self.insulin {
+      Some(insulin) => {
+        w.text(
+          &format!(
+            "Суточная доза инсулина: {:.1} ЕД (базальный {:.1}, \
+            болюсный {:.1}, не указан {:.1})",
+            insulin.total(),
+            insulin.basal,
+            insulin.bolus,
+            insulin.unspecified
+          ),
+          11.0,
+        );
+      }
+      None => w.text("Нет введенного инсулина", 11.0),
+    }
+    if let Some(carbs) = self.carbs_per_day {
+      w.text(&format!("Углеводы в день: {carbs:.0} г"), 11.0);
+    }
+    w.gap();
+  }
+
+  #[allow(clippy::cast_possible_truncation)]
+  fn write_time_in_ranges(&self, w: &mut Writer) {
+    let Some(agp) = &self.agp else {
+      return;
+    };
+    w.heading("Время в диапазонах (доля измерений)");
+    let ranges = &agp.ranges;
+    let bands = [
+      (
+        format!("Очень высокий >{VERY_HIGH_LEVEL}"),
+        ranges.very_high,
+        Rgb::new(0.95, 0.55, 0.1, None),
+      ),
+      (
+        format!("Высокий {HIGH_LEVEL:.1}–{VERY_HIGH_LEVEL}"),
+        ranges.high,
+        Rgb::new(1.0, 0.8, 0.2, None),
+      ),
+      (
+        format!("В диапазоне {LOW_LEVEL}–{HIGH_LEVEL:.1}"),
+        ranges.in_range,
+        Rgb::new(0.3, 0.7, 0.35, None),
+      ),
+      (
+        format!("Низкий {VERY_LOW_LEVEL:.1}–{LOW_LEVEL}"),
+        ranges.low,
+        Rgb::new(0.9, 0.3, 0.3, None),
+      ),
+      (
+        format!("Очень низкий <{VERY_LOW_LEVEL:.1}"),
+        ranges.very_low,
+        Rgb::new(0.6, 0.1, 0.1, None),
+      ),
+    ];
+    for (label, share, color) in bands {
+      w.ensure(6.0);
+      w.rect(MARGIN, w.y - 4.0, 60.0 * share as f32, 3.5, color);
+      w.text_at(&format!("{:.0} %", share * 100.0), 11.0, 62.0);
+      w.text_at(&label, 11.0, 80.0);
+      w.y -= 6.0;
+    }
+    w.gap();
+  }
+
+  fn write_hypos(&self, w: &mut Writer) {
+    w.heading("Эпизоды гипогликемии");
+    if self.hypos.is_empty() {
+      w.text("Не было", 11.0);
+    }
+    for (start, end, episode) in &self.hypos {
+      let minutes = (*end - *start).num_minutes();
+      w.text(
+        &format!(
+          "{}, {} мин, минимум {:.1} ммоль/л",
+          start.format("%d.%m.%Y %H:%M"),
+          minutes,
+          episode.min_level
+        ),
+        11.0,
+      );
+    }
+    w.gap();
+  }
+
+  fn write_pictures(&self, w: &mut Writer) {
+    for picture in &self.pictures {
+      w.picture(picture);
+      w.gap();
+    }
+  }
+
+  fn write_logbook(&self, w: &mut Writer) {
+    w.new_page();
+    w.heading("Дневник");
+    let mut day: Option<NaiveDate> = None;
+    for (time, entry) in &self.entries {
+      if day != Some(time.date()) {
+        day = Some(time.date());
+        w.ensure(20.0);
+        w.gap();
+        w.text(
+          &format!(
+            "{}, {}",
+            weekday(time.weekday()),
+            time.format("%d.%m.%Y")
+          ),
+          13.0,
+        );
+        w.row(
+          &[
+            "Время",
+            "Сахар, ммоль/л",
+            "Инсулин",
+            "Углеводы",
+            "Заметки",
+          ]
+          .map(String::from),
+          10.0,
+        );
+        w.rule();
+      }
+      let mut cells = [
+        time.format("%H:%M").to_string(),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+      ];
+      match entry {
+        Entry::Sugar(level) => cells[1] = format!("{level:.1}"),
+        Entry::Insulin(volume, kind) => {
+          let kind = match kind {
+            Some(InsulinKind::Basal) => " баз.",
+            Some(InsulinKind::Bolus) => " бол.",
+            None => "",
+          };
+          cells[2] = format!("{volume} ЕД{kind}");
+        }
+        Entry::Meal(carbs, note) => {
+          cells[3] = format!("{carbs} г");
+          cells[4] = note.clone().unwrap_or_default();
+        }
+      }
+      w.row(&cells, 10.0);
+    }
+    if self.entries.is_empty() {
+      w.text("Нет записей за период", 11.0);
+    }
+  }
+}
+
+fn weekday(weekday: Weekday) -> &'static str {
+  match weekday {
+    Weekday::Mon => "Пн",
+    Weekday::Tue => "Вт",
+    Weekday::Wed => "Ср",
+    Weekday::Thu => "Чт",
+    Weekday::Fri => "Пт",
+    Weekday::Sat => "Сб",
+    Weekday::Sun => "Вс",
+  }
+}
+
+/// Lays content out top to bottom adding pages when needed
+struct Writer {
+  doc: PdfDocumentReference,
+  font: IndirectFontRef,
+  layer: PdfLayerReference,
+  /// Top of free space on page, mm from bottom
+  y: f32,
+  page: usize,
+}
+
+impl Writer {
+  fn new(title: &str) -> Result<Self> {
+    let (doc, page, layer) = PdfDocument::new(
+      title,
+      Mm(PAGE_WIDTH),
+      Mm(PAGE_HEIGHT),
+      "content",
+    );
+    let font = doc
+      .add_external_font(Cursor::new(FONT_BYTES))
+      .map_err(|err| err.to_string())?;
+    let layer = doc.get_page(page).get_layer(layer);
+    let writer = Self {
+      doc,
+      font,
+      layer,
+      y: PAGE_HEIGHT - MARGIN,
+      page: 1,
+    };
+    writer.footer();
+    Ok(writer)
+  }
+
+  fn new_page(&mut self) {
+    let (page, layer) =
+      self
+        .doc
+        .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "content");
+    self.layer = self.doc.get_page(page).get_layer(layer);
+    self.y = PAGE_HEIGHT - MARGIN;
+    self.page += 1;
+    self.footer();
+  }
+
+  fn footer(&self) {
+    self.layer.use_text(
+      format!("Стр. {}", self.page),
+      9.0,
+      Mm(PAGE_WIDTH / 2.0 - 5.0),
+      Mm(MARGIN / 2.0),
+      &self.font,
+    );
+  }
+
+  /// Starts new page unless `height` fits
+  fn ensure(&mut self, height: f32) {
+    if self.y - height < MARGIN {
+      self.new_page();
+    }
+  }
+
+  fn gap(&mut self) {
+    self.y -= 4.0;
+  }
+
+  fn heading(&mut self, text: &str) {
+    self.ensure(20.0);
+    self.text(text, 14.0);
+    self.y -= 1.0;
+  }
+
+  /// Writes line at left margin
+  fn text(&mut self, text: &str, size: f32) {
+    self.row(&[text.to_string()], size);
+  }
+
+  /// Writes text at `x` from left margin without advancing
+  fn text_at(&self, text: &str, size: f32, x: f32) {
+    let y = self.y - size * LINE_HEIGHT;
+    self.layer.use_text(
+      text,
+      size,
+      Mm(MARGIN + x),
+      Mm(y),
+      &self.font,
+    );
+  }
+
+  /// Writes cells at [`COLUMNS`]
+  fn row(&mut self, cells: &[String], size: f32) {
+    let height = size * LINE_HEIGHT + 1.5;
+    self.ensure(height);
+    for (cell, x) in cells.iter().zip(COLUMNS) {
+      self.text_at(cell, size, x);
+    }
+    self.y -= height;
+  }
+
+  fn rule(&mut self) {
+    let y = Mm(self.y + 0.5);
+    self.layer.add_line(Line {
+      points: vec![
+        (Point::new(Mm(MARGIN), y), false),
+        (Point::new(Mm(PAGE_WIDTH - MARGIN), y), false),
+      ],
+      is_closed: false,
+    });
+    self.y -= 1.0;
+  }
+
+  fn rect(
+    &self,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    color: Rgb,
+  ) {
+    self.layer.set_fill_color(Color::Rgb(color));
+    self.layer.add_rect(Rect::new(
+      Mm(x),
+      Mm(y),
+      Mm(x + width),
+      Mm(y + height),
+    ));
+    self
+      .layer
+      .set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+  }
+
+  /// Places picture scaled to content width
+  #[allow(clippy::cast_precision_loss)]
+  fn picture(&mut self, picture: &Picture) {
+    let dpi = picture.width as f32 / (CONTENT_WIDTH / MM_PER_INCH);
+    let height = picture.height as f32 / dpi * MM_PER_INCH;
+    self.ensure(height);
+    self.y -= height;
+    let image = Image::from(ImageXObject {
+      width: Px(picture.width as usize),
+      height: Px(picture.height as usize),
+      color_space: ColorSpace::Rgb,
+      bits_per_component: ColorBits::Bit8,
+      interpolate: true,
+      image_data: picture.pixels.clone(),
+      image_filter: None,
+      smask: None,
+      clipping_bbox: None,
+    });
+    image.add_to_layer(
+      self.layer.clone(),
+      ImageTransform {
+        translate_x: Some(Mm(MARGIN)),
+        translate_y: Some(Mm(self.y)),
+        dpi: Some(dpi),
+        ..ImageTransform::default()
+      },
+    );
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::Duration;
+
+  use super::*;
+
+  #[test]
+  fn renders_long_logbook() {
+    let start: NaiveDateTime = "2024-03-01T00:00:00".parse().unwrap();
+    let entries = (0..300)
+      .map(|i| {
+        let entry = match i % 3 {
+          0 => Entry::Sugar(6.5),
+          1 => Entry::Insulin(4.0, Some(InsulinKind::Bolus)),
+          _ => Entry::Meal(45.0, Some("овсянка".into())),
+        };
+        (start + Duration::hours(i), entry)
+      })
+      .collect();
+    let report = Report {
+      patient: "Тест".into(),
+      start,
+      end: start + Duration::days(14),
+      glucose: None,
+      insulin: None,
+      carbs_per_day: None,
+      agp: None,
+      hypos: Vec::new(),
+      pictures: vec![Picture {
+        pixels: vec![255; 4 * 3 * 3],
+        width: 4,
+        height: 3,
+      }],
+      entries,
+    };
+    let pdf = report.render().unwrap();
+    assert!(pdf.starts_with(b"%PDF"));
+    let pages =
+      pdf.windows(10).filter(|w| w == b"/Type/Page").count()
+        + pdf.windows(11).filter(|w| w == b"/Type /Page").count();
+    assert!(pages > 2, "{pages}");
+  }
+}
@@ -0,0 +1,216 @@
+use chrono::NaiveDate;
+use plotters::{
+  coord::Shift,
+  prelude::*,
+  style::text_anchor::{HPos, Pos, VPos},
+};
+
+use crate::{
+  app::{
+    chart::render::{
+      encode_png, register_bundled_font, FONT, HEIGHT, WIDTH,
+    },
+    sugar_measurement::{HIGH_LEVEL, LOW_LEVEL},
+  },
+  common::Result,
+};
+
+use super::time_of_day::Buckets;
+
+/// Level above which highs are shown darker, mmol/L
+const VERY_HIGH_LEVEL: f64 = 13.9;
+/// Level below which lows are shown darker, mmol/L
+const VERY_LOW_LEVEL: f64 = 3.0;
+/// Every row is labeled while there are no more rows
+const MAX_LABELED_ROWS: usize = 31;
+const LEGEND_HEIGHT: u32 = 40;
+
+const EMPTY_COLOR: RGBColor = RGBColor(235, 235, 235);
+const VERY_LOW_COLOR: RGBColor = RGBColor(170, 20, 30);
+const LOW_COLOR: RGBColor = RGBColor(230, 90, 90);
+const IN_RANGE_COLOR: RGBColor = RGBColor(120, 195, 120);
+const HIGH_COLOR: RGBColor = RGBColor(250, 205, 90);
+const VERY_HIGH_COLOR: RGBColor = RGBColor(240, 140, 50);
+
+/// Mean levels by day and time of day
+pub struct Heatmap {
+  pub title: String,
+  pub buckets: Buckets,
+  /// Local dates with mean level in mmol/L of every bucket, oldest
+  /// first
+  pub days: Vec<(NaiveDate, Vec<Option<f64>>)>,
+}
+
+fn color(level: f64) -> RGBColor {
+  match level {
+    level if level < VERY_LOW_LEVEL => VERY_LOW_COLOR,
+    level if level < LOW_LEVEL => LOW_COLOR,
+    level if level <= HIGH_LEVEL => IN_RANGE_COLOR,
+    level if level <= VERY_HIGH_LEVEL => HIGH_COLOR,
+    _ => VERY_HIGH_COLOR,
+  }
+}
+
+impl Heatmap {
+  /// Renders heatmap to PNG
+  pub fn render(&self) -> Result<Vec<u8>> {
+    register_bundled_font();
+    let mut pixels = vec![0; (WIDTH * HEIGHT * 3) as usize];
+    self.draw(&mut pixels).map_err(|err| err.to_string())?;
+    encode_png(&pixels, WIDTH, HEIGHT)
+  }
+
+  fn draw(
+    &self,
+    pixels: &mut [u8],
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let root = BitMapBackend::with_buffer(pixels, (WIDTH, HEIGHT))
+      .into_drawing_area();
+    root.fill(&WHITE)?;
+    let (area, legend) =
+      root.split_vertically(HEIGHT - LEGEND_HEIGHT);
+    self.draw_cells(&area)?;
+    draw_legend(&legend)?;
+    root.present()?;
+    Ok(())
+  }
+
+  #[allow(clippy::cast_precision_loss)]
+  fn draw_cells(
+    &self,
+    area: &DrawingArea<BitMapBackend, Shift>,
+  ) -> Result<(), Box<dyn std::error::Error>> {
+    let rows = self.days.len().max(1);
+    let row = |index: usize| (rows - 1 - index) as f64;
+    let mut chart = ChartBuilder::on(area)
+      .caption(&self.title, (FONT, 24))
+      .margin(16)
+      .x_label_area_size(30)
+      .y_label_area_size(60)
+      .build_cartesian_2d(0.0..24.0, 0.0..rows as f64)?;
+    chart
+      .configure_mesh()
+      .disable_mesh()
+      .x_labels(13)
+      .x_label_formatter(&|hour| format!("{hour:02.0}:00"))
+      .y_labels(0)
+      .label_style((FONT, 14))
+      .draw()?;
+    // Dates are drawn in the middle of rows, which mesh can't do
+    let date_style = (FONT, 14)
+      .into_font()
+      .color(&BLACK)
+      .pos(Pos::new(HPos::Right, VPos::Center));
+    let step = rows.div_ceil(MAX_LABELED_ROWS);
+    for (index, (date, _)) in
+      self.days.iter().enumerate().step_by(step)
+    {
+      let (x, y) = chart.backend_coord(&(0.0, row(index) + 0.5));
+      area.draw(&Text::new(
+        date.format("%d.%m").to_string(),
+        (x - 8, y),
+        date_style.clone(),
+      ))?;
+    }
+    let labeled = rows <= MAX_LABELED_ROWS;
+    let value_style = (FONT, 13)
+      .into_font()
+      .color(&BLACK)
+      .pos(Pos::new(HPos::Center, VPos::Center));
+    for (index, (_, means)) in self.days.iter().enumerate() {
+      let y = row(index);
+      for (bucket, mean) in means.iter().enumerate() {
+        let hours = self.buckets.range(bucket);
+        let (start, end) =
+          (f64::from(hours.start), f64::from(hours.end));
+        let fill = mean.map_or(EMPTY_COLOR, color);
+        chart.draw_series([Rectangle::new(
+          [(start, y), (end, y + 1.0)],
+          fill.filled(),
+        )])?;
+        chart.draw_series([Rectangle::new(
+          [(start, y), (end, y + 1.0)],
+          WHITE.stroke_width(1),
+        )])?;
+        if let (true, Some(mean)) = (labeled, mean) {
+          chart.draw_series([Text::new(
+            format!("{mean:.1}"),
+            ((start + end) / 2.0, y + 0.5),
+            value_style.clone(),
+          )])?;
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+fn draw_legend(
+  area: &DrawingArea<BitMapBackend, Shift>,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let items = [
+    (VERY_LOW_COLOR, format!("<{VERY_LOW_LEVEL:.1}")),
+    (LOW_COLOR, format!("{VERY_LOW_LEVEL:.1}–{LOW_LEVEL}")),
+    (IN_RANGE_COLOR, format!("{LOW_LEVEL}–{HIGH_LEVEL:.1}")),
+    (HIGH_COLOR, format!("{HIGH_LEVEL:.1}–{VERY_HIGH_LEVEL}")),
+    (VERY_HIGH_COLOR, format!(">{VERY_HIGH_LEVEL}")),
+    (EMPTY_COLOR, "нет измерений".into()),
+  ];
+  let style = (FONT, 14).into_font().color(&BLACK);
+  let mut x = 40;
+  for (color, label) in items {
+    area.draw(&Rectangle::new(
+      [(x, 10), (x + 18, 28)],
+      color.filled(),
+    ))?;
+    area.draw(&Text::new(
+      label.clone(),
+      (x + 24, 12),
+      style.clone(),
+    ))?;
+    x += 140;
+  }
+  area.draw(&Text::new("ммоль/л", (x, 12), style))?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::app::chart::render::assert_matches_golden;
+
+  use super::*;
+
+  const GOLDEN: &str = "src/app/stats/testdata/heatmap.png";
+
+  fn heatmap() -> Heatmap {
+    let day = |date: &str, means: [Option<f64>; 4]| {
+      (date.parse().unwrap(), means.to_vec())
+    };
+    Heatmap {
+      title: "Сахар по времени суток за 7 дней".into(),
+      buckets: Buckets::PartsOfDay,
+      days: vec![
+        day("2024-03-01", [Some(3.4), Some(11.2), Some(7.5), None]),
+        day(
+          "2024-03-02",
+          [Some(6.1), Some(14.8), Some(8.0), Some(9.2)],
+        ),
+        day("2024-03-03", [None, Some(12.0), Some(2.8), Some(6.6)]),
+      ],
+    }
+  }
+
+  #[test]
+  fn colors_by_range() {
+    assert_eq!(color(2.5), VERY_LOW_COLOR);
+    assert_eq!(color(3.5), LOW_COLOR);
+    assert_eq!(color(10.0), IN_RANGE_COLOR);
+    assert_eq!(color(12.0), HIGH_COLOR);
+    assert_eq!(color(15.0), VERY_HIGH_COLOR);
+  }
+
+  #[test]
+  fn matches_golden_image() {
+    assert_matches_golden(&heatmap().render().unwrap(), GOLDEN);
+  }
+}
@@ -0,0 +1,549 @@
+pub mod heatmap;
+pub mod statistics;
+pub mod time_of_day;
+
+use std::{fmt::Write, sync::Arc};
+
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use teloxide::{
+  dptree::case,
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile},
+  ApiError, RequestError,
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::Db,
+  utils::{filter_callback_query, filter_message},
+};
+
+use self::{
+  heatmap::Heatmap,
+  statistics::{
+    data_warnings, glucose_management_indicator, DataWarning,
+    GlucoseStats, InsulinStats, GMI_MAX_DAYS, GMI_MIN_DAYS,
+  },
+  time_of_day::{bucket_stats, daily_means, Buckets},
+};
+
+use super::{
+  caregiver::readable_profiles,
+  hba1c::{repository::hba1c_results, LabHba1c},
+  insulin_injection::repository::{insulin_injections, Summary},
+  preferences::repository::preferences,
+  profile::{current_profile, Profile, ProfileId},
+  sugar_measurement::{
+    repository::sugar_measurements, HIGH_LEVEL, LOW_LEVEL,
+  },
+  UpdateHandler,
+};
+
+const CALLBACK_PREFIX: &str = "stats:";
+const HEATMAP_CALLBACK_PREFIX: &str = "stats_heatmap:";
+
+/// Period statistics are calculated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+  /// Since local midnight
+  Today,
+  /// Last days until now
+  Days(u32),
+}
+
+impl Period {
+  pub const ALL: [Self; 5] = [
+    Self::Today,
+    Self::Days(7),
+    Self::Days(14),
+    Self::Days(30),
+    Self::Days(90),
+  ];
+
+  /// Start of period ending at `now`
+  pub fn start<Tz: TimeZone>(
+    self,
+    now: DateTime<Tz>,
+  ) -> DateTime<Utc> {
+    match self {
+      Self::Today => now
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|midnight| {
+          midnight.and_local_timezone(now.timezone()).earliest()
+        })
+        .map_or(now.clone(), |midnight| midnight)
+        .to_utc(),
+      Self::Days(days) => (now - Days::new(days.into())).to_utc(),
+    }
+  }
+
+  pub fn title(self) -> String {
+    match self {
+      Self::Today => "сегодня".into(),
+      Self::Days(days) => format!("{days} дней"),
+    }
+  }
+
+  pub fn label(self) -> String {
+    match self {
+      Self::Today => "Сегодня".into(),
+      Self::Days(days) => format!("{days} дн"),
+    }
+  }
+
+  pub fn parse(s: &str) -> Option<Self> {
+    match s {
+      "today" => Some(Self::Today),
+      days => Some(Self::Days(days.parse().ok()?)),
+    }
+  }
+
+  pub fn data(self) -> String {
+    match self {
+      Self::Today => "today".into(),
+      Self::Days(days) => days.to_string(),
+    }
+  }
+}
+
+/// What statistics are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+  Summary,
+  TimeOfDay(Buckets),
+}
+
+impl View {
+  const ALL: [Self; 3] = [
+    Self::Summary,
+    Self::TimeOfDay(Buckets::PartsOfDay),
+    Self::TimeOfDay(Buckets::Hourly),
+  ];
+
+  fn label(self) -> &'static str {
+    match self {
+      Self::Summary => "Общая",
+      Self::TimeOfDay(Buckets::PartsOfDay) => "По времени суток",
+      Self::TimeOfDay(Buckets::Hourly) => "По часам",
+    }
+  }
+
+  fn parse(s: &str) -> Option<Self> {
+    match s {
+      "summary" => Some(Self::Summary),
+      buckets => Some(Self::TimeOfDay(Buckets::parse(buckets)?)),
+    }
+  }
+
+  fn data(self) -> &'static str {
+    match self {
+      Self::Summary => "summary",
+      Self::TimeOfDay(buckets) => buckets.data(),
+    }
+  }
+}
+
+/// Profile, period and view statistics are shown for
+#[derive(Debug, Clone, Copy)]
+struct Selection {
+  profile_id: ProfileId,
+  period: Period,
+  view: View,
+}
+
+impl Selection {
+  fn parse(data: &str) -> Option<Self> {
+    Self::parse_fields(data.strip_prefix(CALLBACK_PREFIX)?)
+  }
+
+  /// Parses selection of heatmap button
+  fn parse_heatmap(data: &str) -> Option<Self> {
+    Self::parse_fields(data.strip_prefix(HEATMAP_CALLBACK_PREFIX)?)
+  }
+
+  fn parse_fields(data: &str) -> Option<Self> {
+    let mut fields = data.split(':');
+    let profile_id = fields.next()?.parse().ok()?;
+    let period = Period::parse(fields.next()?)?;
+    // Buttons sent before views were added have no view
+    let view =
+      fields.next().map_or(Some(View::Summary), View::parse)?;
+    Some(Self {
+      profile_id: ProfileId(profile_id),
+      period,
+      view,
+    })
+  }
+
+  fn fields(self) -> String {
+    let ProfileId(id) = self.profile_id;
+    format!("{id}:{}:{}", self.period.data(), self.view.data())
+  }
+
+  fn data(self) -> String {
+    format!("{CALLBACK_PREFIX}{}", self.fields())
+  }
+
+  fn heatmap_data(self) -> String {
+    format!("{HEATMAP_CALLBACK_PREFIX}{}", self.fields())
+  }
+}
+
+/// Start of `date` in `tz`
+pub fn midnight_in<Tz: TimeZone>(
+  date: NaiveDate,
+  tz: &Tz,
+) -> DateTime<Utc> {
+  let midnight = date.and_time(NaiveTime::MIN);
+  midnight
+    .and_local_timezone(tz.clone())
+    .earliest()
+    .map_or_else(|| midnight.and_utc(), |midnight| midnight.to_utc())
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    dptree::entry()
+      .branch(
+        filter_message()
+          .filter_command::<MenuCommand>()
+          .branch(case![MenuCommand::Stats].endpoint(send_stats)),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            Selection::parse(query.data.as_deref()?)
+          })
+          .endpoint(select),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            Selection::parse_heatmap(query.data.as_deref()?)
+          })
+          .endpoint(send_heatmap),
+      )
+  }
+}
+
+async fn send_stats(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+) -> Result<()> {
+  let profile = current_profile(&db, user_id).await?;
+  let selection = Selection {
+    profile_id: profile.id,
+    period: Period::Days(7),
+    view: View::Summary,
+  };
+  let readable = readable_profiles(&db, user_id).await?;
+  let text =
+    stats_text(&bot, &db, user_id, &profile, selection).await?;
+  let keyboard =
+    keyboard(&bot, user_id, &readable, selection).await?;
+  bot
+    .send_message(chat_id, text)
+    .reply_markup(keyboard)
+    .await?;
+  Ok(())
+}
+
+async fn select(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  selection: Selection,
+  query: CallbackQuery,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  let readable = readable_profiles(&db, user_id).await?;
+  let Some(profile) =
+    readable.iter().find(|p| p.id == selection.profile_id)
+  else {
+    return Ok(());
+  };
+  let text =
+    stats_text(&bot, &db, user_id, profile, selection).await?;
+  let keyboard =
+    keyboard(&bot, user_id, &readable, selection).await?;
+  let res = bot
+    .edit_message_text(msg.chat.id, msg.id, text)
+    .reply_markup(keyboard)
+    .await;
+  match res {
+    Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(()),
+    res => res.map(|_| ()).map_err(Into::into),
+  }
+}
+
+async fn send_heatmap(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  selection: Selection,
+  query: CallbackQuery,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let (Some(msg), View::TimeOfDay(buckets)) =
+    (query.message, selection.view)
+  else {
+    return Ok(());
+  };
+  let readable = readable_profiles(&db, user_id).await?;
+  let Some(profile) =
+    readable.iter().find(|p| p.id == selection.profile_id)
+  else {
+    return Ok(());
+  };
+  let tz = preferences(&db)
+    .fetch(profile.id)
+    .await?
+    .unwrap_or_default()
+    .timezone;
+  let now = Utc::now().with_timezone(&tz);
+  let measurements = sugar_measurements(&db, profile.id)
+    .fetch_between(selection.period.start(now), now.to_utc())
+    .await?;
+  if measurements.is_empty() {
+    bot
+      .send_message(msg.chat.id, "Нет измерений сахара за период")
+      .await?;
+    return Ok(());
+  }
+  let heatmap = Heatmap {
+    title: format!(
+      "Сахар по времени суток за {}{}",
+      selection.period.title(),
+      profile.tag()
+    ),
+    buckets,
+    days: daily_means(&measurements, buckets, &tz)
+      .into_iter()
+      .collect(),
+  };
+  let png = tokio::task::spawn_blocking(move || heatmap.render())
+    .await
+    .map_err(any)??;
+  bot
+    .send_photo(
+      msg.chat.id,
+      InputFile::memory(png).file_name("heatmap.png"),
+    )
+    .await?;
+  Ok(())
+}
+
+async fn keyboard(
+  bot: &Bot,
+  user_id: UserId,
+  readable: &[Profile],
+  selection: Selection,
+) -> Result<InlineKeyboardMarkup> {
+  let mark = |selected: bool| if selected { "✅ " } else { "" };
+  let periods = Period::ALL
+    .into_iter()
+    .map(|period| {
+      InlineKeyboardButton::callback(
+        format!(
+          "{}{}",
+          mark(period == selection.period),
+          period.label()
+        ),
+        Selection {
+          period,
+          ..selection
+        }
+        .data(),
+      )
+    })
+    .collect();
+  let views = View::ALL
+    .into_iter()
+    .map(|view| {
+      InlineKeyboardButton::callback(
+        format!("{}{}", mark(view == selection.view), view.label()),
+        Selection { view, ..selection }.data(),
+      )
+    })
+    .collect();
+  let mut rows = vec![periods, views];
+  if let View::TimeOfDay(_) = selection.view {
+    rows.push(vec![InlineKeyboardButton::callback(
+      "🗺 Тепловая карта",
+      selection.heatmap_data(),
+    )]);
+  }
+  if readable.len() > 1 {
+    for profile in readable {
+      let name = if profile.user_id == user_id {
+        profile.label().to_string()
+      } else {
+        profile.display_name(bot).await?
+      };
+      let profile_id = profile.id;
+      rows.push(vec![InlineKeyboardButton::callback(
+        format!("{}{name}", mark(profile_id == selection.profile_id)),
+        Selection {
+          profile_id,
+          ..selection
+        }
+        .data(),
+      )]);
+    }
+  }
+  Ok(InlineKeyboardMarkup::new(rows))
+}
+
+async fn stats_text(
+  bot: &Bot,
+  db: &Db,
+  user_id: UserId,
+  profile: &Profile,
+  selection: Selection,
+) -> Result<String> {
+  let Selection { period, view, .. } = selection;
+  let tz = preferences(db)
+    .fetch(profile.id)
+    .await?
+    .unwrap_or_default()
+    .timezone;
+  let now = Utc::now().with_timezone(&tz);
+  let start = period.start(now);
+  let end = now.to_utc();
+  let measurements = sugar_measurements(db, profile.id)
+    .fetch_between(start, end)
+    .await?;
+  let mut text = if profile.user_id == user_id {
+    format!("📊 Статистика за {}{}\n", period.title(), profile.tag())
+  } else {
+    let name = profile.display_name(bot).await?;
+    format!("📊 {name}: статистика за {}\n", period.title())
+  };
+  if let View::TimeOfDay(buckets) = view {
+    let stats = bucket_stats(&measurements, buckets, &tz);
+    text.push('\n');
+    text.push_str(&time_of_day::render(&stats, buckets));
+    return Ok(text);
+  }
+  let injections = insulin_injections(db, profile.id);
+  let injection_summary =
+    injections.summary_between(start, end).await?;
+  let injections = injections.fetch_between(start, end).await?;
+  let glucose_stats = GlucoseStats::calculate(&measurements);
+  write_glucose_stats(&mut text, glucose_stats);
+  if let (Period::Days(days), Some(stats)) = (period, glucose_stats) {
+    if (GMI_MIN_DAYS..=GMI_MAX_DAYS).contains(&days) {
+      let warnings = data_warnings(&measurements, days, &tz);
+      let lab = hba1c_results(db, profile.id).fetch_all().await?;
+      write_gmi(&mut text, &stats, &warnings, lab.first(), &tz);
+    }
+  }
+  write_insulin_stats(
+    &mut text,
+    InsulinStats::calculate(&injections, &tz),
+    injection_summary,
+  );
+  Ok(text)
+}
+
+fn write_glucose_stats(
+  text: &mut String,
+  stats: Option<GlucoseStats>,
+) {
+  let Some(stats) = stats else {
+    text.push_str("\nНет измерений сахара за период\n");
+    return;
+  };
+  let percent = |share: f64| share * 100.0;
+  let _ = write!(
+    text,
+    "\nИзмерений: {}\n\
+    Средний сахар: {:.1} ммоль/л\n\
+    Стандартное отклонение: {:.1} ммоль/л\n\
+    Коэффициент вариации: {:.1} %\n\
+    \n\
+    Ниже диапазона (<{LOW_LEVEL}): {:.0} %\n\
+    В диапазоне ({LOW_LEVEL}–{HIGH_LEVEL:.1}): {:.0} %\n\
+    Выше диапазона (>{HIGH_LEVEL:.1}): {:.0} %\n",
+    stats.count,
+    stats.mean,
+    stats.std_dev,
+    stats.coefficient_of_variation(),
+    percent(stats.below_range),
+    percent(stats.in_range),
+    percent(stats.above_range),
+  );
+}
+
+fn write_gmi(
+  text: &mut String,
+  stats: &GlucoseStats,
+  warnings: &[DataWarning],
+  lab: Option<&LabHba1c>,
+  tz: &Tz,
+) {
+  let gmi = glucose_management_indicator(stats.mean);
+  let _ = writeln!(text, "\nGMI (расчетный HbA1c): {gmi:.1} %");
+  for warning in warnings {
+    text.push_str(match warning {
+      DataWarning::Sparse => {
+        "⚠️ Мало измерений: нужно хотя бы 3 в день\n"
+      }
+      DataWarning::Clustered => {
+        "⚠️ Измерения сделаны в основном в одно время суток\n"
+      }
+    });
+  }
+  if let Some(lab) = lab {
+    let date = lab.date_time.with_timezone(tz).format("%d.%m.%Y");
+    let _ = writeln!(
+      text,
+      "HbA1c (лаб.) от {date}: {:.1} %, сравнение: /hba1c",
+      lab.percent
+    );
+  }
+}
+
+fn write_insulin_stats(
+  text: &mut String,
+  stats: Option<InsulinStats>,
+  summary: Summary,
+) {
+  let Some(stats) = stats else {
+    text.push_str("\nНет введенного инсулина за период\n");
+    return;
+  };
+  let _ = write!(
+    text,
+    "\nСуточная доза инсулина: {:.1} ЕД\n\
+    • базальный: {:.1} ЕД\n\
+    • болюсный: {:.1} ЕД\n",
+    stats.total(),
+    stats.basal,
+    stats.bolus,
+  );
+  if stats.unspecified > 0.0 {
+    let _ =
+      writeln!(text, "• не указан: {:.1} ЕД", stats.unspecified);
+  }
+  if let (Some(min), Some(max)) = (summary.min, summary.max) {
+    let _ = writeln!(
+      text,
+      "Инъекций: {}, доза от {} до {} ЕД",
+      summary.count,
+      min.as_cubic_centimeters(),
+      max.as_cubic_centimeters()
+    );
+  }
+}
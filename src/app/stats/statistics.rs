@@ -0,0 +1,376 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, TimeZone, Timelike, Utc};
+
+use crate::app::{
+  insulin_injection::{InsulinInjection, InsulinKind},
+  sugar_measurement::SugarMeasurement,
+};
+
+/// Glucose statistics over a period. Time in range is share of
+/// readings because finger-stick measurements are not continuous.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlucoseStats {
+  pub count: usize,
+  /// Mean level, mmol/L
+  pub mean: f64,
+  /// Sample standard deviation, mmol/L
+  pub std_dev: f64,
+  /// Share of readings below target range, 0..=1
+  pub below_range: f64,
+  /// Share of readings within target range, 0..=1
+  pub in_range: f64,
+  /// Share of readings above target range, 0..=1
+  pub above_range: f64,
+}
+
+impl GlucoseStats {
+  /// `None` if there are no measurements
+  #[allow(clippy::cast_precision_loss)]
+  pub fn calculate(
+    measurements: &[SugarMeasurement],
+  ) -> Option<Self> {
+    let count = measurements.len();
+    if count == 0 {
+      return None;
+    }
+    let n = count as f64;
+    let levels = measurements
+      .iter()
+      .map(|m| m.level.as_millimoles_per_liter());
+    let mean = levels.clone().sum::<f64>() / n;
+    let std_dev = if count > 1 {
+      let sum_sq: f64 = levels.map(|x| (x - mean).powi(2)).sum();
+      (sum_sq / (n - 1.0)).sqrt()
+    } else {
+      0.0
+    };
+    let share = |pred: fn(&&SugarMeasurement) -> bool| {
+      measurements.iter().filter(pred).count() as f64 / n
+    };
+    Some(Self {
+      count,
+      mean,
+      std_dev,
+      below_range: share(|m| m.level.is_low()),
+      in_range: share(|m| m.level.is_in_range()),
+      above_range: share(|m| m.level.is_high()),
+    })
+  }
+
+  /// Coefficient of variation, %
+  pub fn coefficient_of_variation(&self) -> f64 {
+    self.std_dev / self.mean * 100.0
+  }
+}
+
+/// Minimum days of readings GMI is estimated from
+pub const GMI_MIN_DAYS: u32 = 14;
+/// Maximum days of readings GMI is estimated from
+pub const GMI_MAX_DAYS: u32 = 90;
+/// Fewer readings per day make GMI unreliable
+const MIN_READINGS_PER_DAY: f64 = 3.0;
+/// Readings should cover at least this many of six 4-hour blocks
+const MIN_COVERED_BLOCKS: usize = 4;
+
+/// Glucose management indicator (estimated HbA1c), %, from mean
+/// level in mmol/L (Bergenstal et al., 2018)
+pub fn glucose_management_indicator(mean: f64) -> f64 {
+  3.31 + 0.430_56 * mean
+}
+
+/// Why readings may be insufficient for GMI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataWarning {
+  /// Too few readings per day
+  Sparse,
+  /// Readings are taken at few times of day only
+  Clustered,
+}
+
+/// Checks readings over `days` are enough to estimate GMI. Times of
+/// day are taken in `tz`.
+#[allow(clippy::cast_precision_loss)]
+pub fn data_warnings<Tz: TimeZone>(
+  measurements: &[SugarMeasurement],
+  days: u32,
+  tz: &Tz,
+) -> Vec<DataWarning> {
+  let mut warnings = Vec::new();
+  if (measurements.len() as f64)
+    < MIN_READINGS_PER_DAY * f64::from(days)
+  {
+    warnings.push(DataWarning::Sparse);
+  }
+  let blocks: HashSet<_> = measurements
+    .iter()
+    .map(|m| m.date_time.with_timezone(tz).hour() / 4)
+    .collect();
+  if blocks.len() < MIN_COVERED_BLOCKS {
+    warnings.push(DataWarning::Clustered);
+  }
+  warnings
+}
+
+/// Run of low readings until level is back in range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HypoEpisode {
+  pub start: DateTime<Utc>,
+  /// Time of recovery reading or of last low one if there is none
+  pub end: DateTime<Utc>,
+  /// Lowest level, mmol/L
+  pub min_level: f64,
+}
+
+/// Hypoglycemia episodes in chronological order
+pub fn hypo_episodes(
+  measurements: &[SugarMeasurement],
+) -> Vec<HypoEpisode> {
+  let mut measurements = measurements.to_vec();
+  measurements.sort_by_key(|m| m.date_time);
+  let mut episodes = Vec::new();
+  let mut current: Option<HypoEpisode> = None;
+  for m in measurements {
+    let level = m.level.as_millimoles_per_liter();
+    match (&mut current, m.level.is_low()) {
+      (Some(episode), true) => {
+        episode.end = m.date_time;
+        episode.min_level = episode.min_level.min(level);
+      }
+      (None, true) => {
+        current = Some(HypoEpisode {
+          start: m.date_time,
+          end: m.date_time,
+          min_level: level,
+        });
+      }
+      (Some(episode), false) => {
+        episode.end = m.date_time;
+        episodes.extend(current.take());
+      }
+      (None, false) => {}
+    }
+  }
+  episodes.extend(current);
+  episodes
+}
+
+/// Total daily insulin dose averaged over days with injections
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InsulinStats {
+  pub basal: f64,
+  pub bolus: f64,
+  /// Injections logged before kind was asked
+  pub unspecified: f64,
+}
+
+impl InsulinStats {
+  /// `None` if there are no injections. Days are counted in `tz`.
+  #[allow(clippy::cast_precision_loss)]
+  pub fn calculate<Tz: TimeZone>(
+    injections: &[InsulinInjection],
+    tz: &Tz,
+  ) -> Option<Self> {
+    let days: HashSet<_> = injections
+      .iter()
+      .map(|i| i.date_time.with_timezone(tz).date_naive())
+      .collect();
+    if days.is_empty() {
+      return None;
+    }
+    let days = days.len() as f64;
+    let total = |kind| {
+      injections
+        .iter()
+        .filter(|i| i.kind == kind)
+        .map(|i| i.volume.as_cubic_centimeters())
+        .sum::<f64>()
+        / days
+    };
+    Some(Self {
+      basal: total(Some(InsulinKind::Basal)),
+      bolus: total(Some(InsulinKind::Bolus)),
+      unspecified: total(None),
+    })
+  }
+
+  pub fn total(&self) -> f64 {
+    self.basal + self.bolus + self.unspecified
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::app::{
+    insulin_injection::Insulin, sugar_measurement::SugarLevel,
+  };
+
+  use super::*;
+
+  fn at(s: &str) -> DateTime<Utc> {
+    s.parse().unwrap()
+  }
+
+  fn measurements(levels: &[f64]) -> Vec<SugarMeasurement> {
+    levels
+      .iter()
+      .map(|&x| SugarMeasurement {
+        date_time: at("2024-03-01T12:00:00Z"),
+        level: SugarLevel::from_millimoles_per_liter(x),
+      })
+      .collect()
+  }
+
+  fn injection(
+    date_time: &str,
+    volume: f64,
+    kind: Option<InsulinKind>,
+  ) -> InsulinInjection {
+    InsulinInjection {
+      date_time: at(date_time),
+      volume: Insulin::from_cubic_centimeters(volume),
+      kind,
+    }
+  }
+
+  #[test]
+  fn glucose_stats_of_no_measurements() {
+    assert_eq!(GlucoseStats::calculate(&[]), None);
+  }
+
+  #[test]
+  fn glucose_mean_and_variability() {
+    let stats =
+      GlucoseStats::calculate(&measurements(&[4.0, 6.0, 8.0, 10.0]))
+        .unwrap();
+    assert_eq!(stats.count, 4);
+    assert!((stats.mean - 7.0).abs() < 1e-9);
+    // sqrt(20 / 3)
+    assert!((stats.std_dev - 2.581_988_897).abs() < 1e-6);
+    assert!(
+      (stats.coefficient_of_variation() - 36.885_555_67).abs() < 1e-6
+    );
+  }
+
+  #[test]
+  fn single_measurement_has_no_deviation() {
+    let stats =
+      GlucoseStats::calculate(&measurements(&[5.5])).unwrap();
+    assert!(stats.std_dev.abs() < 1e-9);
+  }
+
+  #[test]
+  fn glucose_time_in_range() {
+    let stats = GlucoseStats::calculate(&measurements(&[
+      3.0, 3.9, 5.0, 7.0, 10.0, 12.0, 15.0, 2.5,
+    ]))
+    .unwrap();
+    assert!((stats.below_range - 0.25).abs() < 1e-9);
+    assert!((stats.in_range - 0.5).abs() < 1e-9);
+    assert!((stats.above_range - 0.25).abs() < 1e-9);
+  }
+
+  #[test]
+  fn gmi_from_mean() {
+    // 154 mg/dL corresponds to 7.0 %
+    let gmi = glucose_management_indicator(154.0 / 18.018);
+    assert!((gmi - 6.99).abs() < 0.01);
+  }
+
+  #[test]
+  fn sufficient_data_has_no_warnings() {
+    let measurements: Vec<_> = (0..14 * 6)
+      .map(|i| SugarMeasurement {
+        date_time: at("2024-03-01T00:00:00Z")
+          + chrono::Duration::hours(i * 4),
+        level: SugarLevel::from_millimoles_per_liter(6.0),
+      })
+      .collect();
+    assert!(data_warnings(&measurements, 14, &Utc).is_empty());
+  }
+
+  #[test]
+  fn sparse_and_clustered_data() {
+    let measurements: Vec<_> = (0..14)
+      .map(|i| SugarMeasurement {
+        date_time: at("2024-03-01T08:00:00Z")
+          + chrono::Duration::days(i),
+        level: SugarLevel::from_millimoles_per_liter(6.0),
+      })
+      .collect();
+    assert_eq!(
+      data_warnings(&measurements, 14, &Utc),
+      [DataWarning::Sparse, DataWarning::Clustered]
+    );
+  }
+
+  #[test]
+  fn hypo_episodes_end_on_recovery() {
+    let measurements: Vec<_> = [
+      ("2024-03-01T08:00:00Z", 5.0),
+      ("2024-03-01T09:00:00Z", 3.5),
+      ("2024-03-01T09:15:00Z", 2.8),
+      ("2024-03-01T09:30:00Z", 4.5),
+      ("2024-03-01T12:00:00Z", 3.2),
+    ]
+    .iter()
+    .rev()
+    .map(|&(dt, level)| SugarMeasurement {
+      date_time: at(dt),
+      level: SugarLevel::from_millimoles_per_liter(level),
+    })
+    .collect();
+    assert_eq!(
+      hypo_episodes(&measurements),
+      [
+        HypoEpisode {
+          start: at("2024-03-01T09:00:00Z"),
+          end: at("2024-03-01T09:30:00Z"),
+          min_level: 2.8,
+        },
+        HypoEpisode {
+          start: at("2024-03-01T12:00:00Z"),
+          end: at("2024-03-01T12:00:00Z"),
+          min_level: 3.2,
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn insulin_stats_of_no_injections() {
+    assert_eq!(InsulinStats::calculate(&[], &Utc), None);
+  }
+
+  #[test]
+  fn insulin_daily_dose_split() {
+    let injections = [
+      injection(
+        "2024-03-01T08:00:00Z",
+        10.0,
+        Some(InsulinKind::Basal),
+      ),
+      injection(
+        "2024-03-01T09:00:00Z",
+        6.0,
+        Some(InsulinKind::Bolus),
+      ),
+      injection(
+        "2024-03-01T19:00:00Z",
+        8.0,
+        Some(InsulinKind::Bolus),
+      ),
+      injection(
+        "2024-03-02T08:00:00Z",
+        12.0,
+        Some(InsulinKind::Basal),
+      ),
+      injection("2024-03-02T13:00:00Z", 4.0, None),
+    ];
+    let stats = InsulinStats::calculate(&injections, &Utc).unwrap();
+    assert!((stats.basal - 11.0).abs() < 1e-9);
+    assert!((stats.bolus - 7.0).abs() < 1e-9);
+    assert!((stats.unspecified - 2.0).abs() < 1e-9);
+    assert!((stats.total() - 20.0).abs() < 1e-9);
+  }
+}
@@ -0,0 +1,226 @@
+use std::{collections::BTreeMap, fmt::Write, ops::Range};
+
+use chrono::{NaiveDate, TimeZone, Timelike};
+
+use crate::app::sugar_measurement::SugarMeasurement;
+
+use super::statistics::{hypo_episodes, GlucoseStats};
+
+/// How readings are grouped by local time of day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Buckets {
+  /// Night, morning, afternoon and evening
+  PartsOfDay,
+  Hourly,
+}
+
+impl Buckets {
+  /// Hours of day each bucket spans
+  fn hours(self) -> u32 {
+    match self {
+      Self::PartsOfDay => 6,
+      Self::Hourly => 1,
+    }
+  }
+
+  pub fn count(self) -> usize {
+    (24 / self.hours()) as usize
+  }
+
+  /// Bucket local `hour` falls into
+  pub fn index(self, hour: u32) -> usize {
+    (hour / self.hours()) as usize
+  }
+
+  /// Local hours of bucket
+  pub fn range(self, index: usize) -> Range<u32> {
+    let start = u32::try_from(index).unwrap_or(0) * self.hours();
+    start..start + self.hours()
+  }
+
+  pub fn label(self, index: usize) -> String {
+    let Range { start, end } = self.range(index);
+    match self {
+      Self::PartsOfDay => {
+        let name = ["Ночь", "Утро", "День", "Вечер"][index % 4];
+        format!("{name} ({start:02}–{end:02})")
+      }
+      Self::Hourly => format!("{start:02}:00"),
+    }
+  }
+
+  pub fn parse(s: &str) -> Option<Self> {
+    match s {
+      "parts" => Some(Self::PartsOfDay),
+      "hourly" => Some(Self::Hourly),
+      _ => None,
+    }
+  }
+
+  pub fn data(self) -> &'static str {
+    match self {
+      Self::PartsOfDay => "parts",
+      Self::Hourly => "hourly",
+    }
+  }
+}
+
+/// Statistics of readings within bucket
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketStats {
+  /// `None` if there are no readings
+  pub glucose: Option<GlucoseStats>,
+  /// Hypoglycemia episodes started within bucket
+  pub hypos: usize,
+}
+
+/// Statistics of every bucket. Times of day are taken in `tz`.
+pub fn bucket_stats<Tz: TimeZone>(
+  measurements: &[SugarMeasurement],
+  buckets: Buckets,
+  tz: &Tz,
+) -> Vec<BucketStats> {
+  let bucket = |dt: &chrono::DateTime<chrono::Utc>| {
+    buckets.index(dt.with_timezone(tz).hour())
+  };
+  let mut grouped = vec![Vec::new(); buckets.count()];
+  for m in measurements {
+    grouped[bucket(&m.date_time)].push(*m);
+  }
+  let mut hypos = vec![0; buckets.count()];
+  for episode in hypo_episodes(measurements) {
+    hypos[bucket(&episode.start)] += 1;
+  }
+  grouped
+    .iter()
+    .zip(hypos)
+    .map(|(measurements, hypos)| BucketStats {
+      glucose: GlucoseStats::calculate(measurements),
+      hypos,
+    })
+    .collect()
+}
+
+/// Mean level in mmol/L of every bucket by local date, `None` for
+/// buckets without readings
+#[allow(clippy::cast_precision_loss)]
+pub fn daily_means<Tz: TimeZone>(
+  measurements: &[SugarMeasurement],
+  buckets: Buckets,
+  tz: &Tz,
+) -> BTreeMap<NaiveDate, Vec<Option<f64>>> {
+  let mut sums = BTreeMap::<_, Vec<(f64, usize)>>::new();
+  for m in measurements {
+    let local = m.date_time.with_timezone(tz);
+    let day = sums
+      .entry(local.date_naive())
+      .or_insert_with(|| vec![(0.0, 0); buckets.count()]);
+    let (sum, count) = &mut day[buckets.index(local.hour())];
+    *sum += m.level.as_millimoles_per_liter();
+    *count += 1;
+  }
+  sums
+    .into_iter()
+    .map(|(date, day)| {
+      let means = day
+        .into_iter()
+        .map(|(sum, count)| (count > 0).then(|| sum / count as f64))
+        .collect();
+      (date, means)
+    })
+    .collect()
+}
+
+/// Line per bucket with mean, time in range and hypos
+pub fn render(stats: &[BucketStats], buckets: Buckets) -> String {
+  let mut text = String::new();
+  for (index, bucket) in stats.iter().enumerate() {
+    let label = buckets.label(index);
+    match bucket.glucose {
+      Some(glucose) => {
+        let _ = writeln!(
+          text,
+          "{label}: {:.1} ммоль/л, в диапазоне {:.0} %, гипо: {}",
+          glucose.mean,
+          glucose.in_range * 100.0,
+          bucket.hypos
+        );
+      }
+      None => {
+        let _ = writeln!(text, "{label}: нет измерений");
+      }
+    }
+  }
+  text
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{DateTime, Utc};
+
+  use crate::app::sugar_measurement::SugarLevel;
+
+  use super::*;
+
+  fn reading(date_time: &str, level: f64) -> SugarMeasurement {
+    SugarMeasurement {
+      date_time: date_time.parse::<DateTime<Utc>>().unwrap(),
+      level: SugarLevel::from_millimoles_per_liter(level),
+    }
+  }
+
+  fn readings() -> Vec<SugarMeasurement> {
+    vec![
+      reading("2024-03-01T03:00:00Z", 3.2),
+      reading("2024-03-01T03:30:00Z", 5.0),
+      reading("2024-03-01T08:00:00Z", 11.0),
+      reading("2024-03-01T09:00:00Z", 7.0),
+      reading("2024-03-02T08:30:00Z", 6.0),
+      reading("2024-03-02T19:00:00Z", 9.0),
+    ]
+  }
+
+  #[test]
+  fn groups_by_parts_of_day() {
+    let stats = bucket_stats(&readings(), Buckets::PartsOfDay, &Utc);
+    assert_eq!(stats.len(), 4);
+    let night = stats[0].glucose.unwrap();
+    assert_eq!((night.count, stats[0].hypos), (2, 1));
+    let morning = stats[1].glucose.unwrap();
+    assert_eq!(morning.count, 3);
+    assert!((morning.mean - 8.0).abs() < 1e-9);
+    assert!((morning.in_range - 2.0 / 3.0).abs() < 1e-9);
+    assert_eq!(stats[2].glucose, None);
+    assert_eq!(
+      render(&stats, Buckets::PartsOfDay),
+      "Ночь (00–06): 4.1 ммоль/л, в диапазоне 50 %, гипо: 1\n\
+      Утро (06–12): 8.0 ммоль/л, в диапазоне 67 %, гипо: 0\n\
+      День (12–18): нет измерений\n\
+      Вечер (18–24): 9.0 ммоль/л, в диапазоне 100 %, гипо: 0\n"
+    );
+  }
+
+  #[test]
+  fn groups_hourly_by_day() {
+    let means = daily_means(&readings(), Buckets::Hourly, &Utc);
+    let first = &means[&"2024-03-01".parse().unwrap()];
+    assert_eq!(first.len(), 24);
+    assert!(first[3].is_some_and(|mean| (mean - 4.1).abs() < 1e-9));
+    assert_eq!(first[8], Some(11.0));
+    assert_eq!(first[4], None);
+    assert_eq!(means.len(), 2);
+    assert_eq!(Buckets::Hourly.label(9), "09:00");
+  }
+
+  #[test]
+  fn groups_in_given_timezone() {
+    let tz = chrono_tz::Asia::Novosibirsk;
+    let stats = bucket_stats(&readings(), Buckets::PartsOfDay, &tz);
+    // 03:00 UTC is morning in UTC+7, 19:00 UTC is night
+    let counts = stats
+      .iter()
+      .map(|bucket| bucket.glucose.map_or(0, |g| g.count))
+      .collect::<Vec<_>>();
+    assert_eq!(counts, [1, 2, 3, 0]);
+  }
+}
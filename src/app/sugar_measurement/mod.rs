@@ -1,8 +1,8 @@
 pub mod repository;
 
-use std::sync::Arc;
+use std::{ops::RangeInclusive, sync::Arc};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use teloxide::{
   dispatching::dialogue::InMemStorage, dptree::case, prelude::*,
 };
@@ -12,15 +12,49 @@ use crate::{
   bot_commands::MenuCommand,
   common::{any, Result},
   db::Db,
-  utils::filter_message,
+  utils::{
+    event_publisher::EventPublisher, filter_callback_query,
+    filter_message,
+  },
 };
 
 use self::repository::sugar_measurements;
 
-use super::UpdateHandler;
+use super::{
+  plausibility::{parse_number, Confirmation, Plausibility},
+  profile::{
+    current_profile, repository::profiles, Profile, ProfileId,
+  },
+  UpdateHandler,
+};
 
 type Dialog = Dialogue<State, InMemStorage<State>>;
 
+/// Lower bound of target range, mmol/L
+pub const LOW_LEVEL: f64 = 3.9;
+/// Upper bound of target range, mmol/L
+pub const HIGH_LEVEL: f64 = 10.0;
+/// Glucose mg/dL in 1 mmol/L
+const MILLIGRAMS_PER_DECILITER_IN_MILLIMOLE: f64 = 18.0182;
+/// Meters can't show levels outside, mmol/L
+const POSSIBLE_LEVELS: RangeInclusive<f64> = 0.6..=35.0;
+/// Levels outside are rare and confirmed, mmol/L
+const USUAL_LEVELS: RangeInclusive<f64> = 2.0..=25.0;
+/// Values within are likely entered in mg/dL
+const MILLIGRAMS_PER_DECILITER_LEVELS: RangeInclusive<f64> =
+  36.0..=600.0;
+/// Readings closer in time are compared, minutes
+const JUMP_MINUTES: i64 = 15;
+/// Larger change between close readings is likely an error, mmol/L
+const MAX_JUMP: f64 = 5.0;
+const CONFIRM_CALLBACK_PREFIX: &str = "sugar_confirm:";
+
+#[derive(Debug, Clone)]
+pub struct SugarMeasurementAdded {
+  pub profile: Profile,
+  pub measurement: SugarMeasurement,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct SugarMeasurement {
   pub date_time: DateTime<Utc>,
@@ -48,9 +82,34 @@ impl SugarLevel {
     }
   }
 
+  pub fn from_milligrams_per_deciliter(
+    milligrams_per_deciliter: f64,
+  ) -> Self {
+    Self::from_millimoles_per_liter(
+      milligrams_per_deciliter
+        / MILLIGRAMS_PER_DECILITER_IN_MILLIMOLE,
+    )
+  }
+
   pub fn as_millimoles_per_liter(self) -> f64 {
     self.millimoles_per_liter
   }
+
+  pub fn as_milligrams_per_deciliter(self) -> f64 {
+    self.millimoles_per_liter * MILLIGRAMS_PER_DECILITER_IN_MILLIMOLE
+  }
+
+  pub fn is_low(self) -> bool {
+    self.millimoles_per_liter < LOW_LEVEL
+  }
+
+  pub fn is_high(self) -> bool {
+    self.millimoles_per_liter > HIGH_LEVEL
+  }
+
+  pub fn is_in_range(self) -> bool {
+    !self.is_low() && !self.is_high()
+  }
 }
 
 #[derive(Default, Clone)]
@@ -58,6 +117,8 @@ enum State {
   #[default]
   Ignoring,
   Accepting,
+  /// Doubtful measurement waits for confirmation
+  Confirming(ProfileId, SugarMeasurement),
 }
 
 pub struct Plugin;
@@ -68,14 +129,27 @@ impl app::Plugin for Plugin {
   }
 
   fn update_handler(&self) -> UpdateHandler {
-    filter_message()
-      .enter_dialogue::<Message, InMemStorage<State>, State>()
+    dptree::entry()
       .branch(
-        dptree::entry()
-          .filter_command::<MenuCommand>()
-          .branch(case![MenuCommand::SugarLevel].endpoint(ask)),
+        filter_message()
+          .enter_dialogue::<Message, InMemStorage<State>, State>()
+          .branch(
+            dptree::entry()
+              .filter_command::<MenuCommand>()
+              .branch(case![MenuCommand::SugarLevel].endpoint(ask)),
+          )
+          .branch(case![State::Accepting].endpoint(accept)),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            Confirmation::parse(
+              CONFIRM_CALLBACK_PREFIX,
+              query.data.as_deref()?,
+            )
+          })
+          .endpoint(confirm),
       )
-      .branch(case![State::Accepting].endpoint(accept))
   }
 }
 
@@ -96,22 +170,231 @@ async fn accept(
   msg: Message,
   user_id: UserId,
   db: Arc<Db>,
+  ep: Arc<EventPublisher>,
   dialogue: Dialog,
 ) -> Result<()> {
-  if let Some(sugar_level) = parse(msg.text()) {
-    sugar_measurements(&db, user_id)
-      .add(SugarMeasurement::from_now(sugar_level))
+  let Some(sugar_level) = parse(msg.text()) else {
+    bot
+      .send_message(
+        msg.chat.id,
+        "Неправильный формат. Отправьте число, например 5.6",
+      )
       .await?;
-    bot.send_message(msg.chat.id, "✅").await?;
-  } else {
+    dialogue.reset().await.map_err(any)?;
+    return Ok(());
+  };
+  let profile = current_profile(&db, user_id).await?;
+  let measurement = SugarMeasurement::from_now(sugar_level);
+  let previous = sugar_measurements(&db, profile.id)
+    .fetch_latest(1)
+    .await?
+    .pop();
+  match check(measurement, previous) {
+    Plausibility::Plausible => {
+      save(&bot, &db, &ep, msg.chat.id, profile, measurement).await?;
+      dialogue.reset().await.map_err(any)?;
+    }
+    Plausibility::Doubtful(reason) => {
+      bot
+        .send_message(msg.chat.id, format!("⚠️ {reason} Вы уверены?"))
+        .reply_markup(Confirmation::keyboard(CONFIRM_CALLBACK_PREFIX))
+        .await?;
+      dialogue
+        .update(State::Confirming(profile.id, measurement))
+        .await
+        .map_err(any)?;
+    }
+    Plausibility::Impossible(reason) => {
+      bot
+        .send_message(
+          msg.chat.id,
+          format!("{reason} Отправьте уровень сахара еще раз"),
+        )
+        .await?;
+    }
+  }
+  Ok(())
+}
+
+async fn confirm(
+  bot: Bot,
+  user_id: UserId,
+  db: Arc<Db>,
+  ep: Arc<EventPublisher>,
+  confirmation: Confirmation,
+  query: CallbackQuery,
+  storage: Arc<InMemStorage<State>>,
+) -> Result<()> {
+  let Some(msg) = query.message else {
+    bot.answer_callback_query(query.id).await?;
+    return Ok(());
+  };
+  let dialogue = Dialog::new(storage, msg.chat.id);
+  let Some(State::Confirming(profile_id, measurement)) =
+    dialogue.get().await.map_err(any)?
+  else {
     bot
-      .send_message(msg.chat.id, "Неправильный формат (todo)")
+      .answer_callback_query(query.id)
+      .text("Уже неактуально")
       .await?;
+    return Ok(());
+  };
+  bot.answer_callback_query(query.id).await?;
+  bot.edit_message_reply_markup(msg.chat.id, msg.id).await?;
+  match confirmation {
+    Confirmation::Save => {
+      dialogue.reset().await.map_err(any)?;
+      let profile = profiles(&db).fetch(profile_id).await?;
+      let Some(profile) =
+        profile.filter(|profile| profile.user_id == user_id)
+      else {
+        return Ok(());
+      };
+      save(&bot, &db, &ep, msg.chat.id, profile, measurement).await?;
+    }
+    Confirmation::Retry => {
+      bot
+        .send_message(msg.chat.id, "Отправьте уровень сахара еще раз")
+        .await?;
+      dialogue.update(State::Accepting).await.map_err(any)?;
+    }
   }
-  dialogue.reset().await.map_err(any)?;
+  Ok(())
+}
+
+async fn save(
+  bot: &Bot,
+  db: &Db,
+  ep: &EventPublisher,
+  chat_id: ChatId,
+  profile: Profile,
+  measurement: SugarMeasurement,
+) -> Result<()> {
+  sugar_measurements(db, profile.id).add(measurement).await?;
+  bot
+    .send_message(chat_id, format!("✅{}", profile.tag()))
+    .await?;
+  ep.send(SugarMeasurementAdded {
+    profile,
+    measurement,
+  });
   Ok(())
 }
 
 fn parse(s: Option<&str>) -> Option<SugarLevel> {
-  Some(SugarLevel::from_millimoles_per_liter(s?.parse().ok()?))
+  Some(SugarLevel::from_millimoles_per_liter(parse_number(s?)?))
+}
+
+/// Checks measurement is physiologically possible and consistent
+/// with `previous` one
+fn check(
+  measurement: SugarMeasurement,
+  previous: Option<SugarMeasurement>,
+) -> Plausibility {
+  let level = measurement.level.as_millimoles_per_liter();
+  if !POSSIBLE_LEVELS.contains(&level) {
+    let mut reason = format!(
+      "Уровень сахара должен быть от {:.1} до {:.0} ммоль/л, \
+      похоже на опечатку.",
+      POSSIBLE_LEVELS.start(),
+      POSSIBLE_LEVELS.end()
+    );
+    if MILLIGRAMS_PER_DECILITER_LEVELS.contains(&level) {
+      let converted =
+        SugarLevel::from_milligrams_per_deciliter(level);
+      reason.push_str(&format!(
+        " Если это мг/дл, то это {:.1} ммоль/л.",
+        converted.as_millimoles_per_liter()
+      ));
+    }
+    return Plausibility::Impossible(reason);
+  }
+  if !USUAL_LEVELS.contains(&level) {
+    let kind = if measurement.level.is_low() {
+      "низкий"
+    } else {
+      "высокий"
+    };
+    return Plausibility::Doubtful(format!(
+      "{level:.1} ммоль/л — очень {kind} уровень."
+    ));
+  }
+  let recent = previous.filter(|previous| {
+    let elapsed = measurement.date_time - previous.date_time;
+    elapsed <= Duration::minutes(JUMP_MINUTES)
+  });
+  if let Some(previous) = recent {
+    let previous_level = previous.level.as_millimoles_per_liter();
+    if (level - previous_level).abs() > MAX_JUMP {
+      let minutes =
+        (measurement.date_time - previous.date_time).num_minutes();
+      return Plausibility::Doubtful(format!(
+        "{minutes} мин. назад было {previous_level:.1} ммоль/л, \
+        такое резкое изменение бывает редко."
+      ));
+    }
+  }
+  Plausibility::Plausible
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn measurement(minutes: i64, level: f64) -> SugarMeasurement {
+    let start: DateTime<Utc> =
+      "2024-03-01T12:00:00Z".parse().unwrap();
+    SugarMeasurement {
+      date_time: start + Duration::minutes(minutes),
+      level: SugarLevel::from_millimoles_per_liter(level),
+    }
+  }
+
+  fn check_level(level: f64) -> Plausibility {
+    check(measurement(0, level), None)
+  }
+
+  #[test]
+  fn rejects_impossible_levels() {
+    assert_eq!(parse(Some("NaN")), None);
+    assert!(matches!(check_level(0.3), Plausibility::Impossible(_)));
+    assert!(matches!(check_level(-5.0), Plausibility::Impossible(_)));
+    let Plausibility::Impossible(reason) = check_level(57.0) else {
+      panic!("57 mmol/L is accepted");
+    };
+    assert!(reason.ends_with("Если это мг/дл, то это 3.2 ммоль/л."));
+  }
+
+  #[test]
+  fn asks_to_confirm_unusual_levels() {
+    assert_eq!(check_level(5.6), Plausibility::Plausible);
+    assert_eq!(
+      check_level(1.5),
+      Plausibility::Doubtful(
+        "1.5 ммоль/л — очень низкий уровень.".into()
+      )
+    );
+    assert!(matches!(check_level(30.0), Plausibility::Doubtful(_)));
+  }
+
+  #[test]
+  fn flags_jumps_between_close_readings() {
+    let previous = Some(measurement(0, 5.0));
+    assert_eq!(
+      check(measurement(5, 12.0), previous),
+      Plausibility::Doubtful(
+        "5 мин. назад было 5.0 ммоль/л, такое резкое изменение \
+        бывает редко."
+          .into()
+      )
+    );
+    assert_eq!(
+      check(measurement(5, 9.0), previous),
+      Plausibility::Plausible
+    );
+    assert_eq!(
+      check(measurement(60, 12.0), previous),
+      Plausibility::Plausible
+    );
+  }
 }
@@ -1,31 +1,53 @@
-use teloxide::types::UserId;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
 
-use crate::db::{txn::ExecutorHolder, Db};
+use crate::{
+  app::profile::ProfileId,
+  db::{txn::ExecutorHolder, Db},
+};
 
 use super::{SugarLevel, SugarMeasurement};
 
-pub fn sugar_measurements(db: &Db, user_id: UserId) -> Repository {
+pub fn sugar_measurements(
+  db: &Db,
+  profile_id: ProfileId,
+) -> Repository {
   let exec = db.exec();
-  Repository { user_id, exec }
+  Repository { profile_id, exec }
 }
 
 pub struct Repository {
-  user_id: UserId,
+  profile_id: ProfileId,
   exec: ExecutorHolder,
 }
 
+/// Aggregates of measurements calculated by database
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+  pub count: u32,
+  pub mean: Option<SugarLevel>,
+  pub min: Option<SugarLevel>,
+  pub max: Option<SugarLevel>,
+}
+
 impl Repository {
-  pub async fn fetch_all(
+  /// Measurements in `[start, end)` in chronological order
+  pub async fn fetch_between(
     &self,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
   ) -> sqlx::Result<Vec<SugarMeasurement>> {
-    let user_id = self.user_id();
+    let profile_id = self.profile_id.0;
     sqlx::query!(
       r#"
         SELECT date_time, millimoles_per_liter
         FROM sugar_measurements
-        WHERE user_id = ?
+        WHERE profile_id = ? AND date_time >= ? AND date_time < ?
+        ORDER BY date_time
       "#,
-      user_id
+      profile_id,
+      start,
+      end
     )
     .map(|rec| SugarMeasurement {
       date_time: rec.date_time.and_utc(),
@@ -37,28 +59,120 @@ impl Repository {
     .await
   }
 
-  fn user_id(&self) -> i64 {
-    self.user_id.0.try_into().unwrap()
+  /// Up to `limit` latest measurements, newest first
+  pub async fn fetch_latest(
+    &self,
+    limit: u32,
+  ) -> sqlx::Result<Vec<SugarMeasurement>> {
+    let profile_id = self.profile_id.0;
+    sqlx::query!(
+      r#"
+        SELECT date_time, millimoles_per_liter
+        FROM sugar_measurements
+        WHERE profile_id = ?
+        ORDER BY date_time DESC
+        LIMIT ?
+      "#,
+      profile_id,
+      limit
+    )
+    .map(|rec| SugarMeasurement {
+      date_time: rec.date_time.and_utc(),
+      level: SugarLevel::from_millimoles_per_liter(
+        rec.millimoles_per_liter,
+      ),
+    })
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Passes measurements in `[start, end)` to `f` in chronological
+  /// order as they are read, without collecting them
+  pub async fn for_each_between(
+    &self,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    mut f: impl FnMut(SugarMeasurement),
+  ) -> sqlx::Result<()> {
+    let profile_id = self.profile_id.0;
+    let mut exec = self.exec.borrow();
+    let mut rows = sqlx::query!(
+      r#"
+        SELECT date_time, millimoles_per_liter
+        FROM sugar_measurements
+        WHERE profile_id = ? AND date_time >= ? AND date_time < ?
+        ORDER BY date_time
+      "#,
+      profile_id,
+      start,
+      end
+    )
+    .map(|rec| SugarMeasurement {
+      date_time: rec.date_time.and_utc(),
+      level: SugarLevel::from_millimoles_per_liter(
+        rec.millimoles_per_liter,
+      ),
+    })
+    .fetch(&mut exec);
+    while let Some(measurement) = rows.try_next().await? {
+      f(measurement);
+    }
+    Ok(())
+  }
+
+  /// Count, mean, min and max of measurements in `[start, end)`
+  #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+  pub async fn summary_between(
+    &self,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+  ) -> sqlx::Result<Summary> {
+    let profile_id = self.profile_id.0;
+    let rec = sqlx::query!(
+      r#"
+        SELECT
+          COUNT(*) AS "count!: i64",
+          AVG(millimoles_per_liter) AS "mean: f64",
+          MIN(millimoles_per_liter) AS "min: f64",
+          MAX(millimoles_per_liter) AS "max: f64"
+        FROM sugar_measurements
+        WHERE profile_id = ? AND date_time >= ? AND date_time < ?
+      "#,
+      profile_id,
+      start,
+      end
+    )
+    .fetch_one(&mut self.exec.borrow())
+    .await?;
+    let level = |value: Option<f64>| {
+      value.map(SugarLevel::from_millimoles_per_liter)
+    };
+    Ok(Summary {
+      count: rec.count as u32,
+      mean: level(rec.mean),
+      min: level(rec.min),
+      max: level(rec.max),
+    })
   }
 
   pub async fn add(
     &mut self,
     sugar_measurement: SugarMeasurement,
   ) -> sqlx::Result<()> {
-    let user_id = self.user_id();
+    let profile_id = self.profile_id.0;
     let date_time = sugar_measurement.date_time;
     let millimoles_per_liter =
       sugar_measurement.level.as_millimoles_per_liter();
     sqlx::query!(
       r#"
         INSERT INTO sugar_measurements (
-          user_id,
+          profile_id,
           date_time,
           millimoles_per_liter
         )
         VALUES (?, ?, ?)
       "#,
-      user_id,
+      profile_id,
       date_time,
       millimoles_per_liter
     )
@@ -66,31 +180,286 @@ impl Repository {
     .await?;
     Ok(())
   }
+
+  /// Adds measurement unless there is one at the same time. Returns
+  /// `true` if added.
+  pub async fn add_if_absent(
+    &mut self,
+    sugar_measurement: SugarMeasurement,
+  ) -> sqlx::Result<bool> {
+    let profile_id = self.profile_id.0;
+    let date_time = sugar_measurement.date_time;
+    let millimoles_per_liter =
+      sugar_measurement.level.as_millimoles_per_liter();
+    let res = sqlx::query!(
+      r#"
+        INSERT OR IGNORE INTO sugar_measurements (
+          profile_id,
+          date_time,
+          millimoles_per_liter
+        )
+        VALUES (?, ?, ?)
+      "#,
+      profile_id,
+      date_time,
+      millimoles_per_liter
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(res.rows_affected() > 0)
+  }
 }
 
 #[cfg(test)]
 mod tests {
+  use teloxide::types::UserId;
+
   use crate::{
-    app::user::repository::users,
+    app::{profile::repository::profiles, user::repository::users},
     db::{tests::test_db, txn},
   };
 
   use super::*;
 
   #[tokio::test]
-  async fn add_and_fetch_all() {
+  async fn add_and_fetch_between() {
     let test_db = test_db().await.unwrap();
     txn::begin(test_db.pool(), async {
       let user = UserId(1);
       let sugar_level = SugarLevel::from_millimoles_per_liter(5.7);
       let rec = SugarMeasurement::from_now(sugar_level);
       users(&test_db).add(user).await.unwrap();
-      let mut measurements = sugar_measurements(&test_db, user);
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let mut measurements = sugar_measurements(&test_db, profile.id);
       measurements.add(rec).await.unwrap();
-      let recs = measurements.fetch_all().await.unwrap();
+      let minute = chrono::Duration::minutes(1);
+      let recs = measurements
+        .fetch_between(rec.date_time, rec.date_time + minute)
+        .await
+        .unwrap();
+      assert_eq!(recs, vec![rec]);
+    })
+    .await
+    .unwrap();
+  }
+
+  #[tokio::test]
+  async fn add_if_absent_skips_same_time() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      let rec = SugarMeasurement::from_now(
+        SugarLevel::from_millimoles_per_liter(5.7),
+      );
+      let duplicate = SugarMeasurement {
+        level: SugarLevel::from_millimoles_per_liter(9.0),
+        ..rec
+      };
+      users(&test_db).add(user).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let mut measurements = sugar_measurements(&test_db, profile.id);
+      assert!(measurements.add_if_absent(rec).await.unwrap());
+      assert!(!measurements.add_if_absent(duplicate).await.unwrap());
+      let minute = chrono::Duration::minutes(1);
+      let recs = measurements
+        .fetch_between(rec.date_time, rec.date_time + minute)
+        .await
+        .unwrap();
       assert_eq!(recs, vec![rec]);
     })
     .await
     .unwrap();
   }
+
+  #[tokio::test]
+  async fn fetch_between_is_half_open_and_ordered() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      users(&test_db).add(user).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let mut measurements = sugar_measurements(&test_db, profile.id);
+      let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+      let rec = |s| SugarMeasurement {
+        date_time: at(s),
+        level: SugarLevel::from_millimoles_per_liter(5.0),
+      };
+      for time in [
+        "2024-03-02T00:00:00Z",
+        "2024-03-01T12:30:00.5Z",
+        "2024-03-01T00:00:00Z",
+        "2024-02-29T23:59:59Z",
+      ] {
+        measurements.add(rec(time)).await.unwrap();
+      }
+      let recs = measurements
+        .fetch_between(
+          at("2024-03-01T00:00:00Z"),
+          at("2024-03-02T00:00:00Z"),
+        )
+        .await
+        .unwrap();
+      assert_eq!(
+        recs,
+        vec![
+          rec("2024-03-01T00:00:00Z"),
+          rec("2024-03-01T12:30:00.5Z")
+        ]
+      );
+    })
+    .await
+    .unwrap();
+  }
+
+  #[tokio::test]
+  async fn latest_stream_and_summary() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      users(&test_db).add(user).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let mut measurements = sugar_measurements(&test_db, profile.id);
+      let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+      let rec = |s, level| SugarMeasurement {
+        date_time: at(s),
+        level: SugarLevel::from_millimoles_per_liter(level),
+      };
+      let recs = [
+        rec("2024-03-01T06:00:00Z", 4.0),
+        rec("2024-03-01T12:00:00Z", 8.0),
+        rec("2024-03-01T18:00:00Z", 9.0),
+        rec("2024-03-02T06:00:00Z", 5.0),
+      ];
+      for rec in recs {
+        measurements.add(rec).await.unwrap();
+      }
+      assert_eq!(
+        measurements.fetch_latest(2).await.unwrap(),
+        vec![recs[3], recs[2]]
+      );
+      let (start, end) =
+        (at("2024-03-01T00:00:00Z"), at("2024-03-02T00:00:00Z"));
+      let mut streamed = Vec::new();
+      measurements
+        .for_each_between(start, end, |m| streamed.push(m))
+        .await
+        .unwrap();
+      assert_eq!(streamed, recs[..3]);
+      let level =
+        |value| Some(SugarLevel::from_millimoles_per_liter(value));
+      assert_eq!(
+        measurements.summary_between(start, end).await.unwrap(),
+        Summary {
+          count: 3,
+          mean: level(7.0),
+          min: level(4.0),
+          max: level(9.0),
+        }
+      );
+      let empty = measurements
+        .summary_between(
+          end + chrono::Days::new(1),
+          end + chrono::Days::new(2),
+        )
+        .await
+        .unwrap();
+      assert_eq!(
+        empty,
+        Summary {
+          count: 0,
+          mean: None,
+          min: None,
+          max: None,
+        }
+      );
+    })
+    .await
+    .unwrap();
+  }
+
+  /// Compares loading whole history with range queries on a year of
+  /// CGM readings. Run with
+  /// `cargo test bench_range_queries -- --ignored --nocapture`.
+  #[tokio::test]
+  #[ignore = "benchmark"]
+  async fn bench_range_queries() {
+    use std::time::{Duration, Instant};
+
+    const READINGS: u32 = 365 * 24 * 12;
+
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      users(&test_db).add(user).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let mut measurements = sugar_measurements(&test_db, profile.id);
+      let end: DateTime<Utc> =
+        "2024-03-01T00:00:00Z".parse().unwrap();
+      for i in 0..READINGS {
+        let level = 4.0 + f64::from(i % 100) / 10.0;
+        measurements
+          .add(SugarMeasurement {
+            date_time: end
+              - chrono::Duration::minutes(5 * i64::from(i + 1)),
+            level: SugarLevel::from_millimoles_per_liter(level),
+          })
+          .await
+          .unwrap();
+      }
+      let day_start = end - chrono::Days::new(1);
+      let quarter_start = end - chrono::Days::new(90);
+      // Whole history, as loaded before range queries
+      let history_start = end - chrono::Days::new(366);
+
+      let timer = Instant::now();
+      let day: Vec<_> = measurements
+        .fetch_between(history_start, end)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|m| m.date_time >= day_start && m.date_time < end)
+        .collect();
+      let history_day = timer.elapsed();
+      let timer = Instant::now();
+      let between =
+        measurements.fetch_between(day_start, end).await.unwrap();
+      let fetch_between_day = timer.elapsed();
+      assert_eq!(day.len(), between.len());
+
+      let timer = Instant::now();
+      let quarter: Vec<_> = measurements
+        .fetch_between(history_start, end)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|m| m.date_time >= quarter_start)
+        .map(|m| m.level.as_millimoles_per_liter())
+        .collect();
+      #[allow(clippy::cast_precision_loss)]
+      let mean = quarter.iter().sum::<f64>() / quarter.len() as f64;
+      let history_mean = timer.elapsed();
+      let timer = Instant::now();
+      let summary = measurements
+        .summary_between(quarter_start, end)
+        .await
+        .unwrap();
+      let summary_mean = timer.elapsed();
+      let summary_level =
+        summary.mean.unwrap().as_millimoles_per_liter();
+      assert!((summary_level - mean).abs() < 1e-9);
+
+      let report = |name: &str, time: Duration| {
+        println!("{name:>32}: {time:>10.2?}");
+      };
+      println!("{READINGS} readings");
+      report("whole history + filter, 1 day", history_day);
+      report("fetch_between, 1 day", fetch_between_day);
+      report("whole history + mean, 90 days", history_mean);
+      report("summary_between, 90 days", summary_mean);
+      assert!(fetch_between_day < history_day);
+      assert!(summary_mean < history_mean);
+    })
+    .await
+    .unwrap();
+  }
 }
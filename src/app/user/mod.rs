@@ -5,20 +5,34 @@ use std::sync::Arc;
 use teloxide::{dispatching::HandlerExt, types::UserId};
 
 use crate::{
-  app, bot_commands::StartCommand, db::Db, utils::filter_message,
+  app, bot_commands::StartCommand, db::Db,
+  utils::event_publisher::EventPublisher, utils::filter_message,
 };
 
 use self::repository::users;
 
-use super::UpdateHandler;
+use super::{profile::current_profile, UpdateHandler};
+
+/// User sent `/start`, optionally with deep link payload
+#[derive(Debug, Clone)]
+pub struct UserStarted {
+  pub user_id: UserId,
+  pub payload: String,
+}
 
 pub struct Plugin;
 
 impl app::Plugin for Plugin {
   fn update_handler(&self) -> UpdateHandler {
     filter_message().filter_command::<StartCommand>().endpoint(
-      |db: Arc<Db>, user_id: UserId| async move {
+      |db: Arc<Db>,
+       ep: Arc<EventPublisher>,
+       user_id: UserId,
+       cmd: StartCommand| async move {
+        let StartCommand::Start(payload) = cmd;
         users(&db).add(user_id).await?;
+        current_profile(&db, user_id).await?;
+        ep.send(UserStarted { user_id, payload });
         Ok(())
       },
     )
@@ -20,15 +20,30 @@ impl Repository {
   }
 
   /// Registers user at system. Reactivates user if disabled.
-  ///
-  /// # Implementation details
-  ///
-  /// Replaces user record with order change
   #[allow(clippy::cast_possible_wrap)]
   pub async fn add(&mut self, user_id: UserId) -> sqlx::Result<()> {
     let user_id = user_id.0 as i64;
     sqlx::query!(
-      "REPLACE INTO users (id, disabled) VALUES (?, FALSE)",
+      r#"
+        INSERT INTO users (id, disabled) VALUES (?, FALSE)
+        ON CONFLICT (id) DO UPDATE SET disabled = FALSE
+      "#,
+      user_id
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  /// Stops scheduled messages to user, e.g. after user blocked bot
+  #[allow(clippy::cast_possible_wrap)]
+  pub async fn disable(
+    &mut self,
+    user_id: UserId,
+  ) -> sqlx::Result<()> {
+    let user_id = user_id.0 as i64;
+    sqlx::query!(
+      "UPDATE users SET disabled = TRUE WHERE id = ?",
       user_id
     )
     .execute(&mut self.exec.borrow())
@@ -0,0 +1,349 @@
+use std::{collections::HashSet, fmt::Write};
+
+use chrono::{NaiveDate, TimeZone};
+
+use crate::app::{
+  insulin_injection::{InsulinInjection, InsulinKind},
+  stats::statistics::{hypo_episodes, GlucoseStats, InsulinStats},
+  sugar_measurement::{SugarMeasurement, HIGH_LEVEL, LOW_LEVEL},
+};
+
+/// Days in week, logging consistency is counted out of them
+pub const DAYS: u32 = 7;
+
+/// Results of a week
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeekStats {
+  /// `None` if there are no readings
+  pub glucose: Option<GlucoseStats>,
+  pub hypos: usize,
+  /// Week totals, `None` if there are no injections
+  pub insulin: Option<InsulinStats>,
+  /// Days with at least one reading
+  pub logged_days: usize,
+}
+
+impl WeekStats {
+  /// Days are counted in `tz`
+  pub fn calculate<Tz: TimeZone>(
+    readings: &[SugarMeasurement],
+    injections: &[InsulinInjection],
+    tz: &Tz,
+  ) -> Self {
+    let logged_days: HashSet<_> = readings
+      .iter()
+      .map(|m| m.date_time.with_timezone(tz).date_naive())
+      .collect();
+    let total = |kind| {
+      injections
+        .iter()
+        .filter(|i| i.kind == kind)
+        .map(|i| i.volume.as_cubic_centimeters())
+        .sum()
+    };
+    Self {
+      glucose: GlucoseStats::calculate(readings),
+      hypos: hypo_episodes(readings).len(),
+      insulin: (!injections.is_empty()).then(|| InsulinStats {
+        basal: total(Some(InsulinKind::Basal)),
+        bolus: total(Some(InsulinKind::Bolus)),
+        unspecified: total(None),
+      }),
+      logged_days: logged_days.len(),
+    }
+  }
+}
+
+/// Week compared to the previous one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeeklyDigest {
+  /// First day of week
+  pub start: NaiveDate,
+  /// Last day of week
+  pub end: NaiveDate,
+  pub current: WeekStats,
+  pub previous: WeekStats,
+}
+
+/// Which change of metric is an improvement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Goal {
+  Lower,
+  Higher,
+  /// Change is neither good nor bad, e.g. insulin dose
+  Neutral,
+}
+
+/// " (было X) ⬆️ лучше" if previous week has value. Values are
+/// shown with `precision` decimals, smaller changes are shown as
+/// stable.
+fn comparison(
+  current: f64,
+  previous: Option<f64>,
+  precision: usize,
+  unit: &str,
+  goal: Goal,
+) -> String {
+  let Some(previous) = previous else {
+    return String::new();
+  };
+  let tolerance =
+    0.5 / 10_f64.powi(precision.try_into().unwrap_or(0));
+  let difference = current - previous;
+  let trend = if difference.abs() < tolerance {
+    "➡️"
+  } else {
+    match (difference > 0.0, goal) {
+      (true, Goal::Neutral) => "⬆️",
+      (false, Goal::Neutral) => "⬇️",
+      (true, Goal::Higher) => "⬆️ лучше",
+      (false, Goal::Lower) => "⬇️ лучше",
+      (true, Goal::Lower) => "⬆️ хуже",
+      (false, Goal::Higher) => "⬇️ хуже",
+    }
+  };
+  format!(" (было {previous:.precision$}{unit}) {trend}")
+}
+
+impl WeeklyDigest {
+  /// Message text, `tag` marks not own profile
+  #[allow(clippy::cast_precision_loss)]
+  pub fn render(&self, tag: &str) -> String {
+    let mut text = format!(
+      "📅 Итоги недели {}–{}{tag}\n",
+      self.start.format("%d.%m"),
+      self.end.format("%d.%m.%Y")
+    );
+    let (current, previous) = (self.current, self.previous);
+    match current.glucose {
+      Some(glucose) => {
+        let percent = |share: f64| share * 100.0;
+        let previous = previous.glucose;
+        let _ = writeln!(
+          text,
+          "\nСредний сахар: {:.1} ммоль/л{}",
+          glucose.mean,
+          comparison(
+            glucose.mean,
+            previous.map(|p| p.mean),
+            1,
+            "",
+            Goal::Lower
+          )
+        );
+        let in_range = percent(glucose.in_range);
+        let _ = writeln!(
+          text,
+          "В диапазоне ({LOW_LEVEL}–{HIGH_LEVEL:.1}): {in_range:.0} %{}",
+          comparison(
+            in_range,
+            previous.map(|p| percent(p.in_range)),
+            0,
+            " %",
+            Goal::Higher
+          )
+        );
+        if glucose.count > 1 {
+          let cv = glucose.coefficient_of_variation();
+          let previous = previous
+            .filter(|p| p.count > 1)
+            .map(|p| p.coefficient_of_variation());
+          let _ = writeln!(
+            text,
+            "Вариабельность (CV): {cv:.0} %{}",
+            comparison(cv, previous, 0, " %", Goal::Lower)
+          );
+        }
+      }
+      None => text.push_str(
+        "\nЗа неделю не записано ни одного измерения сахара\n",
+      ),
+    }
+    let count = |n: usize| n as f64;
+    let _ = writeln!(
+      text,
+      "Гипогликемии: {}{}",
+      current.hypos,
+      comparison(
+        count(current.hypos),
+        Some(count(previous.hypos)),
+        0,
+        "",
+        Goal::Lower
+      )
+    );
+    let _ = writeln!(
+      text,
+      "Дней с измерениями: {} из {DAYS}{}",
+      current.logged_days,
+      comparison(
+        count(current.logged_days),
+        Some(count(previous.logged_days)),
+        0,
+        "",
+        Goal::Higher
+      )
+    );
+    match current.insulin {
+      Some(insulin) => {
+        let _ = write!(
+          text,
+          "\nИнсулин за неделю: {:.1} ЕД{}\n\
+          • базальный: {:.1} ЕД\n\
+          • болюсный: {:.1} ЕД\n",
+          insulin.total(),
+          comparison(
+            insulin.total(),
+            previous.insulin.map(|p| p.total()),
+            1,
+            "",
+            Goal::Neutral
+          ),
+          insulin.basal,
+          insulin.bolus,
+        );
+        if insulin.unspecified > 0.0 {
+          let _ = writeln!(
+            text,
+            "• не указан: {:.1} ЕД",
+            insulin.unspecified
+          );
+        }
+      }
+      None => text.push_str("\nИнъекций инсулина не записано\n"),
+    }
+    text
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{DateTime, Utc};
+
+  use crate::app::{
+    insulin_injection::Insulin, sugar_measurement::SugarLevel,
+  };
+
+  use super::*;
+
+  fn at(s: &str) -> DateTime<Utc> {
+    s.parse().unwrap()
+  }
+
+  fn reading(date_time: &str, level: f64) -> SugarMeasurement {
+    SugarMeasurement {
+      date_time: at(date_time),
+      level: SugarLevel::from_millimoles_per_liter(level),
+    }
+  }
+
+  fn glucose(mean: f64, std_dev: f64, in_range: f64) -> GlucoseStats {
+    GlucoseStats {
+      count: 20,
+      mean,
+      std_dev,
+      below_range: 0.0,
+      in_range,
+      above_range: 1.0 - in_range,
+    }
+  }
+
+  #[test]
+  fn calculates_week() {
+    let readings = [
+      reading("2024-02-26T08:00:00Z", 3.5),
+      reading("2024-02-26T08:20:00Z", 5.0),
+      reading("2024-02-28T08:00:00Z", 12.0),
+    ];
+    let injections = [
+      InsulinInjection {
+        date_time: at("2024-02-26T08:00:00Z"),
+        volume: Insulin::from_cubic_centimeters(4.0),
+        kind: Some(InsulinKind::Bolus),
+      },
+      InsulinInjection {
+        date_time: at("2024-02-27T22:00:00Z"),
+        volume: Insulin::from_cubic_centimeters(10.0),
+        kind: Some(InsulinKind::Basal),
+      },
+    ];
+    let week = WeekStats::calculate(&readings, &injections, &Utc);
+    assert_eq!(week.glucose.unwrap().count, 3);
+    assert_eq!(week.hypos, 1);
+    assert_eq!(week.logged_days, 2);
+    assert_eq!(
+      week.insulin,
+      Some(InsulinStats {
+        basal: 10.0,
+        bolus: 4.0,
+        unspecified: 0.0,
+      })
+    );
+  }
+
+  #[test]
+  fn renders_comparison() {
+    let digest = WeeklyDigest {
+      start: "2024-02-26".parse().unwrap(),
+      end: "2024-03-03".parse().unwrap(),
+      current: WeekStats {
+        glucose: Some(glucose(7.2, 2.16, 0.72)),
+        hypos: 2,
+        insulin: Some(InsulinStats {
+          basal: 84.0,
+          bolus: 96.0,
+          unspecified: 0.0,
+        }),
+        logged_days: 7,
+      },
+      previous: WeekStats {
+        glucose: Some(glucose(7.8, 3.12, 0.65)),
+        hypos: 2,
+        insulin: Some(InsulinStats {
+          basal: 84.0,
+          bolus: 86.0,
+          unspecified: 0.0,
+        }),
+        logged_days: 5,
+      },
+    };
+    assert_eq!(
+      digest.render(" [Маша]"),
+      "📅 Итоги недели 26.02–03.03.2024 [Маша]\n\
+      \n\
+      Средний сахар: 7.2 ммоль/л (было 7.8) ⬇️ лучше\n\
+      В диапазоне (3.9–10.0): 72 % (было 65 %) ⬆️ лучше\n\
+      Вариабельность (CV): 30 % (было 40 %) ⬇️ лучше\n\
+      Гипогликемии: 2 (было 2) ➡️\n\
+      Дней с измерениями: 7 из 7 (было 5) ⬆️ лучше\n\
+      \n\
+      Инсулин за неделю: 180.0 ЕД (было 170.0) ⬆️\n\
+      • базальный: 84.0 ЕД\n\
+      • болюсный: 96.0 ЕД\n"
+    );
+  }
+
+  #[test]
+  fn renders_without_previous_readings() {
+    let week = WeekStats {
+      glucose: None,
+      hypos: 0,
+      insulin: None,
+      logged_days: 0,
+    };
+    let digest = WeeklyDigest {
+      start: "2024-02-26".parse().unwrap(),
+      end: "2024-03-03".parse().unwrap(),
+      current: WeekStats {
+        glucose: Some(glucose(6.0, 1.2, 0.9)),
+        logged_days: 3,
+        ..week
+      },
+      previous: week,
+    };
+    let text = digest.render("");
+    assert!(text.contains("Средний сахар: 6.0 ммоль/л\n"));
+    assert!(text.contains("Дней с измерениями: 3 из 7 (было 0) ⬆️"));
+    assert!(text.ends_with("Инъекций инсулина не записано\n"));
+  }
+}
@@ -0,0 +1,378 @@
+pub mod digest;
+pub mod repository;
+
+use std::sync::Arc;
+
+use chrono::{
+  DateTime, Datelike, Days, Duration, NaiveDateTime, NaiveTime,
+  TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use clokwerk::{AsyncScheduler, TimeUnits};
+use teloxide::{
+  dptree::case,
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile},
+  ApiError, RequestError,
+};
+
+use crate::{
+  app,
+  bot_commands::MenuCommand,
+  common::{any, Result},
+  db::Db,
+  event_handler::{filter_event, handler, EventHandler},
+  utils::{
+    event_publisher::EventPublisher, filter_callback_query,
+    filter_message,
+  },
+};
+
+use self::{
+  digest::{WeekStats, WeeklyDigest, DAYS},
+  repository::{weekly_digests, DueDigest},
+};
+
+use super::{
+  chart::render::Chart,
+  insulin_injection::repository::insulin_injections,
+  preferences::repository::preferences,
+  profile::{
+    current_profile, repository::profiles, Profile, ProfileId,
+  },
+  quiet_hours::repository::quiet_hours,
+  stats::midnight_in,
+  sugar_measurement::repository::sugar_measurements,
+  user::repository::users,
+  UpdateHandler,
+};
+
+const CALLBACK_PREFIX: &str = "weekly_digest:";
+/// Digests delayed longer, e.g. while user disabled bot, are skipped
+const MAX_DELAY_HOURS: i64 = 12;
+/// Time on Sunday in profile timezone digest is sent at
+const DIGEST_HOUR: i64 = 20;
+
+#[derive(Debug, Clone)]
+struct WeeklyDigestTick;
+
+/// Digest state chosen by button
+#[derive(Debug, Clone, Copy)]
+struct Toggle {
+  profile_id: ProfileId,
+  enable: bool,
+}
+
+impl Toggle {
+  fn parse(data: &str) -> Option<Self> {
+    let data = data.strip_prefix(CALLBACK_PREFIX)?;
+    let (profile_id, enable) = data.split_once(':')?;
+    Some(Self {
+      profile_id: ProfileId(profile_id.parse().ok()?),
+      enable: match enable {
+        "on" => true,
+        "off" => false,
+        _ => return None,
+      },
+    })
+  }
+
+  fn button(self) -> InlineKeyboardButton {
+    let ProfileId(id) = self.profile_id;
+    let (label, data) = if self.enable {
+      ("Включить", "on")
+    } else {
+      ("Выключить", "off")
+    };
+    InlineKeyboardButton::callback(
+      label,
+      format!("{CALLBACK_PREFIX}{id}:{data}"),
+    )
+  }
+}
+
+pub struct Plugin;
+
+impl app::Plugin for Plugin {
+  fn update_handler(&self) -> UpdateHandler {
+    dptree::entry()
+      .branch(
+        filter_message().filter_command::<MenuCommand>().branch(
+          case![MenuCommand::WeeklyDigest].endpoint(send_settings),
+        ),
+      )
+      .branch(
+        filter_callback_query()
+          .filter_map(|query: CallbackQuery| {
+            Toggle::parse(query.data.as_deref()?)
+          })
+          .endpoint(toggle),
+      )
+  }
+
+  fn event_handler(&self) -> EventHandler {
+    filter_event::<WeeklyDigestTick>().chain(handler(send_due))
+  }
+
+  fn schedule(
+    &self,
+    scheduler: &mut AsyncScheduler,
+    event_publisher: Arc<EventPublisher>,
+  ) {
+    scheduler.every(1.minute()).run(move || {
+      event_publisher.send(WeeklyDigestTick);
+      async {}
+    });
+  }
+}
+
+/// Text and button of digest settings
+fn settings(
+  profile: &Profile,
+  enabled: bool,
+) -> (String, InlineKeyboardMarkup) {
+  let state = if enabled {
+    "включен"
+  } else {
+    "выключен"
+  };
+  let text = format!(
+    "Еженедельный отчет{} {state}. По воскресеньям в \
+    {DIGEST_HOUR}:00 бот сравнивает неделю с предыдущей: средний \
+    сахар, время в диапазоне, вариабельность, гипогликемии, \
+    инсулин и регулярность измерений",
+    profile.tag()
+  );
+  let toggle = Toggle {
+    profile_id: profile.id,
+    enable: !enabled,
+  };
+  (text, InlineKeyboardMarkup::new([[toggle.button()]]))
+}
+
+async fn send_settings(
+  bot: Bot,
+  db: Arc<Db>,
+  chat_id: ChatId,
+  user_id: UserId,
+) -> Result<()> {
+  let profile = current_profile(&db, user_id).await?;
+  let enabled = weekly_digests(&db)
+    .fetch_next_at(profile.id)
+    .await?
+    .is_some();
+  let (text, keyboard) = settings(&profile, enabled);
+  bot
+    .send_message(chat_id, text)
+    .reply_markup(keyboard)
+    .await?;
+  Ok(())
+}
+
+async fn toggle(
+  bot: Bot,
+  db: Arc<Db>,
+  user_id: UserId,
+  toggle: Toggle,
+  query: CallbackQuery,
+) -> Result<()> {
+  bot.answer_callback_query(query.id).await?;
+  let Some(msg) = query.message else {
+    return Ok(());
+  };
+  let profile = profiles(&db).fetch(toggle.profile_id).await?;
+  let Some(profile) =
+    profile.filter(|profile| profile.user_id == user_id)
+  else {
+    return Ok(());
+  };
+  let mut digests = weekly_digests(&db);
+  if toggle.enable {
+    let prefs = preferences(&db)
+      .fetch(profile.id)
+      .await?
+      .unwrap_or_default();
+    let now = Utc::now().with_timezone(&prefs.timezone);
+    digests.enable(profile.id, next_digest_at(now)).await?;
+  } else {
+    digests.disable(profile.id).await?;
+  }
+  let (text, keyboard) = settings(&profile, toggle.enable);
+  bot
+    .edit_message_text(msg.chat.id, msg.id, text)
+    .reply_markup(keyboard)
+    .await?;
+  Ok(())
+}
+
+async fn send_due(bot: Bot, db: Arc<Db>) -> Result<()> {
+  let now = Utc::now();
+  let mut digests = weekly_digests(&db);
+  for DueDigest { profile, due_at } in digests.fetch_due(now).await? {
+    let tz = preferences(&db)
+      .fetch(profile.id)
+      .await?
+      .unwrap_or_default()
+      .timezone;
+    let local_now = now.with_timezone(&tz);
+    let is_stale = now - due_at > Duration::hours(MAX_DELAY_HOURS);
+    if !is_stale {
+      let quiet = quiet_hours(&db).fetch(profile.user_id).await?;
+      if quiet.is_some_and(|quiet| quiet.contains(local_now.time())) {
+        // Sent once quiet hours are over
+        continue;
+      }
+      if !send_digest(&bot, &db, &profile, due_at, tz).await? {
+        users(&db).disable(profile.user_id).await?;
+      }
+    }
+    digests
+      .reschedule(profile.id, next_digest_at(local_now))
+      .await?;
+  }
+  Ok(())
+}
+
+/// Sends digest of week ending at `end`, `false` if user blocked bot
+async fn send_digest(
+  bot: &Bot,
+  db: &Db,
+  profile: &Profile,
+  end: DateTime<Utc>,
+  tz: Tz,
+) -> Result<bool> {
+  let last_day = end.with_timezone(&tz).date_naive();
+  let first_day = last_day - Days::new(u64::from(DAYS - 1));
+  let start = midnight_in(first_day, &tz);
+  let week = Duration::days(DAYS.into());
+  let measurements = sugar_measurements(db, profile.id);
+  let injections = insulin_injections(db, profile.id);
+  let readings = measurements.fetch_between(start, end).await?;
+  let doses = injections.fetch_between(start, end).await?;
+  let previous_readings =
+    measurements.fetch_between(start - week, end - week).await?;
+  let previous_doses =
+    injections.fetch_between(start - week, end - week).await?;
+  let digest = WeeklyDigest {
+    start: first_day,
+    end: last_day,
+    current: WeekStats::calculate(&readings, &doses, &tz),
+    previous: WeekStats::calculate(
+      &previous_readings,
+      &previous_doses,
+      &tz,
+    ),
+  };
+  let text = digest.render(&profile.tag());
+  match bot.send_message(profile.user_id, text).await {
+    Ok(_) => {}
+    Err(RequestError::Api(ApiError::BotBlocked)) => return Ok(false),
+    Err(err) => {
+      // Rescheduled anyway so digest isn't resent every tick
+      log::error!(
+        "Weekly digest of {} isn't sent: {err}",
+        profile.user_id
+      );
+      return Ok(true);
+    }
+  }
+  if readings.is_empty() {
+    return Ok(true);
+  }
+  let local = |dt: DateTime<Utc>| dt.with_timezone(&tz).naive_local();
+  let chart = Chart {
+    title: format!("Сахар за неделю{}", profile.tag()),
+    start: local(start),
+    end: local(end),
+    levels: readings
+      .iter()
+      .map(|m| {
+        (local(m.date_time), m.level.as_millimoles_per_liter())
+      })
+      .collect(),
+    injections: doses
+      .iter()
+      .map(|i| {
+        (local(i.date_time), i.volume.as_cubic_centimeters(), i.kind)
+      })
+      .collect(),
+    meals: Vec::new(),
+  };
+  let png = tokio::task::spawn_blocking(move || chart.render())
+    .await
+    .map_err(any)?;
+  // Text is already sent, so digest isn't failed without chart
+  let png = match png {
+    Ok(png) => png,
+    Err(err) => {
+      log::error!("Weekly chart isn't rendered: {err}");
+      return Ok(true);
+    }
+  };
+  let res = bot
+    .send_photo(
+      profile.user_id,
+      InputFile::memory(png).file_name("week.png"),
+    )
+    .await;
+  if let Err(err) = res {
+    log::error!(
+      "Weekly chart of {} isn't sent: {err}",
+      profile.user_id
+    );
+  }
+  Ok(true)
+}
+
+/// Next Sunday evening after `now` in its timezone
+pub fn next_digest_at<Tz: TimeZone>(
+  now: DateTime<Tz>,
+) -> DateTime<Utc> {
+  let next = next_digest_time(now.naive_local());
+  next
+    .and_local_timezone(now.timezone())
+    .earliest()
+    .map_or_else(|| next.and_utc(), |next| next.to_utc())
+}
+
+fn next_digest_time(now: NaiveDateTime) -> NaiveDateTime {
+  let days_left = (7 - now.weekday().num_days_from_sunday()) % 7;
+  let sunday = now.date() + Days::new(days_left.into());
+  let next =
+    sunday.and_time(NaiveTime::MIN) + Duration::hours(DIGEST_HOUR);
+  if next > now {
+    next
+  } else {
+    next + Days::new(7)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn digest_is_sent_on_sunday_evening() {
+    let at = |s: &str| s.parse::<NaiveDateTime>().unwrap();
+    let next = at("2024-03-03T20:00:00");
+    assert_eq!(next_digest_time(at("2024-02-28T12:00:00")), next);
+    assert_eq!(next_digest_time(at("2024-03-03T19:59:00")), next);
+    assert_eq!(next_digest_time(next), at("2024-03-10T20:00:00"));
+  }
+
+  #[test]
+  fn digest_is_scheduled_in_profile_timezone() {
+    let now =
+      "2024-03-03T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+    let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+    assert_eq!(
+      next_digest_at(now.with_timezone(&chrono_tz::Europe::Moscow)),
+      at("2024-03-03T17:00:00Z")
+    );
+    // Already Sunday night in Kamchatka
+    assert_eq!(
+      next_digest_at(now.with_timezone(&chrono_tz::Asia::Kamchatka)),
+      at("2024-03-10T08:00:00Z")
+    );
+  }
+}
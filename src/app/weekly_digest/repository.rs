@@ -0,0 +1,172 @@
+use chrono::{DateTime, Utc};
+use teloxide::types::UserId;
+
+use crate::{
+  app::profile::{Profile, ProfileId},
+  db::{txn::ExecutorHolder, Db},
+};
+
+pub fn weekly_digests(db: &Db) -> Repository {
+  Repository { exec: db.exec() }
+}
+
+pub struct Repository {
+  exec: ExecutorHolder,
+}
+
+/// Digest which should be sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct DueDigest {
+  pub profile: Profile,
+  pub due_at: DateTime<Utc>,
+}
+
+impl Repository {
+  /// When digest of profile is sent next, `None` if disabled
+  pub async fn fetch_next_at(
+    &self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<Option<DateTime<Utc>>> {
+    sqlx::query!(
+      "SELECT next_at FROM weekly_digests WHERE profile_id = ?",
+      profile_id.0
+    )
+    .map(|rec| rec.next_at.and_utc())
+    .fetch_optional(&mut self.exec.borrow())
+    .await
+  }
+
+  /// Enables digest sent at `next_at` and weekly after
+  pub async fn enable(
+    &mut self,
+    profile_id: ProfileId,
+    next_at: DateTime<Utc>,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      "REPLACE INTO weekly_digests (profile_id, next_at) VALUES (?, ?)",
+      profile_id.0,
+      next_at
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  pub async fn disable(
+    &mut self,
+    profile_id: ProfileId,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      "DELETE FROM weekly_digests WHERE profile_id = ?",
+      profile_id.0
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+
+  /// Digests due at `now` of users who haven't disabled the bot
+  #[allow(clippy::cast_sign_loss)]
+  pub async fn fetch_due(
+    &self,
+    now: DateTime<Utc>,
+  ) -> sqlx::Result<Vec<DueDigest>> {
+    sqlx::query!(
+      r#"
+        SELECT
+          profiles.id AS profile_id,
+          profiles.user_id,
+          profiles.name,
+          weekly_digests.next_at
+        FROM weekly_digests
+        JOIN profiles ON profiles.id = weekly_digests.profile_id
+        JOIN users ON users.id = profiles.user_id
+        WHERE users.disabled = FALSE AND weekly_digests.next_at <= ?
+      "#,
+      now
+    )
+    .map(|rec| DueDigest {
+      profile: Profile {
+        id: ProfileId(rec.profile_id),
+        user_id: UserId(rec.user_id as _),
+        name: rec.name,
+      },
+      due_at: rec.next_at.and_utc(),
+    })
+    .fetch_all(&mut self.exec.borrow())
+    .await
+  }
+
+  pub async fn reschedule(
+    &mut self,
+    profile_id: ProfileId,
+    next_at: DateTime<Utc>,
+  ) -> sqlx::Result<()> {
+    sqlx::query!(
+      "UPDATE weekly_digests SET next_at = ? WHERE profile_id = ?",
+      next_at,
+      profile_id.0
+    )
+    .execute(&mut self.exec.borrow())
+    .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::Duration;
+
+  use crate::{
+    app::{profile::repository::profiles, user::repository::users},
+    db::{tests::test_db, txn},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn toggle_and_fetch_due() {
+    let test_db = test_db().await.unwrap();
+    txn::begin(test_db.pool(), async {
+      let user = UserId(1);
+      users(&test_db).add(user).await.unwrap();
+      let profile = profiles(&test_db).add(user, None).await.unwrap();
+      let now: DateTime<Utc> =
+        "2024-03-03T17:00:00Z".parse().unwrap();
+      let mut digests = weekly_digests(&test_db);
+      assert_eq!(
+        digests.fetch_next_at(profile.id).await.unwrap(),
+        None
+      );
+      digests.enable(profile.id, now).await.unwrap();
+      assert_eq!(
+        digests.fetch_next_at(profile.id).await.unwrap(),
+        Some(now)
+      );
+      assert!(digests
+        .fetch_due(now - Duration::minutes(1))
+        .await
+        .unwrap()
+        .is_empty());
+      assert_eq!(
+        digests.fetch_due(now).await.unwrap(),
+        vec![DueDigest {
+          profile: profile.clone(),
+          due_at: now,
+        }]
+      );
+      digests
+        .reschedule(profile.id, now + Duration::weeks(1))
+        .await
+        .unwrap();
+      assert!(digests.fetch_due(now).await.unwrap().is_empty());
+      digests.disable(profile.id).await.unwrap();
+      assert_eq!(
+        digests.fetch_next_at(profile.id).await.unwrap(),
+        None
+      );
+    })
+    .await
+    .unwrap();
+  }
+}
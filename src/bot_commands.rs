@@ -3,7 +3,7 @@ use teloxide::utils::command::BotCommands;
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "snake_case")]
 pub enum StartCommand {
-  Start,
+  Start(String),
 }
 
 #[derive(BotCommands, Clone)]
@@ -15,4 +15,56 @@ pub enum MenuCommand {
   SugarLevel,
   #[command(description = "Указать введенный инсулин")]
   InsulinInjection,
+  #[command(description = "Указать прием пищи или заметку")]
+  Meal,
+  #[command(description = "Закрепленная сводка за сегодня")]
+  Dashboard,
+  #[command(description = "Дневник по дням")]
+  History,
+  #[command(description = "Статистика за период")]
+  Stats,
+  #[command(description = "Повторяющиеся закономерности")]
+  Insights,
+  #[command(description = "Оценка углеводного коэффициента и ФЧИ")]
+  Ratios,
+  #[command(description = "Базальный тест с пропуском еды")]
+  BasalTest,
+  #[command(description = "Напоминание о длинном инсулине")]
+  BasalReminder,
+  #[command(description = "Ежедневная сводка по вечерам")]
+  DailySummary,
+  #[command(description = "Еженедельный отчет с прошлой неделей")]
+  WeeklyDigest,
+  #[command(description = "График сахара за период")]
+  Chart,
+  #[command(description = "Амбулаторный профиль глюкозы (AGP)")]
+  Agp,
+  #[command(description = "PDF-отчет для врача")]
+  Report,
+  #[command(description = "Выгрузить дневник в CSV или XLSX")]
+  Export,
+  #[command(
+    description = "Импортировать дневник из другого приложения"
+  )]
+  Import,
+  #[command(description = "Лабораторный HbA1c и расчетный GMI")]
+  Hba1c,
+  #[command(description = "Профили пациентов")]
+  Profiles,
+  #[command(description = "Пригласить экстренный контакт")]
+  EmergencyContact,
+  #[command(description = "Пригласить опекуна")]
+  Caregiver,
+  #[command(description = "Подопечные и уведомления о них")]
+  Patients,
+  #[command(description = "Правила безопасности доз инсулина")]
+  InsulinSafety,
+  #[command(description = "Тихие часы без сводок и напоминаний")]
+  QuietHours,
+  #[command(description = "Часовой пояс и единицы сахара")]
+  Preferences,
+  #[command(description = "Выгрузить все мои данные")]
+  MyData,
+  #[command(description = "Удалить аккаунт и все данные")]
+  DeleteMe,
 }
@@ -3,7 +3,9 @@ pub mod send_payload;
 
 use teloxide::{
   dispatching::UpdateFilterExt,
-  types::{Message, Update, User},
+  requests::{Requester, ResponseResult},
+  types::{CallbackQuery, Message, Update, User, UserId},
+  Bot,
 };
 
 use crate::app::UpdateHandler;
@@ -15,3 +17,22 @@ pub fn filter_message() -> UpdateHandler {
     .map(|msg: Message| msg.chat.id)
     .map(|user: User| user.id)
 }
+
+pub fn filter_callback_query() -> UpdateHandler {
+  Update::filter_callback_query()
+    .map(|query: CallbackQuery| query.from.id)
+}
+
+/// First and last name of user if available
+pub async fn full_name(
+  bot: &Bot,
+  user_id: UserId,
+) -> ResponseResult<String> {
+  let chat = bot.get_chat(user_id).await?;
+  let name = [chat.first_name(), chat.last_name()]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(" ");
+  Ok(name)
+}